@@ -0,0 +1,399 @@
+use crate::git_object::*;
+use crate::ignore::IgnoreRules;
+use crate::index::{Index, IndexEntry};
+use crate::object_store::ObjectStore;
+use crate::pathspec::{Pathspec, PathspecItem};
+use crate::repository::{open_repository, Repository};
+use crate::worktree::*;
+use std::{collections::BTreeSet, fs, path::Path};
+
+#[derive(Default)]
+struct AddOption<'a> {
+    dry_run: bool,
+    verbose: bool,
+    force: bool,
+    update: bool,
+    all: bool,
+    intent_to_add: bool,
+    paths: Vec<&'a String>,
+}
+
+pub fn add(commands: &[String]) {
+    let option = parse_options(commands);
+    if option.paths.is_empty() && !option.update && !option.all {
+        println!("Nothing specified, nothing added.");
+        println!("hint: Maybe you wanted to say 'git add .'?");
+        return;
+    }
+    let repo = match open_repository() {
+        Some(r) => r,
+        None => return,
+    };
+    for line in add_to_index(&repo, &option) {
+        println!("{}", line);
+    }
+}
+
+// stages what `option` names and returns what `git add` prints.
+fn add_to_index(repo: &Repository, option: &AddOption) -> Vec<String> {
+    let mut out = vec![];
+    let work_tree = match &repo.work_tree {
+        Some(w) => w,
+        None => {
+            out.push(String::from(
+                "fatal: this operation must be run in a work tree",
+            ));
+            return out;
+        }
+    };
+    let pathspec = if option.paths.is_empty() {
+        Pathspec::everything()
+    } else {
+        match Pathspec::parse(work_tree, &repo.prefix, &option.paths) {
+            Ok(p) => p,
+            Err(e) => {
+                out.push(format!("fatal: {}", e));
+                return out;
+            }
+        }
+    };
+    let config = match repo.config() {
+        Ok(c) => c,
+        Err(e) => {
+            out.push(format!("fatal: {:?}", e));
+            return out;
+        }
+    };
+    let mut ignore = if option.force {
+        IgnoreRules::none(work_tree)
+    } else {
+        IgnoreRules::load(work_tree, &repo.git_dir, &config)
+    };
+    let index_path = repo.index_path();
+    let mut index = match Index::lock_or_report(&index_path) {
+        Ok(i) => i,
+        Err(message) => {
+            out.push(message);
+            return out;
+        }
+    };
+    let index_mtime = file_mtime(&index_path);
+    let store = repo.object_store();
+
+    // the untracked files to add, and the paths named on the command line that are
+    // ignored.
+    let mut untracked = BTreeSet::new();
+    let mut ignored = vec![];
+    for item in &pathspec.items {
+        let tracked = index.entries.iter().any(|e| item.matches(&e.path));
+        let found = if option.update {
+            vec![]
+        } else {
+            match matching_files(work_tree, item, &mut ignore) {
+                Ok(f) => f,
+                Err(e) => {
+                    out.push(format!("fatal: {:?}", e));
+                    return out;
+                }
+            }
+        };
+        let matched = tracked || !found.is_empty();
+        for entry in found {
+            if !entry.ignored {
+                if index.stages(&entry.path).is_empty() {
+                    untracked.insert(entry.path);
+                }
+            } else if !item.is_glob() && entry.path == item.path && !tracked {
+                ignored.push(item.path.clone());
+            }
+        }
+        if !matched && option.update {
+            out.push(format!(
+                "error: pathspec '{}' did not match any file(s) known to git",
+                item.original
+            ));
+            return out;
+        } else if !matched {
+            out.push(format!(
+                "fatal: pathspec '{}' did not match any files",
+                item.original
+            ));
+            return out;
+        }
+    }
+
+    let mut tracked: Vec<String> = index
+        .entries
+        .iter()
+        .filter(|e| pathspec.matches(&e.path))
+        .map(|e| e.path.clone())
+        .collect();
+    tracked.dedup();
+    let changes = AddChanges {
+        store: &store,
+        work_tree,
+        index_mtime,
+        dry_run: option.dry_run,
+        verbose: option.verbose || option.dry_run,
+    };
+    for path in tracked {
+        if !option.intent_to_add {
+            if let Err(e) = changes.update_tracked(&mut index, &path, &mut out) {
+                out.push(format!("error: unable to index file '{}': {:?}", path, e));
+                out.push(String::from("fatal: updating files failed"));
+                return out;
+            }
+        }
+    }
+    for path in untracked {
+        let added = if option.intent_to_add {
+            changes.intend_to_add(&mut index, &path, &mut out)
+        } else {
+            changes.add_untracked(&mut index, &path, &mut out)
+        };
+        if let Err(e) = added {
+            out.push(format!("error: unable to index file '{}': {:?}", path, e));
+            out.push(String::from("fatal: adding files failed"));
+            return out;
+        }
+    }
+
+    if !option.dry_run {
+        if let Err(e) = index.commit() {
+            out.push(format!("fatal: unable to write new index file: {:?}", e));
+            return out;
+        }
+    }
+    if !ignored.is_empty() {
+        out.push(String::from(
+            "The following paths are ignored by one of your .gitignore files:",
+        ));
+        for path in ignored {
+            out.push(path);
+        }
+        out.push(String::from("hint: Use -f if you really want to add them."));
+        out.push(String::from("hint: Turn this message off by running"));
+        out.push(String::from(
+            "hint: \"git config advice.addIgnoredFile false\"",
+        ));
+    }
+    out
+}
+
+// the files in the work tree that `item` names, ignored ones included and marked.
+fn matching_files(
+    work_tree: &Path,
+    item: &PathspecItem,
+    ignore: &mut IgnoreRules,
+) -> Result<Vec<WorkTreeEntry>, GitObjectError> {
+    let base = item.base_dir();
+    let full = work_tree.join(base);
+    let metadata = match fs::symlink_metadata(&full) {
+        Ok(m) => m,
+        Err(_) => return Ok(vec![]),
+    };
+    let is_dir = metadata.is_dir();
+    // a file, another repository or an ignored directory is taken as a whole.
+    if !base.is_empty() && (!is_dir || full.join(".git").exists() || ignore.is_ignored(base, true))
+    {
+        let entry = WorkTreeEntry {
+            path: base.to_string(),
+            is_dir,
+            ignored: ignore.is_ignored(base, is_dir),
+        };
+        return Ok(vec![entry]);
+    }
+    Ok(list_work_tree(work_tree, base, ignore, true)?
+        .into_iter()
+        .filter(|e| item.matches(&e.path))
+        .collect())
+}
+
+struct AddChanges<'a> {
+    store: &'a dyn ObjectStore,
+    work_tree: &'a Path,
+    index_mtime: Option<(u32, u32)>,
+    dry_run: bool,
+    verbose: bool,
+}
+
+impl AddChanges<'_> {
+    // stages what became of a tracked path: its new content, or its removal.
+    fn update_tracked(
+        &self,
+        index: &mut Index,
+        path: &str,
+        out: &mut Vec<String>,
+    ) -> Result<(), GitObjectError> {
+        let metadata = match fs::symlink_metadata(self.work_tree.join(path)) {
+            Ok(m) if !m.is_dir() || self.work_tree.join(path).join(".git").exists() => m,
+            _ => {
+                if self.verbose {
+                    out.push(format!("remove '{}'", path));
+                }
+                index.remove(path);
+                return Ok(());
+            }
+        };
+        let current = index.get(path, 0);
+        if current.is_some_and(|e| is_stat_clean(e, &metadata, self.index_mtime)) {
+            return Ok(());
+        }
+        let entry = entry_from_work_tree(self.store, self.work_tree, path, !self.dry_run)?;
+        match current {
+            // only the stat data is out of date.
+            Some(e) if e.id == entry.id && e.mode == entry.mode && !e.intent_to_add => {
+                if let Some(e) = index.get_mut(path, 0) {
+                    e.stat = entry.stat;
+                }
+            }
+            _ => {
+                if self.verbose {
+                    out.push(format!("add '{}'", path));
+                }
+                index.add(entry);
+            }
+        }
+        Ok(())
+    }
+
+    fn add_untracked(
+        &self,
+        index: &mut Index,
+        path: &str,
+        out: &mut Vec<String>,
+    ) -> Result<(), GitObjectError> {
+        let entry = entry_from_work_tree(self.store, self.work_tree, path, !self.dry_run)?;
+        if self.verbose {
+            out.push(format!("add '{}'", path));
+        }
+        index.add(entry);
+        Ok(())
+    }
+
+    // records that the path will be added, without its content: an empty blob with
+    // the intent-to-add flag.
+    fn intend_to_add(
+        &self,
+        index: &mut Index,
+        path: &str,
+        out: &mut Vec<String>,
+    ) -> Result<(), GitObjectError> {
+        let metadata = fs::symlink_metadata(self.work_tree.join(path))?;
+        let mode = match FileMode::from_metadata(&metadata) {
+            FileMode::Tree => FileMode::Gitlink,
+            mode => mode,
+        };
+        let empty = GitObject::Blob(vec![]);
+        let id = if self.dry_run {
+            empty.to_hash()
+        } else {
+            empty.write(self.store)?
+        };
+        let mut entry = IndexEntry::new(path, &id, mode);
+        entry.intent_to_add = true;
+        if self.verbose {
+            out.push(format!("add '{}'", path));
+        }
+        index.add(entry);
+        Ok(())
+    }
+}
+
+fn parse_options(commands: &[String]) -> AddOption<'_> {
+    let mut option: AddOption = Default::default();
+    let mut only_paths = false;
+    for token in &commands[2..] {
+        match &token[..] {
+            _ if only_paths => option.paths.push(token),
+            "--" => only_paths = true,
+            "-n" | "--dry-run" => option.dry_run = true,
+            "-v" | "--verbose" => option.verbose = true,
+            "-f" | "--force" => option.force = true,
+            "-u" | "--update" => option.update = true,
+            "-A" | "--all" => option.all = true,
+            "-N" | "--intent-to-add" => option.intent_to_add = true,
+            _ if token.starts_with('-') => println!("ignore option {}", token),
+            _ => option.paths.push(token),
+        }
+    }
+    option
+}
+
+#[cfg(test)]
+mod test {
+    use crate::add::*;
+    use crate::init::init_repository;
+    use crate::test_utils::temp_dir;
+
+    fn run(repo: &Repository, args: &[&str]) -> Vec<String> {
+        let commands: Vec<String> = ["git", "add"]
+            .iter()
+            .chain(args)
+            .map(|s| s.to_string())
+            .collect();
+        add_to_index(repo, &parse_options(&commands))
+    }
+
+    fn staged(repo: &Repository) -> Vec<String> {
+        let index = Index::read_from(&repo.index_path()).unwrap();
+        index.entries.into_iter().map(|e| e.path).collect()
+    }
+
+    #[test]
+    fn test_add() {
+        let root = temp_dir("add");
+        init_repository(&root.join(".git"), false, "master", None).unwrap();
+        fs::create_dir_all(root.join("d")).unwrap();
+        for path in ["a", "b", "d/c", "x.log"] {
+            fs::write(root.join(path), path).unwrap();
+        }
+        fs::write(root.join(".gitignore"), "*.log\n").unwrap();
+        let repo = Repository::discover_from(&root, None, None).unwrap();
+
+        assert!(run(&repo, &["."]).is_empty());
+        assert_eq!(staged(&repo), vec![".gitignore", "a", "b", "d/c"]);
+        assert!(!repo.index_path().with_extension("lock").exists());
+
+        // an ignored path named on the command line needs -f.
+        assert_eq!(
+            run(&repo, &["x.log"]),
+            vec![
+                "The following paths are ignored by one of your .gitignore files:",
+                "x.log",
+                "hint: Use -f if you really want to add them.",
+                "hint: Turn this message off by running",
+                "hint: \"git config advice.addIgnoredFile false\"",
+            ]
+        );
+        assert!(!staged(&repo).contains(&String::from("x.log")));
+        assert_eq!(run(&repo, &["-f", "-v", "x.log"]), vec!["add 'x.log'"]);
+        assert!(staged(&repo).contains(&String::from("x.log")));
+
+        // -u stages changes and deletions of tracked files only.
+        fs::remove_file(root.join("a")).unwrap();
+        fs::write(root.join("b"), "changed").unwrap();
+        fs::write(root.join("n"), "new").unwrap();
+        assert_eq!(run(&repo, &["-u", "-v"]), vec!["remove 'a'", "add 'b'"]);
+        assert_eq!(staged(&repo), vec![".gitignore", "b", "d/c", "x.log"]);
+        assert_eq!(
+            run(&repo, &["-u", "n"]),
+            vec!["error: pathspec 'n' did not match any file(s) known to git"]
+        );
+
+        // -A also adds the untracked ones.
+        fs::remove_file(root.join("d/c")).unwrap();
+        assert_eq!(run(&repo, &["-A", "-v"]), vec!["remove 'd/c'", "add 'n'"]);
+        assert_eq!(staged(&repo), vec![".gitignore", "b", "n", "x.log"]);
+        assert_eq!(
+            run(&repo, &["missing"]),
+            vec!["fatal: pathspec 'missing' did not match any files"]
+        );
+
+        // another process holding the index lock stops the command.
+        fs::write(root.join(".git/index.lock"), "").unwrap();
+        let out = run(&repo, &["-A"]);
+        assert!(out[0].starts_with("fatal: unable to create"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,293 @@
+use crate::git_object::*;
+use crate::index::IndexEntry;
+use crate::object_store::ObjectStore;
+use crate::utils::{hash_to_str, object_hash};
+
+type Result<T> = std::result::Result<T, GitObjectError>;
+
+// the cached tree ids of the index (its TREE extension), so trees of directories whose
+// entries did not change need not be built again.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CacheTree {
+    // how many index entries the tree covers, or none when it has to be built again.
+    pub entry_count: Option<usize>,
+    // only kept in the file when the entry count is known.
+    pub id: Vec<u8>,
+    // ordered by name length and then by name, as git keeps them.
+    pub children: Vec<(String, CacheTree)>,
+}
+
+impl Default for CacheTree {
+    fn default() -> Self {
+        CacheTree::new()
+    }
+}
+
+impl CacheTree {
+    pub fn new() -> Self {
+        CacheTree {
+            entry_count: None,
+            id: vec![],
+            children: vec![],
+        }
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let mut pos = 0;
+        let (name, tree) = parse_node(data, &mut pos)?;
+        if !name.is_empty() || pos != data.len() {
+            return Err(corrupt("trailing data"));
+        }
+        Ok(tree)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = vec![];
+        self.write_node(&mut data, "");
+        data
+    }
+
+    fn write_node(&self, data: &mut Vec<u8>, name: &str) {
+        data.extend_from_slice(name.as_bytes());
+        data.push(0);
+        let count = match self.entry_count {
+            Some(c) => c.to_string(),
+            None => String::from("-1"),
+        };
+        data.extend_from_slice(format!("{} {}\n", count, self.children.len()).as_bytes());
+        if self.entry_count.is_some() {
+            data.extend_from_slice(&self.id);
+        }
+        for (name, child) in &self.children {
+            child.write_node(data, name);
+        }
+    }
+
+    pub fn child(&self, name: &str) -> Option<&CacheTree> {
+        self.children
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, c)| c)
+    }
+
+    // the tree of the directory at `path`, below this one.
+    pub fn find(&self, path: &str) -> Option<&CacheTree> {
+        path.split('/')
+            .filter(|c| !c.is_empty())
+            .try_fold(self, |tree, name| tree.child(name))
+    }
+
+    // forgets the trees that contain `path`, after an entry there changed. a
+    // directory of the same name is gone as well.
+    pub fn invalidate(&mut self, path: &str) {
+        self.entry_count = None;
+        self.id.clear();
+        match path.split_once('/') {
+            Some((dir, rest)) => {
+                if let Some((_, child)) = self.children.iter_mut().find(|(n, _)| n == dir) {
+                    child.invalidate(rest);
+                }
+            }
+            None => self.children.retain(|(n, _)| n != path),
+        }
+    }
+
+    // writes the tree of `entries`, the sorted stage 0 entries of the index, and every
+    // tree below it that is not cached. missing blobs are an error unless `missing_ok`.
+    pub fn update(
+        &mut self,
+        store: &dyn ObjectStore,
+        entries: &[IndexEntry],
+        missing_ok: bool,
+    ) -> Result<()> {
+        self.update_dir(store, entries, 0, missing_ok)?;
+        Ok(())
+    }
+
+    // `entries` are those below this directory, whose path starts at `base_len`.
+    // returns whether any of them is only intended to be added. such entries are left
+    // out of the trees, which are then not cached.
+    fn update_dir(
+        &mut self,
+        store: &dyn ObjectStore,
+        entries: &[IndexEntry],
+        base_len: usize,
+        missing_ok: bool,
+    ) -> Result<bool> {
+        if self.entry_count == Some(entries.len()) && store.contains(&hash_to_str(&self.id)) {
+            return Ok(false);
+        }
+        let empty_tree = object_hash("tree", b"");
+        let mut nodes = vec![];
+        let mut children = vec![];
+        let mut intent_to_add = false;
+        let mut i = 0;
+        while i < entries.len() {
+            let entry = &entries[i];
+            let name = &entry.path[base_len..];
+            if let Some((dir, _)) = name.split_once('/') {
+                let below = format!("{}/", &entry.path[..base_len + dir.len()]);
+                let len = entries[i..]
+                    .iter()
+                    .take_while(|e| e.path.starts_with(&below))
+                    .count();
+                let mut sub = match self.children.iter().position(|(n, _)| n == dir) {
+                    Some(p) => self.children.remove(p).1,
+                    None => CacheTree::new(),
+                };
+                let sub_intent =
+                    sub.update_dir(store, &entries[i..i + len], below.len(), missing_ok)?;
+                // `add -N dir` does not make an empty tree.
+                if !(sub_intent && sub.id == empty_tree) {
+                    nodes.push(GitTreeNode::new(dir.to_string(), &sub.id, FileMode::Tree));
+                }
+                intent_to_add |= sub_intent;
+                children.push((dir.to_string(), sub));
+                i += len;
+                continue;
+            }
+            i += 1;
+            if entry.intent_to_add {
+                intent_to_add = true;
+                continue;
+            }
+            if entry.mode != FileMode::Gitlink
+                && !missing_ok
+                && !store.contains(&hash_to_str(&entry.id))
+            {
+                return Err(GitObjectError::NotFoundError(format!(
+                    "invalid object {} {} for '{}'",
+                    entry.mode,
+                    hash_to_str(&entry.id),
+                    entry.path
+                )));
+            }
+            nodes.push(GitTreeNode::new(name.to_string(), &entry.id, entry.mode));
+        }
+        self.id = GitObject::new_tree(nodes).write(store)?;
+        // the id is still what the parent tree needs, but is not written out.
+        self.entry_count = if intent_to_add {
+            None
+        } else {
+            Some(entries.len())
+        };
+        children.sort_by(|(a, _), (b, _)| a.len().cmp(&b.len()).then(a.cmp(b)));
+        self.children = children;
+        Ok(intent_to_add)
+    }
+}
+
+fn parse_node(data: &[u8], pos: &mut usize) -> Result<(String, CacheTree)> {
+    let rest = &data[*pos..];
+    let nul = rest
+        .iter()
+        .position(|b| *b == 0)
+        .ok_or_else(|| corrupt("missing path"))?;
+    let newline = rest[nul..]
+        .iter()
+        .position(|b| *b == b'\n')
+        .ok_or_else(|| corrupt("missing counts"))?
+        + nul;
+    let name = String::from_utf8(rest[..nul].to_vec()).map_err(|_| corrupt("bad path"))?;
+    let counts = std::str::from_utf8(&rest[nul + 1..newline]).map_err(|_| corrupt("bad counts"))?;
+    let (entry_count, subtree_count) = counts
+        .split_once(' ')
+        .and_then(|(e, s)| Some((e.parse::<i64>().ok()?, s.parse::<usize>().ok()?)))
+        .ok_or_else(|| corrupt("bad counts"))?;
+    *pos += newline + 1;
+    let mut tree = CacheTree::new();
+    if entry_count >= 0 {
+        tree.id = data
+            .get(*pos..*pos + 20)
+            .ok_or_else(|| corrupt("truncated id"))?
+            .to_vec();
+        tree.entry_count = Some(entry_count as usize);
+        *pos += 20;
+    }
+    for _ in 0..subtree_count {
+        tree.children.push(parse_node(data, pos)?);
+    }
+    tree.children
+        .sort_by(|(a, _), (b, _)| a.len().cmp(&b.len()).then(a.cmp(b)));
+    Ok((name, tree))
+}
+
+fn corrupt(message: &str) -> GitObjectError {
+    GitObjectError::ParseError(format!("index cache-tree corrupt: {}", message))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cache_tree::*;
+    use crate::object_store::MemoryObjectStore;
+
+    fn blob_entry(store: &MemoryObjectStore, path: &str) -> IndexEntry {
+        let id = GitObject::Blob(path.as_bytes().to_vec())
+            .write(store)
+            .unwrap();
+        IndexEntry::new(path, &id, FileMode::Regular)
+    }
+
+    #[test]
+    fn test_cache_tree() {
+        let store = MemoryObjectStore::new();
+        let mut entries: Vec<IndexEntry> = ["README", "src/a/x.rs", "src/lib.rs", "src/main.rs"]
+            .iter()
+            .map(|p| blob_entry(&store, p))
+            .collect();
+        let mut tree = CacheTree::new();
+        tree.update(&store, &entries, false).unwrap();
+        assert_eq!(tree.entry_count, Some(4));
+        assert_eq!(tree.find("src").unwrap().entry_count, Some(3));
+        assert_eq!(tree.find("src/a/").unwrap().entry_count, Some(1));
+        let src_id = tree.find("src").unwrap().id.clone();
+        match load_object_by_hash(&store, &hash_to_str(&tree.id)).unwrap() {
+            GitObject::Tree(nodes) => {
+                let names: Vec<&str> = nodes.iter().map(|n| n.filename.as_str()).collect();
+                assert_eq!(names, vec!["README", "src"]);
+                assert_eq!(nodes[1].hash, src_id);
+            }
+            _ => panic!("not a tree"),
+        }
+        assert!(store.contains(&hash_to_str(&tree.find("src/a").unwrap().id)));
+
+        let parsed = CacheTree::parse(&tree.to_bytes()).unwrap();
+        assert_eq!(parsed, tree);
+
+        // only the trees above a changed path are built again.
+        let a_id = tree.find("src/a").unwrap().id.clone();
+        entries[2] = blob_entry(&store, "src/lib.rs");
+        entries[2].id = GitObject::Blob(b"changed".to_vec()).write(&store).unwrap();
+        tree.invalidate("src/lib.rs");
+        assert_eq!(tree.entry_count, None);
+        assert_eq!(tree.find("src").unwrap().entry_count, None);
+        assert_eq!(tree.find("src/a").unwrap().entry_count, Some(1));
+        let parsed = CacheTree::parse(&tree.to_bytes()).unwrap();
+        assert_eq!(parsed, tree);
+        tree.update(&store, &entries, false).unwrap();
+        assert_eq!(tree.find("src/a").unwrap().id, a_id);
+        assert_ne!(tree.find("src").unwrap().id, src_id);
+
+        // a file replacing a directory drops its cached tree.
+        tree.invalidate("src/a");
+        assert!(tree.find("src/a").is_none());
+
+        // entries only intended to be added are left out and keep the tree uncached.
+        let mut intent = IndexEntry::new("new/file", &object_hash("blob", b""), FileMode::Regular);
+        intent.intent_to_add = true;
+        entries.insert(1, intent);
+        let mut tree = CacheTree::new();
+        tree.update(&store, &entries, false).unwrap();
+        assert_eq!(tree.entry_count, None);
+        assert!(tree.find("src").unwrap().entry_count.is_some());
+        match load_object_by_hash(&store, &hash_to_str(&tree.id)).unwrap() {
+            GitObject::Tree(nodes) => assert_eq!(nodes.len(), 2),
+            _ => panic!("not a tree"),
+        }
+
+        let missing = vec![IndexEntry::new("x", &[1; 20], FileMode::Regular)];
+        assert!(CacheTree::new().update(&store, &missing, false).is_err());
+        assert!(CacheTree::new().update(&store, &missing, true).is_ok());
+        assert!(CacheTree::parse(b"\0-1 1\n").is_err());
+    }
+}
@@ -1,4 +1,10 @@
 use crate::git_object::*;
+use crate::object_store::ObjectStore;
+use crate::repository::open_repository;
+use crate::rev_parse::report_resolve_error;
+use crate::revision::resolve_revision;
+use crate::utils::hash_to_str;
+use std::io;
 
 #[derive(Default)]
 struct CatFileOption<'a> {
@@ -10,36 +16,74 @@ struct CatFileOption<'a> {
 
 pub fn cat_file(commands: &[String]) {
     let option = parse_options(commands);
-    if option.obj_hash.is_none() {
-        return;
-    }
-
-    let result = match load_object_by_hash(&option.obj_hash.as_ref().unwrap()) {
+    let name = match option.obj_hash {
+        Some(n) => n,
+        None => return,
+    };
+    // only the header is inflated for -s and -t, and blobs are copied through as a stream.
+    let repo = match open_repository() {
+        Some(r) => r,
+        None => return,
+    };
+    let store = repo.object_store();
+    let hash = match resolve_revision(&repo.git_dir, &store, name) {
+        Ok(h) => hash_to_str(&h),
+        Err(e) => {
+            report_resolve_error(&store, name, &e);
+            println!("fatal: Not a valid object name {}", name);
+            return;
+        }
+    };
+    let hash = &hash;
+    // a full id is taken as it is, whether the object exists or not.
+    let mut reader = match store.read_stream(hash) {
         Ok(r) => r,
-        Err(e) => panic!("{:?}", e),
+        Err(GitObjectError::NotFoundError(_)) => {
+            println!("fatal: Not a valid object name {}", name);
+            return;
+        }
+        Err(e) => {
+            println!("fatal: {:?}", e);
+            return;
+        }
     };
     if option.pretty_print {
+        if reader.type_name() == "blob" {
+            match io::copy(&mut reader, &mut io::stdout().lock()) {
+                Ok(_) => {}
+                // the reader stopped early, as `| head` does.
+                Err(e) if e.kind() == io::ErrorKind::BrokenPipe => {}
+                Err(e) => println!("fatal: {}", e),
+            }
+            return;
+        }
+        let result = match load_object_by_hash(&store, hash) {
+            Ok(r) => r,
+            Err(e) => {
+                println!("fatal: {:?}", e);
+                return;
+            }
+        };
         result.pretty_print();
         return;
     }
     if option.show_size {
-        println!("{:?}", result.size());
+        println!("{}", reader.size());
         return;
     }
     if option.show_type {
-        println!("{}", result.type_name());
-        return;
+        println!("{}", reader.type_name());
     }
 }
 
-fn parse_options(commands: &[String]) -> CatFileOption {
+fn parse_options(commands: &[String]) -> CatFileOption<'_> {
     let mut option: CatFileOption = Default::default();
     for token in &commands[2..] {
         match &token[..] {
             "-p" => option.pretty_print = true,
             "-s" => option.show_size = true,
             "-t" => option.show_type = true,
-            _ if token.len() == 40 => option.obj_hash = Some(&token),
+            _ if !token.starts_with('-') => option.obj_hash = Some(token),
             _ => println!("ignore option {}", token),
         }
     }
@@ -1,5 +1,11 @@
 use crate::git_object::*;
-use crate::git_user::USER;
+use crate::git_user::current_signature;
+use crate::object_store::ObjectStore;
+use crate::repository::open_repository;
+use crate::rev_parse::report_resolve_error;
+use crate::revision::{peel, resolve_revision};
+use crate::utils::hash_to_str;
+use std::path::Path;
 
 #[derive(Default, Debug)]
 struct CommitTreeOption<'a> {
@@ -11,42 +17,101 @@ struct CommitTreeOption<'a> {
 pub fn commit_tree(commands: &[String]) {
     let option = parse_options(commands);
     // println!("option: {:?}", option);
+    let (tree_sha, message) = match (option.tree_sha, option.message) {
+        (Some(t), Some(m)) => (t, m),
+        _ => {
+            println!("usage: commit-tree <tree> [-p <parent>] -m <message>");
+            return;
+        }
+    };
+    let repo = match open_repository() {
+        Some(r) => r,
+        None => return,
+    };
+    let store = repo.object_store();
+    let tree = match resolve_as(&repo.git_dir, &store, tree_sha, "tree") {
+        Some(t) => t,
+        None => return,
+    };
+    let parent = match option.parent_sha {
+        Some(rev) => match resolve_as(&repo.git_dir, &store, rev, "commit") {
+            Some(p) => Some(p),
+            None => return,
+        },
+        None => None,
+    };
+    let config = match repo.config() {
+        Ok(c) => c,
+        Err(e) => {
+            println!("fatal: {:?}", e);
+            return;
+        }
+    };
+    let (author, committer) = match (
+        current_signature(&config, "AUTHOR"),
+        current_signature(&config, "COMMITTER"),
+    ) {
+        (Ok(a), Ok(c)) => (a, c),
+        (Err(e), _) | (_, Err(e)) => {
+            println!("{}", e);
+            println!("fatal: unable to auto-detect email address");
+            return;
+        }
+    };
     let commit = GitObject::new_commit(
-        option.tree_sha.unwrap().to_string(),
-        option.parent_sha,
-        &USER,
-        &USER,
-        option.message.unwrap().to_string(),
+        tree,
+        parent.as_ref(),
+        author,
+        committer,
+        message.to_string(),
     );
-    // println!("{}", commit.to_string());
-    commit.write().unwrap();
+    if let Err(e) = commit.write(&store) {
+        println!("fatal: {:?}", e);
+        return;
+    }
     println!("{}", commit.to_hash_str());
 }
 
-fn parse_options(commands: &[String]) -> CommitTreeOption {
+// resolves a revision and peels it to `kind`, reporting failures like git.
+fn resolve_as(git_dir: &Path, store: &dyn ObjectStore, rev: &str, kind: &str) -> Option<String> {
+    match resolve_revision(git_dir, store, rev) {
+        Ok(h) => match peel(store, &h, Some(kind)) {
+            Ok(h) => Some(hash_to_str(&h)),
+            Err(_) => {
+                println!("fatal: {} is not a valid '{}' object", rev, kind);
+                None
+            }
+        },
+        Err(e) => {
+            report_resolve_error(store, rev, &e);
+            println!("fatal: not a valid object name {}", rev);
+            None
+        }
+    }
+}
+
+fn parse_options(commands: &[String]) -> CommitTreeOption<'_> {
     let mut option: CommitTreeOption = Default::default();
     let mut message_flag = false;
     let mut parent_sha_flag = false;
     for token in &commands[2..] {
+        if message_flag {
+            option.message = Some(token);
+            message_flag = false;
+            continue;
+        }
+        if parent_sha_flag {
+            option.parent_sha = Some(token);
+            parent_sha_flag = false;
+            continue;
+        }
         match &token[..] {
             "-m" => message_flag = true,
             "-p" => parent_sha_flag = true,
-            _ if token.len() == 40 => {
-                if parent_sha_flag {
-                    option.parent_sha = Some(&token);
-                    parent_sha_flag = false;
-                } else {
-                    option.tree_sha = Some(&token);
-                }
-            }
-            _ => {
-                if message_flag {
-                    option.message = Some(&token);
-                    message_flag = false;
-                } else {
-                    println!("ignore option {}", token);
-                }
+            _ if option.tree_sha.is_none() && !token.starts_with('-') => {
+                option.tree_sha = Some(token)
             }
+            _ => println!("ignore option {}", token),
         }
     }
     option
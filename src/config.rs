@@ -0,0 +1,1125 @@
+use crate::git_object::GitObjectError;
+use crate::lockfile::LockFile;
+use crate::regex::Regex;
+use crate::repository::Repository;
+use crate::utils::wildmatch;
+use std::{
+    env, fs, io,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+type Result<T> = std::result::Result<T, GitObjectError>;
+
+// includes nested deeper than this are taken to be a loop, like git.
+const MAX_INCLUDE_DEPTH: usize = 10;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigEntry {
+    // section and name are case-insensitive and kept lowercased; subsections are not.
+    pub section: String,
+    pub subsection: Option<String>,
+    pub name: String,
+    // none for a variable without `=`, which reads as true.
+    pub value: Option<String>,
+    pub origin: PathBuf,
+}
+
+impl ConfigEntry {
+    // the key as `config --list` shows it.
+    pub fn key(&self) -> String {
+        match &self.subsection {
+            Some(sub) => format!("{}.{}.{}", self.section, sub, self.name),
+            None => format!("{}.{}", self.section, self.name),
+        }
+    }
+
+    fn matches(&self, key: &ConfigKey) -> bool {
+        self.section == key.section && self.subsection == key.subsection && self.name == key.name
+    }
+}
+
+// configuration read from one or more files, later entries overriding earlier ones.
+#[derive(Debug, Default)]
+pub struct Config {
+    entries: Vec<ConfigEntry>,
+}
+
+impl Config {
+    // system, global and repository configuration, in that order. without a git dir
+    // only the first two are read.
+    pub fn load(git_dir: Option<&Path>) -> Result<Self> {
+        let mut config = Config::default();
+        let mut paths: Vec<PathBuf> = system_config_path().into_iter().collect();
+        paths.extend(global_config_paths());
+        if let Some(git_dir) = git_dir {
+            paths.push(git_dir.join("config"));
+        }
+        for path in paths {
+            config.read_file(&path, git_dir, 0)?;
+        }
+        Ok(config)
+    }
+
+    // a single file and whatever it includes; a missing file is empty.
+    pub fn load_file(path: &Path, git_dir: Option<&Path>) -> Result<Self> {
+        let mut config = Config::default();
+        config.read_file(path, git_dir, 0)?;
+        Ok(config)
+    }
+
+    pub fn entries(&self) -> &[ConfigEntry] {
+        &self.entries
+    }
+
+    pub fn get_all(&self, key: &str) -> Vec<&ConfigEntry> {
+        match ConfigKey::parse(key) {
+            Some(key) => self.entries.iter().filter(|e| e.matches(&key)).collect(),
+            None => vec![],
+        }
+    }
+
+    pub fn get_entry(&self, key: &str) -> Option<&ConfigEntry> {
+        self.get_all(key).pop()
+    }
+
+    pub fn get_string(&self, key: &str) -> Result<Option<String>> {
+        match self.get_entry(key) {
+            Some(entry) => match &entry.value {
+                Some(value) => Ok(Some(value.clone())),
+                None => Err(GitObjectError::ParseError(format!(
+                    "missing value for '{}'",
+                    key
+                ))),
+            },
+            None => Ok(None),
+        }
+    }
+
+    pub fn get_bool(&self, key: &str) -> Result<Option<bool>> {
+        match self.get_entry(key) {
+            Some(entry) => parse_bool(entry.value.as_deref())
+                .map(Some)
+                .ok_or_else(|| bad_value(entry)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn get_int(&self, key: &str) -> Result<Option<i64>> {
+        match self.get_entry(key) {
+            Some(entry) => entry
+                .value
+                .as_deref()
+                .and_then(parse_int)
+                .map(Some)
+                .ok_or_else(|| bad_value(entry)),
+            None => Ok(None),
+        }
+    }
+
+    fn read_file(&mut self, path: &Path, git_dir: Option<&Path>, depth: usize) -> Result<()> {
+        if depth > MAX_INCLUDE_DEPTH {
+            return Err(GitObjectError::ParseError(format!(
+                "exceeded maximum include depth ({}) while including {}",
+                MAX_INCLUDE_DEPTH,
+                path.display()
+            )));
+        }
+        let text = match fs::read_to_string(path) {
+            Ok(t) => t,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(GitObjectError::IOError(e)),
+        };
+        for item in parse_config(&text, path)? {
+            let entry = match item {
+                ConfigItem::Variable(entry, _) => entry,
+                ConfigItem::Section { .. } => continue,
+            };
+            // included files take the place of the include, so later entries still win.
+            let include = match (&entry.section[..], &entry.subsection, &entry.name[..]) {
+                ("include", None, "path") => entry.value.clone(),
+                ("includeif", Some(condition), "path")
+                    if include_condition(condition, path, git_dir) =>
+                {
+                    entry.value.clone()
+                }
+                _ => None,
+            };
+            self.entries.push(entry);
+            if let Some(include) = include {
+                let base = path.parent().unwrap_or_else(|| Path::new(""));
+                let include = base.join(expand_home(&include));
+                self.read_file(&include, git_dir, depth + 1)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// "true", "yes", "on" and non-zero numbers are true, "false", "no", "off", zero and an
+// empty value are false. a variable without a value is true.
+pub fn parse_bool(value: Option<&str>) -> Option<bool> {
+    let value = match value {
+        Some(v) => v.to_ascii_lowercase(),
+        None => return Some(true),
+    };
+    match &value[..] {
+        "true" | "yes" | "on" => Some(true),
+        "false" | "no" | "off" | "" => Some(false),
+        _ => parse_int(&value).map(|n| n != 0),
+    }
+}
+
+// an integer with an optional k, m or g suffix.
+pub fn parse_int(value: &str) -> Option<i64> {
+    let value = value.trim();
+    let (digits, factor) = match value.chars().last()?.to_ascii_lowercase() {
+        'k' => (&value[..value.len() - 1], 1 << 10),
+        'm' => (&value[..value.len() - 1], 1 << 20),
+        'g' => (&value[..value.len() - 1], 1 << 30),
+        _ => (value, 1),
+    };
+    digits.parse::<i64>().ok()?.checked_mul(factor)
+}
+
+// the system file, unless $GIT_CONFIG_NOSYSTEM is set; $GIT_CONFIG_SYSTEM replaces it.
+pub fn system_config_path() -> Option<PathBuf> {
+    if env::var("GIT_CONFIG_NOSYSTEM").is_ok_and(|v| parse_bool(Some(&v)) == Some(true)) {
+        return None;
+    }
+    Some(
+        env::var_os("GIT_CONFIG_SYSTEM")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from("/etc/gitconfig")),
+    )
+}
+
+// the XDG file and then ~/.gitconfig, or $GIT_CONFIG_GLOBAL alone.
+pub fn global_config_paths() -> Vec<PathBuf> {
+    if let Some(path) = env::var_os("GIT_CONFIG_GLOBAL") {
+        return vec![PathBuf::from(path)];
+    }
+    let mut paths = vec![];
+    match env::var_os("XDG_CONFIG_HOME").filter(|x| !x.is_empty()) {
+        Some(xdg) => paths.push(PathBuf::from(xdg).join("git/config")),
+        None => paths.extend(home_dir().map(|h| h.join(".config/git/config"))),
+    }
+    paths.extend(home_dir().map(|h| h.join(".gitconfig")));
+    paths
+}
+
+// where `config --global` writes: ~/.gitconfig, unless only the XDG file exists.
+pub fn global_config_write_path() -> Option<PathBuf> {
+    let paths = global_config_paths();
+    match paths.last() {
+        Some(last) if !last.exists() => paths.iter().find(|p| p.exists()).or(Some(last)).cloned(),
+        last => last.cloned(),
+    }
+}
+
+// sets `key` in the file at `path`, replacing its value, or adding another one when
+// `add` is set. the rest of the file, comments included, is left as it is.
+pub fn set_config_value(path: &Path, key: &str, value: &str, add: bool) -> Result<()> {
+    let key = ConfigKey::parse(key).ok_or_else(|| invalid_key(key))?;
+    let text = read_config_text(path)?;
+    let items = parse_config(&text, path)?;
+    let line = format!("\t{} = {}\n", key.given_name, quote_value(value));
+
+    let existing: Vec<&Range<usize>> = items
+        .iter()
+        .filter_map(|item| match item {
+            ConfigItem::Variable(entry, span) if entry.matches(&key) => Some(span),
+            _ => None,
+        })
+        .collect();
+    if !add {
+        match existing[..] {
+            [span] => {
+                let text = format!("{}{}{}", &text[..span.start], line, &text[span.end..]);
+                return write_config_text(path, &text);
+            }
+            [] => {}
+            _ => {
+                return Err(GitObjectError::WriteError(format!(
+                    "cannot overwrite multiple values with a single value: {}",
+                    key.given
+                )))
+            }
+        }
+    }
+
+    // the new variable goes at the end of the last section it belongs in.
+    let mut insert_at = None;
+    let mut in_section = false;
+    for item in &items {
+        match item {
+            ConfigItem::Section {
+                section,
+                subsection,
+                end,
+            } => {
+                in_section = *section == key.section && *subsection == key.subsection;
+                if in_section {
+                    insert_at = Some(*end);
+                }
+            }
+            ConfigItem::Variable(_, span) if in_section => insert_at = Some(span.end),
+            _ => {}
+        }
+    }
+    let text = match insert_at {
+        Some(at) => {
+            let newline = if at > 0 && !text[..at].ends_with('\n') {
+                "\n"
+            } else {
+                ""
+            };
+            format!("{}{}{}{}", &text[..at], newline, line, &text[at..])
+        }
+        None => {
+            let mut text = text;
+            if !text.is_empty() && !text.ends_with('\n') {
+                text.push('\n');
+            }
+            match &key.given_subsection {
+                Some(sub) => text.push_str(&format!(
+                    "[{} \"{}\"]\n",
+                    key.given_section,
+                    sub.replace('\\', "\\\\").replace('"', "\\\"")
+                )),
+                None => text.push_str(&format!("[{}]\n", key.given_section)),
+            }
+            text.push_str(&line);
+            text
+        }
+    };
+    write_config_text(path, &text)
+}
+
+// removes `key` from the file at `path` and returns how many values went. more than one
+// value is only removed with `all`.
+pub fn unset_config_value(path: &Path, key: &str, all: bool) -> Result<usize> {
+    let key = ConfigKey::parse(key).ok_or_else(|| invalid_key(key))?;
+    let text = read_config_text(path)?;
+    let spans: Vec<Range<usize>> = parse_config(&text, path)?
+        .into_iter()
+        .filter_map(|item| match item {
+            ConfigItem::Variable(entry, span) if entry.matches(&key) => Some(span),
+            _ => None,
+        })
+        .collect();
+    if spans.len() > 1 && !all {
+        return Err(GitObjectError::WriteError(format!(
+            "{} has multiple values",
+            key.given
+        )));
+    }
+    if spans.is_empty() {
+        return Ok(0);
+    }
+    let mut kept = String::new();
+    let mut last = 0;
+    for span in &spans {
+        kept.push_str(&text[last..span.start]);
+        last = span.end;
+    }
+    kept.push_str(&text[last..]);
+    write_config_text(path, &kept)?;
+    Ok(spans.len())
+}
+
+#[derive(Default)]
+struct ConfigOption<'a> {
+    file: Option<PathBuf>,
+    global: bool,
+    system: bool,
+    local: bool,
+    list: bool,
+    get: bool,
+    get_all: bool,
+    unset: bool,
+    unset_all: bool,
+    add: bool,
+    type_name: Option<&'a str>,
+    args: Vec<&'a String>,
+}
+
+pub fn config(commands: &[String]) {
+    for line in run_config(commands) {
+        println!("{}", line);
+    }
+}
+
+// runs a config command and returns the lines it prints.
+fn run_config(commands: &[String]) -> Vec<String> {
+    let option = parse_options(commands);
+    let repo = Repository::discover().ok();
+    let git_dir = repo.as_ref().map(|r| r.git_dir.as_path());
+
+    // a single file when one was named, otherwise every layer for reading. a named
+    // scope without a file is an error rather than a reason to use another one.
+    let file = if let Some(file) = &option.file {
+        Some(file.clone())
+    } else if option.global {
+        match global_config_write_path() {
+            Some(p) => Some(p),
+            None => {
+                return vec![String::from(
+                    "fatal: $HOME not set, so there is no global config file",
+                )]
+            }
+        }
+    } else if option.system {
+        match system_config_path() {
+            Some(p) => Some(p),
+            None => {
+                return vec![String::from(
+                    "fatal: the system config file is disabled by GIT_CONFIG_NOSYSTEM",
+                )]
+            }
+        }
+    } else if option.local {
+        match git_dir {
+            Some(g) => Some(g.join("config")),
+            None => {
+                return vec![String::from(
+                    "fatal: --local can only be used inside a git repository",
+                )]
+            }
+        }
+    } else {
+        None
+    };
+
+    // with a read mode a second argument is a pattern for the values, not one to set.
+    let reading = option.get || option.get_all || option.list;
+    let writing =
+        option.unset || option.unset_all || option.add || (option.args.len() >= 2 && !reading);
+    let (min, max, expected) = if option.list {
+        (0, 0, "0")
+    } else if option.unset || option.unset_all {
+        (1, 1, "1")
+    } else if writing {
+        (2, 2, "2")
+    } else {
+        (1, 2, "from 1 to 2")
+    };
+    if option.args.len() < min || option.args.len() > max {
+        let mut out = vec![format!(
+            "error: wrong number of arguments, should be {}",
+            expected
+        )];
+        out.extend(usage());
+        return out;
+    }
+    if writing {
+        // without a file option, writes go to the repository's own file.
+        let path = match (file, git_dir) {
+            (Some(p), _) => p,
+            (None, Some(g)) => g.join("config"),
+            (None, None) => return vec![String::from("fatal: not in a git directory")],
+        };
+        let key = option.args[0];
+        let result = if option.unset || option.unset_all {
+            unset_config_value(&path, key, option.unset_all).map(|_| ())
+        } else {
+            set_config_value(&path, key, option.args[1], option.add)
+        };
+        return match result {
+            Ok(()) => vec![],
+            Err(e) => vec![format!("error: {:?}", e)],
+        };
+    }
+
+    let loaded = match &file {
+        Some(path) => Config::load_file(path, git_dir),
+        None => Config::load(git_dir),
+    };
+    let loaded = match loaded {
+        Ok(c) => c,
+        Err(e) => return vec![format!("fatal: {:?}", e)],
+    };
+    if option.list {
+        return loaded
+            .entries()
+            .iter()
+            .map(|entry| match &entry.value {
+                Some(value) => format!("{}={}", entry.key(), value),
+                None => entry.key(),
+            })
+            .collect();
+    }
+    let key = option.args[0];
+    if ConfigKey::parse(key).is_none() {
+        return vec![format!("error: invalid key: {}", key)];
+    }
+    let mut entries = loaded.get_all(key);
+    // a pattern starting with `!` keeps the values it does not match.
+    if let Some(pattern) = option.args.get(1) {
+        let (pattern, negate) = match pattern.strip_prefix('!') {
+            Some(p) => (p, true),
+            None => (pattern.as_str(), false),
+        };
+        let regex = match Regex::new(pattern) {
+            Ok(r) => r,
+            Err(e) => return vec![format!("error: invalid pattern: {}: {}", pattern, e)],
+        };
+        entries.retain(|e| regex.is_match(e.value.as_deref().unwrap_or_default()) != negate);
+    }
+    if !option.get_all {
+        entries = entries.into_iter().last().into_iter().collect();
+    }
+    let mut out = vec![];
+    for entry in entries {
+        let value = entry.value.as_deref();
+        match option.type_name {
+            Some("bool") => match parse_bool(value) {
+                Some(b) => out.push(b.to_string()),
+                None => out.push(format!(
+                    "fatal: bad boolean config value '{}' for '{}'",
+                    value.unwrap_or_default(),
+                    key
+                )),
+            },
+            Some("int") => match value.and_then(parse_int) {
+                Some(n) => out.push(n.to_string()),
+                None => out.push(format!(
+                    "fatal: bad numeric config value '{}' for '{}'",
+                    value.unwrap_or_default(),
+                    key
+                )),
+            },
+            _ => out.push(value.unwrap_or_default().to_string()),
+        }
+    }
+    out
+}
+
+fn usage() -> Vec<String> {
+    vec![
+        String::from("usage: config [<file-option>] [--type=<type>] [--get | --get-all] <name> [<value-pattern>]"),
+        String::from("       config [<file-option>] [--add] <name> <value>"),
+        String::from("       config [<file-option>] [--unset | --unset-all] <name>"),
+        String::from("       config [<file-option>] --list"),
+    ]
+}
+
+fn parse_options(commands: &[String]) -> ConfigOption<'_> {
+    let mut option: ConfigOption = Default::default();
+    let mut file_flag = false;
+    for token in &commands[2..] {
+        if file_flag {
+            option.file = Some(PathBuf::from(token));
+            file_flag = false;
+            continue;
+        }
+        match &token[..] {
+            "-f" | "--file" => file_flag = true,
+            "--global" => option.global = true,
+            "--system" => option.system = true,
+            "--local" => option.local = true,
+            "-l" | "--list" => option.list = true,
+            "--get" => option.get = true,
+            "--get-all" => option.get_all = true,
+            "--unset" => option.unset = true,
+            "--unset-all" => option.unset_all = true,
+            "--add" => option.add = true,
+            "--bool" => option.type_name = Some("bool"),
+            "--int" => option.type_name = Some("int"),
+            _ if token.starts_with("--file=") => option.file = Some(PathBuf::from(&token[7..])),
+            _ if token.starts_with("--type=") => option.type_name = Some(&token[7..]),
+            _ if !token.starts_with('-') || !option.args.is_empty() => option.args.push(token),
+            _ => println!("ignore option {}", token),
+        }
+    }
+    option
+}
+
+// a key split into its parts, compared the way git does, and as the user typed it.
+struct ConfigKey {
+    section: String,
+    subsection: Option<String>,
+    name: String,
+    given: String,
+    given_section: String,
+    given_subsection: Option<String>,
+    given_name: String,
+}
+
+impl ConfigKey {
+    fn parse(key: &str) -> Option<Self> {
+        let first = key.find('.')?;
+        let last = key.rfind('.')?;
+        let section = &key[..first];
+        let name = &key[last + 1..];
+        let subsection = if first < last {
+            Some(key[first + 1..last].to_string())
+        } else {
+            None
+        };
+        if !is_valid_section(section) || !is_valid_name(name) {
+            return None;
+        }
+        Some(ConfigKey {
+            section: section.to_ascii_lowercase(),
+            subsection: subsection.clone(),
+            name: name.to_ascii_lowercase(),
+            given: key.to_string(),
+            given_section: section.to_string(),
+            given_subsection: subsection,
+            given_name: name.to_string(),
+        })
+    }
+}
+
+enum ConfigItem {
+    // `end` is just past the header's line.
+    Section {
+        section: String,
+        subsection: Option<String>,
+        end: usize,
+    },
+    // the span covers the variable's whole line, or lines when continued.
+    Variable(ConfigEntry, Range<usize>),
+}
+
+// parses the git config format: `[section]` and `[section "subsection"]` headers (or the
+// old `[section.subsection]`), `name = value` and bare `name` lines, `#` and `;`
+// comments, double quotes, the \n \t \b \" \\ escapes and backslash line continuation.
+fn parse_config(text: &str, path: &Path) -> Result<Vec<ConfigItem>> {
+    let bytes = text.as_bytes();
+    let bad_line = |at: usize| {
+        GitObjectError::ParseError(format!(
+            "bad config line {} in file {}",
+            bytes[..at.min(bytes.len())]
+                .iter()
+                .filter(|&&c| c == b'\n')
+                .count()
+                + 1,
+            path.display()
+        ))
+    };
+    let line_end = |at: usize| {
+        bytes[at..]
+            .iter()
+            .position(|&c| c == b'\n')
+            .map_or(bytes.len(), |p| at + p + 1)
+    };
+
+    let mut items = vec![];
+    let mut current: Option<(String, Option<String>)> = None;
+    let mut i = 0;
+    // a leading byte order mark is skipped, like git.
+    if text.starts_with('\u{feff}') {
+        i = 3;
+    }
+    while i < bytes.len() {
+        let c = bytes[i];
+        if c.is_ascii_whitespace() {
+            i += 1;
+        } else if c == b'#' || c == b';' {
+            i = line_end(i);
+        } else if c == b'[' {
+            let close = i + bytes[i..]
+                .iter()
+                .position(|&c| c == b']' || c == b'"' || c == b'\n')
+                .ok_or_else(|| bad_line(i))?;
+            let raw = &text[i + 1..close];
+            let name = raw.trim_end();
+            let (section, subsection, after) = if bytes[close] == b'"' {
+                // the subsection is separated from the section by whitespace.
+                if name.len() == raw.len() {
+                    return Err(bad_line(i));
+                }
+                let mut sub = String::new();
+                let mut j = close + 1;
+                loop {
+                    match bytes.get(j) {
+                        Some(b'"') => break,
+                        Some(b'\\') if j + 1 < bytes.len() && bytes[j + 1] != b'\n' => {
+                            let next = text[j + 1..].chars().next().unwrap();
+                            sub.push(next);
+                            j += 1 + next.len_utf8();
+                        }
+                        Some(b'\n') | Some(b'\\') | None => return Err(bad_line(j)),
+                        Some(_) => {
+                            let next = text[j..].chars().next().unwrap();
+                            sub.push(next);
+                            j += next.len_utf8();
+                        }
+                    }
+                }
+                if bytes.get(j + 1) != Some(&b']') {
+                    return Err(bad_line(j));
+                }
+                (name.to_string(), Some(sub), j + 2)
+            } else if bytes[close] == b']' {
+                // the old `[section.subsection]` form, with the subsection lowercased.
+                match name.split_once('.') {
+                    Some((section, sub)) => (
+                        section.to_string(),
+                        Some(sub.to_ascii_lowercase()),
+                        close + 1,
+                    ),
+                    None => (name.to_string(), None, close + 1),
+                }
+            } else {
+                return Err(bad_line(i));
+            };
+            if !is_valid_section(&section) {
+                return Err(bad_line(i));
+            }
+            let section = section.to_ascii_lowercase();
+            items.push(ConfigItem::Section {
+                section: section.clone(),
+                subsection: subsection.clone(),
+                end: line_end(after),
+            });
+            current = Some((section, subsection));
+            i = after;
+        } else if c.is_ascii_alphabetic() {
+            let (section, subsection) = current.clone().ok_or_else(|| bad_line(i))?;
+            let line_start = text[..i].rfind('\n').map_or(0, |p| p + 1);
+            let start = if text[line_start..i].trim().is_empty() {
+                line_start
+            } else {
+                i
+            };
+            let name_end = i + bytes[i..]
+                .iter()
+                .position(|&c| !(c.is_ascii_alphanumeric() || c == b'-'))
+                .unwrap_or(bytes.len() - i);
+            let name = text[i..name_end].to_ascii_lowercase();
+            let mut j = name_end;
+            while j < bytes.len() && (bytes[j] == b' ' || bytes[j] == b'\t') {
+                j += 1;
+            }
+            let (value, end) = match bytes.get(j) {
+                Some(b'=') => {
+                    let (value, end) = parse_value(text, j + 1).ok_or_else(|| bad_line(j))?;
+                    (Some(value), end)
+                }
+                Some(b'\n') | Some(b'\r') | Some(b'#') | Some(b';') | None => (None, line_end(j)),
+                _ => return Err(bad_line(j)),
+            };
+            items.push(ConfigItem::Variable(
+                ConfigEntry {
+                    section,
+                    subsection,
+                    name,
+                    value,
+                    origin: path.to_path_buf(),
+                },
+                start..end,
+            ));
+            i = end;
+        } else {
+            return Err(bad_line(i));
+        }
+    }
+    Ok(items)
+}
+
+// the value starting at `start` and the offset just past its line. whitespace outside
+// quotes is trimmed at both ends, and each whitespace character inside becomes a space.
+fn parse_value(text: &str, start: usize) -> Option<(String, usize)> {
+    let mut value = String::new();
+    let mut quoted = false;
+    let mut spaces = 0;
+    let mut chars = text[start..].char_indices().map(|(k, c)| (start + k, c));
+    while let Some((k, c)) = chars.next() {
+        match c {
+            '\n' if quoted => return None,
+            '\n' => return Some((value, k + 1)),
+            '\r' => {}
+            ' ' | '\t' if !quoted => {
+                if !value.is_empty() {
+                    spaces += 1;
+                }
+            }
+            '#' | ';' if !quoted => {
+                let end = text[k..].find('\n').map_or(text.len(), |p| k + p + 1);
+                return Some((value, end));
+            }
+            _ => {
+                for _ in 0..spaces {
+                    value.push(' ');
+                }
+                spaces = 0;
+                match c {
+                    '"' => quoted = !quoted,
+                    '\\' => match chars.next()?.1 {
+                        '\n' => {}
+                        'n' => value.push('\n'),
+                        't' => value.push('\t'),
+                        'b' => value.push('\u{8}'),
+                        '"' => value.push('"'),
+                        '\\' => value.push('\\'),
+                        _ => return None,
+                    },
+                    _ => value.push(c),
+                }
+            }
+        }
+    }
+    if quoted {
+        None
+    } else {
+        Some((value, text.len()))
+    }
+}
+
+// values with surrounding whitespace or comment characters are quoted when written.
+fn quote_value(value: &str) -> String {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t");
+    let needs_quotes = value.starts_with(' ')
+        || value.ends_with(' ')
+        || value.contains('#')
+        || value.contains(';');
+    if needs_quotes {
+        format!("\"{}\"", escaped)
+    } else {
+        escaped
+    }
+}
+
+// `gitdir:` (or `gitdir/i:`) matches the git dir against a glob, `onbranch:` the
+// checked out branch.
+fn include_condition(condition: &str, path: &Path, git_dir: Option<&Path>) -> bool {
+    let git_dir = match git_dir {
+        Some(g) => g,
+        None => return false,
+    };
+    if let Some(pattern) = condition.strip_prefix("onbranch:") {
+        let head = fs::read_to_string(git_dir.join("HEAD")).unwrap_or_default();
+        return match head.trim().strip_prefix("ref: refs/heads/") {
+            Some(branch) => wildmatch(&directory_pattern(pattern), branch, true),
+            None => false,
+        };
+    }
+    let (pattern, icase) = match condition.strip_prefix("gitdir:") {
+        Some(p) => (p, false),
+        None => match condition.strip_prefix("gitdir/i:") {
+            Some(p) => (p, true),
+            None => return false,
+        },
+    };
+    let mut pattern = expand_home(pattern).to_string_lossy().to_string();
+    if let Some(rest) = pattern.strip_prefix("./") {
+        let base = path.parent().unwrap_or_else(|| Path::new(""));
+        pattern = base.join(rest).to_string_lossy().to_string();
+    } else if !Path::new(&pattern).is_absolute() {
+        pattern = format!("**/{}", pattern);
+    }
+    let pattern = directory_pattern(&pattern);
+    let git_dir = fs::canonicalize(git_dir).unwrap_or_else(|_| git_dir.to_path_buf());
+    let git_dir = git_dir.to_string_lossy();
+    if icase {
+        wildmatch(&pattern.to_lowercase(), &git_dir.to_lowercase(), true)
+    } else {
+        wildmatch(&pattern, &git_dir, true)
+    }
+}
+
+// a pattern ending in a slash matches everything below it.
+fn directory_pattern(pattern: &str) -> String {
+    if pattern.ends_with('/') {
+        format!("{}**", pattern)
+    } else {
+        pattern.to_string()
+    }
+}
+
+pub fn home_dir() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .filter(|h| !h.is_empty())
+        .map(PathBuf::from)
+}
+
+pub fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+fn is_valid_section(section: &str) -> bool {
+    !section.is_empty()
+        && section
+            .bytes()
+            .all(|c| c.is_ascii_alphanumeric() || c == b'-' || c == b'.')
+}
+
+fn is_valid_name(name: &str) -> bool {
+    name.bytes().next().is_some_and(|c| c.is_ascii_alphabetic())
+        && name.bytes().all(|c| c.is_ascii_alphanumeric() || c == b'-')
+}
+
+fn read_config_text(path: &Path) -> Result<String> {
+    match fs::read_to_string(path) {
+        Ok(t) => Ok(t),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(String::new()),
+        Err(e) => Err(GitObjectError::IOError(e)),
+    }
+}
+
+// written through `<path>.lock`, which also keeps two writers from racing.
+fn write_config_text(path: &Path, text: &str) -> Result<()> {
+    let mut lock = LockFile::acquire(path).map_err(|e| match e {
+        GitObjectError::WriteError(_) => {
+            GitObjectError::WriteError(format!("could not lock config file {}", path.display()))
+        }
+        e => e,
+    })?;
+    lock.write_all(text.as_bytes())?;
+    lock.commit()
+}
+
+fn bad_value(entry: &ConfigEntry) -> GitObjectError {
+    GitObjectError::ParseError(format!(
+        "bad config value '{}' for '{}' in {}",
+        entry.value.as_deref().unwrap_or_default(),
+        entry.key(),
+        entry.origin.display()
+    ))
+}
+
+fn invalid_key(key: &str) -> GitObjectError {
+    GitObjectError::ParseError(format!("invalid key: {}", key))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::config::*;
+    use crate::test_utils::temp_dir;
+
+    fn parse(text: &str) -> Result<Vec<ConfigEntry>> {
+        Ok(parse_config(text, Path::new("config"))?
+            .into_iter()
+            .filter_map(|item| match item {
+                ConfigItem::Variable(entry, _) => Some(entry),
+                _ => None,
+            })
+            .collect())
+    }
+
+    #[test]
+    fn test_parse_config() {
+        let text = "# comment\n\
+            [Core]\n\
+            \tbare = false ; trailing comment\n\
+            \tFlag\n\
+            \tempty =\n\
+            [remote \"Origin\"] url = a\\\n  b\n\
+            [branch.Main]\n\
+            \tmerge = \"  spaced \\\"#x\\\" \"\n\
+            \tlist = a  b\\tc\n";
+        let entries = parse(text).unwrap();
+        let pairs: Vec<(String, Option<&str>)> = entries
+            .iter()
+            .map(|e| (e.key(), e.value.as_deref()))
+            .collect();
+        assert_eq!(
+            pairs,
+            vec![
+                (String::from("core.bare"), Some("false")),
+                (String::from("core.flag"), None),
+                (String::from("core.empty"), Some("")),
+                (String::from("remote.Origin.url"), Some("a  b")),
+                (String::from("branch.main.merge"), Some("  spaced \"#x\" ")),
+                (String::from("branch.main.list"), Some("a  b\tc")),
+            ]
+        );
+
+        for bad in &[
+            "x = 1\n",
+            "[core\n",
+            "[core]\n1x = 2\n",
+            "[core]\nx = \"open\n",
+            "[core]\nx = \\q\n",
+            "[a b]\n",
+        ] {
+            assert!(parse(bad).is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn test_typed_values() {
+        let dir = temp_dir("config-typed");
+        let path = dir.join("config");
+        fs::write(
+            &path,
+            "[core]\n\tbare\n\tfilemode = off\n\tsize = 2k\n\tbig = 1G\n\tbad = maybe\n\tempty =\n\
+             [multi]\n\tv = 1\n\tv = 2\n",
+        )
+        .unwrap();
+        let config = Config::load_file(&path, None).unwrap();
+        assert_eq!(config.get_bool("core.bare").unwrap(), Some(true));
+        assert_eq!(config.get_bool("Core.FileMode").unwrap(), Some(false));
+        assert_eq!(config.get_bool("core.empty").unwrap(), Some(false));
+        assert_eq!(config.get_bool("core.missing").unwrap(), None);
+        assert!(config.get_bool("core.bad").is_err());
+        assert_eq!(config.get_int("core.size").unwrap(), Some(2048));
+        assert_eq!(config.get_int("core.big").unwrap(), Some(1 << 30));
+        assert!(config.get_int("core.bad").is_err());
+        assert!(config.get_string("core.bare").is_err());
+        assert_eq!(
+            config.get_string("multi.v").unwrap(),
+            Some(String::from("2"))
+        );
+        assert_eq!(config.get_all("multi.v").len(), 2);
+        assert_eq!(parse_bool(Some("10")), Some(true));
+        assert_eq!(parse_int("-3m"), Some(-3 << 20));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_includes() {
+        let dir = temp_dir("config-include");
+        let git_dir = dir.join("work/.git");
+        fs::create_dir_all(&git_dir).unwrap();
+        fs::write(git_dir.join("HEAD"), "ref: refs/heads/topic/x\n").unwrap();
+        fs::write(dir.join("extra"), "[user]\n\tname = included\n").unwrap();
+        fs::write(
+            dir.join("work-only"),
+            "[user]\n\temail = work@example.com\n",
+        )
+        .unwrap();
+        fs::write(dir.join("branch-only"), "[user]\n\tsigningkey = topic\n").unwrap();
+        fs::write(dir.join("loop"), "[include]\n\tpath = loop\n").unwrap();
+        fs::write(
+            dir.join("config"),
+            "[user]\n\tname = first\n[include]\n\tpath = extra\n\
+             [includeIf \"gitdir:work/\"]\n\tpath = work-only\n\
+             [includeIf \"gitdir:elsewhere/\"]\n\tpath = loop\n\
+             [includeIf \"onbranch:topic/\"]\n\tpath = branch-only\n\
+             [include]\n\tpath = missing\n",
+        )
+        .unwrap();
+        let config = Config::load_file(&dir.join("config"), Some(&git_dir)).unwrap();
+        assert_eq!(
+            config.get_string("user.name").unwrap(),
+            Some(String::from("included"))
+        );
+        assert_eq!(
+            config.get_string("user.email").unwrap(),
+            Some(String::from("work@example.com"))
+        );
+        assert_eq!(
+            config.get_string("user.signingkey").unwrap(),
+            Some(String::from("topic"))
+        );
+        // without a repository the conditions are false.
+        let config = Config::load_file(&dir.join("config"), None).unwrap();
+        assert!(config.get_entry("user.email").is_none());
+        assert!(Config::load_file(&dir.join("loop"), None).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_set_and_unset() {
+        let dir = temp_dir("config-set");
+        let path = dir.join("config");
+        fs::write(
+            &path,
+            "# keep me\n[core]\n\tbare = false\n[Foo]\n\tx = 1 ; note\n",
+        )
+        .unwrap();
+
+        set_config_value(&path, "core.bare", "true", false).unwrap();
+        set_config_value(&path, "foo.y", "two words ", false).unwrap();
+        set_config_value(&path, "Foo.Sub.Key", "a\"b", false).unwrap();
+        set_config_value(&path, "foo.x", "3", true).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "# keep me\n[core]\n\tbare = true\n[Foo]\n\tx = 1 ; note\n\ty = \"two words \"\n\
+             \tx = 3\n[Foo \"Sub\"]\n\tKey = a\\\"b\n"
+        );
+        let config = Config::load_file(&path, None).unwrap();
+        assert_eq!(
+            config.get_string("foo.y").unwrap(),
+            Some(String::from("two words "))
+        );
+        assert_eq!(
+            config.get_string("foo.Sub.key").unwrap(),
+            Some(String::from("a\"b"))
+        );
+
+        assert!(set_config_value(&path, "foo.x", "4", false).is_err());
+        assert!(unset_config_value(&path, "foo.x", false).is_err());
+        assert_eq!(unset_config_value(&path, "foo.x", true).unwrap(), 2);
+        assert_eq!(unset_config_value(&path, "foo.x", true).unwrap(), 0);
+        assert!(set_config_value(&path, "nodot", "1", false).is_err());
+        assert!(!dir.join("config.lock").exists());
+
+        // a file that does not exist yet is created.
+        set_config_value(&dir.join("new"), "user.name", "a", false).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.join("new")).unwrap(),
+            "[user]\n\tname = a\n"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_get_with_value_pattern() {
+        let dir = temp_dir("config-get-pattern");
+        let path = dir.join("config");
+        let text = "[user]\n\tname = Ann\n\tname = Bob\n";
+        fs::write(&path, text).unwrap();
+        let run = |args: &[&str]| {
+            let mut commands = vec![String::from("git"), String::from("config")];
+            commands.push(String::from("--file"));
+            commands.push(path.to_string_lossy().to_string());
+            commands.extend(args.iter().map(|a| a.to_string()));
+            run_config(&commands)
+        };
+        // the second argument of a read is a value pattern, so nothing is written.
+        assert_eq!(run(&["--get-all", "user.name", "A"]), vec!["Ann"]);
+        assert_eq!(run(&["--get", "user.name", "^B"]), vec!["Bob"]);
+        assert_eq!(run(&["--get-all", "user.name", "!A"]), vec!["Bob"]);
+        assert_eq!(
+            run(&["--get-all", "user.name", "^(Ann|Bob)$"]),
+            vec!["Ann", "Bob"]
+        );
+        // --get takes the last value that matches.
+        assert_eq!(run(&["--get", "user.name", "n|b"]), vec!["Bob"]);
+        assert!(run(&["--get-all", "user.name", "^C"]).is_empty());
+        assert_eq!(
+            run(&["--get", "user.name", "(A"]),
+            vec!["error: invalid pattern: (A: unmatched ( in pattern"]
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap(), text);
+        assert!(!dir.join("config.lock").exists());
+
+        // a missing or extra argument is an error, not a panic.
+        for args in [
+            &["--unset"][..],
+            &["--unset-all", "user.name", "x"],
+            &["--add", "user.name"],
+            &["user.name", "a", "b"],
+            &["--get"],
+            &["--get", "user.name", "a", "b"],
+            &["--list", "user.name"],
+        ] {
+            let out = run(args);
+            assert!(
+                out[0].starts_with("error: wrong number of arguments"),
+                "{:?}",
+                args
+            );
+            assert!(out[1].starts_with("usage: "));
+        }
+        assert_eq!(
+            run(&[])[0],
+            "error: wrong number of arguments, should be from 1 to 2"
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap(), text);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,64 @@
+use std::time::{Duration, SystemTime};
+
+// a date given as when things expire, like git's parse_expiry_date: "now", "never"
+// (None), "yesterday", a unix timestamp or "<n>.<unit>.ago" such as "2.weeks.ago". the
+// outer None means `spec` was not understood.
+pub fn parse_expiry_date(spec: &str, now: SystemTime) -> Option<Option<SystemTime>> {
+    match spec {
+        "now" => return Some(Some(now)),
+        "never" => return Some(None),
+        "yesterday" => return Some(now.checked_sub(Duration::from_secs(24 * 60 * 60))),
+        _ => {}
+    }
+    if let Ok(timestamp) = spec.parse::<u64>() {
+        return Some(Some(
+            SystemTime::UNIX_EPOCH + Duration::from_secs(timestamp),
+        ));
+    }
+    let parts: Vec<&str> = spec.split(['.', ' ']).collect();
+    let (n, unit) = match parts[..] {
+        [n, unit, "ago"] => (n.parse::<u64>().ok()?, unit.trim_end_matches('s')),
+        _ => return None,
+    };
+    let seconds = match unit {
+        "second" => 1,
+        "minute" => 60,
+        "hour" => 60 * 60,
+        "day" => 24 * 60 * 60,
+        "week" => 7 * 24 * 60 * 60,
+        "month" => 30 * 24 * 60 * 60,
+        "year" => 365 * 24 * 60 * 60,
+        _ => return None,
+    };
+    Some(now.checked_sub(Duration::from_secs(n.checked_mul(seconds)?)))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::date::*;
+
+    #[test]
+    fn test_parse_expiry_date() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(10_000_000);
+        assert_eq!(parse_expiry_date("now", now), Some(Some(now)));
+        assert_eq!(parse_expiry_date("never", now), Some(None));
+        assert_eq!(
+            parse_expiry_date("2.weeks.ago", now),
+            Some(Some(now - Duration::from_secs(14 * 24 * 60 * 60)))
+        );
+        assert_eq!(
+            parse_expiry_date("1 hour ago", now),
+            Some(Some(now - Duration::from_secs(3600)))
+        );
+        assert_eq!(
+            parse_expiry_date("12345", now),
+            Some(Some(SystemTime::UNIX_EPOCH + Duration::from_secs(12345)))
+        );
+        assert_eq!(
+            parse_expiry_date("yesterday", now),
+            Some(Some(now - Duration::from_secs(24 * 60 * 60)))
+        );
+        assert_eq!(parse_expiry_date("2.fortnights.ago", now), None);
+        assert_eq!(parse_expiry_date("soon", now), None);
+    }
+}
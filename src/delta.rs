@@ -0,0 +1,274 @@
+use crate::git_object::GitObjectError;
+use std::collections::HashMap;
+
+type Result<T> = std::result::Result<T, GitObjectError>;
+
+fn corrupt(what: &str) -> GitObjectError {
+    GitObjectError::ParseError(format!("corrupt delta: {}", what))
+}
+
+// reads the little-endian base-128 sizes at the start of a delta.
+fn read_size(delta: &[u8], pos: &mut usize) -> Result<u64> {
+    let mut size = 0u64;
+    let mut shift = 0;
+    loop {
+        let c = *delta.get(*pos).ok_or_else(|| corrupt("truncated size"))?;
+        *pos += 1;
+        if shift > 63 {
+            return Err(corrupt("size overflows"));
+        }
+        size |= ((c & 0x7f) as u64) << shift;
+        shift += 7;
+        if c & 0x80 == 0 {
+            return Ok(size);
+        }
+    }
+}
+
+// the base and result sizes recorded in the delta header.
+pub fn delta_sizes(delta: &[u8]) -> Result<(u64, u64)> {
+    let mut pos = 0;
+    let base_size = read_size(delta, &mut pos)?;
+    let result_size = read_size(delta, &mut pos)?;
+    Ok((base_size, result_size))
+}
+
+// rebuilds an object from `base` and a git delta: a sequence of copy-from-base and
+// insert-literal instructions.
+pub fn apply_delta(base: &[u8], delta: &[u8]) -> Result<Vec<u8>> {
+    let mut pos = 0;
+    let base_size = read_size(delta, &mut pos)?;
+    let result_size = read_size(delta, &mut pos)?;
+    if base_size != base.len() as u64 {
+        return Err(corrupt(&format!(
+            "base size {} does not match the actual base size {}",
+            base_size,
+            base.len()
+        )));
+    }
+    // the header is untrusted; don't let it reserve more than the delta could produce.
+    let mut result = Vec::with_capacity(result_size.min(delta.len() as u64 * 128) as usize);
+
+    while pos < delta.len() {
+        let op = delta[pos];
+        pos += 1;
+        if op & 0x80 != 0 {
+            let mut offset = 0usize;
+            let mut size = 0usize;
+            for i in 0..4 {
+                if op & (1 << i) != 0 {
+                    let b = *delta.get(pos).ok_or_else(|| corrupt("truncated copy"))?;
+                    pos += 1;
+                    offset |= (b as usize) << (i * 8);
+                }
+            }
+            for i in 0..3 {
+                if op & (0x10 << i) != 0 {
+                    let b = *delta.get(pos).ok_or_else(|| corrupt("truncated copy"))?;
+                    pos += 1;
+                    size |= (b as usize) << (i * 8);
+                }
+            }
+            if size == 0 {
+                size = 0x10000;
+            }
+            let end = offset
+                .checked_add(size)
+                .filter(|&end| end <= base.len())
+                .ok_or_else(|| corrupt("copy out of the base bounds"))?;
+            result.extend_from_slice(&base[offset..end]);
+        } else if op != 0 {
+            let end = pos + op as usize;
+            let literal = delta
+                .get(pos..end)
+                .ok_or_else(|| corrupt("truncated insert"))?;
+            result.extend_from_slice(literal);
+            pos = end;
+        } else {
+            return Err(corrupt("reserved instruction 0"));
+        }
+        if result.len() as u64 > result_size {
+            return Err(corrupt("result is larger than the recorded size"));
+        }
+    }
+
+    if result.len() as u64 != result_size {
+        return Err(corrupt(&format!(
+            "result size {} does not match the recorded size {}",
+            result.len(),
+            result_size
+        )));
+    }
+    Ok(result)
+}
+
+// matches shorter than a block are not looked for.
+const BLOCK_SIZE: usize = 16;
+// candidates kept per distinct block, so that repetitive bases stay cheap to search.
+const MAX_BLOCK_CANDIDATES: usize = 64;
+const MAX_INSERT: usize = 0x7f;
+const MAX_COPY: usize = 0x10000;
+
+// builds a delta turning `base` into `target`. the base is indexed in fixed size blocks;
+// every target position whose next block appears in the base starts a copy, extended as
+// far as the bytes agree in both directions, and the bytes in between are inserted.
+pub fn create_delta(base: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut delta = vec![];
+    write_size(&mut delta, base.len() as u64);
+    write_size(&mut delta, target.len() as u64);
+
+    let mut index: HashMap<&[u8], Vec<usize>> = HashMap::new();
+    for start in (0..base.len() / BLOCK_SIZE).map(|i| i * BLOCK_SIZE) {
+        let candidates = index.entry(&base[start..start + BLOCK_SIZE]).or_default();
+        if candidates.len() < MAX_BLOCK_CANDIDATES {
+            candidates.push(start);
+        }
+    }
+
+    let mut literal_start = 0;
+    let mut pos = 0;
+    while pos + BLOCK_SIZE <= target.len() {
+        let mut best: Option<(usize, usize)> = None;
+        if let Some(candidates) = index.get(&target[pos..pos + BLOCK_SIZE]) {
+            for &c in candidates {
+                let len = base[c..]
+                    .iter()
+                    .zip(&target[pos..])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if best.is_none_or(|(_, l)| len > l) {
+                    best = Some((c, len));
+                }
+            }
+        }
+        let (mut offset, mut len) = match best {
+            Some(found) => found,
+            None => {
+                pos += 1;
+                continue;
+            }
+        };
+        let mut start = pos;
+        while start > literal_start && offset > 0 && base[offset - 1] == target[start - 1] {
+            start -= 1;
+            offset -= 1;
+            len += 1;
+        }
+        write_insert(&mut delta, &target[literal_start..start]);
+        write_copy(&mut delta, offset, len);
+        pos = start + len;
+        literal_start = pos;
+    }
+    write_insert(&mut delta, &target[literal_start..]);
+    delta
+}
+
+fn write_size(delta: &mut Vec<u8>, mut size: u64) {
+    while size >= 0x80 {
+        delta.push(0x80 | (size & 0x7f) as u8);
+        size >>= 7;
+    }
+    delta.push(size as u8);
+}
+
+fn write_insert(delta: &mut Vec<u8>, literal: &[u8]) {
+    for chunk in literal.chunks(MAX_INSERT) {
+        delta.push(chunk.len() as u8);
+        delta.extend_from_slice(chunk);
+    }
+}
+
+// a copy instruction only carries the non-zero bytes of its offset and size; a size
+// of 0x10000 is written as no size bytes at all.
+fn write_copy(delta: &mut Vec<u8>, mut offset: usize, mut len: usize) {
+    while len > 0 {
+        let size = len.min(MAX_COPY);
+        let mut op = 0x80u8;
+        let mut args = vec![];
+        for i in 0..4 {
+            let b = (offset >> (i * 8)) as u8;
+            if b != 0 {
+                op |= 1 << i;
+                args.push(b);
+            }
+        }
+        for i in 0..3 {
+            let b = ((size % MAX_COPY) >> (i * 8)) as u8;
+            if b != 0 {
+                op |= 0x10 << i;
+                args.push(b);
+            }
+        }
+        delta.push(op);
+        delta.extend(args);
+        offset += size;
+        len -= size;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::delta::*;
+
+    #[test]
+    fn test_apply_delta() {
+        let base = b"hello, world\n";
+        // sizes 13 -> 20, copy 7 bytes at 0, insert "there,", copy 7 bytes at 6.
+        let mut delta = vec![13, 20];
+        delta.extend_from_slice(&[0x90, 7]);
+        delta.push(6);
+        delta.extend_from_slice(b"there,");
+        delta.extend_from_slice(&[0x91, 6, 7]);
+        assert_eq!(delta_sizes(&delta).unwrap(), (13, 20));
+        assert_eq!(
+            apply_delta(base, &delta).unwrap(),
+            b"hello, there, world\n".to_vec()
+        );
+    }
+
+    #[test]
+    fn test_copy_size_zero_means_64k() {
+        let base = vec![7u8; 0x10000];
+        // result size 0x10000 is 0x80 0x80 0x04 in base-128.
+        let delta = vec![0x80, 0x80, 0x04, 0x80, 0x80, 0x04, 0x80];
+        assert_eq!(apply_delta(&base, &delta).unwrap(), base);
+    }
+
+    #[test]
+    fn test_corrupt_delta() {
+        let base = b"hello";
+        // wrong base size.
+        assert!(apply_delta(base, &[4, 5, 0x90, 5]).is_err());
+        // copy past the end of the base.
+        assert!(apply_delta(base, &[5, 6, 0x90, 6]).is_err());
+        // result shorter than recorded.
+        assert!(apply_delta(base, &[5, 6, 0x90, 5]).is_err());
+        // insert runs past the end of the delta.
+        assert!(apply_delta(base, &[5, 3, 3, b'a']).is_err());
+        // reserved opcode.
+        assert!(apply_delta(base, &[5, 0, 0]).is_err());
+        assert!(delta_sizes(&[0x80]).is_err());
+    }
+
+    #[test]
+    fn test_create_delta() {
+        let base: Vec<u8> = (0..200_000u32).map(|i| (i * 7 % 251) as u8).collect();
+        let mut target = b"prefix ".to_vec();
+        target.extend_from_slice(&base[1000..150_000]);
+        target.extend_from_slice(b"in the middle");
+        target.extend_from_slice(&base[..500]);
+        let delta = create_delta(&base, &target);
+        assert!(delta.len() < 100, "delta is {} bytes", delta.len());
+        assert_eq!(apply_delta(&base, &delta).unwrap(), target);
+
+        for (base, target) in [
+            (&b""[..], &b""[..]),
+            (b"", b"new content"),
+            (b"old content that is long enough", b""),
+            (b"hello, world\n", b"hello, there, world\n"),
+        ] {
+            let delta = create_delta(base, target);
+            assert_eq!(apply_delta(base, &delta).unwrap(), target.to_vec());
+        }
+    }
+}
@@ -0,0 +1,695 @@
+use crate::gc::{index_tips, loose_objects, reflog_tips};
+use crate::git_object::*;
+use crate::object_store::{open_object_store_at, LooseObjectStore, RawObject};
+use crate::object_stream::parse_object_header;
+use crate::pack::{PackFile, PackIndex, PackObjectStore};
+use crate::refs::ref_tips;
+use crate::repository::open_repository;
+use crate::utils::*;
+use flate2::read::ZlibDecoder;
+use sha1::{Digest, Sha1};
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet, VecDeque},
+    fs,
+    io::{BufReader, Read},
+    iter,
+    path::Path,
+};
+
+type Result<T> = std::result::Result<T, GitObjectError>;
+
+#[derive(Default)]
+struct FsckOption {
+    unreachable: bool,
+    no_dangling: bool,
+}
+
+pub fn fsck(commands: &[String]) {
+    let option = parse_options(commands);
+    let repo = match open_repository() {
+        Some(r) => r,
+        None => return,
+    };
+    let report = match check_repository(&repo.git_dir) {
+        Ok(r) => r,
+        Err(e) => {
+            println!("fatal: {:?}", e);
+            return;
+        }
+    };
+    for line in report.errors.iter().chain(&report.warnings) {
+        println!("{}", line);
+    }
+    for (from, to) in &report.broken_links {
+        println!("broken link from {} {}", from.0, hash_to_str(&from.1));
+        println!("              to {} {}", to.0, hash_to_str(&to.1));
+    }
+    for (type_name, hash) in &report.missing {
+        println!("missing {} {}", type_name, hash_to_str(hash));
+    }
+    if option.unreachable {
+        for (type_name, hash) in &report.unreachable {
+            println!("unreachable {} {}", type_name, hash_to_str(hash));
+        }
+    } else if !option.no_dangling {
+        for (type_name, hash) in &report.dangling {
+            println!("dangling {} {}", type_name, hash_to_str(hash));
+        }
+    }
+}
+
+type TypedId = (String, Vec<u8>);
+
+// what fsck found. objects are listed as (type, raw id) sorted by id; for missing
+// objects the type is the one they were expected to have.
+#[derive(Debug, Default)]
+pub struct FsckReport {
+    // objects that are corrupt or malformed.
+    pub errors: Vec<String>,
+    // objects that are readable but not in the form git itself writes.
+    pub warnings: Vec<String>,
+    // references to objects that do not exist, as (from, to).
+    pub broken_links: Vec<(TypedId, TypedId)>,
+    pub missing: Vec<TypedId>,
+    // unreachable objects that no other object refers to either.
+    pub dangling: Vec<TypedId>,
+    pub unreachable: Vec<TypedId>,
+}
+
+impl FsckReport {
+    pub fn is_clean(&self) -> bool {
+        self.errors.is_empty() && self.broken_links.is_empty() && self.missing.is_empty()
+    }
+}
+
+// a reference from one object to another, and the type the target must have.
+struct Link {
+    to: Vec<u8>,
+    type_name: &'static str,
+}
+
+#[derive(Default)]
+struct Checker {
+    report: FsckReport,
+    types: HashMap<Vec<u8>, String>,
+    links: HashMap<Vec<u8>, Vec<Link>>,
+}
+
+// checks every loose and packed object of the repository at `git_dir`: streams, headers
+// and ids, the format of trees, commits and tags, and which objects the refs reach.
+pub fn check_repository(git_dir: &Path) -> Result<FsckReport> {
+    let objects_dir = git_dir.join("objects");
+    let mut checker = Checker::default();
+
+    for (hex, path) in loose_objects(&objects_dir)? {
+        match read_loose(&path, &hex) {
+            Ok(raw) => checker.check_object(str_to_hash(hex), raw),
+            Err(e) => checker.error(format!("error: {}: {}", hex, describe(&e))),
+        }
+    }
+
+    let pack_dir = objects_dir.join("pack");
+    let mut idx_paths = match fs::read_dir(&pack_dir) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.extension() == Some("idx".as_ref()))
+            .collect::<Vec<_>>(),
+        Err(_) => vec![],
+    };
+    idx_paths.sort();
+    let mut packs =
+        PackObjectStore::default().with_base_store(Box::new(LooseObjectStore::new(&objects_dir)));
+    for path in idx_paths {
+        match PackFile::open(&path) {
+            Ok(pack) => packs.add_pack(pack),
+            Err(e) => checker.error(format!("error: {}: {}", path.display(), describe(&e))),
+        }
+    }
+    for (i, pack) in packs.packs().iter().enumerate() {
+        checker.check_pack_layout(pack);
+        let index = pack.index();
+        for n in 0..index.len() {
+            let hash = index.hash(n).to_vec();
+            match packs.read_entry(i, index.offset(n)) {
+                Ok(raw) => checker.check_object(hash, raw),
+                Err(e) => checker.error(format!(
+                    "error: {}: {} (in {})",
+                    hash_to_str(&hash),
+                    describe(&e),
+                    pack.pack_path().display()
+                )),
+            }
+        }
+    }
+
+    let mut roots = match ref_tips(git_dir) {
+        Ok(tips) => tips,
+        Err(e) => {
+            checker.error(format!("error: {}", describe(&e)));
+            vec![]
+        }
+    };
+    roots.extend(reflog_tips(git_dir, &open_object_store_at(&objects_dir))?);
+    match index_tips(git_dir) {
+        Ok(tips) => roots.extend(tips),
+        Err(e) => checker.error(format!("error: {}", describe(&e))),
+    }
+    checker.check_connectivity(&roots);
+    Ok(checker.report)
+}
+
+fn describe(e: &GitObjectError) -> String {
+    match e {
+        GitObjectError::IOError(e) => e.to_string(),
+        GitObjectError::CustomIOError(m)
+        | GitObjectError::ParseError(m)
+        | GitObjectError::WriteError(m)
+        | GitObjectError::NotImplementError(m)
+        | GitObjectError::NotFoundError(m)
+        | GitObjectError::AmbiguousError(m, _) => m.clone(),
+    }
+}
+
+// reads a loose object without trusting its file name; the id is checked afterwards.
+fn read_loose(path: &Path, hex: &str) -> Result<RawObject> {
+    let data = fs::read(path)?;
+    let mut buf = vec![];
+    ZlibDecoder::new(&data[..])
+        .read_to_end(&mut buf)
+        .map_err(|_| GitObjectError::ParseError(String::from("corrupt zlib stream")))?;
+    let nul = buf.iter().position(|&c| c == 0).ok_or_else(|| {
+        GitObjectError::ParseError(String::from("object header is not terminated"))
+    })?;
+    let (type_name, size) = parse_object_header(&buf[..nul])?;
+    let content = buf.split_off(nul + 1);
+    if content.len() as u64 != size {
+        return Err(GitObjectError::ParseError(format!(
+            "header says {} bytes but the object has {}",
+            size,
+            content.len()
+        )));
+    }
+    if hash_to_str(&object_hash(&type_name, &content)) != hex {
+        return Err(GitObjectError::ParseError(format!(
+            "hash mismatch, the content hashes to {}",
+            hash_to_str(&object_hash(&type_name, &content))
+        )));
+    }
+    Ok(RawObject { type_name, content })
+}
+
+impl Checker {
+    fn error(&mut self, message: String) {
+        self.report.errors.push(message);
+    }
+
+    fn check_object(&mut self, hash: Vec<u8>, raw: RawObject) {
+        // the same object may be both loose and packed.
+        if self.types.contains_key(&hash) {
+            return;
+        }
+        let hex = hash_to_str(&hash);
+        if object_hash(&raw.type_name, &raw.content) != hash {
+            self.error(format!("error: sha1 mismatch for {}", hex));
+            return;
+        }
+        let mut links = vec![];
+        match &raw.type_name[..] {
+            "blob" => {}
+            "tree" => self.check_tree(&hex, &raw.content, &mut links),
+            "commit" | "tag" => match GitObject::from_raw(&raw) {
+                Ok(GitObject::Commit(commit)) => {
+                    links.push(Link {
+                        to: commit.tree,
+                        type_name: "tree",
+                    });
+                    for parent in commit.parents {
+                        links.push(Link {
+                            to: parent,
+                            type_name: "commit",
+                        });
+                    }
+                }
+                Ok(GitObject::Tag(tag)) => {
+                    let type_name = match &tag.object_type[..] {
+                        "commit" => "commit",
+                        "tree" => "tree",
+                        "blob" => "blob",
+                        "tag" => "tag",
+                        t => {
+                            self.error(format!(
+                                "error in tag {}: badType: invalid type {}",
+                                hex, t
+                            ));
+                            return;
+                        }
+                    };
+                    if tag.tagger.is_none() {
+                        self.report.warnings.push(format!(
+                            "warning in tag {}: missingTaggerEntry: invalid format - expected 'tagger' line",
+                            hex
+                        ));
+                    }
+                    links.push(Link {
+                        to: tag.object,
+                        type_name,
+                    });
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    self.error(format!(
+                        "error in {} {}: {}",
+                        raw.type_name,
+                        hex,
+                        describe(&e)
+                    ));
+                    return;
+                }
+            },
+            t => {
+                self.error(format!("error: {}: unknown object type {}", hex, t));
+                return;
+            }
+        }
+        self.types.insert(hash.clone(), raw.type_name);
+        self.links.insert(hash, links);
+    }
+
+    fn check_tree(&mut self, hex: &str, content: &[u8], links: &mut Vec<Link>) {
+        let mut problems: Vec<&str> = vec![];
+        let mut names = HashSet::new();
+        let mut previous: Option<(&[u8], bool)> = None;
+        let mut rest = content;
+        while !rest.is_empty() {
+            let space = rest.iter().position(|&c| c == b' ');
+            let nul = rest.iter().position(|&c| c == 0);
+            let (space, nul) = match (space, nul) {
+                (Some(s), Some(n)) if s < n && n + 21 <= rest.len() => (s, n),
+                _ => {
+                    self.error(format!("error in tree {}: badTree: malformed entry", hex));
+                    return;
+                }
+            };
+            let mode_str = String::from_utf8_lossy(&rest[..space]).to_string();
+            let name = &rest[space + 1..nul];
+            let hash = rest[nul + 1..nul + 21].to_vec();
+            rest = &rest[nul + 21..];
+
+            let mode = FileMode::parse(&mode_str);
+            match mode {
+                None => problems.push("badFilemode: contains bad file modes"),
+                Some(m) if m.to_tree_string() != mode_str => {
+                    let message = if mode_str.starts_with('0') {
+                        "zeroPaddedFilemode: contains zero-padded file modes"
+                    } else {
+                        "badFilemode: contains bad file modes"
+                    };
+                    self.report
+                        .warnings
+                        .push(format!("warning in tree {}: {}", hex, message));
+                }
+                Some(_) => {}
+            }
+            if name.is_empty() {
+                problems.push("emptyName: contains empty pathname");
+            } else if name.contains(&b'/') {
+                problems.push("fullPathname: contains full pathnames");
+            } else if name == b"." {
+                problems.push("hasDot: contains '.'");
+            } else if name == b".." {
+                problems.push("hasDotdot: contains '..'");
+            } else if name.eq_ignore_ascii_case(b".git") {
+                problems.push("hasDotgit: contains '.git'");
+            }
+            if !names.insert(name) {
+                problems.push("duplicateEntries: contains duplicate file entries");
+            }
+            let is_tree = mode == Some(FileMode::Tree);
+            if let Some((prev, prev_is_tree)) = previous {
+                if tree_entry_cmp(prev, prev_is_tree, name, is_tree) != Ordering::Less {
+                    problems.push("treeNotSorted: not properly sorted");
+                }
+            }
+            previous = Some((name, is_tree));
+
+            match mode {
+                Some(FileMode::Tree) => links.push(Link {
+                    to: hash,
+                    type_name: "tree",
+                }),
+                // submodule commits live in another repository.
+                Some(FileMode::Gitlink) => {}
+                _ => links.push(Link {
+                    to: hash,
+                    type_name: "blob",
+                }),
+            }
+        }
+        problems.dedup();
+        for problem in problems {
+            self.error(format!("error in tree {}: {}", hex, problem));
+        }
+    }
+
+    // the index and pack checksums and the crc32 of every entry, which cover the
+    // compressed bytes that resolving objects alone would not notice if they happen to
+    // inflate. the pack is read once from start to end, an entry at a time.
+    fn check_pack_layout(&mut self, pack: &PackFile) {
+        let idx_path = pack.pack_path().with_extension("idx");
+        match fs::read(&idx_path) {
+            Ok(data) if !PackIndex::verify_checksum(&data) => self.error(format!(
+                "error: {}: index checksum mismatch",
+                idx_path.display()
+            )),
+            Ok(_) => {}
+            Err(e) => self.error(format!("error: {}: {}", idx_path.display(), e)),
+        }
+        let name = pack.pack_path().display().to_string();
+        if let Err(e) = self.check_pack_stream(pack, &name) {
+            self.error(format!("error: {}: {}", name, describe(&e)));
+        }
+    }
+
+    fn check_pack_stream(&mut self, pack: &PackFile, name: &str) -> Result<()> {
+        let file = fs::File::open(pack.pack_path())?;
+        let len = file.metadata()?.len();
+        if len < 12 + 20 {
+            return Err(GitObjectError::ParseError(String::from(
+                "pack is too short",
+            )));
+        }
+        let end = len - 20;
+        let index = pack.index();
+        let mut offsets: Vec<(u64, usize)> =
+            (0..index.len()).map(|n| (index.offset(n), n)).collect();
+        offsets.sort();
+
+        let mut reader = BufReader::new(file);
+        let mut hasher = Sha1::new();
+        let mut buf = vec![0; 64 * 1024];
+        let mut pos = 0;
+        // the header, then each entry up to the next one or the trailer.
+        let starts = offsets.iter().map(|o| o.0).chain(iter::once(end));
+        let entries = iter::once(None).chain(offsets.iter().map(Some));
+        for (entry, next) in entries.zip(starts) {
+            let mut crc = 0;
+            while pos < next.min(end) {
+                let want = (next.min(end) - pos).min(buf.len() as u64) as usize;
+                reader.read_exact(&mut buf[..want])?;
+                hasher.update(&buf[..want]);
+                crc = crc32_update(crc, &buf[..want]);
+                pos += want as u64;
+            }
+            if let Some(&(offset, n)) = entry {
+                if crc != index.crc32(n) || offset > end {
+                    self.error(format!(
+                        "error: {}: crc32 mismatch for {} at offset {}",
+                        name,
+                        hash_to_str(index.hash(n)),
+                        offset
+                    ));
+                }
+            }
+        }
+        let mut trailer = [0; 20];
+        reader.read_exact(&mut trailer)?;
+        if hasher.finalize()[..] != trailer {
+            self.error(format!("error: {}: pack checksum mismatch", name));
+        }
+        Ok(())
+    }
+
+    fn check_connectivity(&mut self, roots: &[Vec<u8>]) {
+        let mut referenced = HashSet::new();
+        let mut broken = vec![];
+        for (from, links) in &self.links {
+            for link in links {
+                referenced.insert(link.to.clone());
+                match self.types.get(&link.to) {
+                    None => broken.push((
+                        (self.types[from].clone(), from.clone()),
+                        (link.type_name.to_string(), link.to.clone()),
+                    )),
+                    Some(actual) if actual != link.type_name => self.report.errors.push(format!(
+                        "error in {} {}: {} is a {}, not a {}",
+                        self.types[from],
+                        hash_to_str(from),
+                        hash_to_str(&link.to),
+                        actual,
+                        link.type_name
+                    )),
+                    Some(_) => {}
+                }
+            }
+        }
+        broken.sort_by(|a, b| (&a.1 .1, &a.0 .1).cmp(&(&b.1 .1, &b.0 .1)));
+        let mut missing: Vec<TypedId> = broken.iter().map(|(_, to)| to.clone()).collect();
+        for root in roots {
+            if !self.types.contains_key(root) {
+                missing.push((String::from("object"), root.clone()));
+            }
+        }
+        missing.sort_by(|a, b| a.1.cmp(&b.1));
+        missing.dedup_by(|a, b| a.1 == b.1);
+        self.report.broken_links = broken;
+        self.report.missing = missing;
+
+        let mut reachable = HashSet::new();
+        let mut queue: VecDeque<&Vec<u8>> = roots.iter().collect();
+        while let Some(id) = queue.pop_front() {
+            if !self.types.contains_key(id) || !reachable.insert(id.clone()) {
+                continue;
+            }
+            queue.extend(self.links[id].iter().map(|l| &l.to));
+        }
+        let mut unreachable: Vec<TypedId> = self
+            .types
+            .iter()
+            .filter(|(id, _)| !reachable.contains(*id))
+            .map(|(id, t)| (t.clone(), id.clone()))
+            .collect();
+        unreachable.sort_by(|a, b| a.1.cmp(&b.1));
+        self.report.dangling = unreachable
+            .iter()
+            .filter(|(_, id)| !referenced.contains(id))
+            .cloned()
+            .collect();
+        self.report.unreachable = unreachable;
+    }
+}
+
+fn parse_options(commands: &[String]) -> FsckOption {
+    let mut option: FsckOption = Default::default();
+    for token in &commands[2..] {
+        match &token[..] {
+            "--unreachable" => option.unreachable = true,
+            "--no-dangling" => option.no_dangling = true,
+            // every object is always checked in full.
+            "--full" | "--strict" => {}
+            _ => println!("ignore option {}", token),
+        }
+    }
+    option
+}
+
+#[cfg(test)]
+mod test {
+    use crate::fsck::*;
+    use crate::object_store::ObjectStore;
+    use crate::object_stream::loose_object_path;
+    use crate::pack_objects::{build_pack, list_objects, PackOptions};
+    use crate::test_utils::{commit, set_ref, temp_git_dir, tree};
+
+    fn tree_entry(mode: &str, name: &str, hash: &[u8]) -> Vec<u8> {
+        let mut v = format!("{} {}\0", mode, name).into_bytes();
+        v.extend_from_slice(hash);
+        v
+    }
+
+    #[test]
+    fn test_clean_repository() {
+        let git_dir = temp_git_dir("fsck-clean");
+        let store = LooseObjectStore::new(&git_dir.join("objects"));
+        let files: &[(&str, &[u8])] = &[("dir/f", b"content\n"), ("a.txt", b"content\n")];
+        let first = commit(&store, &[], files, 1_600_000_000, "");
+        let head = commit(&store, &[&first], files, 1_600_000_000, "");
+        set_ref(&git_dir, "refs/heads/master", &head);
+
+        let report = check_repository(&git_dir).unwrap();
+        assert!(report.is_clean(), "{:?}", report);
+        assert!(report.warnings.is_empty());
+        assert!(report.unreachable.is_empty());
+        fs::remove_dir_all(&git_dir).unwrap();
+    }
+
+    #[test]
+    fn test_bad_trees() {
+        let git_dir = temp_git_dir("fsck-trees");
+        let store = LooseObjectStore::new(&git_dir.join("objects"));
+        let blob = store.write("blob", b"content\n").unwrap();
+        let bad = |entries: &[(&str, &str)]| {
+            let content: Vec<u8> = entries
+                .iter()
+                .flat_map(|(mode, name)| tree_entry(mode, name, &blob))
+                .collect();
+            hash_to_str(&store.write("tree", &content).unwrap())
+        };
+        let cases = [
+            (bad(&[("100644", "b"), ("100644", "a")]), "treeNotSorted"),
+            (bad(&[("100644", "a"), ("100644", "a")]), "duplicateEntries"),
+            (bad(&[("100644", ".GIT")]), "hasDotgit"),
+            (bad(&[("100644", "a/b")]), "fullPathname"),
+            (bad(&[("100644", "..")]), "hasDotdot"),
+            (bad(&[("100600", "a")]), "badFilemode"),
+        ];
+        let report = check_repository(&git_dir).unwrap();
+        for (hex, problem) in &cases {
+            let expected = format!("error in tree {}: {}", hex, problem);
+            assert!(
+                report.errors.iter().any(|e| e.starts_with(&expected)),
+                "{} not in {:?}",
+                expected,
+                report.errors
+            );
+        }
+        // "a.txt" sorts before the tree "a", which compares as "a/".
+        assert_eq!(tree_entry_cmp(b"a.txt", false, b"a", true), Ordering::Less);
+        let padded = tree_entry("040000", "a", &blob);
+        let hex = hash_to_str(&store.write("tree", &padded).unwrap());
+        let report = check_repository(&git_dir).unwrap();
+        assert!(report.warnings.contains(&format!(
+            "warning in tree {}: zeroPaddedFilemode: contains zero-padded file modes",
+            hex
+        )));
+        fs::remove_dir_all(&git_dir).unwrap();
+    }
+
+    #[test]
+    fn test_corrupt_and_missing_objects() {
+        let git_dir = temp_git_dir("fsck-corrupt");
+        let objects_dir = git_dir.join("objects");
+        let store = LooseObjectStore::new(&objects_dir);
+        let missing_blob = object_hash("blob", b"never written\n");
+        let files: &[(&str, &[u8])] = &[("gone", b"never written\n")];
+        let tree = tree(&store, files);
+        let head = commit(&store, &[], files, 1_600_000_000, "");
+        set_ref(&git_dir, "refs/heads/master", &head);
+        fs::remove_file(loose_object_path(&objects_dir, &hash_to_str(&missing_blob))).unwrap();
+
+        // a stream that does not inflate and an object stored under the wrong id.
+        let garbage = "1111111111111111111111111111111111111111";
+        fs::create_dir_all(objects_dir.join("11")).unwrap();
+        fs::write(loose_object_path(&objects_dir, garbage), b"not zlib").unwrap();
+        let moved = store.write("blob", b"moved\n").unwrap();
+        let wrong = "2222222222222222222222222222222222222222";
+        fs::create_dir_all(objects_dir.join("22")).unwrap();
+        fs::rename(
+            loose_object_path(&objects_dir, &hash_to_str(&moved)),
+            loose_object_path(&objects_dir, wrong),
+        )
+        .unwrap();
+        let dangling = store.write("blob", b"dangling\n").unwrap();
+
+        let report = check_repository(&git_dir).unwrap();
+        assert!(!report.is_clean());
+        assert!(report
+            .errors
+            .iter()
+            .any(|e| e.contains(garbage) && e.contains("zlib")));
+        assert!(report
+            .errors
+            .iter()
+            .any(|e| e.contains(wrong) && e.contains("hash mismatch")));
+        assert_eq!(
+            report.missing,
+            vec![(String::from("blob"), missing_blob.clone())]
+        );
+        assert_eq!(
+            report.broken_links,
+            vec![(
+                (String::from("tree"), tree.clone()),
+                (String::from("blob"), missing_blob)
+            )]
+        );
+        assert_eq!(report.dangling, vec![(String::from("blob"), dangling)]);
+        fs::remove_dir_all(&git_dir).unwrap();
+    }
+
+    #[test]
+    fn test_unreachable_and_packs() {
+        let git_dir = temp_git_dir("fsck-packs");
+        let objects_dir = git_dir.join("objects");
+        let store = LooseObjectStore::new(&objects_dir);
+        let head = commit(&store, &[], &[("x", &[b'x'; 1000])], 1_600_000_000, "");
+        // an old commit nobody points at: the commit is dangling, its tree only unreachable.
+        let old_tree = tree(&store, &[]);
+        let old = commit(&store, &[], &[], 1_600_000_000, "");
+        set_ref(&git_dir, "refs/heads/master", &head);
+
+        let objects = list_objects(&store, std::slice::from_ref(&head), &[]).unwrap();
+        let mut pack = vec![];
+        let packed = build_pack(&store, &objects, &PackOptions::default(), &mut pack).unwrap();
+        let report = check_repository(&git_dir).unwrap();
+        assert!(report.is_clean(), "{:?}", report);
+        assert_eq!(report.dangling, vec![(String::from("commit"), old.clone())]);
+        let mut unreachable = vec![
+            (String::from("commit"), old),
+            (String::from("tree"), old_tree),
+        ];
+        unreachable.sort_by(|a, b| a.1.cmp(&b.1));
+        assert_eq!(report.unreachable, unreachable);
+
+        // flip a byte inside the compressed blob; the pack checksum is fixed up so that
+        // only the entry crc and the object itself can tell.
+        let pack_dir = objects_dir.join("pack");
+        let len = pack.len();
+        pack[len - 30] ^= 0xff;
+        let checksum = hash(&pack[..len - 20]);
+        pack[len - 20..].copy_from_slice(&checksum);
+        let mut index = packed.index.clone();
+        let idx_len = index.len();
+        index[idx_len - 40..idx_len - 20].copy_from_slice(&checksum);
+        let idx_checksum = hash(&index[..idx_len - 20]);
+        index[idx_len - 20..].copy_from_slice(&idx_checksum);
+        fs::create_dir_all(&pack_dir).unwrap();
+        fs::write(pack_dir.join("pack-test.pack"), &pack).unwrap();
+        fs::write(pack_dir.join("pack-test.idx"), &index).unwrap();
+        let report = check_repository(&git_dir).unwrap();
+        assert!(
+            report.errors.iter().any(|e| e.contains("crc32 mismatch")),
+            "{:?}",
+            report.errors
+        );
+
+        // with the byte put back, the entries are fine but the trailer no longer matches.
+        pack[len - 30] ^= 0xff;
+        fs::write(pack_dir.join("pack-test.pack"), &pack).unwrap();
+        let report = check_repository(&git_dir).unwrap();
+        assert_eq!(
+            report.errors,
+            vec![format!(
+                "error: {}: pack checksum mismatch",
+                pack_dir.join("pack-test.pack").display()
+            )]
+        );
+        assert_eq!(report.dangling.len(), 1);
+
+        // with the pack trailer fixed and the index pointing at it but not rehashed, only
+        // the index checksum is off, which opening the pack does not look at.
+        let checksum = hash(&pack[..len - 20]);
+        pack[len - 20..].copy_from_slice(&checksum);
+        index[idx_len - 40..idx_len - 20].copy_from_slice(&checksum);
+        fs::write(pack_dir.join("pack-test.pack"), &pack).unwrap();
+        fs::write(pack_dir.join("pack-test.idx"), &index).unwrap();
+        let report = check_repository(&git_dir).unwrap();
+        assert_eq!(
+            report.errors,
+            vec![format!(
+                "error: {}: index checksum mismatch",
+                pack_dir.join("pack-test.idx").display()
+            )]
+        );
+        fs::remove_dir_all(&git_dir).unwrap();
+    }
+}
@@ -0,0 +1,409 @@
+use crate::config::Config;
+use crate::date::parse_expiry_date;
+use crate::git_object::{FileMode, GitObjectError};
+use crate::index::{index_path, Index};
+use crate::object_store::{open_object_store_at, LooseObjectStore, ObjectStore};
+use crate::object_stream::loose_object_path;
+use crate::pack::PackObjectStore;
+use crate::pack_objects::{list_objects, write_pack, PackOptions};
+use crate::refs::ref_tips;
+use crate::repository::open_repository;
+use crate::utils::*;
+use std::{
+    collections::HashSet,
+    fs, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+type Result<T> = std::result::Result<T, GitObjectError>;
+
+// unreachable loose objects younger than this are kept, as they may belong to a command
+// that is still running.
+pub const DEFAULT_PRUNE_EXPIRE: &str = "2.weeks.ago";
+
+#[derive(Default)]
+struct RepackOption {
+    all: bool,
+    delete: bool,
+}
+
+#[derive(Default)]
+struct GcOption<'a> {
+    prune: Option<&'a str>,
+    no_prune: bool,
+}
+
+pub fn repack(commands: &[String]) {
+    let option = parse_repack_options(commands);
+    let repo = match open_repository() {
+        Some(r) => r,
+        None => return,
+    };
+    match repack_objects(&repo.git_dir, option.all, option.delete, false) {
+        Ok(None) => println!("Nothing new to pack."),
+        Ok(Some(_)) => {}
+        Err(e) => println!("fatal: {:?}", e),
+    }
+}
+
+pub fn gc(commands: &[String]) {
+    let option = parse_gc_options(commands);
+    let repo = match open_repository() {
+        Some(r) => r,
+        None => return,
+    };
+    let configured = match repo.config().and_then(|c| c.get_string("gc.pruneexpire")) {
+        Ok(c) => c,
+        Err(e) => {
+            println!("fatal: {:?}", e);
+            return;
+        }
+    };
+    let spec = if option.no_prune {
+        "never"
+    } else {
+        option
+            .prune
+            .or(configured.as_deref())
+            .unwrap_or(DEFAULT_PRUNE_EXPIRE)
+    };
+    let cutoff = match parse_expiry_date(spec, SystemTime::now()) {
+        Some(c) => c,
+        None => {
+            println!("fatal: invalid prune date '{}'", spec);
+            return;
+        }
+    };
+    let git_dir = &repo.git_dir;
+    let result = repack_objects(git_dir, true, true, true).and_then(|_| match cutoff {
+        Some(cutoff) => prune_loose_objects(git_dir, cutoff).map(|_| ()),
+        None => Ok(()),
+    });
+    if let Err(e) = result {
+        println!("fatal: {:?}", e);
+    }
+}
+
+// the objects staged in the index. submodule commits live in another repository.
+pub fn index_tips(git_dir: &Path) -> Result<Vec<Vec<u8>>> {
+    let index = Index::read_from(&index_path(git_dir))?;
+    Ok(index
+        .entries
+        .into_iter()
+        .filter(|e| e.mode != FileMode::Gitlink)
+        .map(|e| e.id)
+        .collect())
+}
+
+// objects recorded in the reflogs under logs/, old and new values alike. entries whose
+// object is already gone are skipped, as git does.
+pub fn reflog_tips(git_dir: &Path, store: &dyn ObjectStore) -> Result<Vec<Vec<u8>>> {
+    let mut tips = vec![];
+    let mut paths = vec![git_dir.join("logs")];
+    while let Some(path) = paths.pop() {
+        if path.is_dir() {
+            for entry in fs::read_dir(&path)? {
+                paths.push(entry?.path());
+            }
+            continue;
+        }
+        let log = match fs::read(&path) {
+            Ok(l) => l,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(GitObjectError::IOError(e)),
+        };
+        for line in String::from_utf8_lossy(&log).lines() {
+            for id in line.split(' ').take(2) {
+                if parse_tip(id, &path).is_ok() && id != NULL_ID && store.contains(id) {
+                    tips.push(str_to_hash(id.to_string()));
+                }
+            }
+        }
+    }
+    Ok(tips)
+}
+
+const NULL_ID: &str = "0000000000000000000000000000000000000000";
+
+// everything gc has to keep: what the refs, the reflogs and the index reach.
+fn reachable_objects(git_dir: &Path, store: &dyn ObjectStore) -> Result<Vec<(Vec<u8>, String)>> {
+    let mut tips = ref_tips(git_dir)?;
+    tips.extend(reflog_tips(git_dir, store)?);
+    tips.extend(index_tips(git_dir)?);
+    list_objects(store, &tips, &[])
+}
+
+fn parse_tip(id: &str, path: &Path) -> Result<Vec<u8>> {
+    if id.len() == 40 && id.bytes().all(|c| c.is_ascii_hexdigit()) {
+        Ok(str_to_hash(id.to_ascii_lowercase()))
+    } else {
+        Err(GitObjectError::ParseError(format!(
+            "{} does not hold an object id",
+            path.display()
+        )))
+    }
+}
+
+// packs the objects reachable from the refs and reflogs: all of them into a single pack with `all`,
+// otherwise only the loose ones. with `delete`, packs made redundant by the new one and
+// loose objects that are now packed are removed. `keep_unreachable` writes objects only
+// found in a removed pack back as loose objects dated like the pack, so that pruning
+// decides their fate. returns the new pack's checksum, or None if there was nothing to
+// pack.
+pub fn repack_objects(
+    git_dir: &Path,
+    all: bool,
+    delete: bool,
+    keep_unreachable: bool,
+) -> Result<Option<Vec<u8>>> {
+    let objects_dir = git_dir.join("objects");
+    let store = open_object_store_at(&objects_dir);
+    let old_packs = PackObjectStore::open(&objects_dir)?;
+    let mut objects = reachable_objects(git_dir, &store)?;
+    if !all {
+        objects.retain(|(hash, _)| !old_packs.contains(&hash_to_str(hash)));
+    }
+    if objects.is_empty() {
+        return Ok(None);
+    }
+
+    let options = PackOptions::from_config(&Config::load(Some(git_dir))?)?;
+    let prefix = objects_dir.join("pack").join("pack");
+    let packed = write_pack(&store, &objects, &options, &prefix.to_string_lossy())?;
+    let pack_path = objects_dir
+        .join("pack")
+        .join(format!("pack-{}.pack", hash_to_str(&packed.checksum)));
+
+    if delete {
+        if all {
+            let reachable: HashSet<&Vec<u8>> = objects.iter().map(|(h, _)| h).collect();
+            let redundant: Vec<&Path> = old_packs
+                .packs()
+                .iter()
+                .map(|p| p.pack_path())
+                .filter(|p| *p != pack_path && !p.with_extension("keep").exists())
+                .collect();
+            // every pack is still readable while objects are copied out, as deltas may
+            // refer to bases in another pack.
+            if keep_unreachable {
+                for path in &redundant {
+                    explode_unreachable(&old_packs, path, &reachable, &objects_dir)?;
+                }
+            }
+            for path in redundant {
+                fs::remove_file(path.with_extension("idx"))?;
+                fs::remove_file(path)?;
+            }
+        }
+        prune_packed(&objects_dir)?;
+    }
+    Ok(Some(packed.checksum))
+}
+
+fn explode_unreachable(
+    packs: &PackObjectStore,
+    pack_path: &Path,
+    reachable: &HashSet<&Vec<u8>>,
+    objects_dir: &Path,
+) -> Result<()> {
+    let mtime = fs::metadata(pack_path)?.modified()?;
+    let loose = LooseObjectStore::new(objects_dir);
+    let pack = packs
+        .packs()
+        .iter()
+        .find(|p| p.pack_path() == pack_path)
+        .unwrap();
+    for i in 0..pack.index().len() {
+        let hash = pack.index().hash(i).to_vec();
+        let hex = hash_to_str(&hash);
+        if reachable.contains(&hash) || loose.contains(&hex) {
+            continue;
+        }
+        let raw = packs.read(&hex)?;
+        loose.write(&raw.type_name, &raw.content)?;
+        fs::File::options()
+            .write(true)
+            .open(loose_object_path(objects_dir, &hex))?
+            .set_modified(mtime)?;
+    }
+    Ok(())
+}
+
+// every loose object as (hex id, path).
+pub fn loose_objects(objects_dir: &Path) -> Result<Vec<(String, PathBuf)>> {
+    let mut objects = vec![];
+    for entry in fs::read_dir(objects_dir)? {
+        let entry = entry?;
+        let dir_name = entry.file_name().to_string_lossy().to_string();
+        if dir_name.len() != 2 || !dir_name.bytes().all(|c| c.is_ascii_hexdigit()) {
+            continue;
+        }
+        for file in fs::read_dir(entry.path())? {
+            let file = file?;
+            let name = file.file_name().to_string_lossy().to_string();
+            if name.len() == 38 && name.bytes().all(|c| c.is_ascii_hexdigit()) {
+                objects.push((format!("{}{}", dir_name, name), file.path()));
+            }
+        }
+    }
+    Ok(objects)
+}
+
+// removes loose objects that are also in a pack; returns how many were removed.
+pub fn prune_packed(objects_dir: &Path) -> Result<usize> {
+    let packs = PackObjectStore::open(objects_dir)?;
+    let mut removed = 0;
+    for (hash, path) in loose_objects(objects_dir)? {
+        if packs.contains(&hash) {
+            fs::remove_file(&path)?;
+            removed += 1;
+        }
+    }
+    remove_empty_dirs(objects_dir)?;
+    Ok(removed)
+}
+
+// removes loose objects that no ref or reflog reaches and that were last modified before `cutoff`,
+// along with temporary files left behind by interrupted writes; returns how many objects
+// were removed.
+pub fn prune_loose_objects(git_dir: &Path, cutoff: SystemTime) -> Result<usize> {
+    let objects_dir = git_dir.join("objects");
+    let store = open_object_store_at(&objects_dir);
+    let reachable: HashSet<String> = reachable_objects(git_dir, &store)?
+        .iter()
+        .map(|(h, _)| hash_to_str(h))
+        .collect();
+
+    let expired = |path: &Path| -> Result<bool> { Ok(fs::metadata(path)?.modified()? < cutoff) };
+    let mut removed = 0;
+    for (hash, path) in loose_objects(&objects_dir)? {
+        if !reachable.contains(&hash) && expired(&path)? {
+            fs::remove_file(&path)?;
+            removed += 1;
+        }
+    }
+    for entry in fs::read_dir(&objects_dir)? {
+        let path = entry?.path();
+        let is_tmp = path
+            .file_name()
+            .is_some_and(|n| n.to_string_lossy().starts_with("tmp_obj_"));
+        if is_tmp && expired(&path)? {
+            fs::remove_file(&path)?;
+        }
+    }
+    remove_empty_dirs(&objects_dir)?;
+    Ok(removed)
+}
+
+fn remove_empty_dirs(objects_dir: &Path) -> Result<()> {
+    for entry in fs::read_dir(objects_dir)? {
+        let path = entry?.path();
+        let name = path.file_name().unwrap().to_string_lossy().to_string();
+        if name.len() == 2 && path.is_dir() && fs::read_dir(&path)?.next().is_none() {
+            fs::remove_dir(&path)?;
+        }
+    }
+    Ok(())
+}
+
+fn parse_repack_options(commands: &[String]) -> RepackOption {
+    let mut option: RepackOption = Default::default();
+    for token in &commands[2..] {
+        match &token[..] {
+            "-a" => option.all = true,
+            "-d" => option.delete = true,
+            "-ad" | "-da" => {
+                option.all = true;
+                option.delete = true;
+            }
+            _ => println!("ignore option {}", token),
+        }
+    }
+    option
+}
+
+fn parse_gc_options(commands: &[String]) -> GcOption<'_> {
+    let mut option: GcOption = Default::default();
+    for token in &commands[2..] {
+        match &token[..] {
+            "--no-prune" => option.no_prune = true,
+            "--prune" => option.prune = Some(DEFAULT_PRUNE_EXPIRE),
+            _ if token.starts_with("--prune=") => option.prune = Some(&token[8..]),
+            _ => println!("ignore option {}", token),
+        }
+    }
+    option
+}
+
+#[cfg(test)]
+mod test {
+    use crate::gc::*;
+    use crate::test_utils::{commit, set_ref, temp_git_dir};
+    use std::time::Duration;
+
+    fn pack_count(git_dir: &Path) -> usize {
+        PackObjectStore::open(&git_dir.join("objects"))
+            .unwrap()
+            .packs()
+            .len()
+    }
+
+    #[test]
+    fn test_gc() {
+        let git_dir = temp_git_dir("gc");
+        let objects_dir = git_dir.join("objects");
+        let store = LooseObjectStore::new(&objects_dir);
+        let first = commit(&store, &[], &[("file", b"first\n")], 1_600_000_000, "");
+        set_ref(&git_dir, "refs/heads/master", &first);
+        assert!(repack_objects(&git_dir, false, true, false)
+            .unwrap()
+            .is_some());
+        assert_eq!(pack_count(&git_dir), 1);
+        assert!(loose_objects(&objects_dir).unwrap().is_empty());
+        assert!(repack_objects(&git_dir, false, true, false)
+            .unwrap()
+            .is_none());
+
+        // the first commit is no longer reachable once master moves on.
+        let second = commit(&store, &[], &[("file", b"second\n")], 1_600_000_000, "");
+        set_ref(&git_dir, "refs/heads/master", &second);
+        let stray = store.write("blob", b"stray\n").unwrap();
+        repack_objects(&git_dir, true, true, true).unwrap();
+        assert_eq!(pack_count(&git_dir), 1);
+        let loose: Vec<String> = loose_objects(&objects_dir)
+            .unwrap()
+            .into_iter()
+            .map(|(h, _)| h)
+            .collect();
+        // the first commit, its tree and blob come back loose next to the stray blob.
+        assert_eq!(loose.len(), 4);
+        assert!(loose.contains(&hash_to_str(&first)));
+
+        // recent objects survive a prune with the default grace period.
+        let cutoff = parse_expiry_date(DEFAULT_PRUNE_EXPIRE, SystemTime::now()).unwrap();
+        assert_eq!(prune_loose_objects(&git_dir, cutoff.unwrap()).unwrap(), 0);
+        let now = SystemTime::now() + Duration::from_secs(1);
+        assert_eq!(prune_loose_objects(&git_dir, now).unwrap(), 4);
+        assert!(loose_objects(&objects_dir).unwrap().is_empty());
+        let store = open_object_store_at(&objects_dir);
+        assert!(!store.contains(&hash_to_str(&stray)));
+        assert!(!store.contains(&hash_to_str(&first)));
+        fs::remove_dir_all(&git_dir).unwrap();
+    }
+
+    #[test]
+    fn test_reflog_tips() {
+        let git_dir = temp_git_dir("reflog-tips");
+        let store = LooseObjectStore::new(&git_dir.join("objects"));
+        let head = commit(&store, &[], &[("file", b"content\n")], 1_600_000_000, "");
+        let missing = "0123456789012345678901234567890123456789";
+        fs::create_dir_all(git_dir.join("logs")).unwrap();
+        let line = |old: &str, new: &str| format!("{} {} a <a@example.com> 0 +0000\tx\n", old, new);
+        let log = line(NULL_ID, missing) + &line(missing, &hash_to_str(&head));
+        fs::write(git_dir.join("logs/HEAD"), log).unwrap();
+        // the first new id does not name a stored object.
+        assert_eq!(reflog_tips(&git_dir, &store).unwrap(), vec![head]);
+        fs::remove_dir_all(&git_dir).unwrap();
+    }
+}
@@ -1,49 +1,202 @@
-use crate::git_user::User;
+use crate::git_user::Signature;
+use crate::object_store::{ObjectStore, RawObject};
 use crate::utils::*;
-use flate2::read::ZlibDecoder;
-use std::{
-    fs, io,
-    io::Read,
-    io::Write,
-    path::Path,
-    result,
-    time::{SystemTime, UNIX_EPOCH},
-};
+use std::{cmp::Ordering, fmt, fs, io, io::Write, result};
 
 #[derive(Debug)]
-pub enum GitObject<'a> {
+pub enum GitObject {
     Blob(Vec<u8>),
     Tree(Vec<GitTreeNode>),
-    Commit(GitCommitObject<'a>),
+    Commit(GitCommitObject),
+    Tag(GitTagObject),
 }
 
 #[derive(Debug)]
-pub struct GitCommitObject<'a> {
+pub struct GitCommitObject {
     pub tree: Vec<u8>,
-    pub parent: Option<Vec<u8>>,
-    pub author: &'a User,
-    pub committer: &'a User,
-    pub message: String,
+    pub parents: Vec<Vec<u8>>,
+    pub author: Signature,
+    pub committer: Signature,
+    // headers following the committer line (encoding, mergetag, gpgsig, ...).
+    // multi-line values are stored without the leading space of continuation lines, and
+    // as bytes since a mergetag may carry a tagger in another encoding.
+    pub extra_headers: Vec<(String, Vec<u8>)>,
+    pub message: Vec<u8>,
 }
 
-impl<'a> GitCommitObject<'a> {
+impl GitCommitObject {
     pub fn size(&self) -> usize {
-        0
+        self.get_content().len()
+    }
+
+    pub fn get_content(&self) -> Vec<u8> {
+        let mut v = vec![];
+        v.extend_from_slice(format!("tree {}\n", hash_to_str(&self.tree)).as_bytes());
+        for parent in &self.parents {
+            v.extend_from_slice(format!("parent {}\n", hash_to_str(parent)).as_bytes());
+        }
+        v.extend_from_slice(b"author ");
+        v.extend_from_slice(&self.author.to_bytes());
+        v.extend_from_slice(b"\ncommitter ");
+        v.extend_from_slice(&self.committer.to_bytes());
+        v.push(b'\n');
+        for (key, value) in &self.extra_headers {
+            v.extend_from_slice(key.as_bytes());
+            v.push(b' ');
+            for &c in value {
+                v.push(c);
+                if c == b'\n' {
+                    v.push(b' ');
+                }
+            }
+            v.push(b'\n');
+        }
+        v.push(b'\n');
+        v.extend_from_slice(&self.message);
+        v
+    }
+}
+
+#[derive(Debug)]
+pub struct GitTagObject {
+    pub object: Vec<u8>,
+    pub object_type: String,
+    pub tag: String,
+    // tags created by very old git versions have no tagger.
+    pub tagger: Option<Signature>,
+    pub message: Vec<u8>,
+    // armored signature appended to the message by `tag -s`.
+    pub signature: Option<Vec<u8>>,
+}
+
+impl GitTagObject {
+    pub fn size(&self) -> usize {
+        self.get_content().len()
+    }
+
+    pub fn get_content(&self) -> Vec<u8> {
+        let mut v = vec![];
+        v.extend_from_slice(format!("object {}\n", hash_to_str(&self.object)).as_bytes());
+        v.extend_from_slice(format!("type {}\n", self.object_type).as_bytes());
+        v.extend_from_slice(format!("tag {}\n", self.tag).as_bytes());
+        if let Some(tagger) = &self.tagger {
+            v.extend_from_slice(b"tagger ");
+            v.extend_from_slice(&tagger.to_bytes());
+            v.push(b'\n');
+        }
+        v.push(b'\n');
+        v.extend_from_slice(&self.message);
+        if let Some(signature) = &self.signature {
+            v.extend_from_slice(signature);
+        }
+        v
     }
 }
 
 #[derive(Debug)]
 pub struct GitTreeNode {
-    pub mode: u32,
+    pub mode: FileMode,
     pub node_type: GitNodeType,
     pub hash: Vec<u8>,
     pub filename: String,
+    // the mode as read, when it is not how `mode` is written: "100664" from old
+    // versions of git, or zero-padded. written back as it was so the tree keeps its id.
+    mode_text: Option<String>,
 }
 
 #[derive(Debug)]
 pub enum GitNodeType {
     Blob,
     Tree,
+    Commit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileMode {
+    Regular,
+    Executable,
+    Symlink,
+    Tree,
+    // a commit in another repository (submodule).
+    Gitlink,
+}
+
+impl FileMode {
+    pub fn from_octal(mode: u32) -> Option<Self> {
+        match mode {
+            0o100644 => Some(FileMode::Regular),
+            // written by old versions of git for group-writable files.
+            0o100664 => Some(FileMode::Regular),
+            0o100755 => Some(FileMode::Executable),
+            0o120000 => Some(FileMode::Symlink),
+            0o040000 => Some(FileMode::Tree),
+            0o160000 => Some(FileMode::Gitlink),
+            _ => None,
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        u32::from_str_radix(s, 8)
+            .ok()
+            .and_then(FileMode::from_octal)
+    }
+
+    pub fn to_octal(self) -> u32 {
+        match self {
+            FileMode::Regular => 0o100644,
+            FileMode::Executable => 0o100755,
+            FileMode::Symlink => 0o120000,
+            FileMode::Tree => 0o040000,
+            FileMode::Gitlink => 0o160000,
+        }
+    }
+
+    // the mode as written in tree objects, without zero padding ("40000").
+    pub fn to_tree_string(self) -> String {
+        format!("{:o}", self.to_octal())
+    }
+
+    pub fn node_type(self) -> GitNodeType {
+        match self {
+            FileMode::Tree => GitNodeType::Tree,
+            FileMode::Gitlink => GitNodeType::Commit,
+            _ => GitNodeType::Blob,
+        }
+    }
+
+    #[cfg(unix)]
+    pub fn from_metadata(metadata: &fs::Metadata) -> Self {
+        use std::os::unix::fs::PermissionsExt;
+        let file_type = metadata.file_type();
+        if file_type.is_symlink() {
+            FileMode::Symlink
+        } else if file_type.is_dir() {
+            FileMode::Tree
+        } else if metadata.permissions().mode() & 0o111 != 0 {
+            FileMode::Executable
+        } else {
+            FileMode::Regular
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub fn from_metadata(metadata: &fs::Metadata) -> Self {
+        let file_type = metadata.file_type();
+        if file_type.is_symlink() {
+            FileMode::Symlink
+        } else if file_type.is_dir() {
+            FileMode::Tree
+        } else {
+            FileMode::Regular
+        }
+    }
+}
+
+// zero-padded, as printed by ls-tree ("040000").
+impl fmt::Display for FileMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:06o}", self.to_octal())
+    }
 }
 
 #[derive(Debug)]
@@ -53,42 +206,87 @@ pub enum GitObjectError {
     ParseError(String),
     WriteError(String),
     NotImplementError(String),
+    NotFoundError(String),
+    // an abbreviated id and the objects it could mean.
+    AmbiguousError(String, Vec<Vec<u8>>),
 }
 
 type Result<T> = result::Result<T, GitObjectError>;
 
-impl<'a> GitObject<'a> {
+impl From<io::Error> for GitObjectError {
+    fn from(e: io::Error) -> Self {
+        GitObjectError::IOError(e)
+    }
+}
+
+impl GitObject {
     pub fn new_tree(mut nodes: Vec<GitTreeNode>) -> Self {
-        nodes.sort_by(|a, b| a.filename.cmp(&b.filename));
+        nodes.sort_by(|a, b| {
+            tree_entry_cmp(
+                a.filename.as_bytes(),
+                a.mode == FileMode::Tree,
+                b.filename.as_bytes(),
+                b.mode == FileMode::Tree,
+            )
+        });
         GitObject::Tree(nodes)
     }
 
-    pub fn new_commit<'b>(
+    pub fn new_commit(
         tree_sha: String,
         parent: Option<&String>,
-        author: &'a User,
-        committer: &'a User,
+        author: Signature,
+        committer: Signature,
         message: String,
     ) -> Self {
-        let parent_sha = if parent.is_some() {
-            Some(str_to_hash(parent.unwrap().to_string()))
-        } else {
-            None
-        };
+        let mut message = message.into_bytes();
+        if message.last() != Some(&b'\n') {
+            message.push(b'\n');
+        }
         let commit_obj = GitCommitObject {
             tree: str_to_hash(tree_sha),
-            author: author,
-            committer: committer,
-            parent: parent_sha,
-            message: message,
+            parents: parent
+                .map(|p| str_to_hash(p.to_string()))
+                .into_iter()
+                .collect(),
+            author,
+            committer,
+            extra_headers: vec![],
+            message,
         };
         GitObject::Commit(commit_obj)
     }
 
+    pub fn new_tag(
+        object_sha: String,
+        object_type: String,
+        tag: String,
+        tagger: Signature,
+        message: String,
+    ) -> Self {
+        let mut message = message.into_bytes();
+        if message.last() != Some(&b'\n') {
+            message.push(b'\n');
+        }
+        GitObject::Tag(GitTagObject {
+            object: str_to_hash(object_sha),
+            object_type,
+            tag,
+            tagger: Some(tagger),
+            message,
+            signature: None,
+        })
+    }
+
     pub fn pretty_print(&self) {
         match self {
             GitObject::Blob(s) => print!("{}", String::from_utf8_lossy(s)),
-            GitObject::Commit(_) => print!("commit"),
+            GitObject::Commit(c) => {
+                io::stdout().write_all(&c.get_content()).unwrap();
+            }
+            GitObject::Tag(t) => {
+                io::stdout().write_all(&t.get_content()).unwrap();
+            }
             GitObject::Tree(lst) => {
                 for e in lst {
                     println!(
@@ -105,49 +303,18 @@ impl<'a> GitObject<'a> {
 
     pub fn get_content(&self) -> Vec<u8> {
         match self {
-            GitObject::Commit(c) => {
-                let mut v = vec![];
-                v.append(&mut "tree ".as_bytes().to_vec());
-                v.append(&mut hash_to_str(&c.tree).as_bytes().to_vec());
-                v.push(0x0a);
-                if c.parent.is_some() {
-                    v.append(&mut "parent ".as_bytes().to_vec());
-                    v.append(&mut hash_to_str(&c.parent.as_ref().unwrap()).as_bytes().to_vec());
-                    v.push(0x0a);
-                }
-                let now = SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs();
-                v.append(
-                    &mut format!(
-                        "author {} <{}> {:?} +0000",
-                        c.author.name, c.author.email, now
-                    )
-                    .as_bytes()
-                    .to_vec(),
-                );
-                v.push(0x0a);
-                v.append(
-                    &mut format!(
-                        "committer {} <{}> {:?} +0000",
-                        c.committer.name, c.committer.email, now
-                    )
-                    .as_bytes()
-                    .to_vec(),
-                );
-                v.push(0x0a);
-                v.push(0x0a);
-                v.append(&mut c.message.as_bytes().to_vec());
-                v.push(0x0a);
-                v
-            }
+            GitObject::Commit(c) => c.get_content(),
+            GitObject::Tag(t) => t.get_content(),
             GitObject::Blob(s) => s.to_vec(),
             GitObject::Tree(t) => {
                 t.iter()
                     .flat_map(|e| {
+                        let mode = match &e.mode_text {
+                            Some(text) => text.clone(),
+                            None => e.mode.to_tree_string(),
+                        };
                         let mut v: Vec<u8> =
-                            format!("{} {}\0", e.mode, e.filename).as_bytes().to_vec();
+                            format!("{} {}\0", mode, e.filename).as_bytes().to_vec();
                         // println!("file: {} hash: {:02X?}", e.filename, e.hash.to_vec());
                         v.append(&mut e.hash.to_vec());
                         v
@@ -159,8 +326,9 @@ impl<'a> GitObject<'a> {
     pub fn size(&self) -> usize {
         match self {
             GitObject::Blob(s) => s.len(),
-            GitObject::Tree(_) => 40,
+            GitObject::Tree(_) => self.get_content().len(),
             GitObject::Commit(c) => c.size(),
+            GitObject::Tag(t) => t.size(),
         }
     }
     pub fn type_name(&self) -> String {
@@ -168,81 +336,49 @@ impl<'a> GitObject<'a> {
             GitObject::Blob(_) => String::from("blob"),
             GitObject::Tree(_) => String::from("tree"),
             GitObject::Commit(_) => String::from("commit"),
+            GitObject::Tag(_) => String::from("tag"),
         }
     }
     pub fn to_node_type(&self) -> GitNodeType {
         match self {
             GitObject::Blob(_) => GitNodeType::Blob,
             GitObject::Tree(_) => GitNodeType::Tree,
-            GitObject::Commit(_) => GitNodeType::Blob,
+            GitObject::Commit(_) => GitNodeType::Commit,
+            GitObject::Tag(_) => GitNodeType::Blob,
         }
     }
 
-    pub fn to_string(&self) -> String {
-        let content = self.get_content();
-        format!(
-            "{} {}\0{}",
-            self.type_name(),
-            content.len(),
-            content.iter().map(|r| *r as char).collect::<String>()
-        )
-    }
-
     pub fn to_node_content(&self) -> Vec<u8> {
-        let mut content = self.get_content();
-        let mut v: Vec<u8> = format!("{} {}\0", self.type_name(), content.len())
-            .as_bytes()
-            .to_vec();
-        v.append(&mut content);
+        let content = self.get_content();
+        let mut v = object_header(&self.type_name(), content.len());
+        v.extend_from_slice(&content);
         v
     }
 
     pub fn to_hash(&self) -> Vec<u8> {
-        hash(self.to_string().as_bytes())
+        object_hash(&self.type_name(), &self.get_content())
     }
 
     pub fn to_hash_str(&self) -> String {
-        hash_to_str(&hash(&self.to_node_content()))
-    }
-    pub fn write(&self) -> Result<()> {
-        let hash = self.to_hash_str();
-        // println!(
-        //     "write****\nstr: {:02X?}\nhash: {:02X?}",
-        //     self.to_node_content(),
-        //     self.get_content()
-        // );
-        let compressed = compress(&self.to_node_content());
-        let path_str = hash_to_path_str(&hash);
-        let path = Path::new(&path_str);
-
-        if !path.exists() {
-            let parent_path = match path.parent() {
-                Some(p) => p,
-                None => {
-                    return Err(GitObjectError::WriteError(format!(
-                        "parent path does not exist. {}",
-                        path.display()
-                    )))
-                }
-            };
-            if !parent_path.exists() {
-                let builder = fs::DirBuilder::new();
-                builder.create(path.parent().unwrap()).unwrap();
-            }
-            let mut f = fs::File::create(&path).unwrap();
-            f.write_all(&compressed).expect("failed to write");
-        }
-        Ok(())
+        hash_to_str(&self.to_hash())
+    }
+    pub fn write(&self, store: &dyn ObjectStore) -> Result<Vec<u8>> {
+        store.write(&self.type_name(), &self.get_content())
+    }
+
+    pub fn from_raw(raw: &RawObject) -> Result<Self> {
+        parse_body(&raw.type_name, &raw.content)
     }
 }
 
 impl GitTreeNode {
-    pub fn new(filename: String, hash: &[u8], mode: u32, node_type: GitNodeType) -> Self {
+    pub fn new(filename: String, hash: &[u8], mode: FileMode) -> Self {
         GitTreeNode {
             filename,
             hash: hash.to_vec(),
             mode,
-            node_type,
+            node_type: mode.node_type(),
+            mode_text: None,
         }
     }
 
@@ -250,134 +386,369 @@ impl GitTreeNode {
         match self.node_type {
             GitNodeType::Blob => String::from("blob"),
             GitNodeType::Tree => String::from("tree"),
+            GitNodeType::Commit => String::from("commit"),
         }
     }
 }
 
-fn parse<'a>(content: Vec<u8>) -> Result<GitObject<'a>> {
-    let content_str = String::from_utf8_lossy(&content);
-    if content_str.starts_with("blob") {
-        return parse_blob(content);
-    }
-    if content_str.starts_with("tree") {
-        return parse_tree(content);
-    }
-    if content_str.starts_with("commit") {
-        panic!("to be implemented");
-    }
+#[cfg(test)]
+fn parse(content: Vec<u8>) -> Result<GitObject> {
+    let (type_name, body) = split_header(&content)?;
+    parse_body(type_name, body)
+}
 
-    panic!("unknown content: {:?}", content);
+fn parse_body(type_name: &str, body: &[u8]) -> Result<GitObject> {
+    match type_name {
+        "blob" => parse_blob(body),
+        "tree" => parse_tree(body),
+        "commit" => parse_commit(body),
+        "tag" => parse_tag(body),
+        _ => Err(GitObjectError::ParseError(format!(
+            "unknown object type: {}",
+            type_name
+        ))),
+    }
 }
 
-fn parse_blob<'a>(content: Vec<u8>) -> Result<GitObject<'a>> {
-    if content.len() < 6 {
+// splits "<type> <size>\0<body>" into the type name and the body.
+#[cfg(test)]
+fn split_header(content: &[u8]) -> Result<(&str, &[u8])> {
+    let nul = match content.iter().position(|&e| e == 0) {
+        Some(i) => i,
+        None => {
+            return Err(GitObjectError::ParseError(String::from(
+                "object header is not terminated",
+            )))
+        }
+    };
+    let header = std::str::from_utf8(&content[..nul])
+        .map_err(|_| GitObjectError::ParseError(String::from("object header is not utf-8")))?;
+    let body = &content[nul + 1..];
+    let mut it = header.splitn(2, ' ');
+    let type_name = it.next().unwrap_or_default();
+    let size = it.next().and_then(|n| n.parse::<usize>().ok());
+    if size != Some(body.len()) {
         return Err(GitObjectError::ParseError(format!(
-            "blob too short. {:?}",
-            content
+            "object size mismatch. header: {}, actual: {}",
+            header,
+            body.len()
         )));
     }
-    let s = &content[5..];
-    if let Some(i) = s.iter().position(|&e| e == 0) {
-        let blob = &s[(i + 1)..];
-        Ok(GitObject::Blob(blob.to_vec()))
-    } else {
-        Err(GitObjectError::ParseError(format!(
-            "blob too short. {:?}",
-            content
-        )))
-    }
+    Ok((type_name, body))
+}
+
+fn parse_blob(body: &[u8]) -> Result<GitObject> {
+    Ok(GitObject::Blob(body.to_vec()))
 }
 
-fn parse_tree<'a>(content: Vec<u8>) -> Result<GitObject<'a>> {
-    let s = &content[5..];
-    let si = s.iter().position(|&e| e == 0).unwrap();
-    let mut blob = &s[(si + 1)..];
+fn parse_tree(body: &[u8]) -> Result<GitObject> {
+    let mut blob = body;
 
     let mut v = vec![];
-    loop {
-        // println!("{} : {}", blob[i] as char, blob[i]);
-        let mode_index = blob.iter().position(|&c| c == 32).unwrap();
-        let name_index = blob.iter().position(|&c| c == 0).unwrap();
+    while !blob.is_empty() {
+        let entry_error = || GitObjectError::ParseError(String::from("malformed tree entry"));
+        let mode_index = blob
+            .iter()
+            .position(|&c| c == b' ')
+            .ok_or_else(entry_error)?;
+        let name_index = blob.iter().position(|&c| c == 0).ok_or_else(entry_error)?;
         let hash_index = name_index + 21;
-        let mode = String::from_utf8(blob[..mode_index].to_vec())
-            .unwrap()
-            .parse::<u32>()
-            .unwrap();
-        let filename = String::from_utf8(blob[mode_index + 1..name_index].to_vec()).unwrap();
+        if name_index < mode_index || hash_index > blob.len() {
+            return Err(entry_error());
+        }
+        let mode_str = std::str::from_utf8(&blob[..mode_index]).map_err(|_| entry_error())?;
+        let mode = FileMode::parse(mode_str).ok_or_else(|| {
+            GitObjectError::ParseError(format!("invalid file mode: {}", mode_str))
+        })?;
+        let filename = String::from_utf8(blob[mode_index + 1..name_index].to_vec())
+            .map_err(|_| entry_error())?;
         let hash = &blob[name_index + 1..hash_index];
-        let hash_str = hash_to_str(hash);
 
-        let obj = match load_obj_file(&hash_str) {
-            Ok(o) => o,
-            Err(e) => return Err(e),
-        };
-
-        let content = match parse(obj) {
-            Ok(o) => o,
-            Err(e) => return Err(e),
-        };
-
-        v.push(GitTreeNode::new(
-            filename.clone(),
-            hash,
-            mode,
-            content.to_node_type(),
-        ));
-        if hash_index >= blob.len() {
-            break;
+        let mut node = GitTreeNode::new(filename, hash, mode);
+        if mode.to_tree_string() != mode_str {
+            node.mode_text = Some(mode_str.to_string());
         }
+        v.push(node);
         blob = &blob[hash_index..];
     }
     Ok(GitObject::Tree(v))
 }
 
-fn load_obj_file(hash: &str) -> Result<Vec<u8>> {
-    let path_str = hash_to_path_str(&hash);
-    let object_path = Path::new(&path_str);
-    if !object_path.exists() {
-        return Err(GitObjectError::CustomIOError(format!(
-            "the file {} does not exists.",
-            object_path.display()
-        )));
+fn parse_commit(body: &[u8]) -> Result<GitObject> {
+    // headers end at the first empty line; everything after it is the message.
+    let (header, message) = match body.windows(2).position(|w| w == b"\n\n") {
+        Some(i) => (&body[..=i], &body[i + 2..]),
+        None => (body, &body[body.len()..]),
+    };
+
+    // fold continuation lines (starting with a space) into the preceding header. the
+    // values are kept as bytes, as names in signatures need not be utf-8.
+    let mut fields: Vec<(&str, Vec<u8>)> = vec![];
+    for line in header_lines(header) {
+        if let Some(rest) = line.strip_prefix(b" ") {
+            match fields.last_mut() {
+                Some((_, value)) => {
+                    value.push(b'\n');
+                    value.extend_from_slice(rest);
+                }
+                None => {
+                    return Err(GitObjectError::ParseError(format!(
+                        "unexpected continuation line: {}",
+                        String::from_utf8_lossy(line)
+                    )))
+                }
+            }
+            continue;
+        }
+        let (key, value) = split_header_line(line)?;
+        fields.push((key, value.to_vec()));
+    }
+
+    let mut tree = None;
+    let mut parents = vec![];
+    let mut author = None;
+    let mut committer = None;
+    let mut extra_headers = vec![];
+    for (key, value) in fields {
+        match key {
+            "tree" if tree.is_none() && author.is_none() => tree = Some(parse_hex_hash(&value)?),
+            "parent" if tree.is_some() && author.is_none() => parents.push(parse_hex_hash(&value)?),
+            "author" if tree.is_some() && author.is_none() => {
+                author = Some(Signature::parse(&value).ok_or_else(|| {
+                    GitObjectError::ParseError(format!(
+                        "invalid author: {}",
+                        String::from_utf8_lossy(&value)
+                    ))
+                })?)
+            }
+            "committer" if author.is_some() && committer.is_none() => {
+                committer = Some(Signature::parse(&value).ok_or_else(|| {
+                    GitObjectError::ParseError(format!(
+                        "invalid committer: {}",
+                        String::from_utf8_lossy(&value)
+                    ))
+                })?)
+            }
+            _ if committer.is_some() => extra_headers.push((key.to_string(), value)),
+            _ => {
+                return Err(GitObjectError::ParseError(format!(
+                    "unexpected commit header: {}",
+                    key
+                )))
+            }
+        }
     }
-    let file_content = fs::read(object_path)
-        .unwrap_or_else(|e| panic!("failed to read: {}. path: {}", e, object_path.display()));
-    let mut d = ZlibDecoder::new(&*file_content);
-    let mut buf = vec![];
-    match d.read_to_end(&mut buf) {
-        Ok(_) => Ok(buf),
-        Err(e) => Err(GitObjectError::IOError(e)),
+
+    match (tree, author, committer) {
+        (Some(tree), Some(author), Some(committer)) => Ok(GitObject::Commit(GitCommitObject {
+            tree,
+            parents,
+            author,
+            committer,
+            extra_headers,
+            message: message.to_vec(),
+        })),
+        _ => Err(GitObjectError::ParseError(String::from(
+            "commit is missing tree, author or committer",
+        ))),
     }
 }
 
-pub fn load_object_by_hash(hash: &str) -> Result<GitObject> {
-    match load_obj_file(hash) {
-        Ok(obj) => parse(obj),
-        Err(e) => Err(e),
+fn parse_tag(body: &[u8]) -> Result<GitObject> {
+    let (header, message) = match body.windows(2).position(|w| w == b"\n\n") {
+        Some(i) => (&body[..=i], &body[i + 2..]),
+        None => (body, &body[body.len()..]),
+    };
+    let mut object = None;
+    let mut object_type = None;
+    let mut tag = None;
+    let mut tagger = None;
+    for line in header_lines(header) {
+        let (key, value) = split_header_line(line)?;
+        let text = || {
+            String::from_utf8(value.to_vec()).map_err(|_| {
+                GitObjectError::ParseError(format!("tag header is not utf-8: {}", key))
+            })
+        };
+        match key {
+            "object" if object.is_none() => object = Some(parse_hex_hash(value)?),
+            "type" if object.is_some() && object_type.is_none() => object_type = Some(text()?),
+            "tag" if object_type.is_some() && tag.is_none() => tag = Some(text()?),
+            "tagger" if tag.is_some() && tagger.is_none() => {
+                tagger = Some(Signature::parse(value).ok_or_else(|| {
+                    GitObjectError::ParseError(format!(
+                        "invalid tagger: {}",
+                        String::from_utf8_lossy(value)
+                    ))
+                })?)
+            }
+            _ => {
+                return Err(GitObjectError::ParseError(format!(
+                    "unexpected tag header: {}",
+                    String::from_utf8_lossy(line)
+                )))
+            }
+        }
+    }
+
+    // a signature starts at the last armor line and runs to the end of the message, so
+    // that a message quoting one keeps it.
+    let signature_start = message
+        .split_inclusive(|&c| c == b'\n')
+        .scan(0, |offset, line| {
+            let start = *offset;
+            *offset += line.len();
+            Some((start, line))
+        })
+        .filter(|(_, line)| {
+            line.starts_with(b"-----BEGIN PGP SIGNATURE-----")
+                || line.starts_with(b"-----BEGIN SSH SIGNATURE-----")
+        })
+        .last()
+        .map(|(start, _)| start);
+    let (message, signature) = match signature_start {
+        Some(i) => (&message[..i], Some(message[i..].to_vec())),
+        None => (message, None),
+    };
+
+    match (object, object_type, tag) {
+        (Some(object), Some(object_type), Some(tag)) => Ok(GitObject::Tag(GitTagObject {
+            object,
+            object_type,
+            tag,
+            tagger,
+            message: message.to_vec(),
+            signature,
+        })),
+        _ => Err(GitObjectError::ParseError(String::from(
+            "tag is missing object, type or tag",
+        ))),
+    }
+}
+
+// the lines of an object header, which ends with a newline.
+fn header_lines(header: &[u8]) -> impl Iterator<Item = &[u8]> {
+    header
+        .strip_suffix(b"\n")
+        .unwrap_or(header)
+        .split(|&c| c == b'\n')
+}
+
+// splits "<key> <value>"; keys are always ascii, values are whatever the writer put there.
+fn split_header_line(line: &[u8]) -> Result<(&str, &[u8])> {
+    let (key, value) = match line.iter().position(|&c| c == b' ') {
+        Some(i) => (&line[..i], &line[i + 1..]),
+        None => (line, &line[line.len()..]),
+    };
+    let key = std::str::from_utf8(key).map_err(|_| {
+        GitObjectError::ParseError(format!("invalid header: {}", String::from_utf8_lossy(line)))
+    })?;
+    Ok((key, value))
+}
+
+fn parse_hex_hash(s: &[u8]) -> Result<Vec<u8>> {
+    if s.len() != 40 || !s.iter().all(|c| c.is_ascii_hexdigit()) {
+        return Err(GitObjectError::ParseError(format!(
+            "invalid object id: {}",
+            String::from_utf8_lossy(s)
+        )));
     }
+    Ok(str_to_hash(String::from_utf8_lossy(s).into_owned()))
+}
+
+// git orders tree entries by name, comparing subtrees as if their name ended in '/', so
+// "foo.c" comes before the tree "foo" and "foo0" after it.
+pub fn tree_entry_cmp(a: &[u8], a_is_tree: bool, b: &[u8], b_is_tree: bool) -> Ordering {
+    let len = a.len().min(b.len());
+    let end = |name: &[u8], is_tree: bool| match name.get(len) {
+        Some(c) => *c,
+        None if is_tree => b'/',
+        None => 0,
+    };
+    a[..len]
+        .cmp(&b[..len])
+        .then_with(|| end(a, a_is_tree).cmp(&end(b, b_is_tree)))
+}
+
+pub fn load_object_by_hash(store: &dyn ObjectStore, hash: &str) -> Result<GitObject> {
+    GitObject::from_raw(&store.read(hash)?)
 }
 
 #[cfg(test)]
 mod test {
     use crate::git_object::*;
-    use crate::git_user::USER;
+    use crate::git_user::Signature;
 
     #[test]
     fn test_blob_hash() {
         let o = GitObject::Blob(String::from("hogehoge\n").as_bytes().to_vec());
-        println!("{}", o.to_string());
-        assert_eq!(o.to_string(), "blob 9\0hogehoge\n");
+        assert_eq!(o.to_node_content(), b"blob 9\0hogehoge\n".to_vec());
         assert_eq!(o.to_hash_str(), "e9bc11025c28829eedf6d30cd3b65628648cad5f");
     }
 
+    #[test]
+    fn test_binary_blob_hash() {
+        // every byte value, including the ones that are not valid utf-8 on their own.
+        let o = GitObject::Blob((0..=255).collect());
+        assert_eq!(o.to_hash_str(), "c86626638e0bc8cf47ca49bb1525b40e9737ee64");
+        assert_eq!(hash_to_str(&o.to_hash()), o.to_hash_str());
+        assert_eq!(o.to_hash(), hash(&o.to_node_content()));
+
+        let t = GitObject::new_tree(vec![GitTreeNode::new(
+            String::from("bin"),
+            &o.to_hash(),
+            FileMode::Regular,
+        )]);
+        assert_eq!(t.to_hash_str(), "21cbf95f66244ec71961d61a5f98339f4f96e186");
+        assert_eq!(t.size(), 31);
+    }
+
+    #[test]
+    fn test_tree_entry_order() {
+        let blob = GitObject::Blob(b"x".to_vec()).to_hash();
+        let sub = GitObject::new_tree(vec![GitTreeNode::new(
+            String::from("x"),
+            &blob,
+            FileMode::Regular,
+        )]);
+        let node = |name: &str, mode: FileMode| {
+            let id = if mode == FileMode::Tree {
+                sub.to_hash()
+            } else {
+                blob.clone()
+            };
+            GitTreeNode::new(name.to_string(), &id, mode)
+        };
+        let t = GitObject::new_tree(vec![
+            node("foo0", FileMode::Regular),
+            node("foo", FileMode::Tree),
+            node("foo.c", FileMode::Regular),
+            node("foo-bar", FileMode::Regular),
+        ]);
+        match &t {
+            GitObject::Tree(nodes) => {
+                let names: Vec<&str> = nodes.iter().map(|n| n.filename.as_str()).collect();
+                assert_eq!(names, vec!["foo-bar", "foo.c", "foo", "foo0"]);
+            }
+            _ => panic!("not a tree"),
+        }
+        // as `git mktree` writes it.
+        assert_eq!(t.to_hash_str(), "ca41620649d273a77c6eb95078c35ccb5c65158a");
+
+        assert_eq!(tree_entry_cmp(b"a.txt", false, b"a", true), Ordering::Less);
+        assert_eq!(tree_entry_cmp(b"a", false, b"a.txt", false), Ordering::Less);
+        assert_eq!(tree_entry_cmp(b"a", false, b"a", true), Ordering::Less);
+        assert_eq!(tree_entry_cmp(b"a", true, b"a", true), Ordering::Equal);
+        assert_eq!(tree_entry_cmp(b"a0", false, b"a", true), Ordering::Greater);
+    }
+
     #[test]
     fn test_tree_hash() {
         let o = GitObject::Blob(String::from("hogehoge\n").as_bytes().to_vec());
         let t = GitObject::Tree(vec![GitTreeNode::new(
             "hogehoge".to_string(),
             &o.to_hash(),
-            100644,
-            GitNodeType::Tree,
+            FileMode::Regular,
         )]);
 
         let hash = o.to_hash();
@@ -395,22 +766,289 @@ mod test {
         assert_eq!(t.to_hash_str(), "341d422eca9785ce3f93590d66bda0a47facb5d9");
     }
 
+    #[test]
+    fn test_file_mode() {
+        assert_eq!(FileMode::Tree.to_tree_string(), "40000");
+        assert_eq!(FileMode::Tree.to_string(), "040000");
+        assert_eq!(FileMode::Executable.to_tree_string(), "100755");
+        assert_eq!(FileMode::parse("40000"), Some(FileMode::Tree));
+        assert_eq!(FileMode::parse("120000"), Some(FileMode::Symlink));
+        assert_eq!(FileMode::parse("160000"), Some(FileMode::Gitlink));
+        assert_eq!(FileMode::parse("100664"), Some(FileMode::Regular));
+        assert_eq!(FileMode::parse("100600"), None);
+        assert_eq!(FileMode::parse("1o0644"), None);
+    }
+
+    #[test]
+    fn test_tree_modes_roundtrip() {
+        let blob = str_to_hash(String::from("e9bc11025c28829eedf6d30cd3b65628648cad5f"));
+        let t = GitObject::new_tree(vec![
+            GitTreeNode::new(String::from("run.sh"), &blob, FileMode::Executable),
+            GitTreeNode::new(String::from("link"), &blob, FileMode::Symlink),
+            GitTreeNode::new(
+                String::from("sub"),
+                &str_to_hash(String::from("3444d9915457d1f5e0c4a85f63f25d84672ef524")),
+                FileMode::Gitlink,
+            ),
+            GitTreeNode::new(
+                String::from("dir"),
+                &str_to_hash(String::from("341d422eca9785ce3f93590d66bda0a47facb5d9")),
+                FileMode::Tree,
+            ),
+        ]);
+        assert_eq!(t.to_hash_str(), "c40bf76decb4295a3cde2dd23fa5febb448c404b");
+
+        let parsed = parse(t.to_node_content()).unwrap();
+        match &parsed {
+            GitObject::Tree(nodes) => {
+                let modes: Vec<FileMode> = nodes.iter().map(|n| n.mode).collect();
+                assert_eq!(
+                    modes,
+                    vec![
+                        FileMode::Tree,
+                        FileMode::Symlink,
+                        FileMode::Executable,
+                        FileMode::Gitlink
+                    ]
+                );
+                assert_eq!(nodes[3].type_name(), "commit");
+            }
+            _ => panic!("not a tree: {:?}", parsed),
+        }
+        assert_eq!(parsed.to_hash_str(), t.to_hash_str());
+
+        // legacy and zero-padded modes are read as the mode they stand for, and kept.
+        let mut legacy = b"100664 a\0".to_vec();
+        legacy.extend_from_slice(&blob);
+        legacy.extend_from_slice(b"040000 d\0");
+        legacy.extend_from_slice(&blob);
+        let parsed = parse(with_header("tree", &legacy)).unwrap();
+        match &parsed {
+            GitObject::Tree(nodes) => {
+                assert_eq!(
+                    (nodes[0].mode, nodes[1].mode),
+                    (FileMode::Regular, FileMode::Tree)
+                );
+            }
+            _ => panic!("not a tree: {:?}", parsed),
+        }
+        assert_eq!(parsed.get_content(), legacy);
+    }
+
     #[test]
     fn test_commit_content() {
+        let signature = Signature::new("zigen", "zigen@horol.org", 1600000000, 540);
         let c = GitObject::new_commit(
             String::from("341d422eca9785ce3f93590d66bda0a47facb5d9"),
             None,
-            &USER,
-            &USER,
+            signature.clone(),
+            signature,
             String::from("hogehoge piyopiyo"),
         );
         let content = c.get_content();
         println!("{}", String::from_utf8_lossy(&content.to_vec()));
         assert_eq!(content.to_vec()[..5], b"tree "[..]);
-        // assert_eq!(content.to_vec()[8..15], b"100644 "[..]);
-        // assert_eq!(content.to_vec()[15..23], b"hogehoge"[..]);
-        // assert_eq!(content.to_vec()[23], b'\0');
-        // hash: 40 chars in hex notation, 20 bytes.
-        // assert_eq!(content.len(), 44);
+        assert!(content.ends_with(b"\n\nhogehoge piyopiyo\n"));
+        // serializing again must not change the commit.
+        assert_eq!(c.get_content(), content);
+        assert_eq!(c.to_hash_str(), "83e0cb0a08204e6634b29e6041d175e40356af6f");
+    }
+
+    #[test]
+    fn test_signature_format() {
+        let s = Signature::new("zigen", "zigen@horol.org", 1600000000, -90);
+        assert_eq!(s.to_string(), "zigen <zigen@horol.org> 1600000000 -0130");
+        assert_eq!(Signature::parse(s.to_string().as_bytes()), Some(s));
+        assert_eq!(
+            Signature::parse(b"zigen <zigen@horol.org> 1600000000"),
+            None
+        );
+        assert_eq!(Signature::parse(b"zigen <zigen@horol.org> now +0900"), None);
+
+        // kept as written, even where the parsed fields would print differently.
+        let odd = b"zigen   <zigen@horol.org>  1600000000 -0000";
+        let s = Signature::parse(odd).unwrap();
+        assert_eq!((s.name(), s.tz_offset()), ("zigen", 0));
+        assert_eq!(s.to_bytes(), odd.to_vec());
+        assert_ne!(s, Signature::new("zigen", "zigen@horol.org", 1600000000, 0));
+        // a changed field is written as it is now.
+        let mut changed = s.clone();
+        changed.set_email("z@horol.org");
+        assert_eq!(changed.to_string(), "zigen <z@horol.org> 1600000000 +0000");
+        let mut changed = s;
+        changed.set_time(1700000000, 60);
+        assert_eq!(
+            changed.to_bytes(),
+            b"zigen <zigen@horol.org> 1700000000 +0100"
+        );
+        changed.set_name("Z");
+        assert_eq!(
+            changed,
+            Signature::new("Z", "zigen@horol.org", 1700000000, 60)
+        );
+    }
+
+    fn with_header(type_name: &str, body: &[u8]) -> Vec<u8> {
+        let mut v = format!("{} {}\0", type_name, body.len()).into_bytes();
+        v.extend_from_slice(body);
+        v
+    }
+
+    #[test]
+    fn test_parse_commit() {
+        let body = b"tree c49897f29f9819a0ab6850d7e22443508a1a29d5\n\
+parent 341d422eca9785ce3f93590d66bda0a47facb5d9\n\
+parent e9bc11025c28829eedf6d30cd3b65628648cad5f\n\
+author zigen <zigen@horol.org> 1600000000 +0900\n\
+committer Someone Else <else@example.com> 1600000100 -0130\n\
+encoding ISO-8859-1\n\
+\n\
+first line\n\
+\n\
+body\n";
+        let c = match parse(with_header("commit", body)).unwrap() {
+            GitObject::Commit(c) => c,
+            o => panic!("not a commit: {:?}", o),
+        };
+        assert_eq!(
+            hash_to_str(&c.tree),
+            "c49897f29f9819a0ab6850d7e22443508a1a29d5"
+        );
+        assert_eq!(c.parents.len(), 2);
+        assert_eq!(c.author.name(), "zigen");
+        assert_eq!(c.author.email(), "zigen@horol.org");
+        assert_eq!(c.author.time(), 1600000000);
+        assert_eq!(c.author.tz_offset(), 540);
+        assert_eq!(c.committer.name(), "Someone Else");
+        assert_eq!(c.committer.tz_offset(), -90);
+        assert_eq!(
+            c.extra_headers,
+            vec![(String::from("encoding"), b"ISO-8859-1".to_vec())]
+        );
+        assert_eq!(c.message, b"first line\n\nbody\n".to_vec());
+        assert_eq!(c.get_content(), body.to_vec());
+    }
+
+    #[test]
+    fn test_parse_commit_roundtrip_gpgsig() {
+        let body = concat!(
+            "tree c49897f29f9819a0ab6850d7e22443508a1a29d5\n",
+            "author zigen <zigen@horol.org> 1600000000 +0900\n",
+            "committer zigen <zigen@horol.org> 1600000000 +0900\n",
+            "gpgsig -----BEGIN PGP SIGNATURE-----\n",
+            " \n",
+            " iQEzBAABCAAdFiEE\n",
+            " -----END PGP SIGNATURE-----\n",
+            "\n",
+            "signed\n"
+        )
+        .as_bytes();
+        let o = parse(with_header("commit", body)).unwrap();
+        if let GitObject::Commit(c) = &o {
+            assert_eq!(c.extra_headers[0].0, "gpgsig");
+            assert!(c.extra_headers[0]
+                .1
+                .ends_with(b"\n-----END PGP SIGNATURE-----"));
+        }
+        assert_eq!(o.get_content(), body.to_vec());
+        assert_eq!(o.size(), body.len());
+    }
+
+    #[test]
+    fn test_parse_commit_roundtrip_legacy() {
+        // a latin-1 author, as recorded with i18n.commitEncoding, and an unknown zone.
+        let mut body = b"tree c49897f29f9819a0ab6850d7e22443508a1a29d5\nauthor Jos\xe9 ".to_vec();
+        body.extend_from_slice(b"<jose@example.com> 1600000000 +0100\n");
+        body.extend_from_slice(b"committer zigen <zigen@horol.org> 1600000000 -0000\n");
+        body.extend_from_slice(b"encoding ISO-8859-1\n\nol\xe9\n");
+        let o = parse(with_header("commit", &body)).unwrap();
+        match &o {
+            GitObject::Commit(c) => {
+                assert_eq!(c.author.name(), "Jos\u{fffd}");
+                assert_eq!(c.author.email(), "jose@example.com");
+                assert_eq!(c.committer.tz_offset(), 0);
+            }
+            _ => panic!("not a commit: {:?}", o),
+        }
+        assert_eq!(o.get_content(), body);
+
+        let mut tag = b"object 3444d9915457d1f5e0c4a85f63f25d84672ef524\ntype commit\n".to_vec();
+        tag.extend_from_slice(b"tag v1.0\ntagger Jos\xe9 <jose@example.com> 1600000000 -0000\n");
+        tag.extend_from_slice(b"\nrelease\n");
+        let o = parse(with_header("tag", &tag)).unwrap();
+        assert_eq!(o.get_content(), tag);
+    }
+
+    #[test]
+    fn test_parse_tag() {
+        let body = concat!(
+            "object 3444d9915457d1f5e0c4a85f63f25d84672ef524\n",
+            "type commit\n",
+            "tag v1.0\n",
+            "tagger zigen <zigen@horol.org> 1600000000 +0900\n",
+            "\n",
+            "release 1.0\n",
+            "-----BEGIN PGP SIGNATURE-----\n",
+            "\n",
+            "iQEzBAABCAAdFiEE\n",
+            "-----END PGP SIGNATURE-----\n"
+        )
+        .as_bytes();
+        let o = parse(with_header("tag", body)).unwrap();
+        assert_eq!(o.type_name(), "tag");
+        match &o {
+            GitObject::Tag(t) => {
+                assert_eq!(
+                    hash_to_str(&t.object),
+                    "3444d9915457d1f5e0c4a85f63f25d84672ef524"
+                );
+                assert_eq!(t.object_type, "commit");
+                assert_eq!(t.tag, "v1.0");
+                assert_eq!(t.tagger.as_ref().unwrap().time(), 1600000000);
+                assert_eq!(t.message, b"release 1.0\n".to_vec());
+                assert!(t.signature.as_ref().unwrap().starts_with(b"-----BEGIN"));
+            }
+            _ => panic!("not a tag: {:?}", o),
+        }
+        assert_eq!(o.get_content(), body.to_vec());
+
+        // only the last armor line starts the signature; earlier ones are message.
+        let signed = [
+            body,
+            b"-----BEGIN SSH SIGNATURE-----\nU1NIU0lH\n-----END SSH SIGNATURE-----\n",
+        ]
+        .concat();
+        let o = parse(with_header("tag", &signed)).unwrap();
+        match &o {
+            GitObject::Tag(t) => {
+                assert!(t.message.starts_with(b"release 1.0\n-----BEGIN PGP"));
+                assert!(t.message.ends_with(b"-----END PGP SIGNATURE-----\n"));
+                assert!(t.signature.as_ref().unwrap().starts_with(b"-----BEGIN SSH"));
+            }
+            _ => panic!("not a tag: {:?}", o),
+        }
+        assert_eq!(o.get_content(), signed);
+    }
+
+    #[test]
+    fn test_tag_hash() {
+        let tag = GitObject::new_tag(
+            String::from("3444d9915457d1f5e0c4a85f63f25d84672ef524"),
+            String::from("commit"),
+            String::from("v1.0"),
+            Signature::new("zigen", "zigen@horol.org", 1600000000, 540),
+            String::from("release 1.0"),
+        );
+        assert_eq!(
+            tag.to_hash_str(),
+            "6fdd81af6ba3aee7428f2992635889139e7ccf61"
+        );
+    }
+
+    #[test]
+    fn test_parse_commit_errors() {
+        assert!(parse(with_header("commit", b"author zigen\n\nmsg\n")).is_err());
+        assert!(parse(with_header("commit", b"tree 1234\n\nmsg\n")).is_err());
+        assert!(parse(b"commit 99\0tree".to_vec()).is_err());
     }
 }
@@ -1,10 +1,176 @@
-#[derive(Debug)]
-pub struct User {
-    pub name: &'static str,
-    pub email: &'static str,
+use crate::config::Config;
+use crate::timezone::local_tz_offset;
+use std::{
+    env, fmt, fs,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+// the fields are only changed through the setters, which drop `raw`: it always reads
+// back as the fields, so comparing it compares how the signature is written.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signature {
+    name: String,
+    email: String,
+    // seconds since the unix epoch.
+    time: i64,
+    // offset from UTC in minutes, e.g. +0900 is 540.
+    tz_offset: i32,
+    // the signature as read, when writing the fields back would not reproduce it: a name
+    // that is not utf-8, odd spacing or a "-0000" zone.
+    raw: Option<Vec<u8>>,
 }
 
-pub static USER: User = User {
-    name: "zigen",
-    email: "zigen@horol.org",
-};
+impl Signature {
+    pub fn new(name: &str, email: &str, time: i64, tz_offset: i32) -> Self {
+        Signature {
+            name: name.to_string(),
+            email: email.to_string(),
+            time,
+            tz_offset,
+            raw: None,
+        }
+    }
+
+    // signature for the current time in the local timezone.
+    pub fn now(name: &str, email: &str) -> Self {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+        Signature::new(name, email, time, local_tz_offset(time))
+    }
+
+    // parses "Name <email> 1600000000 +0900".
+    pub fn parse(s: &[u8]) -> Option<Self> {
+        let lt = s.iter().position(|&c| c == b'<')?;
+        let gt = lt + s[lt..].iter().position(|&c| c == b'>')?;
+        let name = String::from_utf8_lossy(s[..lt].trim_ascii_end());
+        let email = String::from_utf8_lossy(&s[lt + 1..gt]);
+        let rest = std::str::from_utf8(&s[gt + 1..]).ok()?;
+        let mut it = rest.split_whitespace();
+        let time = it.next()?.parse::<i64>().ok()?;
+        let tz_offset = parse_tz_offset(it.next()?)?;
+        if it.next().is_some() {
+            return None;
+        }
+        let mut signature = Signature::new(&name, &email, time, tz_offset);
+        if signature.to_bytes() != s {
+            signature.raw = Some(s.to_vec());
+        }
+        Some(signature)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn email(&self) -> &str {
+        &self.email
+    }
+
+    pub fn time(&self) -> i64 {
+        self.time
+    }
+
+    pub fn tz_offset(&self) -> i32 {
+        self.tz_offset
+    }
+
+    pub fn set_name(&mut self, name: &str) {
+        self.name = name.to_string();
+        self.raw = None;
+    }
+
+    pub fn set_email(&mut self, email: &str) {
+        self.email = email.to_string();
+        self.raw = None;
+    }
+
+    pub fn set_time(&mut self, time: i64, tz_offset: i32) {
+        self.time = time;
+        self.tz_offset = tz_offset;
+        self.raw = None;
+    }
+
+    // the signature as written in commit and tag headers.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match &self.raw {
+            Some(raw) => raw.clone(),
+            None => self.to_string().into_bytes(),
+        }
+    }
+}
+
+// the signature git would record now for `role`, "AUTHOR" or "COMMITTER". the name and
+// email come from $GIT_<role>_NAME and $GIT_<role>_EMAIL, then user.name and user.email,
+// and the email last from $EMAIL.
+pub fn current_signature(config: &Config, role: &str) -> Result<Signature, String> {
+    let lookup = |var: &str, key: &str| {
+        env::var(format!("GIT_{}_{}", role, var))
+            .ok()
+            .or_else(|| config.get_string(key).ok().flatten())
+            .filter(|v| !v.is_empty())
+    };
+    let name = lookup("NAME", "user.name");
+    let email = lookup("EMAIL", "user.email").or_else(|| env::var("EMAIL").ok());
+    match (name, email) {
+        (Some(name), Some(email)) => Ok(Signature::now(&name, &email)),
+        _ => Err(String::from(
+            "*** Please tell me who you are.\n\n\
+             Run\n\n  \
+             git config --global user.email \"you@example.com\"\n  \
+             git config --global user.name \"Your Name\"\n\n\
+             to set your account's default identity.",
+        )),
+    }
+}
+
+// the identity reflog entries are recorded with. unlike commits they are written even
+// without a configured one, with the login and host name like git's default ident.
+pub fn reflog_signature(config: &Config) -> Signature {
+    current_signature(config, "COMMITTER").unwrap_or_else(|_| {
+        let user = env::var("USER")
+            .or_else(|_| env::var("LOGNAME"))
+            .unwrap_or_else(|_| String::from("unknown"));
+        let host = fs::read_to_string("/etc/hostname")
+            .map(|h| h.trim().to_string())
+            .ok()
+            .filter(|h| !h.is_empty())
+            .unwrap_or_else(|| String::from("localhost"));
+        Signature::now(&user, &format!("{}@{}", user, host))
+    })
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(raw) = &self.raw {
+            return write!(f, "{}", String::from_utf8_lossy(raw));
+        }
+        let sign = if self.tz_offset < 0 { '-' } else { '+' };
+        let offset = self.tz_offset.abs();
+        write!(
+            f,
+            "{} <{}> {} {}{:02}{:02}",
+            self.name,
+            self.email,
+            self.time,
+            sign,
+            offset / 60,
+            offset % 60
+        )
+    }
+}
+
+fn parse_tz_offset(s: &str) -> Option<i32> {
+    if s.len() != 5 || !s[1..].bytes().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let sign = match &s[..1] {
+        "+" => 1,
+        "-" => -1,
+        _ => return None,
+    };
+    let hours = s[1..3].parse::<i32>().ok()?;
+    let minutes = s[3..5].parse::<i32>().ok()?;
+    Some(sign * (hours * 60 + minutes))
+}
@@ -1,5 +1,8 @@
-use crate::git_object::*;
-use std::{fs, path::Path};
+use crate::object_store::ObjectStore;
+use crate::object_stream::hash_stream;
+use crate::repository::open_repository;
+use crate::utils::hash_to_str;
+use std::{fs, io::BufReader};
 
 #[derive(Default)]
 struct HashObjectOption<'a> {
@@ -9,30 +12,49 @@ struct HashObjectOption<'a> {
 
 pub fn hash_object(commands: &[String]) {
     let option = parse_options(commands);
-    if option.filename.is_none() {
-        return;
-    }
-
-    let content = load_file(&option.filename.as_ref().unwrap());
-    let blob = GitObject::Blob(content);
-    let sha1hash = blob.to_hash_str();
-    if option.write_object {
-        blob.write().expect("failed to write");
-    }
-    println!("{}", sha1hash);
-}
+    let path = match option.filename {
+        Some(p) => p,
+        None => return,
+    };
 
-fn load_file(filename: &str) -> Vec<u8> {
-    let object_path = Path::new(&filename);
-    fs::read(object_path).unwrap()
+    // the file is streamed so that large files are never held in memory.
+    let opened = fs::File::open(path).and_then(|f| Ok((f.metadata()?.len(), f)));
+    let (size, file) = match opened {
+        Ok(o) => o,
+        Err(_) => {
+            println!("fatal: could not open '{}' for reading", path);
+            return;
+        }
+    };
+    let mut reader = BufReader::new(file);
+    let hashed = if option.write_object {
+        let repo = match open_repository() {
+            Some(r) => r,
+            None => return,
+        };
+        repo.object_store()
+            .write_stream("blob", size, &mut reader)
+            .map_err(|e| format!("fatal: Unable to add {} to database: {:?}", path, e))
+    } else {
+        hash_stream("blob", size, reader)
+            .map_err(|e| format!("fatal: could not read '{}': {:?}", path, e))
+    };
+    let sha1hash = match hashed {
+        Ok(h) => h,
+        Err(message) => {
+            println!("{}", message);
+            return;
+        }
+    };
+    println!("{}", hash_to_str(&sha1hash));
 }
 
-fn parse_options(commands: &[String]) -> HashObjectOption {
+fn parse_options(commands: &[String]) -> HashObjectOption<'_> {
     let mut option: HashObjectOption = Default::default();
     for token in &commands[2..] {
         match &token[..] {
             "-w" => option.write_object = true,
-            _ => option.filename = Some(&token),
+            _ => option.filename = Some(token),
         }
     }
     option
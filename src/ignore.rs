@@ -0,0 +1,238 @@
+use crate::config::{expand_home, home_dir, Config};
+use crate::utils::wildmatch;
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+// one line of a .gitignore, info/exclude or core.excludesFile.
+#[derive(Debug, Clone, PartialEq)]
+struct IgnorePattern {
+    pattern: String,
+    // the directory of the .gitignore it came from, relative to the top. empty for the
+    // top and for the exclude files.
+    base: String,
+    negated: bool,
+    dir_only: bool,
+    // a pattern with a `/` before its end is matched against the path below `base`,
+    // others against the last component only.
+    anchored: bool,
+}
+
+impl IgnorePattern {
+    fn parse(line: &str, base: &str) -> Option<Self> {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if line.starts_with('#') {
+            return None;
+        }
+        // trailing spaces go unless escaped with a backslash.
+        let mut line = line;
+        while line.ends_with(' ') && !line.ends_with("\\ ") {
+            line = &line[..line.len() - 1];
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let pattern = line.strip_prefix('/').unwrap_or(line);
+        if pattern.is_empty() {
+            return None;
+        }
+        Some(IgnorePattern {
+            pattern: pattern.to_string(),
+            base: base.to_string(),
+            negated,
+            dir_only,
+            anchored,
+        })
+    }
+
+    fn matches(&self, path: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let relative = if self.base.is_empty() {
+            path
+        } else {
+            match path
+                .strip_prefix(self.base.as_str())
+                .and_then(|p| p.strip_prefix('/'))
+            {
+                Some(r) => r,
+                None => return false,
+            }
+        };
+        if self.anchored {
+            wildmatch(&self.pattern, relative, true)
+        } else {
+            let name = relative.rsplit('/').next().unwrap_or(relative);
+            wildmatch(&self.pattern, name, true)
+        }
+    }
+}
+
+fn parse_patterns(text: &str, base: &str) -> Vec<IgnorePattern> {
+    text.lines()
+        .filter_map(|line| IgnorePattern::parse(line, base))
+        .collect()
+}
+
+// the ignore rules of a work tree: the .gitignore in each directory, then
+// .git/info/exclude and then core.excludesFile, the first of them with a matching
+// pattern deciding.
+pub struct IgnoreRules {
+    work_tree: PathBuf,
+    // core.excludesFile first and info/exclude after it, as later patterns win.
+    exclude_files: Vec<IgnorePattern>,
+    // the patterns of each directory's .gitignore, read when first needed.
+    per_directory: HashMap<String, Vec<IgnorePattern>>,
+    // false to not look at any pattern, like `add -f`.
+    enabled: bool,
+}
+
+impl IgnoreRules {
+    pub fn load(work_tree: &Path, git_dir: &Path, config: &Config) -> Self {
+        let excludes_file = match config.get_string("core.excludesfile").ok().flatten() {
+            Some(path) => Some(expand_home(&path)),
+            None => match env::var_os("XDG_CONFIG_HOME").filter(|x| !x.is_empty()) {
+                Some(xdg) => Some(PathBuf::from(xdg).join("git/ignore")),
+                None => home_dir().map(|h| h.join(".config/git/ignore")),
+            },
+        };
+        let mut exclude_files = vec![];
+        for path in excludes_file
+            .into_iter()
+            .chain(Some(git_dir.join("info/exclude")))
+        {
+            if let Ok(text) = fs::read_to_string(path) {
+                exclude_files.extend(parse_patterns(&text, ""));
+            }
+        }
+        IgnoreRules {
+            work_tree: work_tree.to_path_buf(),
+            exclude_files,
+            per_directory: HashMap::new(),
+            enabled: true,
+        }
+    }
+
+    // no rules at all, for when ignoring is turned off.
+    pub fn none(work_tree: &Path) -> Self {
+        IgnoreRules {
+            work_tree: work_tree.to_path_buf(),
+            exclude_files: vec![],
+            per_directory: HashMap::new(),
+            enabled: false,
+        }
+    }
+
+    // whether `path`, relative to the top, is ignored. everything inside an ignored
+    // directory is, whatever its own patterns say.
+    pub fn is_ignored(&mut self, path: &str, is_dir: bool) -> bool {
+        let parents: Vec<usize> = path.match_indices('/').map(|(i, _)| i).collect();
+        parents
+            .into_iter()
+            .any(|i| self.is_excluded(&path[..i], true))
+            || self.is_excluded(path, is_dir)
+    }
+
+    // whether the patterns exclude `path` itself, without looking at its parents. the
+    // .gitignore nearest to it goes first, and within a file the last matching line.
+    pub fn is_excluded(&mut self, path: &str, is_dir: bool) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        let mut dir = path;
+        while let Some(i) = dir.rfind('/') {
+            dir = &dir[..i];
+            if let Some(excluded) = last_match(self.patterns_in(dir), path, is_dir) {
+                return excluded;
+            }
+        }
+        if let Some(excluded) = last_match(self.patterns_in(""), path, is_dir) {
+            return excluded;
+        }
+        last_match(&self.exclude_files, path, is_dir).unwrap_or(false)
+    }
+
+    fn patterns_in(&mut self, dir: &str) -> &[IgnorePattern] {
+        if !self.per_directory.contains_key(dir) {
+            let text =
+                fs::read_to_string(self.work_tree.join(dir).join(".gitignore")).unwrap_or_default();
+            self.per_directory
+                .insert(dir.to_string(), parse_patterns(&text, dir));
+        }
+        &self.per_directory[dir]
+    }
+}
+
+fn last_match(patterns: &[IgnorePattern], path: &str, is_dir: bool) -> Option<bool> {
+    patterns
+        .iter()
+        .rev()
+        .find(|p| p.matches(path, is_dir))
+        .map(|p| !p.negated)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ignore::*;
+    use crate::test_utils::temp_dir;
+
+    #[test]
+    fn test_ignore_rules() {
+        let root = temp_dir("ignore");
+        let git_dir = root.join(".git");
+        fs::create_dir_all(git_dir.join("info")).unwrap();
+        fs::create_dir_all(root.join("src/gen")).unwrap();
+        fs::write(git_dir.join("info/exclude"), "*.swp\n").unwrap();
+        fs::write(
+            root.join(".gitignore"),
+            "# build output\n/target/\n*.log\n!keep.log\n\\#notes\ndoc/*.html\nspace\\ \n",
+        )
+        .unwrap();
+        fs::write(root.join("src/.gitignore"), "gen/\n!debug.log\n*.py[co]\n").unwrap();
+        fs::write(
+            root.join("config"),
+            "[core]\n\texcludesfile = /nonexistent\n",
+        )
+        .unwrap();
+        let config = Config::load_file(&root.join("config"), None).unwrap();
+        let mut rules = IgnoreRules::load(&root, &git_dir, &config);
+
+        for (path, is_dir, ignored) in [
+            ("target", true, true),
+            ("target", false, false),
+            ("src/target", true, false),
+            ("target/debug/app", false, true),
+            ("a.log", false, true),
+            ("src/deep/a.log", false, true),
+            ("keep.log", false, false),
+            ("src/debug.log", false, false),
+            ("debug.log", false, true),
+            ("#notes", false, true),
+            ("doc/a.html", false, true),
+            ("doc/sub/a.html", false, false),
+            ("space ", false, true),
+            ("src/gen", true, true),
+            ("src/gen/x.rs", false, true),
+            ("gen", true, false),
+            ("src/a.pyc", false, true),
+            ("src/a.py", false, false),
+            (".x.swp", false, true),
+            ("src/main.rs", false, false),
+        ] {
+            assert_eq!(rules.is_ignored(path, is_dir), ignored, "{}", path);
+        }
+        // a pattern for a file inside an ignored directory does not bring it back.
+        assert!(!rules.is_excluded("target/debug/app", false));
+        assert!(!IgnoreRules::none(&root).is_ignored("a.log", false));
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
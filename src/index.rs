@@ -0,0 +1,798 @@
+use crate::cache_tree::CacheTree;
+use crate::git_object::*;
+use crate::lockfile::LockFile;
+use crate::utils::*;
+use std::{
+    env, fs, io,
+    ops::{Deref, DerefMut, Range},
+    path::{Path, PathBuf},
+};
+
+type Result<T> = std::result::Result<T, GitObjectError>;
+
+const INDEX_SIGNATURE: &[u8; 4] = b"DIRC";
+pub const DEFAULT_INDEX_VERSION: u32 = 2;
+
+const FLAG_ASSUME_VALID: u16 = 0x8000;
+const FLAG_EXTENDED: u16 = 0x4000;
+const FLAG_STAGE_MASK: u16 = 0x3000;
+const FLAG_NAME_MASK: u16 = 0x0fff;
+const EXTENDED_SKIP_WORKTREE: u16 = 0x4000;
+const EXTENDED_INTENT_TO_ADD: u16 = 0x2000;
+// ctime, mtime, dev, ino, mode, uid, gid, size, id and flags.
+const ENTRY_FIXED_LEN: usize = 62;
+
+// the parts of a file's stat data git compares to notice that it changed. everything is
+// truncated to 32 bits, as in the file.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct StatData {
+    pub ctime: u32,
+    pub ctime_nsec: u32,
+    pub mtime: u32,
+    pub mtime_nsec: u32,
+    pub dev: u32,
+    pub ino: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u32,
+}
+
+impl StatData {
+    #[cfg(unix)]
+    pub fn from_metadata(metadata: &fs::Metadata) -> Self {
+        use std::os::unix::fs::MetadataExt;
+        StatData {
+            ctime: metadata.ctime() as u32,
+            ctime_nsec: metadata.ctime_nsec() as u32,
+            mtime: metadata.mtime() as u32,
+            mtime_nsec: metadata.mtime_nsec() as u32,
+            dev: metadata.dev() as u32,
+            ino: metadata.ino() as u32,
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+            size: metadata.len() as u32,
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub fn from_metadata(metadata: &fs::Metadata) -> Self {
+        let since_epoch = |t: io::Result<std::time::SystemTime>| {
+            t.ok()
+                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                .unwrap_or_default()
+        };
+        let mtime = since_epoch(metadata.modified());
+        let ctime = since_epoch(metadata.created());
+        StatData {
+            ctime: ctime.as_secs() as u32,
+            ctime_nsec: ctime.subsec_nanos(),
+            mtime: mtime.as_secs() as u32,
+            mtime_nsec: mtime.subsec_nanos(),
+            size: metadata.len() as u32,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexEntry {
+    pub stat: StatData,
+    pub mode: FileMode,
+    pub id: Vec<u8>,
+    // 0 for a normal entry, 1 to 3 for the base, ours and theirs of a conflict.
+    pub stage: u8,
+    pub assume_valid: bool,
+    pub skip_worktree: bool,
+    pub intent_to_add: bool,
+    // relative to the top of the work tree, with `/` between components.
+    pub path: String,
+}
+
+impl IndexEntry {
+    pub fn new(path: &str, id: &[u8], mode: FileMode) -> Self {
+        IndexEntry {
+            stat: Default::default(),
+            mode,
+            id: id.to_vec(),
+            stage: 0,
+            assume_valid: false,
+            skip_worktree: false,
+            intent_to_add: false,
+            path: path.to_string(),
+        }
+    }
+
+    // extended flags need an index of version 3 or later.
+    fn is_extended(&self) -> bool {
+        self.skip_worktree || self.intent_to_add
+    }
+}
+
+// an optional extension kept as it was read. only the resolve undo data (REUC) is kept;
+// the cache tree (TREE) is parsed and the rest are caches git rebuilds when missing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexExtension {
+    pub signature: [u8; 4],
+    pub data: Vec<u8>,
+}
+
+// the staging area, `.git/index`. entries are kept sorted by path and then by stage.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Index {
+    pub version: u32,
+    pub entries: Vec<IndexEntry>,
+    pub cache_tree: Option<CacheTree>,
+    pub extensions: Vec<IndexExtension>,
+}
+
+impl Default for Index {
+    fn default() -> Self {
+        Index::new()
+    }
+}
+
+impl Index {
+    pub fn new() -> Self {
+        Index {
+            version: DEFAULT_INDEX_VERSION,
+            entries: vec![],
+            cache_tree: None,
+            extensions: vec![],
+        }
+    }
+
+    // a missing file is an empty index.
+    pub fn read_from(path: &Path) -> Result<Self> {
+        match fs::read(path) {
+            Ok(data) => Index::parse(&data),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Index::new()),
+            Err(e) => Err(GitObjectError::IOError(e)),
+        }
+    }
+
+    pub fn write_to(&self, path: &Path) -> Result<()> {
+        let mut lock = LockFile::acquire(path)?;
+        lock.write_all(&self.to_bytes())?;
+        lock.commit()
+    }
+
+    // reads the index while holding `index.lock`, for a command that changes it.
+    pub fn lock(path: &Path) -> Result<LockedIndex> {
+        let lock = LockFile::acquire(path)?;
+        let index = Index::read_from(path)?;
+        Ok(LockedIndex {
+            index,
+            lock: Some(lock),
+        })
+    }
+
+    // `lock` for commands, with a failure as the message they print. an index that
+    // can not be read is not reported as corrupt.
+    pub fn lock_or_report(path: &Path) -> std::result::Result<LockedIndex, String> {
+        let lock = match LockFile::acquire(path) {
+            Ok(l) => l,
+            Err(GitObjectError::IOError(e)) => {
+                return Err(format!(
+                    "fatal: unable to create '{}.lock': {}",
+                    path.display(),
+                    e
+                ))
+            }
+            Err(GitObjectError::WriteError(message)) => return Err(format!("fatal: {}", message)),
+            Err(e) => return Err(format!("fatal: {:?}", e)),
+        };
+        match Index::read_from(path) {
+            Ok(index) => Ok(LockedIndex {
+                index,
+                lock: Some(lock),
+            }),
+            Err(GitObjectError::IOError(e)) => {
+                Err(format!("fatal: unable to read '{}': {}", path.display(), e))
+            }
+            Err(GitObjectError::NotImplementError(message)) => Err(format!("fatal: {}", message)),
+            Err(e) => Err(format!("fatal: index file corrupt: {:?}", e)),
+        }
+    }
+
+    // like `lock`, but when the lock can not be taken the index is read without it
+    // and committing writes nothing. for commands that only refresh stat data or
+    // cached trees, which git also skips when the index is busy.
+    pub fn lock_if_free(path: &Path) -> Result<LockedIndex> {
+        match Index::lock(path) {
+            Ok(locked) => Ok(locked),
+            // the lock file already exists; any other failure is reported.
+            Err(GitObjectError::WriteError(_)) => Ok(LockedIndex {
+                index: Index::read_from(path)?,
+                lock: None,
+            }),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        if data.len() < 12 + 20 || &data[..4] != INDEX_SIGNATURE {
+            return Err(corrupt("bad signature"));
+        }
+        let (content, checksum) = data.split_at(data.len() - 20);
+        // index.skipHash leaves the checksum zeroed.
+        if checksum.iter().any(|b| *b != 0) && hash(content) != checksum {
+            return Err(corrupt("bad index file sha1 signature"));
+        }
+        let version = be_u32(&content[4..]);
+        if !(2..=4).contains(&version) {
+            return Err(corrupt(&format!("bad index version {}", version)));
+        }
+        let count = be_u32(&content[8..]) as usize;
+
+        let mut entries = Vec::with_capacity(count.min(content.len() / ENTRY_FIXED_LEN));
+        let mut pos = 12;
+        let mut previous_path: Vec<u8> = vec![];
+        for _ in 0..count {
+            let (entry, len) = parse_entry(&content[pos..], version, &previous_path)?;
+            previous_path = entry.path.as_bytes().to_vec();
+            entries.push(entry);
+            pos += len;
+        }
+
+        let mut cache_tree = None;
+        let mut extensions = vec![];
+        while pos < content.len() {
+            if content.len() - pos < 8 {
+                return Err(corrupt("truncated extension"));
+            }
+            let signature = [
+                content[pos],
+                content[pos + 1],
+                content[pos + 2],
+                content[pos + 3],
+            ];
+            let size = be_u32(&content[pos + 4..]) as usize;
+            let data = content
+                .get(pos + 8..pos + 8 + size)
+                .ok_or_else(|| corrupt("truncated extension"))?;
+            // extensions whose name does not start with a capital letter must be understood.
+            if !signature[0].is_ascii_uppercase() {
+                return Err(corrupt(&format!(
+                    "index uses {} extension, which we do not understand",
+                    String::from_utf8_lossy(&signature)
+                )));
+            }
+            if &signature == b"TREE" {
+                cache_tree = Some(CacheTree::parse(data)?);
+            } else if &signature == b"REUC" {
+                extensions.push(IndexExtension {
+                    signature,
+                    data: data.to_vec(),
+                });
+            }
+            pos += 8 + size;
+        }
+
+        let index = Index {
+            version,
+            entries,
+            cache_tree,
+            extensions,
+        };
+        if index
+            .entries
+            .windows(2)
+            .any(|w| entry_cmp(&w[0], &w[1]) != std::cmp::Ordering::Less)
+        {
+            return Err(corrupt("unordered stage entries in index"));
+        }
+        Ok(index)
+    }
+
+    // version 2 and 3 are picked by whether any entry needs extended flags, like git.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let extended = self.entries.iter().any(|e| e.is_extended());
+        let version = match self.version {
+            2 | 3 if extended => 3,
+            2 | 3 => 2,
+            v => v,
+        };
+        let mut data = INDEX_SIGNATURE.to_vec();
+        data.extend_from_slice(&version.to_be_bytes());
+        data.extend_from_slice(&(self.entries.len() as u32).to_be_bytes());
+        let mut previous_path: &[u8] = &[];
+        for entry in &self.entries {
+            write_entry(&mut data, entry, version, previous_path);
+            previous_path = entry.path.as_bytes();
+        }
+        if let Some(tree) = &self.cache_tree {
+            let tree = tree.to_bytes();
+            data.extend_from_slice(b"TREE");
+            data.extend_from_slice(&(tree.len() as u32).to_be_bytes());
+            data.extend_from_slice(&tree);
+        }
+        for extension in &self.extensions {
+            data.extend_from_slice(&extension.signature);
+            data.extend_from_slice(&(extension.data.len() as u32).to_be_bytes());
+            data.extend_from_slice(&extension.data);
+        }
+        let checksum = hash(&data);
+        data.extend_from_slice(&checksum);
+        data
+    }
+
+    // where the entry for `path` at `stage` is, or where it would go.
+    pub fn position(&self, path: &str, stage: u8) -> std::result::Result<usize, usize> {
+        self.entries.binary_search_by(|e| {
+            e.path
+                .as_bytes()
+                .cmp(path.as_bytes())
+                .then(e.stage.cmp(&stage))
+        })
+    }
+
+    pub fn get(&self, path: &str, stage: u8) -> Option<&IndexEntry> {
+        self.position(path, stage).ok().map(|i| &self.entries[i])
+    }
+
+    pub fn get_mut(&mut self, path: &str, stage: u8) -> Option<&mut IndexEntry> {
+        match self.position(path, stage) {
+            Ok(i) => Some(&mut self.entries[i]),
+            Err(_) => None,
+        }
+    }
+
+    // every stage recorded for `path`; more than one when it is unmerged.
+    pub fn stages(&self, path: &str) -> &[IndexEntry] {
+        &self.entries[self.path_range(path)]
+    }
+
+    // adds or replaces the entry at its path and stage. a stage 0 entry resolves a
+    // conflict, so the other stages of the path go. a file also replaces the entries
+    // below a directory of its name and those where its parent directories go.
+    pub fn add(&mut self, entry: IndexEntry) {
+        self.invalidate_cache_tree(&entry.path);
+        for (i, _) in entry.path.match_indices('/') {
+            let parent = self.path_range(&entry.path[..i]);
+            self.entries.drain(parent);
+        }
+        let below = format!("{}/", entry.path);
+        let start = self.position(&below, 0).unwrap_or_else(|i| i);
+        let len = self.entries[start..]
+            .iter()
+            .take_while(|e| e.path.starts_with(&below))
+            .count();
+        self.entries.drain(start..start + len);
+        if entry.stage == 0 {
+            let stages = self.path_range(&entry.path);
+            self.entries.drain(stages);
+        }
+        match self.position(&entry.path, entry.stage) {
+            Ok(i) => self.entries[i] = entry,
+            Err(i) => self.entries.insert(i, entry),
+        }
+    }
+
+    // removes every stage of `path`, returning whether there was any.
+    pub fn remove(&mut self, path: &str) -> bool {
+        let before = self.entries.len();
+        self.entries.retain(|e| e.path != path);
+        let removed = self.entries.len() != before;
+        if removed {
+            self.invalidate_cache_tree(path);
+        }
+        removed
+    }
+
+    pub fn has_conflicts(&self) -> bool {
+        self.entries.iter().any(|e| e.stage != 0)
+    }
+
+    fn path_range(&self, path: &str) -> Range<usize> {
+        let start = self.position(path, 0).unwrap_or_else(|i| i);
+        let len = self.entries[start..]
+            .iter()
+            .take_while(|e| e.path == path)
+            .count();
+        start..start + len
+    }
+
+    // the cached trees containing `path` no longer match once its entry changes.
+    fn invalidate_cache_tree(&mut self, path: &str) {
+        if let Some(tree) = &mut self.cache_tree {
+            tree.invalidate(path);
+        }
+    }
+}
+
+// an index read under its lock. changes are made through it and written by `commit`;
+// dropping it releases the lock and leaves the file as it was.
+pub struct LockedIndex {
+    index: Index,
+    lock: Option<LockFile>,
+}
+
+impl LockedIndex {
+    pub fn commit(self) -> Result<()> {
+        match self.lock {
+            Some(mut lock) => {
+                lock.write_all(&self.index.to_bytes())?;
+                lock.commit()
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl Deref for LockedIndex {
+    type Target = Index;
+
+    fn deref(&self) -> &Index {
+        &self.index
+    }
+}
+
+impl DerefMut for LockedIndex {
+    fn deref_mut(&mut self) -> &mut Index {
+        &mut self.index
+    }
+}
+
+// $GIT_INDEX_FILE, or the index in the git dir.
+pub fn index_path(git_dir: &Path) -> PathBuf {
+    match env::var_os("GIT_INDEX_FILE") {
+        Some(path) => PathBuf::from(path),
+        None => git_dir.join("index"),
+    }
+}
+
+// entries sort by path bytes and then by stage.
+fn entry_cmp(a: &IndexEntry, b: &IndexEntry) -> std::cmp::Ordering {
+    a.path
+        .as_bytes()
+        .cmp(b.path.as_bytes())
+        .then(a.stage.cmp(&b.stage))
+}
+
+// returns the entry and the bytes it took.
+fn parse_entry(data: &[u8], version: u32, previous_path: &[u8]) -> Result<(IndexEntry, usize)> {
+    if data.len() < ENTRY_FIXED_LEN {
+        return Err(corrupt("truncated entry"));
+    }
+    let field = |i: usize| be_u32(&data[i * 4..]);
+    let stat = StatData {
+        ctime: field(0),
+        ctime_nsec: field(1),
+        mtime: field(2),
+        mtime_nsec: field(3),
+        dev: field(4),
+        ino: field(5),
+        uid: field(7),
+        gid: field(8),
+        size: field(9),
+    };
+    let mode = FileMode::from_octal(field(6))
+        .ok_or_else(|| corrupt(&format!("bad mode {:o}", field(6))))?;
+    let id = data[40..60].to_vec();
+    let flags = be_u16(&data[60..]);
+    let mut pos = ENTRY_FIXED_LEN;
+
+    let mut extended_flags = 0;
+    if flags & FLAG_EXTENDED != 0 {
+        if version < 3 {
+            return Err(corrupt("extended flags in a version 2 index"));
+        }
+        extended_flags = be_u16(
+            data.get(pos..pos + 2)
+                .ok_or_else(|| corrupt("truncated entry"))?,
+        );
+        if extended_flags & !(EXTENDED_SKIP_WORKTREE | EXTENDED_INTENT_TO_ADD) != 0 {
+            return Err(corrupt(&format!(
+                "unknown index entry format 0x{:04x}",
+                extended_flags
+            )));
+        }
+        pos += 2;
+    }
+
+    let path = if version == 4 {
+        // the number of bytes to drop from the end of the previous path, then the rest of
+        // this one up to a NUL, with no padding after it.
+        let (strip, len) = decode_varint(&data[pos..]).ok_or_else(|| corrupt("bad path prefix"))?;
+        pos += len;
+        let keep = previous_path
+            .len()
+            .checked_sub(strip as usize)
+            .ok_or_else(|| corrupt("bad path prefix"))?;
+        let end = pos + nul_position(&data[pos..])?;
+        let mut path = previous_path[..keep].to_vec();
+        path.extend_from_slice(&data[pos..end]);
+        pos = end + 1;
+        path
+    } else {
+        // the name length in the flags is only usable below its maximum.
+        let name_len = (flags & FLAG_NAME_MASK) as usize;
+        let end = if name_len < FLAG_NAME_MASK as usize {
+            pos + name_len
+        } else {
+            pos + nul_position(&data[pos..])?
+        };
+        let path = data
+            .get(pos..end)
+            .ok_or_else(|| corrupt("truncated entry"))?
+            .to_vec();
+        // padded with 1 to 8 NULs to a multiple of 8 bytes.
+        pos = (end + 8) & !7;
+        if pos > data.len() {
+            return Err(corrupt("truncated entry"));
+        }
+        path
+    };
+    // the index itself is fine, but such a path can not be represented here.
+    let path = String::from_utf8(path).map_err(|e| {
+        GitObjectError::NotImplementError(format!(
+            "path is not valid UTF-8: {}",
+            e.as_bytes().escape_ascii()
+        ))
+    })?;
+    Ok((
+        IndexEntry {
+            stat,
+            mode,
+            id,
+            stage: ((flags & FLAG_STAGE_MASK) >> 12) as u8,
+            assume_valid: flags & FLAG_ASSUME_VALID != 0,
+            skip_worktree: extended_flags & EXTENDED_SKIP_WORKTREE != 0,
+            intent_to_add: extended_flags & EXTENDED_INTENT_TO_ADD != 0,
+            path,
+        },
+        pos,
+    ))
+}
+
+fn write_entry(data: &mut Vec<u8>, entry: &IndexEntry, version: u32, previous_path: &[u8]) {
+    let start = data.len();
+    let stat = &entry.stat;
+    for field in [
+        stat.ctime,
+        stat.ctime_nsec,
+        stat.mtime,
+        stat.mtime_nsec,
+        stat.dev,
+        stat.ino,
+        entry.mode.to_octal(),
+        stat.uid,
+        stat.gid,
+        stat.size,
+    ] {
+        data.extend_from_slice(&field.to_be_bytes());
+    }
+    data.extend_from_slice(&entry.id);
+
+    let path = entry.path.as_bytes();
+    let mut flags = (path.len().min(FLAG_NAME_MASK as usize) as u16) | ((entry.stage as u16) << 12);
+    if entry.assume_valid {
+        flags |= FLAG_ASSUME_VALID;
+    }
+    if entry.is_extended() {
+        flags |= FLAG_EXTENDED;
+    }
+    data.extend_from_slice(&flags.to_be_bytes());
+    if entry.is_extended() {
+        let mut extended_flags = 0u16;
+        if entry.skip_worktree {
+            extended_flags |= EXTENDED_SKIP_WORKTREE;
+        }
+        if entry.intent_to_add {
+            extended_flags |= EXTENDED_INTENT_TO_ADD;
+        }
+        data.extend_from_slice(&extended_flags.to_be_bytes());
+    }
+
+    if version == 4 {
+        let common = previous_path
+            .iter()
+            .zip(path)
+            .take_while(|(a, b)| a == b)
+            .count();
+        data.extend_from_slice(&encode_varint((previous_path.len() - common) as u64));
+        data.extend_from_slice(&path[common..]);
+        data.push(0);
+    } else {
+        data.extend_from_slice(path);
+        let len = data.len() - start;
+        data.resize(start + ((len + 8) & !7), 0);
+    }
+}
+
+fn nul_position(data: &[u8]) -> Result<usize> {
+    data.iter()
+        .position(|b| *b == 0)
+        .ok_or_else(|| corrupt("unterminated path"))
+}
+
+fn be_u32(b: &[u8]) -> u32 {
+    u32::from_be_bytes([b[0], b[1], b[2], b[3]])
+}
+
+fn be_u16(b: &[u8]) -> u16 {
+    u16::from_be_bytes([b[0], b[1]])
+}
+
+fn corrupt(message: &str) -> GitObjectError {
+    GitObjectError::ParseError(format!("index file corrupt: {}", message))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::index::*;
+    use crate::test_utils::temp_dir;
+
+    fn sample_index(version: u32) -> Index {
+        let mut index = Index::new();
+        index.version = version;
+        for (i, path) in ["src/main.rs", "README.md", "src/lib.rs", "src/index/mod.rs"]
+            .iter()
+            .enumerate()
+        {
+            let mut entry = IndexEntry::new(path, &[i as u8; 20], FileMode::Regular);
+            entry.stat.mtime = 1_600_000_000 + i as u32;
+            entry.stat.size = 10 * i as u32;
+            index.add(entry);
+        }
+        let mut long = IndexEntry::new(&"x/".repeat(2100), &[9; 20], FileMode::Executable);
+        long.assume_valid = true;
+        index.add(long);
+        index
+    }
+
+    #[test]
+    fn test_index_round_trip() {
+        for version in 2..=4 {
+            let index = sample_index(version);
+            let paths: Vec<&str> = index.entries.iter().map(|e| e.path.as_str()).collect();
+            assert_eq!(
+                &paths[..4],
+                &["README.md", "src/index/mod.rs", "src/lib.rs", "src/main.rs"]
+            );
+            let data = index.to_bytes();
+            let parsed = Index::parse(&data).unwrap();
+            assert_eq!(parsed.entries, index.entries, "version {}", version);
+            // without extended flags version 3 is written as 2.
+            assert_eq!(parsed.version, if version == 3 { 2 } else { version });
+            if version != 4 {
+                // every entry is padded to a multiple of 8 bytes.
+                assert_eq!(data.len() % 8, (12 + 20) % 8);
+            }
+        }
+        // version 4 shares path prefixes with the previous entry.
+        assert!(sample_index(4).to_bytes().len() < sample_index(2).to_bytes().len());
+
+        // extended flags make a version 2 index version 3.
+        let mut index = sample_index(2);
+        index.get_mut("src/lib.rs", 0).unwrap().intent_to_add = true;
+        let parsed = Index::parse(&index.to_bytes()).unwrap();
+        assert_eq!(parsed.version, 3);
+        assert!(parsed.get("src/lib.rs", 0).unwrap().intent_to_add);
+        assert!(!parsed.get("src/main.rs", 0).unwrap().skip_worktree);
+
+        let mut data = index.to_bytes();
+        let len = data.len();
+        data[len - 1] ^= 1;
+        assert!(Index::parse(&data).is_err());
+        assert!(Index::parse(b"DIRC").is_err());
+
+        // a path that is not utf-8 is named in the error.
+        let mut data = sample_index(2).to_bytes();
+        let at = data.windows(9).position(|w| w == b"README.md").unwrap();
+        data[at] = 0xff;
+        let len = data.len();
+        data[len - 20..].fill(0);
+        match Index::parse(&data) {
+            Err(GitObjectError::NotImplementError(m)) => assert!(m.ends_with("\\xffEADME.md")),
+            r => panic!("{:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_index_stages() {
+        let mut index = sample_index(2);
+        for stage in [3, 1, 2] {
+            let mut entry = IndexEntry::new("conflict", &[stage; 20], FileMode::Regular);
+            entry.stage = stage;
+            index.add(entry);
+        }
+        assert!(index.has_conflicts());
+        let stages: Vec<u8> = index.stages("conflict").iter().map(|e| e.stage).collect();
+        assert_eq!(stages, vec![1, 2, 3]);
+        assert_eq!(index.get("conflict", 2).unwrap().id, vec![2; 20]);
+        assert!(index.get("conflict", 0).is_none());
+        assert!(index.stages("missing").is_empty());
+
+        let parsed = Index::parse(&index.to_bytes()).unwrap();
+        assert_eq!(parsed.stages("conflict").len(), 3);
+
+        // staging the resolution drops the conflict.
+        index.add(IndexEntry::new("conflict", &[7; 20], FileMode::Regular));
+        assert!(!index.has_conflicts());
+        assert_eq!(index.stages("conflict").len(), 1);
+        assert!(index.remove("conflict"));
+        assert!(!index.remove("conflict"));
+        assert!(index.get("src/lib.rs", 0).is_some());
+
+        // files and directories of the same name replace each other.
+        index.add(IndexEntry::new("src", &[8; 20], FileMode::Regular));
+        let paths: Vec<&str> = index.entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(&paths[..2], &["README.md", "src"]);
+        index.add(IndexEntry::new("src/a/b", &[8; 20], FileMode::Regular));
+        assert!(index.get("src", 0).is_none());
+        assert!(index.get("src/a/b", 0).is_some());
+    }
+
+    #[test]
+    fn test_index_file() {
+        let dir = temp_dir("index");
+        let path = dir.join("index");
+        assert_eq!(Index::read_from(&path).unwrap(), Index::new());
+
+        let mut index = sample_index(2);
+        index.extensions.push(IndexExtension {
+            signature: *b"REUC",
+            data: b"data".to_vec(),
+        });
+        let mut tree = CacheTree::new();
+        tree.entry_count = Some(3);
+        tree.id = vec![9; 20];
+        tree.children.push((String::from("src"), CacheTree::new()));
+        index.cache_tree = Some(tree);
+        index.write_to(&path).unwrap();
+        assert_eq!(Index::read_from(&path).unwrap(), index);
+        // changing an entry forgets the cached trees above it.
+        index.add(IndexEntry::new("src/new.rs", &[1; 20], FileMode::Regular));
+        let tree = index.cache_tree.as_ref().unwrap();
+        assert_eq!((tree.entry_count, tree.children.len()), (None, 1));
+        assert!(!dir.join("index.lock").exists());
+
+        // changes made under the lock are written on commit, and only then.
+        let mut locked = Index::lock(&path).unwrap();
+        assert!(Index::lock(&path).is_err());
+        locked.remove("README.md");
+        let mut busy = Index::lock_if_free(&path).unwrap();
+        busy.entries.clear();
+        busy.commit().unwrap();
+        assert_eq!(Index::read_from(&path).unwrap().entries.len(), 5);
+        // only a lock that is already taken counts as busy.
+        assert!(Index::lock_if_free(&dir.join("missing/index")).is_err());
+        locked.commit().unwrap();
+        assert_eq!(Index::read_from(&path).unwrap().entries.len(), 4);
+        let mut dropped = Index::lock(&path).unwrap();
+        dropped.entries.clear();
+        drop(dropped);
+        assert_eq!(Index::read_from(&path).unwrap().entries.len(), 4);
+        assert!(!dir.join("index.lock").exists());
+
+        // commands are told why the index could not be locked and read.
+        let held = Index::lock(&path).unwrap();
+        let message = Index::lock_or_report(&path).err().unwrap();
+        assert!(
+            message.starts_with("fatal: unable to create"),
+            "{}",
+            message
+        );
+        drop(held);
+        assert!(Index::lock_or_report(&path).is_ok());
+        let unreadable = dir.join("unreadable");
+        fs::create_dir_all(&unreadable).unwrap();
+        let message = Index::lock_or_report(&unreadable).err().unwrap();
+        assert!(message.starts_with("fatal: unable to read"), "{}", message);
+        let corrupt = dir.join("corrupt");
+        fs::write(&corrupt, "not an index").unwrap();
+        let message = Index::lock_or_report(&corrupt).err().unwrap();
+        assert!(
+            message.starts_with("fatal: index file corrupt"),
+            "{}",
+            message
+        );
+
+        fs::write(dir.join("file"), "content").unwrap();
+        let stat = StatData::from_metadata(&fs::metadata(dir.join("file")).unwrap());
+        assert_eq!(stat.size, 7);
+        assert!(stat.mtime > 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,697 @@
+use crate::delta::apply_delta;
+use crate::git_object::GitObjectError;
+use crate::object_store::ObjectStore;
+use crate::object_stream::hash_stream;
+use crate::pack::*;
+use crate::repository::open_repository;
+use crate::utils::*;
+use flate2::bufread::ZlibDecoder;
+use sha1::{Digest, Sha1};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    io::{self, BufRead, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    process,
+};
+
+type Result<T> = std::result::Result<T, GitObjectError>;
+
+#[derive(Default)]
+struct IndexPackOption<'a> {
+    stdin: bool,
+    fix_thin: bool,
+    index_file: Option<&'a String>,
+    pack_file: Option<&'a String>,
+}
+
+pub fn index_pack(commands: &[String]) {
+    let option = parse_options(commands);
+    if option.fix_thin && !option.stdin {
+        println!("fatal: --fix-thin cannot be used without --stdin");
+        return;
+    }
+
+    // only packs read from stdin go into the repository; a pack file is indexed in place.
+    let result = if option.stdin {
+        let repo = match open_repository() {
+            Some(r) => r,
+            None => return,
+        };
+        let store = repo.object_store();
+        let bases: Option<&dyn ObjectStore> = if option.fix_thin { Some(&store) } else { None };
+        let pack_path = option.pack_file.map(PathBuf::from);
+        let idx_path = option.index_file.map(PathBuf::from);
+        store_pack(
+            &repo.objects_dir(),
+            &mut io::stdin().lock(),
+            pack_path.as_deref(),
+            idx_path.as_deref(),
+            bases,
+        )
+    } else {
+        let pack_path = match option.pack_file {
+            Some(p) => PathBuf::from(p),
+            None => {
+                println!("usage: index-pack [-o <index-file>] <pack-file>");
+                println!("       index-pack --stdin [--fix-thin] [-o <index-file>] [<pack-file>]");
+                return;
+            }
+        };
+        let idx_path = option.index_file.map(PathBuf::from);
+        index_pack_file(&pack_path, idx_path.as_deref())
+    };
+
+    match result {
+        Ok(checksum) if option.stdin => println!("pack\t{}", hash_to_str(&checksum)),
+        Ok(checksum) => println!("{}", hash_to_str(&checksum)),
+        Err(e) => println!("fatal: {:?}", e),
+    }
+}
+
+// the version 2 index of a pack, with what was learned while building it.
+pub struct IndexedPack {
+    pub index: Vec<u8>,
+    pub checksum: Vec<u8>,
+    pub object_count: usize,
+    // how many bases were appended to complete a thin pack.
+    pub appended_bases: usize,
+}
+
+impl IndexedPack {
+    // written after the pack, so that the index never refers to a missing pack.
+    pub fn write_index(&self, idx_path: &Path) -> Result<()> {
+        write_file(idx_path, &self.index)
+    }
+}
+
+// one entry as found while scanning the pack; the id of a delta is only known once it
+// has been resolved.
+struct Entry {
+    offset: u64,
+    base: Option<DeltaBase>,
+    crc32: u32,
+    hash: Option<Vec<u8>>,
+}
+
+// indexes the pack file at `pack_path`. the entries are scanned one at a time to find
+// their offsets and crc32s and the id of every whole object, and the trailing checksum
+// is verified; deltas are then resolved by applying them to their inflated bases.
+// REF_DELTA bases missing from the pack are taken from `bases` and appended to the file
+// when given (fixing a thin pack in place); otherwise they are an error.
+pub fn build_index(pack_path: &Path, bases: Option<&dyn ObjectStore>) -> Result<IndexedPack> {
+    let (mut entries, mut checksum) = scan_pack(io::BufReader::new(fs::File::open(pack_path)?))?;
+    let mut end = fs::metadata(pack_path)?.len() - 20;
+
+    let mut appended_bases = 0;
+    loop {
+        let unresolved = resolve_deltas(pack_path, &mut entries, &checksum)?;
+        if unresolved == 0 {
+            break;
+        }
+        // bases referenced by id that are in no entry of the pack.
+        let resolved: HashSet<&Vec<u8>> = entries.iter().filter_map(|e| e.hash.as_ref()).collect();
+        let mut missing: Vec<Vec<u8>> = entries
+            .iter()
+            .filter(|e| e.hash.is_none())
+            .filter_map(|e| match &e.base {
+                Some(DeltaBase::Ref(h)) if !resolved.contains(h) => Some(h.clone()),
+                _ => None,
+            })
+            .collect();
+        missing.sort();
+        missing.dedup();
+        let available: Vec<Vec<u8>> = match bases {
+            Some(store) => missing
+                .iter()
+                .filter(|h| store.contains(&hash_to_str(h)))
+                .cloned()
+                .collect(),
+            None => vec![],
+        };
+        if available.is_empty() {
+            return Err(match missing.first() {
+                Some(h) => GitObjectError::NotFoundError(format!(
+                    "pack has {} unresolved deltas; base {} is missing",
+                    unresolved,
+                    hash_to_str(h)
+                )),
+                None => {
+                    GitObjectError::ParseError(format!("pack has {} unresolved deltas", unresolved))
+                }
+            });
+        }
+        end = append_bases(pack_path, end, bases.unwrap(), &available, &mut entries)?;
+        appended_bases += available.len();
+    }
+    if appended_bases > 0 {
+        checksum = finish_appended_pack(pack_path, end, entries.len() as u32)?;
+    }
+
+    let mut records: Vec<IndexEntry> = entries
+        .into_iter()
+        .map(|e| IndexEntry {
+            hash: e.hash.unwrap(),
+            crc32: e.crc32,
+            offset: e.offset,
+        })
+        .collect();
+    let index = encode_pack_index(&mut records, &checksum);
+    Ok(IndexedPack {
+        index,
+        checksum,
+        object_count: records.len(),
+        appended_bases,
+    })
+}
+
+// writes the index for the pack file at `pack_path` (next to it unless `idx_path` is
+// given) and returns the pack checksum.
+pub fn index_pack_file(pack_path: &Path, idx_path: Option<&Path>) -> Result<Vec<u8>> {
+    let indexed = build_index(pack_path, None)?;
+    let default_idx = pack_path.with_extension("idx");
+    write_file(idx_path.unwrap_or(&default_idx), &indexed.index)?;
+    Ok(indexed.checksum)
+}
+
+// stores a pack read from `input`, as objects/pack/pack-<checksum>.pack unless a path
+// is given, and writes its index; returns the pack checksum. the pack is received into
+// a temporary file first, as its name depends on its content.
+pub fn store_pack(
+    objects_dir: &Path,
+    input: &mut dyn Read,
+    pack_path: Option<&Path>,
+    idx_path: Option<&Path>,
+    bases: Option<&dyn ObjectStore>,
+) -> Result<Vec<u8>> {
+    let pack_dir = objects_dir.join("pack");
+    fs::create_dir_all(&pack_dir)?;
+    let tmp_path = pack_dir.join(format!("tmp_pack_{}", process::id()));
+    let indexed = match receive_pack(&tmp_path, input, bases) {
+        Ok(i) => i,
+        Err(e) => {
+            let _ = fs::remove_file(&tmp_path);
+            return Err(e);
+        }
+    };
+    let pack_path = match pack_path {
+        Some(p) => p.to_path_buf(),
+        None => pack_dir.join(format!("pack-{}.pack", hash_to_str(&indexed.checksum))),
+    };
+    let idx_path = match idx_path {
+        Some(p) => p.to_path_buf(),
+        None => pack_path.with_extension("idx"),
+    };
+    if let Some(dir) = pack_path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    fs::rename(&tmp_path, &pack_path)?;
+    indexed.write_index(&idx_path)?;
+    Ok(indexed.checksum)
+}
+
+fn receive_pack(
+    path: &Path,
+    input: &mut dyn Read,
+    bases: Option<&dyn ObjectStore>,
+) -> Result<IndexedPack> {
+    let mut file = fs::File::create(path)?;
+    io::copy(input, &mut file)?;
+    file.sync_all()?;
+    drop(file);
+    build_index(path, bases)
+}
+
+// replaces `path` atomically through a temporary file in the same directory.
+fn write_file(path: &Path, data: &[u8]) -> Result<()> {
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(format!(".tmp{}", std::process::id()));
+    fs::write(&tmp, data)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+// reads a pack from its start, keeping the offset of what was consumed, the checksum
+// of all of it and the crc32 of the current entry.
+struct PackScanner<R: BufRead> {
+    inner: R,
+    hasher: Sha1,
+    offset: u64,
+    crc: u32,
+}
+
+impl<R: BufRead> Read for PackScanner<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for PackScanner<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        // the data to consume is still buffered, so this reads nothing.
+        if let Ok(buf) = self.inner.fill_buf() {
+            let taken = &buf[..amt.min(buf.len())];
+            self.hasher.update(taken);
+            self.crc = crc32_update(self.crc, taken);
+        }
+        self.offset += amt as u64;
+        self.inner.consume(amt);
+    }
+}
+
+// reads every entry of the pack in `reader` without keeping its data: whole objects are
+// hashed as they are inflated and deltas only checked to inflate to their size. returns
+// the entries and the pack checksum.
+fn scan_pack<R: BufRead>(reader: R) -> Result<(Vec<Entry>, Vec<u8>)> {
+    let mut scanner = PackScanner {
+        inner: reader,
+        hasher: Sha1::new(),
+        offset: 0,
+        crc: 0,
+    };
+    let mut header = [0; 12];
+    scanner
+        .read_exact(&mut header)
+        .map_err(|_| GitObjectError::ParseError(String::from("not a pack: bad signature")))?;
+    let count = check_pack_header(&header)?;
+
+    let mut entries = Vec::with_capacity(count.min(1 << 20));
+    for _ in 0..count {
+        let offset = scanner.offset;
+        scanner.crc = 0;
+        let header = read_entry_header(&mut scanner, offset)?;
+        let bad_size = || {
+            GitObjectError::ParseError(format!(
+                "pack entry at {} does not inflate to its recorded size {}",
+                offset, header.size
+            ))
+        };
+        let mut decoder = ZlibDecoder::new(&mut scanner);
+        let hash = match type_name_of(header.type_code) {
+            Some(type_name) => {
+                Some(hash_stream(type_name, header.size, &mut decoder).map_err(|_| bad_size())?)
+            }
+            None => {
+                let inflated = io::copy(&mut (&mut decoder).take(header.size + 1), &mut io::sink());
+                if inflated.ok() != Some(header.size) {
+                    return Err(bad_size());
+                }
+                None
+            }
+        };
+        drop(decoder);
+        entries.push(Entry {
+            offset,
+            base: header.base,
+            crc32: scanner.crc,
+            hash,
+        });
+    }
+
+    let checksum = scanner.hasher.finalize().to_vec();
+    let mut trailer = vec![];
+    scanner.inner.take(21).read_to_end(&mut trailer)?;
+    if trailer.len() > 20 {
+        return Err(GitObjectError::ParseError(String::from(
+            "pack has garbage after the last entry",
+        )));
+    }
+    if trailer != checksum {
+        return Err(GitObjectError::ParseError(String::from(
+            "pack checksum mismatch",
+        )));
+    }
+    Ok((entries, checksum))
+}
+
+// reads the type and size header of the entry at `offset` and its delta base, if any.
+fn read_entry_header(reader: &mut dyn Read, offset: u64) -> Result<EntryHeader> {
+    let mut buf = vec![];
+    let mut next = |buf: &mut Vec<u8>| -> Result<u8> {
+        let mut c = [0];
+        reader.read_exact(&mut c).map_err(|_| {
+            GitObjectError::ParseError(format!("truncated pack entry at {}", offset))
+        })?;
+        buf.push(c[0]);
+        Ok(c[0])
+    };
+    let mut c = next(&mut buf)?;
+    let type_code = (c >> 4) & 7;
+    // parse_entry_header rejects sizes longer than this.
+    while c & 0x80 != 0 && buf.len() < 11 {
+        c = next(&mut buf)?;
+    }
+    match type_code {
+        OBJ_OFS_DELTA => loop {
+            if next(&mut buf)? & 0x80 == 0 || buf.len() > 32 {
+                break;
+            }
+        },
+        OBJ_REF_DELTA => {
+            for _ in 0..20 {
+                next(&mut buf)?;
+            }
+        }
+        _ => {}
+    }
+    parse_entry_header(&buf, offset)
+}
+
+// the number of entries announced by a version 2 or 3 pack header.
+fn check_pack_header(header: &[u8]) -> Result<usize> {
+    if &header[..4] != b"PACK" {
+        return Err(GitObjectError::ParseError(String::from(
+            "not a pack: bad signature",
+        )));
+    }
+    let version = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+    if version != 2 && version != 3 {
+        return Err(GitObjectError::NotImplementError(format!(
+            "pack version {}",
+            version
+        )));
+    }
+    Ok(u32::from_be_bytes([header[8], header[9], header[10], header[11]]) as usize)
+}
+
+// names every delta whose chain ends in an object already known and returns how many
+// are left. like git's find_unresolved_deltas, the deltas waiting on each base (by
+// offset or by id) are looked up once, and each is resolved as soon as its base is
+// inflated, walking down from every whole object with only the current chain in memory.
+fn resolve_deltas(pack_path: &Path, entries: &mut [Entry], checksum: &[u8]) -> Result<usize> {
+    let mut by_offset: HashMap<u64, Vec<usize>> = HashMap::new();
+    let mut by_id: HashMap<Vec<u8>, Vec<usize>> = HashMap::new();
+    for (i, entry) in entries.iter().enumerate() {
+        match (&entry.hash, &entry.base) {
+            (None, Some(DeltaBase::Offset(base))) => by_offset.entry(*base).or_default().push(i),
+            (None, Some(DeltaBase::Ref(base))) => by_id.entry(base.clone()).or_default().push(i),
+            _ => {}
+        }
+    }
+    let mut waiting_on = |offset: u64, hash: &[u8]| {
+        let mut waiting = by_offset.remove(&offset).unwrap_or_default();
+        waiting.extend(by_id.remove(hash).unwrap_or_default());
+        waiting
+    };
+    // entries are only read by offset, the index is never looked at.
+    let index = PackIndex::parse(&encode_pack_index(&mut [], checksum))?;
+    let pack = PackFile::with_index(pack_path.to_path_buf(), index);
+
+    for root in 0..entries.len() {
+        let waiting = match (&entries[root].hash, &entries[root].base) {
+            (Some(hash), None) => waiting_on(entries[root].offset, hash),
+            _ => continue,
+        };
+        if waiting.is_empty() {
+            continue;
+        }
+        let (header, content) = pack.read_entry_data(entries[root].offset)?;
+        let type_name = type_name_of(header.type_code).unwrap();
+        // each base on the current chain with the deltas on it still to resolve.
+        let mut stack = vec![(content, waiting)];
+        while let Some((base, waiting)) = stack.last_mut() {
+            let child = match waiting.pop() {
+                Some(child) => child,
+                None => {
+                    stack.pop();
+                    continue;
+                }
+            };
+            let offset = entries[child].offset;
+            let (_, delta) = pack.read_entry_data(offset)?;
+            let content = apply_delta(base, &delta).map_err(|e| match e {
+                GitObjectError::ParseError(m) => {
+                    GitObjectError::ParseError(format!("{} (entry at {})", m, offset))
+                }
+                e => e,
+            })?;
+            let hash = object_hash(type_name, &content);
+            let waiting = waiting_on(offset, &hash);
+            entries[child].hash = Some(hash);
+            if !waiting.is_empty() {
+                stack.push((content, waiting));
+            }
+        }
+    }
+    Ok(entries.iter().filter(|e| e.hash.is_none()).count())
+}
+
+// appends the objects `ids` from `store` as whole entries after the last entry of the
+// pack, which ends at `end`; returns where the pack ends now. the header and checksum
+// are left to finish_appended_pack.
+fn append_bases(
+    pack_path: &Path,
+    end: u64,
+    store: &dyn ObjectStore,
+    ids: &[Vec<u8>],
+    entries: &mut Vec<Entry>,
+) -> Result<u64> {
+    let mut file = fs::OpenOptions::new().write(true).open(pack_path)?;
+    file.set_len(end)?;
+    file.seek(SeekFrom::Start(end))?;
+    let mut pack = PackWriter::resume(io::BufWriter::new(file), end);
+    for id in ids {
+        let hex = hash_to_str(id);
+        let (type_name, size) = store.header(&hex)?;
+        let type_code = type_code_of(&type_name).ok_or_else(|| {
+            GitObjectError::ParseError(format!("unknown object type {}", type_name))
+        })?;
+        let offset = pack.begin_entry();
+        let mut reader = store.read_stream(&hex)?;
+        pack.write_entry(&encode_entry_header(type_code, size), size, &mut reader)?;
+        entries.push(Entry {
+            offset,
+            base: None,
+            crc32: pack.entry_crc32(),
+            hash: Some(id.clone()),
+        });
+    }
+    pack.flush()?;
+    Ok(pack.offset())
+}
+
+// sets the entry count of a pack that had entries appended and writes its new checksum
+// after them.
+fn finish_appended_pack(pack_path: &Path, end: u64, count: u32) -> Result<Vec<u8>> {
+    let mut file = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(pack_path)?;
+    file.seek(SeekFrom::Start(8))?;
+    file.write_all(&count.to_be_bytes())?;
+    file.seek(SeekFrom::Start(0))?;
+    let mut hasher = Sha1::new();
+    let mut reader = io::BufReader::new(&file).take(end);
+    loop {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            break;
+        }
+        hasher.update(buf);
+        let n = buf.len();
+        reader.consume(n);
+    }
+    let checksum = hasher.finalize().to_vec();
+    file.seek(SeekFrom::Start(end))?;
+    file.write_all(&checksum)?;
+    file.set_len(end + 20)?;
+    Ok(checksum)
+}
+
+fn parse_options(commands: &[String]) -> IndexPackOption<'_> {
+    let mut option: IndexPackOption = Default::default();
+    let mut index_flag = false;
+    for token in &commands[2..] {
+        if index_flag {
+            option.index_file = Some(token);
+            index_flag = false;
+            continue;
+        }
+        match &token[..] {
+            "--stdin" => option.stdin = true,
+            "--fix-thin" => option.fix_thin = true,
+            "-o" => index_flag = true,
+            _ if option.pack_file.is_none() && !token.starts_with('-') => {
+                option.pack_file = Some(token)
+            }
+            _ => println!("ignore option {}", token),
+        }
+    }
+    option
+}
+
+#[cfg(test)]
+mod test {
+    use crate::index_pack::*;
+    use crate::object_store::MemoryObjectStore;
+    use crate::test_utils::temp_dir;
+
+    // a pack with "hello, world\n", an offset delta on it and a ref delta on that.
+    fn delta_pack(thin: bool) -> (Vec<u8>, Vec<Vec<u8>>) {
+        let v1 = b"hello, world\n".to_vec();
+        let v2 = b"hello, there, world\n".to_vec();
+        let v3 = b"hello, there, world\nbye\n".to_vec();
+        let mut d2 = vec![13, 20, 0x90, 7, 6];
+        d2.extend_from_slice(b"there,");
+        d2.extend_from_slice(&[0x91, 6, 7]);
+        let mut d3 = vec![20, 24, 0x90, 20, 4];
+        d3.extend_from_slice(b"bye\n");
+        let ids: Vec<Vec<u8>> = [&v1, &v2, &v3]
+            .iter()
+            .map(|v| object_hash("blob", v))
+            .collect();
+
+        let mut pack = b"PACK".to_vec();
+        pack.extend_from_slice(&2u32.to_be_bytes());
+        pack.extend_from_slice(&(if thin { 2u32 } else { 3 }).to_be_bytes());
+        if thin {
+            // v2 refers to v1 by id without carrying it.
+            pack.extend(encode_entry_header(OBJ_REF_DELTA, d2.len() as u64));
+            pack.extend_from_slice(&ids[0]);
+            pack.extend(compress(&d2));
+        } else {
+            pack.extend(encode_entry_header(OBJ_BLOB, v1.len() as u64));
+            pack.extend(compress(&v1));
+            let offset = pack.len();
+            pack.extend(encode_entry_header(OBJ_OFS_DELTA, d2.len() as u64));
+            pack.extend(encode_varint((offset - 12) as u64));
+            pack.extend(compress(&d2));
+        }
+        pack.extend(encode_entry_header(OBJ_REF_DELTA, d3.len() as u64));
+        pack.extend_from_slice(&ids[1]);
+        pack.extend(compress(&d3));
+        let checksum = hash(&pack);
+        pack.extend_from_slice(&checksum);
+        (pack, ids)
+    }
+
+    fn pack_file(dir: &Path, name: &str, pack: &[u8]) -> PathBuf {
+        fs::create_dir_all(dir).unwrap();
+        let path = dir.join(format!("{}.pack", name));
+        fs::write(&path, pack).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_build_index() {
+        let dir = temp_dir("build-index");
+        let (pack, ids) = delta_pack(false);
+        let path = pack_file(&dir, "test", &pack);
+        let indexed = build_index(&path, None).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), pack);
+        assert_eq!(indexed.checksum, pack[pack.len() - 20..].to_vec());
+        assert_eq!(indexed.object_count, 3);
+        assert_eq!(indexed.appended_bases, 0);
+
+        let index = PackIndex::parse(&indexed.index).unwrap();
+        assert_eq!(index.len(), 3);
+        let i = index.lookup(&ids[0]).unwrap();
+        assert_eq!(index.offset(i), 12);
+        // the crc covers the entry header and the compressed data.
+        let first_len = 1 + compress(b"hello, world\n").len();
+        assert_eq!(index.crc32(i), crc32(&pack[12..12 + first_len]));
+        assert!(ids[1..].iter().all(|id| index.lookup(id).is_some()));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_store_pack() {
+        let dir = temp_dir("index-pack");
+        let (pack, ids) = delta_pack(false);
+        let checksum = store_pack(&dir, &mut &pack[..], None, None, None).unwrap();
+        let name = format!("pack/pack-{}", hash_to_str(&checksum));
+        assert!(dir.join(format!("{}.pack", name)).is_file());
+        assert!(dir.join(format!("{}.idx", name)).is_file());
+        assert_eq!(fs::read_dir(dir.join("pack")).unwrap().count(), 2);
+
+        let store = PackObjectStore::open(&dir).unwrap();
+        let raw = store.read(&hash_to_str(&ids[2])).unwrap();
+        assert_eq!(raw.content, b"hello, there, world\nbye\n".to_vec());
+
+        // indexing the stored file again gives the same index.
+        let idx = fs::read(dir.join(format!("{}.idx", name))).unwrap();
+        let out = dir.join("again.idx");
+        index_pack_file(&dir.join(format!("{}.pack", name)), Some(&out)).unwrap();
+        assert_eq!(fs::read(&out).unwrap(), idx);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_store_pack_input_error() {
+        struct Failing;
+        impl Read for Failing {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::other("broken pipe"))
+            }
+        }
+        let dir = temp_dir("index-pack-input");
+        assert!(store_pack(&dir, &mut Failing, None, None, None).is_err());
+        // nothing is left behind.
+        assert_eq!(fs::read_dir(dir.join("pack")).unwrap().count(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_fix_thin_pack() {
+        let dir = temp_dir("fix-thin");
+        let (pack, ids) = delta_pack(true);
+        let path = pack_file(&dir, "thin", &pack);
+        match build_index(&path, None) {
+            Err(GitObjectError::NotFoundError(m)) => assert!(m.contains(&hash_to_str(&ids[0]))),
+            r => panic!("unexpected result: {:?}", r.map(|p| p.checksum)),
+        }
+        assert_eq!(fs::read(&path).unwrap(), pack);
+
+        let bases = MemoryObjectStore::new();
+        bases.write("blob", b"hello, world\n").unwrap();
+        let indexed = build_index(&path, Some(&bases)).unwrap();
+        assert_eq!(indexed.appended_bases, 1);
+        assert_eq!(indexed.object_count, 3);
+        let fixed = fs::read(&path).unwrap();
+        assert_eq!(&fixed[8..12], &3u32.to_be_bytes());
+        assert!(fixed.len() > pack.len());
+        assert_eq!(fixed[fixed.len() - 20..], indexed.checksum[..]);
+
+        // the completed pack indexes on its own.
+        let again = build_index(&path, None).unwrap();
+        assert_eq!(again.index, indexed.index);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_corrupt_pack() {
+        let dir = temp_dir("corrupt-pack");
+        let (mut pack, _) = delta_pack(false);
+        let last = pack.len() - 1;
+        pack[last] ^= 1;
+        assert!(build_index(&pack_file(&dir, "checksum", &pack), None).is_err());
+
+        // bytes after the checksum.
+        let (mut pack, _) = delta_pack(false);
+        pack.push(0);
+        assert!(build_index(&pack_file(&dir, "garbage", &pack), None).is_err());
+
+        // a delta whose base is itself never resolves.
+        let (pack, ids) = delta_pack(false);
+        let mut looped = pack[..pack.len() - 20].to_vec();
+        let ref_start = looped.windows(20).rposition(|w| w == &ids[1][..]).unwrap();
+        looped[ref_start..ref_start + 20].copy_from_slice(&ids[2]);
+        let checksum = hash(&looped);
+        looped.extend_from_slice(&checksum);
+        assert!(build_index(&pack_file(&dir, "looped", &looped), None).is_err());
+
+        assert!(build_index(&pack_file(&dir, "short", b"PACK"), None).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,269 @@
+use crate::config::Config;
+use crate::git_object::GitObjectError;
+use crate::repository::is_git_dir;
+use crate::utils::is_valid_ref_name;
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+type Result<T> = std::result::Result<T, GitObjectError>;
+
+pub const DEFAULT_BRANCH: &str = "master";
+
+#[derive(Default)]
+struct InitOption<'a> {
+    bare: bool,
+    quiet: bool,
+    initial_branch: Option<&'a str>,
+    object_format: Option<&'a str>,
+    template: Option<&'a str>,
+    directory: Option<&'a String>,
+    // the option given last without the value it takes.
+    missing_value: Option<&'a str>,
+}
+
+pub fn init(commands: &[String]) {
+    let option = parse_options(commands);
+    match option.missing_value {
+        Some("-b") => {
+            println!("error: switch 'b' requires a value");
+            return;
+        }
+        Some(_) => {
+            println!("error: option 'initial-branch' requires a value");
+            return;
+        }
+        None => {}
+    }
+    match option.object_format {
+        None | Some("sha1") => {}
+        Some("sha256") => {
+            println!("fatal: object format 'sha256' is not supported");
+            return;
+        }
+        Some(f) => {
+            println!("fatal: unknown hash algorithm '{}'", f);
+            return;
+        }
+    }
+    // init.defaultBranch can only come from the system and global configuration here.
+    let default_branch = Config::load(None)
+        .ok()
+        .and_then(|c| c.get_string("init.defaultbranch").ok().flatten());
+    let branch = option
+        .initial_branch
+        .or(default_branch.as_deref())
+        .unwrap_or(DEFAULT_BRANCH);
+    if !is_valid_ref_name(branch) {
+        println!("fatal: invalid initial branch name: '{}'", branch);
+        return;
+    }
+
+    let cwd = env::current_dir().unwrap();
+    let top = match option.directory {
+        Some(d) => cwd.join(d),
+        None => cwd.clone(),
+    };
+    // $GIT_DIR is relative to where we were started; a git dir that is the directory
+    // itself means a bare repository, like git's guess_repository_type.
+    let (git_dir, bare) = match env::var_os("GIT_DIR") {
+        Some(d) => {
+            let d = cwd.join(d);
+            let bare = option.bare || d == top;
+            (d, bare)
+        }
+        None if option.bare => (top.clone(), true),
+        None => (top.join(".git"), false),
+    };
+    // an empty --template= means no templates at all.
+    let template = option
+        .template
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("GIT_TEMPLATE_DIR").map(PathBuf::from))
+        .filter(|t| !t.as_os_str().is_empty());
+
+    match init_repository(&git_dir, bare, branch, template.as_deref()) {
+        Ok(reinit) => {
+            if reinit && option.initial_branch.is_some() {
+                println!("warning: re-init: ignored --initial-branch={}", branch);
+            }
+            if !option.quiet {
+                let path = fs::canonicalize(&git_dir).unwrap_or(git_dir);
+                let verb = if reinit {
+                    "Reinitialized existing"
+                } else {
+                    "Initialized empty"
+                };
+                println!("{} Git repository in {}/", verb, path.display());
+            }
+        }
+        Err(e) => println!("fatal: {:?}", e),
+    }
+}
+
+// creates the layout of a repository in `git_dir`, leaving everything that already
+// exists alone, so running it again is harmless. returns whether it already was one.
+pub fn init_repository(
+    git_dir: &Path,
+    bare: bool,
+    initial_branch: &str,
+    template: Option<&Path>,
+) -> Result<bool> {
+    let reinit = is_git_dir(git_dir);
+    for dir in &[
+        "objects/info",
+        "objects/pack",
+        "refs/heads",
+        "refs/tags",
+        "info",
+        "hooks",
+    ] {
+        fs::create_dir_all(git_dir.join(dir))?;
+    }
+    if !git_dir.join("config").exists() {
+        write_config(git_dir, bare)?;
+    }
+    if let Some(template) = template {
+        copy_template(template, git_dir)?;
+    }
+    if !git_dir.join("HEAD").exists() {
+        fs::write(
+            git_dir.join("HEAD"),
+            format!("ref: refs/heads/{}\n", initial_branch),
+        )?;
+    }
+    Ok(reinit)
+}
+
+fn write_config(git_dir: &Path, bare: bool) -> Result<()> {
+    let mut config = String::from("[core]\n\trepositoryformatversion = 0\n");
+    config.push_str(&format!("\tfilemode = {}\n", cfg!(unix)));
+    config.push_str(&format!("\tbare = {}\n", bare));
+    if !bare {
+        config.push_str("\tlogallrefupdates = true\n");
+    }
+    if !cfg!(unix) {
+        config.push_str("\tsymlinks = false\n");
+    }
+    let path = git_dir.join("config");
+    fs::write(&path, &config)?;
+    // a file found under a differently cased name means the filesystem ignores case.
+    if git_dir.join("CoNfIg").exists() {
+        config.push_str("\tignorecase = true\n");
+        fs::write(&path, &config)?;
+    }
+    Ok(())
+}
+
+// copies a template directory into the git dir without replacing anything.
+fn copy_template(template: &Path, dest: &Path) -> Result<()> {
+    let entries = match fs::read_dir(template) {
+        Ok(e) => e,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            println!("warning: templates not found in {}", template.display());
+            return Ok(());
+        }
+        Err(e) => return Err(GitObjectError::IOError(e)),
+    };
+    for entry in entries {
+        let entry = entry?;
+        let target = dest.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            fs::create_dir_all(&target)?;
+            copy_template(&entry.path(), &target)?;
+        } else if !target.exists() {
+            fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+fn parse_options(commands: &[String]) -> InitOption<'_> {
+    let mut option: InitOption = Default::default();
+    for token in &commands[2..] {
+        if option.missing_value.take().is_some() {
+            option.initial_branch = Some(token);
+            continue;
+        }
+        match &token[..] {
+            "--bare" => option.bare = true,
+            "-q" | "--quiet" => option.quiet = true,
+            "-b" | "--initial-branch" => option.missing_value = Some(token),
+            _ if token.starts_with("--initial-branch=") => {
+                option.initial_branch = Some(&token[17..])
+            }
+            _ if token.starts_with("--object-format=") => option.object_format = Some(&token[16..]),
+            _ if token.starts_with("--template=") => option.template = Some(&token[11..]),
+            _ if !token.starts_with('-') && option.directory.is_none() => {
+                option.directory = Some(token)
+            }
+            _ => println!("ignore option {}", token),
+        }
+    }
+    option
+}
+
+#[cfg(test)]
+mod test {
+    use crate::init::*;
+    use crate::repository::Repository;
+    use crate::test_utils::temp_dir;
+
+    #[test]
+    fn test_init_repository() {
+        let root = temp_dir("init");
+        let template = root.join("template");
+        fs::create_dir_all(template.join("hooks")).unwrap();
+        fs::write(template.join("description"), "template\n").unwrap();
+        fs::write(template.join("hooks/pre-commit"), "#!/bin/sh\n").unwrap();
+
+        let git_dir = root.join("work/.git");
+        assert!(!init_repository(&git_dir, false, "main", Some(&template)).unwrap());
+        for dir in &[
+            "objects/info",
+            "objects/pack",
+            "refs/heads",
+            "refs/tags",
+            "info",
+            "hooks",
+        ] {
+            assert!(git_dir.join(dir).is_dir(), "{}", dir);
+        }
+        assert_eq!(
+            fs::read_to_string(git_dir.join("HEAD")).unwrap(),
+            "ref: refs/heads/main\n"
+        );
+        assert!(git_dir.join("hooks/pre-commit").is_file());
+        let config = fs::read_to_string(git_dir.join("config")).unwrap();
+        assert!(config.starts_with("[core]\n\trepositoryformatversion = 0\n"));
+        assert!(config.contains("\tbare = false\n\tlogallrefupdates = true\n"));
+
+        // a second run keeps what is there.
+        fs::write(git_dir.join("description"), "mine\n").unwrap();
+        assert!(init_repository(&git_dir, false, "other", Some(&template)).unwrap());
+        assert_eq!(
+            fs::read_to_string(git_dir.join("HEAD")).unwrap(),
+            "ref: refs/heads/main\n"
+        );
+        assert_eq!(
+            fs::read_to_string(git_dir.join("description")).unwrap(),
+            "mine\n"
+        );
+        let repo = Repository::discover_from(&root.join("work"), None, None).unwrap();
+        assert_eq!(repo.git_dir, git_dir);
+
+        let bare = root.join("bare.git");
+        assert!(!init_repository(&bare, true, "main", None).unwrap());
+        assert_eq!(
+            fs::read_to_string(bare.join("HEAD")).unwrap(),
+            "ref: refs/heads/main\n"
+        );
+        let config = fs::read_to_string(bare.join("config")).unwrap();
+        assert!(config.contains("\tbare = true\n") && !config.contains("logallrefupdates"));
+        assert!(Repository::discover_from(&bare, None, None)
+            .unwrap()
+            .is_bare());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -1,8 +1,39 @@
+pub mod add;
+pub mod cache_tree;
 pub mod cat_file;
 pub mod commit_tree;
+pub mod config;
+pub mod date;
+pub mod delta;
+pub mod fsck;
+pub mod gc;
 pub mod git_object;
 pub mod git_user;
 pub mod hash_object;
+pub mod ignore;
+pub mod index;
+pub mod index_pack;
+pub mod init;
+pub mod lockfile;
 pub mod ls_tree;
+pub mod mv;
+pub mod object_store;
+pub mod object_stream;
+pub mod pack;
+pub mod pack_objects;
+pub mod pathspec;
+pub mod reflog;
+pub mod refs;
+pub mod regex;
+pub mod repository;
+pub mod rev_parse;
+pub mod revision;
+pub mod rm;
+pub mod status;
+pub mod tag;
+#[cfg(test)]
+mod test_utils;
+pub mod timezone;
 pub mod utils;
+pub mod worktree;
 pub mod write_tree;
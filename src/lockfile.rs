@@ -0,0 +1,128 @@
+use crate::git_object::GitObjectError;
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+type Result<T> = std::result::Result<T, GitObjectError>;
+
+// `<path>.lock`, created exclusively so that only one writer can hold it. the new
+// content is written to it and renamed over `path` on commit; dropping it without
+// committing leaves `path` as it was.
+pub struct LockFile {
+    path: PathBuf,
+    lock_path: PathBuf,
+    file: Option<fs::File>,
+    // set once the lock has been renamed or removed; until then dropping it removes it.
+    committed: bool,
+}
+
+impl LockFile {
+    pub fn acquire(path: &Path) -> Result<Self> {
+        let mut lock_path = path.as_os_str().to_owned();
+        lock_path.push(".lock");
+        let lock_path = PathBuf::from(lock_path);
+        let file = match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&lock_path)
+        {
+            Ok(f) => f,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                return Err(GitObjectError::WriteError(format!(
+                    "unable to create '{}': File exists",
+                    lock_path.display()
+                )))
+            }
+            Err(e) => return Err(GitObjectError::IOError(e)),
+        };
+        Ok(LockFile {
+            path: path.to_path_buf(),
+            lock_path,
+            file: Some(file),
+            committed: false,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn write_all(&mut self, data: &[u8]) -> Result<()> {
+        match &mut self.file {
+            Some(f) => Ok(f.write_all(data)?),
+            None => Err(GitObjectError::WriteError(String::from(
+                "lock file is already closed",
+            ))),
+        }
+    }
+
+    pub fn commit(mut self) -> Result<()> {
+        if let Some(file) = self.file.take() {
+            file.sync_all()?;
+        }
+        fs::rename(&self.lock_path, &self.path)?;
+        self.committed = true;
+        Ok(())
+    }
+
+    // removes the target instead of replacing it, for deletions done under the lock.
+    pub fn commit_removal(mut self) -> Result<()> {
+        self.file.take();
+        match fs::remove_file(&self.path) {
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(GitObjectError::IOError(e)),
+        }
+        fs::remove_file(&self.lock_path)?;
+        self.committed = true;
+        Ok(())
+    }
+}
+
+impl Drop for LockFile {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = fs::remove_file(&self.lock_path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::lockfile::*;
+    use crate::test_utils::temp_dir;
+
+    #[test]
+    fn test_lock_file() {
+        let dir = temp_dir("lockfile");
+        let path = dir.join("file");
+        fs::write(&path, "old").unwrap();
+
+        let mut lock = LockFile::acquire(&path).unwrap();
+        assert!(LockFile::acquire(&path).is_err());
+        lock.write_all(b"new").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old");
+        lock.commit().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+
+        // an abandoned lock changes nothing and can be taken again.
+        let mut lock = LockFile::acquire(&path).unwrap();
+        lock.write_all(b"lost").unwrap();
+        drop(lock);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        LockFile::acquire(&path).unwrap().commit_removal().unwrap();
+        assert!(!path.exists() && !dir.join("file.lock").exists());
+
+        // a commit that fails does not leave the lock behind.
+        fs::create_dir_all(path.join("busy")).unwrap();
+        let mut lock = LockFile::acquire(&path).unwrap();
+        lock.write_all(b"new").unwrap();
+        assert!(lock.commit().is_err());
+        assert!(!dir.join("file.lock").exists());
+        assert!(LockFile::acquire(&path).unwrap().commit_removal().is_err());
+        assert!(!dir.join("file.lock").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
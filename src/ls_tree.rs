@@ -1,22 +1,57 @@
 use crate::git_object::*;
+use crate::repository::open_repository;
+use crate::rev_parse::{find_unique_abbrev, report_resolve_error, DEFAULT_ABBREV};
+use crate::revision::{peel, resolve_revision};
+use crate::utils::hash_to_str;
 
 #[derive(Default)]
 struct LsTreeOption<'a> {
     name_only: bool,
+    abbrev: Option<usize>,
     obj_hash: Option<&'a String>,
 }
 
 pub fn ls_tree(commands: &[String]) {
     let option = parse_options(commands);
-    let hash = option.obj_hash.expect("no object hash given");
+    let name = match option.obj_hash {
+        Some(n) => n,
+        None => {
+            println!("usage: ls-tree [--name-only] [--abbrev[=<n>]] <tree-ish>");
+            return;
+        }
+    };
 
-    let result = match load_object_by_hash(hash) {
+    let repo = match open_repository() {
+        Some(r) => r,
+        None => return,
+    };
+    let store = repo.object_store();
+    let hash = match resolve_revision(&repo.git_dir, &store, name) {
+        Ok(h) => h,
+        Err(e) => {
+            report_resolve_error(&store, name, &e);
+            println!("fatal: Not a valid object name {}", name);
+            return;
+        }
+    };
+    // commits and tags are listed by their tree.
+    let hash = match peel(&store, &hash, Some("tree")) {
+        Ok(h) => hash_to_str(&h),
+        Err(_) => {
+            println!("fatal: not a tree object");
+            return;
+        }
+    };
+    let result = match load_object_by_hash(&store, &hash) {
         Ok(r) => r,
-        Err(e) => panic!("{:?}", e),
+        Err(e) => {
+            println!("fatal: {:?}", e);
+            return;
+        }
     };
 
     match &result {
-        GitObject::Commit(_) | GitObject::Blob(_) => {
+        GitObject::Commit(_) | GitObject::Blob(_) | GitObject::Tag(_) => {
             println!("not a tree object");
         }
         GitObject::Tree(lst) => {
@@ -24,6 +59,16 @@ pub fn ls_tree(commands: &[String]) {
                 for e in lst {
                     println!("{}", e.filename);
                 }
+            } else if let Some(len) = option.abbrev {
+                for e in lst {
+                    println!(
+                        "{} {} {}\t{}",
+                        e.mode,
+                        e.type_name(),
+                        find_unique_abbrev(&store, &e.hash, len),
+                        e.filename
+                    )
+                }
             } else {
                 result.pretty_print();
             }
@@ -31,12 +76,16 @@ pub fn ls_tree(commands: &[String]) {
     }
 }
 
-fn parse_options(commands: &[String]) -> LsTreeOption {
+fn parse_options(commands: &[String]) -> LsTreeOption<'_> {
     let mut option: LsTreeOption = Default::default();
     for token in &commands[2..] {
         match &token[..] {
             "--name-only" => option.name_only = true,
-            _ if token.len() == 40 => option.obj_hash = Some(&token),
+            "--abbrev" => option.abbrev = Some(DEFAULT_ABBREV),
+            _ if token.starts_with("--abbrev=") => {
+                option.abbrev = Some(token[9..].parse().unwrap_or(DEFAULT_ABBREV))
+            }
+            _ if !token.starts_with('-') => option.obj_hash = Some(token),
             _ => println!("ignore option {}", token),
         }
     }
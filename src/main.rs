@@ -1,36 +1,104 @@
+use git_starter_rust::add::add;
 use git_starter_rust::cat_file::cat_file;
 use git_starter_rust::commit_tree::commit_tree;
+use git_starter_rust::config::config;
+use git_starter_rust::fsck::fsck;
+use git_starter_rust::gc::{gc, repack};
 use git_starter_rust::hash_object::hash_object;
+use git_starter_rust::index_pack::index_pack;
+use git_starter_rust::init::init;
 use git_starter_rust::ls_tree::ls_tree;
+use git_starter_rust::mv::mv;
+use git_starter_rust::pack_objects::pack_objects;
+use git_starter_rust::reflog::reflog;
+use git_starter_rust::refs::{show_ref, symbolic_ref, update_ref};
+use git_starter_rust::rev_parse::rev_parse;
+use git_starter_rust::rm::rm;
+use git_starter_rust::status::status;
+use git_starter_rust::tag::tag;
 use git_starter_rust::write_tree::write_tree;
 #[allow(unused_imports)]
 use std::{env, fs};
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    execute_command(args)
+    if let Some(args) = apply_global_options(args) {
+        execute_command(args)
+    }
+}
+
+// handles the options given before the command, like git does: `-C` changes directory
+// and the others are passed on to repository discovery through the environment.
+fn apply_global_options(mut args: Vec<String>) -> Option<Vec<String>> {
+    while args.len() > 1 && args[1].starts_with('-') {
+        let option = args.remove(1);
+        let (name, value) = match option.split_once('=') {
+            Some((name, value)) => (name.to_string(), Some(value.to_string())),
+            None => (option.clone(), None),
+        };
+        let takes_value = matches!(&name[..], "-C" | "--git-dir" | "--work-tree");
+        let value = match value {
+            Some(v) => Some(v),
+            None if takes_value && args.len() > 1 => Some(args.remove(1)),
+            None if takes_value => {
+                println!("fatal: no directory given for '{}' option", name);
+                return None;
+            }
+            None => None,
+        };
+        match (&name[..], value) {
+            ("-C", Some(dir)) => {
+                if !dir.is_empty() {
+                    if let Err(e) = env::set_current_dir(&dir) {
+                        println!("fatal: cannot change to '{}': {}", dir, e);
+                        return None;
+                    }
+                }
+            }
+            ("--git-dir", Some(dir)) => env::set_var("GIT_DIR", dir),
+            ("--work-tree", Some(dir)) => env::set_var("GIT_WORK_TREE", dir),
+            ("--bare", None) => env::set_var("GIT_DIR", env::current_dir().ok()?),
+            _ => {
+                println!("unknown option: {}", option);
+                return None;
+            }
+        }
+    }
+    if args.len() < 2 {
+        help();
+        return None;
+    }
+    Some(args)
 }
 
 fn execute_command(commands: Vec<String>) {
     match &*commands[1] {
-        "init" => init(),
+        "init" => init(&commands),
         "cat-file" => cat_file(&commands),
         "hash-object" => hash_object(&commands),
         "ls-tree" => ls_tree(&commands),
         "write-tree" => write_tree(&commands),
         "commit-tree" => commit_tree(&commands),
+        "tag" => tag(&commands),
+        "index-pack" => index_pack(&commands),
+        "pack-objects" => pack_objects(&commands),
+        "repack" => repack(&commands),
+        "gc" => gc(&commands),
+        "fsck" => fsck(&commands),
+        "rev-parse" => rev_parse(&commands),
+        "config" => config(&commands),
+        "update-ref" => update_ref(&commands),
+        "symbolic-ref" => symbolic_ref(&commands),
+        "show-ref" => show_ref(&commands),
+        "reflog" => reflog(&commands),
+        "add" => add(&commands),
+        "rm" => rm(&commands),
+        "mv" => mv(&commands),
+        "status" => status(&commands),
         _ => help(),
     }
 }
 
-fn init() {
-    fs::create_dir(".git").unwrap();
-    fs::create_dir(".git/objects").unwrap();
-    fs::create_dir(".git/refs").unwrap();
-    fs::write(".git/HEAD", "ref: refs/heads/master\n").unwrap();
-    println!("Initialized git directory")
-}
-
 fn help() {
-    println!("[help] your git \n  cat-file\n  ls-tree\n  commit-tree\n  hash-object")
+    println!("[help] your git \n  cat-file\n  ls-tree\n  commit-tree\n  hash-object\n  tag\n  index-pack\n  pack-objects\n  repack\n  gc\n  fsck\n  rev-parse\n  config\n  update-ref\n  symbolic-ref\n  show-ref\n  reflog\n  add\n  rm\n  mv\n  status")
 }
@@ -0,0 +1,331 @@
+use crate::index::Index;
+use crate::pathspec::normalize_path;
+use crate::repository::{open_repository, Repository};
+use std::{fs, path::Path};
+
+#[derive(Default)]
+struct MvOption<'a> {
+    force: bool,
+    dry_run: bool,
+    skip_errors: bool,
+    verbose: bool,
+    paths: Vec<&'a String>,
+}
+
+pub fn mv(commands: &[String]) {
+    let option = parse_options(commands);
+    if option.paths.len() < 2 {
+        println!("usage: git mv [<options>] <source>... <destination>");
+        return;
+    }
+    let repo = match open_repository() {
+        Some(r) => r,
+        None => return,
+    };
+    for line in move_paths(&repo, &option) {
+        println!("{}", line);
+    }
+}
+
+// moves what `option` names and returns what `git mv` prints.
+fn move_paths(repo: &Repository, option: &MvOption) -> Vec<String> {
+    let mut out = vec![];
+    let work_tree = match &repo.work_tree {
+        Some(w) => w,
+        None => {
+            out.push(String::from(
+                "fatal: this operation must be run in a work tree",
+            ));
+            return out;
+        }
+    };
+    let index_path = repo.index_path();
+    let mut index = match Index::lock_or_report(&index_path) {
+        Ok(i) => i,
+        Err(message) => {
+            out.push(message);
+            return out;
+        }
+    };
+    let mut paths = vec![];
+    for arg in &option.paths {
+        match normalize_path(work_tree, &repo.prefix, arg) {
+            Ok(p) => paths.push(p),
+            Err(e) => {
+                out.push(format!("fatal: {}", e));
+                return out;
+            }
+        }
+    }
+    let destination = paths.pop().unwrap_or_default();
+    // sources moved into an existing directory keep their names.
+    let into_dir = work_tree.join(&destination).is_dir();
+    if paths.len() > 1 && !into_dir {
+        out.push(format!(
+            "fatal: destination '{}' is not a directory",
+            destination
+        ));
+        return out;
+    }
+
+    let mut moves: Vec<(String, String)> = vec![];
+    for source in paths {
+        let target = if into_dir {
+            let name = source.rsplit('/').next().unwrap_or(&source);
+            if destination.is_empty() {
+                name.to_string()
+            } else {
+                format!("{}/{}", destination, name)
+            }
+        } else {
+            destination.clone()
+        };
+        if option.dry_run {
+            out.push(format!("Checking rename of '{}' to '{}'", source, target));
+        }
+        let bad = check_move(work_tree, &index, &source, &target, option.force, &moves);
+        match bad {
+            None => {
+                if option.dry_run {
+                    for (path, moved) in tracked_below(&index, &source, &target) {
+                        out.push(format!("Checking rename of '{}' to '{}'", path, moved));
+                    }
+                }
+                moves.push((source, target))
+            }
+            Some(_) if option.skip_errors => {}
+            Some(reason) => {
+                out.push(format!(
+                    "fatal: {}, source={}, destination={}",
+                    reason, source, target
+                ));
+                return out;
+            }
+        }
+    }
+
+    for (source, target) in &moves {
+        if option.verbose || option.dry_run {
+            out.push(format!("Renaming {} to {}", source, target));
+            for (path, moved) in tracked_below(&index, source, target) {
+                out.push(format!("Renaming {} to {}", path, moved));
+            }
+        }
+        if option.dry_run {
+            continue;
+        }
+        if let Err(e) = fs::rename(work_tree.join(source), work_tree.join(target)) {
+            out.push(format!("fatal: renaming '{}' failed: {}", source, e));
+            return out;
+        }
+        rename_entries(&mut index, source, target);
+    }
+    if !option.dry_run {
+        if let Err(e) = index.commit() {
+            out.push(format!("fatal: Unable to write new index file: {:?}", e));
+        }
+    }
+    out
+}
+
+// why `source` can not be moved to `target`, if it can not.
+fn check_move(
+    work_tree: &Path,
+    index: &Index,
+    source: &str,
+    target: &str,
+    force: bool,
+    moves: &[(String, String)],
+) -> Option<&'static str> {
+    let metadata = match fs::symlink_metadata(work_tree.join(source)) {
+        Ok(m) => m,
+        Err(_) => return Some("bad source"),
+    };
+    let below = format!("{}/", source);
+    if metadata.is_dir() && (source.is_empty() || target == source || target.starts_with(&below)) {
+        return Some("can not move directory into itself");
+    }
+    if metadata.is_dir() && index.stages(source).is_empty() {
+        if !index.entries.iter().any(|e| e.path.starts_with(&below)) {
+            return Some("source directory is empty");
+        }
+    } else {
+        match index.stages(source) {
+            [] => return Some("not under version control"),
+            [e] if e.stage == 0 => {}
+            _ => return Some("conflicted"),
+        }
+    }
+    let target_metadata = fs::symlink_metadata(work_tree.join(target));
+    if let Ok(m) = &target_metadata {
+        // only a file may be overwritten, and only with -f.
+        if !force || m.is_dir() || metadata.is_dir() {
+            return Some("destination exists");
+        }
+    }
+    if moves.iter().any(|(_, t)| t == target) {
+        return Some("multiple sources for the same target");
+    }
+    None
+}
+
+// the tracked paths below a directory being moved, and where they go.
+fn tracked_below(index: &Index, source: &str, target: &str) -> Vec<(String, String)> {
+    let below = format!("{}/", source);
+    index
+        .entries
+        .iter()
+        .filter(|e| e.path.starts_with(&below))
+        .map(|e| {
+            let moved = format!("{}{}", target, &e.path[source.len()..]);
+            (e.path.clone(), moved)
+        })
+        .collect()
+}
+
+// moves the entries at and below `source` to `target`, keeping their stat data.
+fn rename_entries(index: &mut Index, source: &str, target: &str) {
+    let below = format!("{}/", source);
+    let moved: Vec<_> = index
+        .entries
+        .iter()
+        .filter(|e| e.path == source || e.path.starts_with(&below))
+        .cloned()
+        .collect();
+    for mut entry in moved {
+        index.remove(&entry.path);
+        entry.path = format!("{}{}", target, &entry.path[source.len()..]);
+        index.add(entry);
+    }
+}
+
+fn parse_options(commands: &[String]) -> MvOption<'_> {
+    let mut option: MvOption = Default::default();
+    let mut only_paths = false;
+    for token in &commands[2..] {
+        match &token[..] {
+            _ if only_paths => option.paths.push(token),
+            "--" => only_paths = true,
+            "-f" | "--force" => option.force = true,
+            "-n" | "--dry-run" => option.dry_run = true,
+            "-k" => option.skip_errors = true,
+            "-v" | "--verbose" => option.verbose = true,
+            _ if token.starts_with('-') => println!("ignore option {}", token),
+            _ => option.paths.push(token),
+        }
+    }
+    option
+}
+
+#[cfg(test)]
+mod test {
+    use crate::git_object::FileMode;
+    use crate::index::IndexEntry;
+    use crate::init::init_repository;
+    use crate::mv::*;
+    use crate::test_utils::temp_dir;
+    use crate::worktree::entry_from_work_tree;
+
+    fn run(repo: &Repository, args: &[&str]) -> Vec<String> {
+        let commands: Vec<String> = ["git", "mv"]
+            .iter()
+            .chain(args)
+            .map(|s| s.to_string())
+            .collect();
+        move_paths(repo, &parse_options(&commands))
+    }
+
+    fn staged(repo: &Repository) -> Vec<(String, u8)> {
+        let index = Index::read_from(&repo.index_path()).unwrap();
+        index
+            .entries
+            .into_iter()
+            .map(|e| (e.path, e.stage))
+            .collect()
+    }
+
+    #[test]
+    fn test_mv() {
+        let root = temp_dir("mv");
+        init_repository(&root.join(".git"), false, "master", None).unwrap();
+        let repo = Repository::discover_from(&root, None, None).unwrap();
+        let store = repo.object_store();
+        let mut index = Index::new();
+        fs::create_dir_all(root.join("dir/sub")).unwrap();
+        fs::create_dir_all(root.join("to")).unwrap();
+        for path in ["a", "b", "c", "dir/x", "dir/sub/y", "to/t", "u"] {
+            fs::write(root.join(path), path).unwrap();
+            index.add(entry_from_work_tree(&store, &root, path, true).unwrap());
+        }
+        // u is conflicted.
+        let id = index.get("u", 0).unwrap().id.clone();
+        index.remove("u");
+        for stage in 1..=3 {
+            let mut entry = IndexEntry::new("u", &id, FileMode::Regular);
+            entry.stage = stage;
+            index.add(entry);
+        }
+        index.write_to(&repo.index_path()).unwrap();
+
+        assert!(run(&repo, &["a", "a2"]).is_empty());
+        assert!(!root.join("a").exists() && root.join("a2").exists());
+        assert_eq!(run(&repo, &["-v", "b", "to"]), vec!["Renaming b to to/b"]);
+        assert!(root.join("to/b").exists());
+        assert_eq!(
+            run(&repo, &["-v", "dir", "moved"]),
+            vec![
+                "Renaming dir to moved",
+                "Renaming dir/sub/y to moved/sub/y",
+                "Renaming dir/x to moved/x",
+            ]
+        );
+        assert!(root.join("moved/sub/y").exists());
+        let paths: Vec<String> = staged(&repo).into_iter().map(|(p, _)| p).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "a2",
+                "c",
+                "moved/sub/y",
+                "moved/x",
+                "to/b",
+                "to/t",
+                "u",
+                "u",
+                "u"
+            ]
+        );
+
+        assert_eq!(
+            run(&repo, &["c", "a2"]),
+            vec!["fatal: destination exists, source=c, destination=a2"]
+        );
+        assert_eq!(
+            run(&repo, &["u", "u2"]),
+            vec!["fatal: conflicted, source=u, destination=u2"]
+        );
+        assert_eq!(
+            run(&repo, &["missing", "c", "to"]),
+            vec!["fatal: bad source, source=missing, destination=to/missing"]
+        );
+        assert!(root.join("c").exists());
+        // -k skips what can not be moved and moves the rest.
+        assert!(run(&repo, &["-k", "missing", "u", "c", "to"]).is_empty());
+        assert!(root.join("to/c").exists() && root.join("u").exists());
+        assert_eq!(
+            staged(&repo),
+            vec![
+                (String::from("a2"), 0),
+                (String::from("moved/sub/y"), 0),
+                (String::from("moved/x"), 0),
+                (String::from("to/b"), 0),
+                (String::from("to/c"), 0),
+                (String::from("to/t"), 0),
+                (String::from("u"), 1),
+                (String::from("u"), 2),
+                (String::from("u"), 3),
+            ]
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,381 @@
+use crate::git_object::GitObjectError;
+use crate::object_stream::*;
+use crate::pack::PackObjectStore;
+use crate::utils::*;
+use flate2::read::ZlibDecoder;
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fs, io,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+type Result<T> = std::result::Result<T, GitObjectError>;
+
+// an object as stored: its type name and the content without the "<type> <size>\0" header.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawObject {
+    pub type_name: String,
+    pub content: Vec<u8>,
+}
+
+// object ids are passed as 40 character hex strings and returned as raw 20 byte hashes,
+// like the rest of the crate.
+pub trait ObjectStore {
+    fn read(&self, hash: &str) -> Result<RawObject>;
+    fn write(&self, type_name: &str, content: &[u8]) -> Result<Vec<u8>>;
+    fn contains(&self, hash: &str) -> bool;
+    // type name and size, without reading the content if the backend allows it.
+    fn header(&self, hash: &str) -> Result<(String, u64)>;
+    // ids of the stored objects whose hex form starts with `prefix` (lowercase hex).
+    fn find_by_prefix(&self, prefix: &str) -> Result<Vec<Vec<u8>>>;
+
+    fn read_stream(&self, hash: &str) -> Result<ObjectReader<'_>> {
+        let raw = self.read(hash)?;
+        Ok(ObjectReader::from_bytes(&raw.type_name, raw.content))
+    }
+
+    fn write_stream(&self, type_name: &str, size: u64, reader: &mut dyn Read) -> Result<Vec<u8>> {
+        let mut content = Vec::with_capacity(size as usize);
+        reader.take(size).read_to_end(&mut content)?;
+        if content.len() as u64 != size {
+            return Err(GitObjectError::CustomIOError(format!(
+                "input ended {} bytes short of the expected size {}",
+                size - content.len() as u64,
+                size
+            )));
+        }
+        self.write(type_name, &content)
+    }
+}
+
+// the standard layout: one zlib-compressed file per object under objects/xx/yyyy...
+pub struct LooseObjectStore {
+    objects_dir: PathBuf,
+}
+
+impl LooseObjectStore {
+    pub fn new(objects_dir: &Path) -> Self {
+        LooseObjectStore {
+            objects_dir: objects_dir.to_path_buf(),
+        }
+    }
+
+    pub fn objects_dir(&self) -> &Path {
+        &self.objects_dir
+    }
+}
+
+impl ObjectStore for LooseObjectStore {
+    fn read(&self, hash: &str) -> Result<RawObject> {
+        check_hash(hash)?;
+        let path = loose_object_path(&self.objects_dir, hash);
+        let file_content = match fs::read(&path) {
+            Ok(c) => c,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(GitObjectError::NotFoundError(hash.to_string()))
+            }
+            Err(e) => return Err(GitObjectError::IOError(e)),
+        };
+        let mut buf = vec![];
+        ZlibDecoder::new(&*file_content).read_to_end(&mut buf)?;
+        let nul = match buf.iter().position(|&c| c == 0) {
+            Some(i) => i,
+            None => {
+                return Err(GitObjectError::ParseError(format!(
+                    "object header is not terminated. {}",
+                    hash
+                )))
+            }
+        };
+        let (type_name, size) = parse_object_header(&buf[..nul])?;
+        let content = buf.split_off(nul + 1);
+        if content.len() as u64 != size {
+            return Err(GitObjectError::ParseError(format!(
+                "object size mismatch. {}: header {}, actual {}",
+                hash,
+                size,
+                content.len()
+            )));
+        }
+        Ok(RawObject { type_name, content })
+    }
+
+    fn write(&self, type_name: &str, content: &[u8]) -> Result<Vec<u8>> {
+        let mut reader = content;
+        self.write_stream(type_name, content.len() as u64, &mut reader)
+    }
+
+    fn contains(&self, hash: &str) -> bool {
+        check_hash(hash).is_ok() && loose_object_path(&self.objects_dir, hash).is_file()
+    }
+
+    fn header(&self, hash: &str) -> Result<(String, u64)> {
+        let reader = self.read_stream(hash)?;
+        Ok((reader.type_name().to_string(), reader.size()))
+    }
+
+    fn find_by_prefix(&self, prefix: &str) -> Result<Vec<Vec<u8>>> {
+        let entries = match fs::read_dir(&self.objects_dir) {
+            Ok(e) => e,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(GitObjectError::IOError(e)),
+        };
+        let mut found = vec![];
+        for dir in entries {
+            let dir = dir?;
+            let dir_name = dir.file_name().to_string_lossy().to_string();
+            let head = &prefix[..prefix.len().min(2)];
+            if dir_name.len() != 2 || !dir_name.starts_with(head) {
+                continue;
+            }
+            for file in fs::read_dir(dir.path())? {
+                let hex = format!("{}{}", dir_name, file?.file_name().to_string_lossy());
+                if hex.starts_with(prefix) && check_hash(&hex).is_ok() {
+                    found.push(str_to_hash(hex));
+                }
+            }
+        }
+        Ok(found)
+    }
+
+    fn read_stream(&self, hash: &str) -> Result<ObjectReader<'_>> {
+        check_hash(hash)?;
+        if !self.contains(hash) {
+            return Err(GitObjectError::NotFoundError(hash.to_string()));
+        }
+        ObjectReader::open_in(&self.objects_dir, hash)
+    }
+
+    fn write_stream(&self, type_name: &str, size: u64, reader: &mut dyn Read) -> Result<Vec<u8>> {
+        fs::create_dir_all(&self.objects_dir)?;
+        write_stream_in(&self.objects_dir, type_name, size, reader)
+    }
+}
+
+// keeps objects in memory; for tests and for embedding without a repository on disk.
+#[derive(Default)]
+pub struct MemoryObjectStore {
+    objects: RefCell<HashMap<String, RawObject>>,
+}
+
+impl MemoryObjectStore {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.objects.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.borrow().is_empty()
+    }
+}
+
+impl ObjectStore for MemoryObjectStore {
+    fn read(&self, hash: &str) -> Result<RawObject> {
+        match self.objects.borrow().get(hash) {
+            Some(raw) => Ok(raw.clone()),
+            None => Err(GitObjectError::NotFoundError(hash.to_string())),
+        }
+    }
+
+    fn write(&self, type_name: &str, content: &[u8]) -> Result<Vec<u8>> {
+        let hash = object_hash(type_name, content);
+        self.objects
+            .borrow_mut()
+            .entry(hash_to_str(&hash))
+            .or_insert_with(|| RawObject {
+                type_name: type_name.to_string(),
+                content: content.to_vec(),
+            });
+        Ok(hash)
+    }
+
+    fn contains(&self, hash: &str) -> bool {
+        self.objects.borrow().contains_key(hash)
+    }
+
+    fn header(&self, hash: &str) -> Result<(String, u64)> {
+        match self.objects.borrow().get(hash) {
+            Some(raw) => Ok((raw.type_name.clone(), raw.content.len() as u64)),
+            None => Err(GitObjectError::NotFoundError(hash.to_string())),
+        }
+    }
+
+    fn find_by_prefix(&self, prefix: &str) -> Result<Vec<Vec<u8>>> {
+        Ok(self
+            .objects
+            .borrow()
+            .keys()
+            .filter(|h| h.starts_with(prefix))
+            .map(|h| str_to_hash(h.clone()))
+            .collect())
+    }
+}
+
+// searches several stores in order; new objects go to the first one.
+#[derive(Default)]
+pub struct CompositeObjectStore {
+    stores: Vec<Box<dyn ObjectStore>>,
+}
+
+impl CompositeObjectStore {
+    pub fn new(stores: Vec<Box<dyn ObjectStore>>) -> Self {
+        CompositeObjectStore { stores }
+    }
+
+    pub fn push(&mut self, store: Box<dyn ObjectStore>) {
+        self.stores.push(store);
+    }
+
+    fn find(&self, hash: &str) -> Result<&dyn ObjectStore> {
+        match self.stores.iter().find(|s| s.contains(hash)) {
+            Some(s) => Ok(s.as_ref()),
+            None => Err(GitObjectError::NotFoundError(hash.to_string())),
+        }
+    }
+
+    fn first(&self) -> Result<&dyn ObjectStore> {
+        match self.stores.first() {
+            Some(s) => Ok(s.as_ref()),
+            None => Err(GitObjectError::WriteError(String::from(
+                "no object store to write to",
+            ))),
+        }
+    }
+}
+
+impl ObjectStore for CompositeObjectStore {
+    fn read(&self, hash: &str) -> Result<RawObject> {
+        self.find(hash)?.read(hash)
+    }
+
+    fn write(&self, type_name: &str, content: &[u8]) -> Result<Vec<u8>> {
+        self.first()?.write(type_name, content)
+    }
+
+    fn contains(&self, hash: &str) -> bool {
+        self.stores.iter().any(|s| s.contains(hash))
+    }
+
+    fn header(&self, hash: &str) -> Result<(String, u64)> {
+        self.find(hash)?.header(hash)
+    }
+
+    fn find_by_prefix(&self, prefix: &str) -> Result<Vec<Vec<u8>>> {
+        let mut found = vec![];
+        for store in &self.stores {
+            found.extend(store.find_by_prefix(prefix)?);
+        }
+        found.sort();
+        found.dedup();
+        Ok(found)
+    }
+
+    fn read_stream(&self, hash: &str) -> Result<ObjectReader<'_>> {
+        self.find(hash)?.read_stream(hash)
+    }
+
+    fn write_stream(&self, type_name: &str, size: u64, reader: &mut dyn Read) -> Result<Vec<u8>> {
+        self.first()?.write_stream(type_name, size, reader)
+    }
+}
+
+// the object database in `objects_dir`: loose objects first, then packs.
+pub fn open_object_store_at(objects_dir: &Path) -> CompositeObjectStore {
+    let mut store = CompositeObjectStore::new(vec![Box::new(LooseObjectStore::new(objects_dir))]);
+    match PackObjectStore::open(objects_dir) {
+        Ok(packs) => {
+            let bases = LooseObjectStore::new(objects_dir);
+            store.push(Box::new(packs.with_base_store(Box::new(bases))))
+        }
+        Err(e) => eprintln!("warning: ignoring packs: {:?}", e),
+    }
+    store
+}
+
+fn check_hash(hash: &str) -> Result<()> {
+    if hash.len() != 40 || !hash.bytes().all(|c| c.is_ascii_hexdigit()) {
+        return Err(GitObjectError::ParseError(format!(
+            "invalid object id: {}",
+            hash
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::object_store::*;
+    use crate::test_utils::temp_dir;
+
+    fn exercise(store: &dyn ObjectStore) {
+        let hash = store.write("blob", b"hogehoge\n").unwrap();
+        let hash = hash_to_str(&hash);
+        assert_eq!(hash, "e9bc11025c28829eedf6d30cd3b65628648cad5f");
+        assert!(store.contains(&hash));
+        assert_eq!(store.header(&hash).unwrap(), (String::from("blob"), 9));
+        let raw = store.read(&hash).unwrap();
+        assert_eq!(raw.type_name, "blob");
+        assert_eq!(raw.content, b"hogehoge\n".to_vec());
+
+        let mut body = vec![];
+        store
+            .read_stream(&hash)
+            .unwrap()
+            .read_to_end(&mut body)
+            .unwrap();
+        assert_eq!(body, b"hogehoge\n".to_vec());
+
+        assert_eq!(
+            store.find_by_prefix("e9bc").unwrap(),
+            vec![str_to_hash(hash.clone())]
+        );
+        assert_eq!(store.find_by_prefix(&hash).unwrap().len(), 1);
+        assert!(store.find_by_prefix("e9bd").unwrap().is_empty());
+
+        let missing = "0000000000000000000000000000000000000000";
+        assert!(!store.contains(missing));
+        match store.read(missing) {
+            Err(GitObjectError::NotFoundError(_)) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_memory_store() {
+        let store = MemoryObjectStore::new();
+        exercise(&store);
+        assert_eq!(store.len(), 1);
+    }
+
+    #[test]
+    fn test_loose_store() {
+        let dir = temp_dir("loose-store");
+        let store = LooseObjectStore::new(&dir);
+        exercise(&store);
+        assert!(dir
+            .join("e9/bc11025c28829eedf6d30cd3b65628648cad5f")
+            .is_file());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_composite_store() {
+        let lower = MemoryObjectStore::new();
+        let only_lower = hash_to_str(&lower.write("blob", b"lower\n").unwrap());
+        let store =
+            CompositeObjectStore::new(vec![Box::new(MemoryObjectStore::new()), Box::new(lower)]);
+        exercise(&store);
+        assert!(store.contains(&only_lower));
+        assert_eq!(
+            store.read(&only_lower).unwrap().content,
+            b"lower\n".to_vec()
+        );
+
+        assert!(CompositeObjectStore::default().write("blob", b"").is_err());
+    }
+}
@@ -0,0 +1,246 @@
+use crate::git_object::GitObjectError;
+use crate::utils::*;
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use sha1::{Digest, Sha1};
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, Read, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+type Result<T> = std::result::Result<T, GitObjectError>;
+
+const BUFFER_SIZE: usize = 64 * 1024;
+
+// computes the object id of `size` bytes read from `reader` without keeping them in memory.
+pub fn hash_stream<R: Read>(type_name: &str, size: u64, reader: R) -> Result<Vec<u8>> {
+    let mut hasher = Sha1::new();
+    hasher.update(object_header(type_name, size as usize));
+    copy_exact(reader, size, |buf| {
+        hasher.update(buf);
+        Ok(())
+    })?;
+    Ok(hasher.finalize().to_vec())
+}
+
+// stores `size` bytes read from `reader` as a loose object and returns its id.
+// the object is compressed into a temporary file while it is hashed and renamed into
+// place afterwards, so readers never observe a partially written object.
+pub fn write_stream_in<R: Read>(
+    objects_dir: &Path,
+    type_name: &str,
+    size: u64,
+    reader: R,
+) -> Result<Vec<u8>> {
+    let tmp_path = temporary_path(objects_dir);
+    let file = fs::File::create(&tmp_path)?;
+    let result = compress_to(file, type_name, size, reader);
+    let hash = match result {
+        Ok(h) => h,
+        Err(e) => {
+            let _ = fs::remove_file(&tmp_path);
+            return Err(e);
+        }
+    };
+
+    let path = loose_object_path(objects_dir, &hash_to_str(&hash));
+    if path.exists() {
+        fs::remove_file(&tmp_path)?;
+        return Ok(hash);
+    }
+    fs::create_dir_all(path.parent().unwrap())?;
+    fs::rename(&tmp_path, &path)?;
+    Ok(hash)
+}
+
+fn compress_to<R: Read>(file: fs::File, type_name: &str, size: u64, reader: R) -> Result<Vec<u8>> {
+    let header = object_header(type_name, size as usize);
+    let mut hasher = Sha1::new();
+    let mut encoder = ZlibEncoder::new(io::BufWriter::new(file), Compression::default());
+    hasher.update(&header);
+    encoder.write_all(&header)?;
+    copy_exact(reader, size, |buf| {
+        hasher.update(buf);
+        encoder.write_all(buf)?;
+        Ok(())
+    })?;
+    encoder.finish()?.flush()?;
+    Ok(hasher.finalize().to_vec())
+}
+
+// feeds exactly `size` bytes of `reader` to `f`, failing if the input is shorter or longer.
+fn copy_exact<R: Read, F: FnMut(&[u8]) -> Result<()>>(
+    mut reader: R,
+    size: u64,
+    mut f: F,
+) -> Result<()> {
+    let mut buf = vec![0; BUFFER_SIZE];
+    let mut remaining = size;
+    while remaining > 0 {
+        let want = remaining.min(buf.len() as u64) as usize;
+        let n = match reader.read(&mut buf[..want]) {
+            Ok(0) => {
+                return Err(GitObjectError::CustomIOError(format!(
+                    "input ended {} bytes short of the expected size {}",
+                    remaining, size
+                )))
+            }
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(GitObjectError::IOError(e)),
+        };
+        f(&buf[..n])?;
+        remaining -= n as u64;
+    }
+    if reader.read(&mut [0])? != 0 {
+        return Err(GitObjectError::CustomIOError(format!(
+            "input is longer than the expected size {}",
+            size
+        )));
+    }
+    Ok(())
+}
+
+fn temporary_path(objects_dir: &Path) -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let n = COUNTER.fetch_add(1, Ordering::SeqCst);
+    objects_dir.join(format!("tmp_obj_{}_{}", process::id(), n))
+}
+
+pub fn loose_object_path(objects_dir: &Path, hash: &str) -> PathBuf {
+    objects_dir.join(&hash[0..2]).join(&hash[2..])
+}
+
+// reads an object incrementally: the header is known up front and the body is
+// produced on demand through `Read`.
+pub struct ObjectReader<'a> {
+    type_name: String,
+    size: u64,
+    body: io::Take<Box<dyn Read + 'a>>,
+}
+
+impl<'a> ObjectReader<'a> {
+    pub fn new(type_name: &str, size: u64, body: Box<dyn Read + 'a>) -> Self {
+        ObjectReader {
+            type_name: type_name.to_string(),
+            size,
+            body: body.take(size),
+        }
+    }
+
+    pub fn from_bytes(type_name: &str, content: Vec<u8>) -> Self {
+        let size = content.len() as u64;
+        ObjectReader::new(type_name, size, Box::new(io::Cursor::new(content)))
+    }
+
+    // opens a loose object; only the header is inflated here.
+    pub fn open_in(objects_dir: &Path, hash: &str) -> Result<Self> {
+        let path = loose_object_path(objects_dir, hash);
+        let file = match fs::File::open(&path) {
+            Ok(f) => f,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(GitObjectError::CustomIOError(format!(
+                    "the file {} does not exists.",
+                    path.display()
+                )))
+            }
+            Err(e) => return Err(GitObjectError::IOError(e)),
+        };
+        let mut decoder = BufReader::new(ZlibDecoder::new(BufReader::new(file)));
+        let mut header = vec![];
+        decoder.read_until(0, &mut header)?;
+        if header.pop() != Some(0) {
+            return Err(GitObjectError::ParseError(format!(
+                "object header is not terminated. {}",
+                hash
+            )));
+        }
+        let (type_name, size) = parse_object_header(&header)?;
+        Ok(ObjectReader::new(&type_name, size, Box::new(decoder)))
+    }
+
+    pub fn type_name(&self) -> &str {
+        &self.type_name
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+impl<'a> Read for ObjectReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.body.read(buf)?;
+        if n == 0 && !buf.is_empty() && self.body.limit() > 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "object is shorter than its header says",
+            ));
+        }
+        Ok(n)
+    }
+}
+
+// parses "<type> <size>" (without the terminating NUL).
+pub fn parse_object_header(header: &[u8]) -> Result<(String, u64)> {
+    let header = std::str::from_utf8(header)
+        .map_err(|_| GitObjectError::ParseError(String::from("object header is not utf-8")))?;
+    let mut it = header.splitn(2, ' ');
+    let type_name = it.next().unwrap_or_default();
+    match it.next().and_then(|n| n.parse::<u64>().ok()) {
+        Some(size) if !type_name.is_empty() => Ok((type_name.to_string(), size)),
+        _ => Err(GitObjectError::ParseError(format!(
+            "invalid object header: {}",
+            header
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::object_stream::*;
+    use crate::test_utils::temp_dir;
+
+    #[test]
+    fn test_hash_stream() {
+        let content: Vec<u8> = (0..=255).collect();
+        let hash = hash_stream("blob", content.len() as u64, &content[..]).unwrap();
+        assert_eq!(
+            hash_to_str(&hash),
+            "c86626638e0bc8cf47ca49bb1525b40e9737ee64"
+        );
+        assert!(hash_stream("blob", 257, &content[..]).is_err());
+        assert!(hash_stream("blob", 255, &content[..]).is_err());
+    }
+
+    #[test]
+    fn test_write_and_read_stream() {
+        let dir = temp_dir("stream");
+        // larger than the copy buffer so several chunks go through the encoder.
+        let content: Vec<u8> = (0..3 * BUFFER_SIZE + 7).map(|i| (i % 251) as u8).collect();
+        let hash = write_stream_in(&dir, "blob", content.len() as u64, &content[..]).unwrap();
+        assert_eq!(hash, object_hash("blob", &content));
+        // writing again keeps the existing object and leaves no temporary file behind.
+        write_stream_in(&dir, "blob", content.len() as u64, &content[..]).unwrap();
+        let leftovers = fs::read_dir(&dir)
+            .unwrap()
+            .filter(|e| {
+                let name = e.as_ref().unwrap().file_name();
+                name.to_string_lossy().starts_with("tmp_obj_")
+            })
+            .count();
+        assert_eq!(leftovers, 0);
+
+        let mut reader = ObjectReader::open_in(&dir, &hash_to_str(&hash)).unwrap();
+        assert_eq!(reader.type_name(), "blob");
+        assert_eq!(reader.size(), content.len() as u64);
+        let mut body = vec![];
+        reader.read_to_end(&mut body).unwrap();
+        assert_eq!(body, content);
+
+        assert!(ObjectReader::open_in(&dir, "0000000000000000000000000000000000000000").is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,1143 @@
+use crate::delta::{apply_delta, delta_sizes};
+use crate::git_object::GitObjectError;
+use crate::object_store::{ObjectStore, RawObject};
+use crate::object_stream::ObjectReader;
+use crate::utils::*;
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use sha1::{Digest, Sha1};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet, VecDeque},
+    fs,
+    io::{self, BufReader, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+type Result<T> = std::result::Result<T, GitObjectError>;
+
+pub const OBJ_COMMIT: u8 = 1;
+pub const OBJ_TREE: u8 = 2;
+pub const OBJ_BLOB: u8 = 3;
+pub const OBJ_TAG: u8 = 4;
+pub const OBJ_OFS_DELTA: u8 = 6;
+pub const OBJ_REF_DELTA: u8 = 7;
+
+const IDX_MAGIC: &[u8] = b"\xfftOc";
+
+pub fn type_name_of(type_code: u8) -> Option<&'static str> {
+    match type_code {
+        OBJ_COMMIT => Some("commit"),
+        OBJ_TREE => Some("tree"),
+        OBJ_BLOB => Some("blob"),
+        OBJ_TAG => Some("tag"),
+        _ => None,
+    }
+}
+
+pub fn type_code_of(type_name: &str) -> Option<u8> {
+    match type_name {
+        "commit" => Some(OBJ_COMMIT),
+        "tree" => Some(OBJ_TREE),
+        "blob" => Some(OBJ_BLOB),
+        "tag" => Some(OBJ_TAG),
+        _ => None,
+    }
+}
+
+// a version 2 pack index: object ids sorted with a 256 entry fanout table, their crc32s
+// and pack offsets. offsets that do not fit in 31 bits live in a separate 64-bit table.
+#[derive(Debug)]
+pub struct PackIndex {
+    fanout: Vec<u32>,
+    hashes: Vec<u8>,
+    crcs: Vec<u32>,
+    offsets: Vec<u64>,
+    pack_checksum: Vec<u8>,
+}
+
+impl PackIndex {
+    pub fn open(path: &Path) -> Result<Self> {
+        PackIndex::parse(&fs::read(path)?)
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let corrupt =
+            |what: &str| GitObjectError::ParseError(format!("corrupt pack index: {}", what));
+        if data.len() < 8 + 256 * 4 + 40 || &data[..4] != IDX_MAGIC {
+            return Err(corrupt("bad signature"));
+        }
+        if be_u32(&data[4..]) != 2 {
+            return Err(GitObjectError::NotImplementError(format!(
+                "pack index version {}",
+                be_u32(&data[4..])
+            )));
+        }
+        let fanout: Vec<u32> = (0..256).map(|i| be_u32(&data[8 + i * 4..])).collect();
+        if fanout.windows(2).any(|w| w[0] > w[1]) {
+            return Err(corrupt("fanout table is not monotonic"));
+        }
+        let count = fanout[255] as usize;
+        let hashes_start = 8 + 256 * 4;
+        let crcs_start = hashes_start + count * 20;
+        let offsets_start = crcs_start + count * 4;
+        let large_start = offsets_start + count * 4;
+        if data.len() < large_start + 40 {
+            return Err(corrupt("truncated"));
+        }
+        let large_count = (data.len() - 40 - large_start) / 8;
+
+        let mut offsets = Vec::with_capacity(count);
+        for i in 0..count {
+            let offset = be_u32(&data[offsets_start + i * 4..]);
+            if offset & 0x8000_0000 == 0 {
+                offsets.push(offset as u64);
+                continue;
+            }
+            let large = (offset & 0x7fff_ffff) as usize;
+            if large >= large_count {
+                return Err(corrupt("64-bit offset out of range"));
+            }
+            offsets.push(be_u64(&data[large_start + large * 8..]));
+        }
+
+        // the trailing checksum is left to fsck, hashing the whole index on every open
+        // would cost more than the lookups it serves.
+        let trailer = data.len() - 40;
+        Ok(PackIndex {
+            fanout,
+            hashes: data[hashes_start..crcs_start].to_vec(),
+            crcs: (0..count)
+                .map(|i| be_u32(&data[crcs_start + i * 4..]))
+                .collect(),
+            offsets,
+            pack_checksum: data[trailer..trailer + 20].to_vec(),
+        })
+    }
+
+    // whether the index file `data` ends with the checksum of everything before it.
+    pub fn verify_checksum(data: &[u8]) -> bool {
+        data.len() >= 20 && hash(&data[..data.len() - 20]) == data[data.len() - 20..]
+    }
+
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    pub fn hash(&self, i: usize) -> &[u8] {
+        &self.hashes[i * 20..(i + 1) * 20]
+    }
+
+    pub fn offset(&self, i: usize) -> u64 {
+        self.offsets[i]
+    }
+
+    pub fn crc32(&self, i: usize) -> u32 {
+        self.crcs[i]
+    }
+
+    pub fn pack_checksum(&self) -> &[u8] {
+        &self.pack_checksum
+    }
+
+    // positions of the ids whose hex form starts with `prefix`.
+    pub fn find_prefix(&self, prefix: &str) -> Vec<usize> {
+        // the first one or two hex digits select a range of the fanout table.
+        let (lo, hi) = match u8::from_str_radix(prefix.get(..2).unwrap_or(""), 16) {
+            Ok(b) => (b as usize, b as usize),
+            Err(_) => match u8::from_str_radix(prefix.get(..1).unwrap_or(""), 16) {
+                Ok(n) => ((n << 4) as usize, ((n << 4) | 0x0f) as usize),
+                Err(_) => (0, 255),
+            },
+        };
+        let start = if lo == 0 {
+            0
+        } else {
+            self.fanout[lo - 1] as usize
+        };
+        (start..self.fanout[hi] as usize)
+            .filter(|&i| hash_to_str(self.hash(i)).starts_with(prefix))
+            .collect()
+    }
+
+    // position of `hash` (20 raw bytes) in the index.
+    pub fn lookup(&self, hash: &[u8]) -> Option<usize> {
+        let first = *hash.first()? as usize;
+        let mut lo = if first == 0 {
+            0
+        } else {
+            self.fanout[first - 1] as usize
+        };
+        let mut hi = self.fanout[first] as usize;
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            match self.hash(mid).cmp(hash) {
+                std::cmp::Ordering::Less => lo = mid + 1,
+                std::cmp::Ordering::Greater => hi = mid,
+                std::cmp::Ordering::Equal => return Some(mid),
+            }
+        }
+        None
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DeltaBase {
+    // absolute pack offset of the base entry.
+    Offset(u64),
+    Ref(Vec<u8>),
+}
+
+// the variable length header in front of each pack entry.
+#[derive(Debug, PartialEq)]
+pub struct EntryHeader {
+    pub type_code: u8,
+    // inflated size of the entry data (for deltas, the size of the delta itself).
+    pub size: u64,
+    pub base: Option<DeltaBase>,
+    // bytes taken by the header, including the delta base reference.
+    pub header_len: usize,
+}
+
+pub fn parse_entry_header(data: &[u8], offset: u64) -> Result<EntryHeader> {
+    let truncated = || GitObjectError::ParseError(format!("truncated pack entry at {}", offset));
+    let mut i = 0;
+    let mut c = *data.get(i).ok_or_else(truncated)?;
+    i += 1;
+    let type_code = (c >> 4) & 7;
+    let mut size = (c & 0x0f) as u64;
+    let mut shift = 4;
+    while c & 0x80 != 0 {
+        c = *data.get(i).ok_or_else(truncated)?;
+        i += 1;
+        if shift > 57 {
+            return Err(GitObjectError::ParseError(format!(
+                "pack entry size overflows at {}",
+                offset
+            )));
+        }
+        size |= ((c & 0x7f) as u64) << shift;
+        shift += 7;
+    }
+
+    let base = match type_code {
+        OBJ_OFS_DELTA => {
+            let (distance, len) = decode_varint(&data[i..]).ok_or_else(truncated)?;
+            i += len;
+            if distance == 0 || distance > offset {
+                return Err(GitObjectError::ParseError(format!(
+                    "delta base offset out of bounds at {}",
+                    offset
+                )));
+            }
+            Some(DeltaBase::Offset(offset - distance))
+        }
+        OBJ_REF_DELTA => {
+            let base = data.get(i..i + 20).ok_or_else(truncated)?.to_vec();
+            i += 20;
+            Some(DeltaBase::Ref(base))
+        }
+        OBJ_COMMIT | OBJ_TREE | OBJ_BLOB | OBJ_TAG => None,
+        _ => {
+            return Err(GitObjectError::ParseError(format!(
+                "unknown pack entry type {} at {}",
+                type_code, offset
+            )))
+        }
+    };
+    Ok(EntryHeader {
+        type_code,
+        size,
+        base,
+        header_len: i,
+    })
+}
+
+// a .pack file together with its .idx.
+pub struct PackFile {
+    pack_path: PathBuf,
+    index: PackIndex,
+}
+
+impl PackFile {
+    pub fn open(idx_path: &Path) -> Result<Self> {
+        let index = PackIndex::open(idx_path)?;
+        let pack_path = idx_path.with_extension("pack");
+        let mut file = fs::File::open(&pack_path)?;
+        let mut header = [0; 12];
+        file.read_exact(&mut header)?;
+        if &header[..4] != b"PACK" || !matches!(be_u32(&header[4..]), 2 | 3) {
+            return Err(GitObjectError::ParseError(format!(
+                "{} is not a version 2 or 3 pack",
+                pack_path.display()
+            )));
+        }
+        if be_u32(&header[8..]) as usize != index.len() {
+            return Err(GitObjectError::ParseError(format!(
+                "{} does not match its index",
+                pack_path.display()
+            )));
+        }
+        let mut trailer = [0; 20];
+        file.seek(SeekFrom::End(-20))?;
+        file.read_exact(&mut trailer)?;
+        if trailer[..] != index.pack_checksum()[..] {
+            return Err(GitObjectError::ParseError(format!(
+                "{} checksum does not match its index",
+                pack_path.display()
+            )));
+        }
+        Ok(PackFile { pack_path, index })
+    }
+
+    // a pack whose index was built rather than read, as index-pack does while it still
+    // learns which objects the pack holds. nothing is checked against the pack.
+    pub fn with_index(pack_path: PathBuf, index: PackIndex) -> Self {
+        PackFile { pack_path, index }
+    }
+
+    pub fn index(&self) -> &PackIndex {
+        &self.index
+    }
+
+    pub fn pack_path(&self) -> &Path {
+        &self.pack_path
+    }
+
+    pub fn find_offset(&self, hash: &[u8]) -> Option<u64> {
+        self.index.lookup(hash).map(|i| self.index.offset(i))
+    }
+
+    pub fn entry_header(&self, offset: u64) -> Result<EntryHeader> {
+        let mut file = fs::File::open(&self.pack_path)?;
+        file.seek(SeekFrom::Start(offset))?;
+        // type and size take at most 10 bytes, a delta base at most 20 more.
+        let mut buf = Vec::with_capacity(32);
+        file.take(32).read_to_end(&mut buf)?;
+        parse_entry_header(&buf, offset)
+    }
+
+    // a reader over the inflated data of the entry at `offset`.
+    pub fn entry_data(&self, offset: u64) -> Result<(EntryHeader, impl Read)> {
+        let header = self.entry_header(offset)?;
+        let mut file = fs::File::open(&self.pack_path)?;
+        file.seek(SeekFrom::Start(offset + header.header_len as u64))?;
+        let size = header.size;
+        Ok((header, ZlibDecoder::new(BufReader::new(file)).take(size)))
+    }
+
+    pub fn read_entry_data(&self, offset: u64) -> Result<(EntryHeader, Vec<u8>)> {
+        let (header, mut reader) = self.entry_data(offset)?;
+        let mut data = Vec::with_capacity(header.size as usize);
+        reader.read_to_end(&mut data)?;
+        if data.len() as u64 != header.size {
+            return Err(GitObjectError::ParseError(format!(
+                "pack entry at {} is shorter than its header says",
+                offset
+            )));
+        }
+        Ok((header, data))
+    }
+}
+
+// deltas are not followed deeper than this; real chains are far shorter (git writes at
+// most 50 by default), so anything longer is treated as corruption.
+const MAX_DELTA_DEPTH: usize = 10000;
+const DELTA_BASE_CACHE_LIMIT: usize = 32 * 1024 * 1024;
+
+// recently used delta bases keyed by (pack, offset), evicted oldest first once the
+// total content size exceeds the limit.
+struct DeltaBaseCache {
+    entries: HashMap<(usize, u64), Rc<RawObject>>,
+    order: VecDeque<(usize, u64)>,
+    bytes: usize,
+    limit: usize,
+}
+
+impl DeltaBaseCache {
+    fn new(limit: usize) -> Self {
+        DeltaBaseCache {
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+            bytes: 0,
+            limit,
+        }
+    }
+
+    fn get(&self, key: (usize, u64)) -> Option<Rc<RawObject>> {
+        self.entries.get(&key).cloned()
+    }
+
+    fn insert(&mut self, key: (usize, u64), object: Rc<RawObject>) {
+        if object.content.len() > self.limit / 4 || self.entries.contains_key(&key) {
+            return;
+        }
+        self.bytes += object.content.len();
+        self.entries.insert(key, object);
+        self.order.push_back(key);
+        while self.bytes > self.limit {
+            let oldest = match self.order.pop_front() {
+                Some(k) => k,
+                None => break,
+            };
+            if let Some(o) = self.entries.remove(&oldest) {
+                self.bytes -= o.content.len();
+            }
+        }
+    }
+}
+
+// read-only access to every pack under objects/pack. REF_DELTA bases that are in none
+// of the packs are looked up in the base store (normally the loose objects).
+pub struct PackObjectStore {
+    packs: Vec<PackFile>,
+    base_store: Option<Box<dyn ObjectStore>>,
+    cache: RefCell<DeltaBaseCache>,
+}
+
+impl Default for PackObjectStore {
+    fn default() -> Self {
+        PackObjectStore {
+            packs: vec![],
+            base_store: None,
+            cache: RefCell::new(DeltaBaseCache::new(DELTA_BASE_CACHE_LIMIT)),
+        }
+    }
+}
+
+impl PackObjectStore {
+    pub fn open(objects_dir: &Path) -> Result<Self> {
+        let pack_dir = objects_dir.join("pack");
+        let mut idx_paths = match fs::read_dir(&pack_dir) {
+            Ok(entries) => entries
+                .filter_map(|e| e.ok().map(|e| e.path()))
+                .filter(|p| p.extension() == Some("idx".as_ref()))
+                .collect::<Vec<_>>(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => vec![],
+            Err(e) => return Err(GitObjectError::IOError(e)),
+        };
+        idx_paths.sort();
+        let mut store = PackObjectStore::default();
+        for path in idx_paths {
+            // a pack that can not be read is left out, not every pack with it.
+            match PackFile::open(&path) {
+                Ok(pack) => store.packs.push(pack),
+                Err(e) => eprintln!("warning: ignoring pack {}: {:?}", path.display(), e),
+            }
+        }
+        Ok(store)
+    }
+
+    pub fn with_base_store(mut self, store: Box<dyn ObjectStore>) -> Self {
+        self.base_store = Some(store);
+        self
+    }
+
+    pub fn add_pack(&mut self, pack: PackFile) {
+        self.packs.push(pack);
+    }
+
+    pub fn packs(&self) -> &[PackFile] {
+        &self.packs
+    }
+
+    // pack number and offset of the object with the raw 20 byte id.
+    fn locate(&self, hash: &[u8]) -> Option<(usize, u64)> {
+        self.packs
+            .iter()
+            .enumerate()
+            .find_map(|(i, pack)| pack.find_offset(hash).map(|offset| (i, offset)))
+    }
+
+    fn find(&self, hash: &str) -> Result<(usize, u64)> {
+        if hash.len() == 40 && hash.bytes().all(|c| c.is_ascii_hexdigit()) {
+            if let Some(found) = self.locate(&str_to_hash(hash.to_string())) {
+                return Ok(found);
+            }
+        }
+        Err(GitObjectError::NotFoundError(hash.to_string()))
+    }
+
+    fn external_base(&self, hash: &[u8]) -> Result<&dyn ObjectStore> {
+        let hex = hash_to_str(hash);
+        match &self.base_store {
+            Some(store) if store.contains(&hex) => Ok(store.as_ref()),
+            _ => Err(GitObjectError::NotFoundError(format!(
+                "delta base {} is missing",
+                hex
+            ))),
+        }
+    }
+
+    // reads the entry at `offset` of pack `pack`, resolving its delta chain.
+    pub fn read_entry(&self, pack: usize, offset: u64) -> Result<RawObject> {
+        let mut chain: Vec<((usize, u64), Vec<u8>)> = vec![];
+        let mut visited = HashSet::new();
+        let mut key = (pack, offset);
+        let (mut base, mut base_key) = loop {
+            if !visited.insert(key) {
+                return Err(GitObjectError::ParseError(format!(
+                    "delta chain of the entry at {} in {} loops back to {}",
+                    offset,
+                    self.packs[pack].pack_path.display(),
+                    key.1
+                )));
+            }
+            if chain.len() > MAX_DELTA_DEPTH {
+                return Err(GitObjectError::ParseError(format!(
+                    "delta chain of the entry at {} is deeper than {}",
+                    offset, MAX_DELTA_DEPTH
+                )));
+            }
+            if let Some(cached) = self.cache.borrow().get(key) {
+                break (cached, Some(key));
+            }
+            let (header, data) = self.packs[key.0].read_entry_data(key.1)?;
+            match header.base {
+                None => {
+                    let type_name = type_name_of(header.type_code).unwrap();
+                    let object = RawObject {
+                        type_name: type_name.to_string(),
+                        content: data,
+                    };
+                    break (Rc::new(object), Some(key));
+                }
+                Some(DeltaBase::Offset(base_offset)) => {
+                    chain.push((key, data));
+                    key = (key.0, base_offset);
+                }
+                Some(DeltaBase::Ref(base_hash)) => {
+                    chain.push((key, data));
+                    match self.locate(&base_hash) {
+                        Some(found) => key = found,
+                        None => {
+                            let store = self.external_base(&base_hash)?;
+                            break (Rc::new(store.read(&hash_to_str(&base_hash))?), None);
+                        }
+                    }
+                }
+            }
+        };
+
+        // apply the deltas from the innermost one outwards, remembering the bases.
+        while let Some((key, delta)) = chain.pop() {
+            let content = apply_delta(&base.content, &delta).map_err(|e| match e {
+                GitObjectError::ParseError(m) => GitObjectError::ParseError(format!(
+                    "{} (entry at {} in {})",
+                    m,
+                    key.1,
+                    self.packs[key.0].pack_path.display()
+                )),
+                e => e,
+            })?;
+            if let Some(k) = base_key {
+                self.cache.borrow_mut().insert(k, base.clone());
+            }
+            base = Rc::new(RawObject {
+                type_name: base.type_name.clone(),
+                content,
+            });
+            base_key = Some(key);
+        }
+        Ok(Rc::try_unwrap(base).unwrap_or_else(|rc| (*rc).clone()))
+    }
+
+    // type name of the entry, following delta bases without applying any delta.
+    fn entry_type(&self, pack: usize, offset: u64) -> Result<String> {
+        let mut visited = HashSet::new();
+        let mut key = (pack, offset);
+        loop {
+            if !visited.insert(key) || visited.len() > MAX_DELTA_DEPTH {
+                return Err(GitObjectError::ParseError(format!(
+                    "delta chain of the entry at {} does not end in a base object",
+                    offset
+                )));
+            }
+            let header = self.packs[key.0].entry_header(key.1)?;
+            match header.base {
+                None => return Ok(type_name_of(header.type_code).unwrap().to_string()),
+                Some(DeltaBase::Offset(base_offset)) => key = (key.0, base_offset),
+                Some(DeltaBase::Ref(base_hash)) => match self.locate(&base_hash) {
+                    Some(found) => key = found,
+                    None => {
+                        let store = self.external_base(&base_hash)?;
+                        return Ok(store.header(&hash_to_str(&base_hash))?.0);
+                    }
+                },
+            }
+        }
+    }
+}
+
+impl ObjectStore for PackObjectStore {
+    fn read(&self, hash: &str) -> Result<RawObject> {
+        let (pack, offset) = self.find(hash)?;
+        self.read_entry(pack, offset)
+    }
+
+    fn write(&self, _type_name: &str, _content: &[u8]) -> Result<Vec<u8>> {
+        Err(GitObjectError::WriteError(String::from(
+            "packs are read-only",
+        )))
+    }
+
+    fn contains(&self, hash: &str) -> bool {
+        self.find(hash).is_ok()
+    }
+
+    fn header(&self, hash: &str) -> Result<(String, u64)> {
+        let (pack, offset) = self.find(hash)?;
+        let header = self.packs[pack].entry_header(offset)?;
+        match type_name_of(header.type_code) {
+            Some(type_name) => Ok((type_name.to_string(), header.size)),
+            None => {
+                // the result size is recorded at the start of the delta.
+                let (_, delta) = self.packs[pack].read_entry_data(offset)?;
+                let (_, size) = delta_sizes(&delta)?;
+                Ok((self.entry_type(pack, offset)?, size))
+            }
+        }
+    }
+
+    fn find_by_prefix(&self, prefix: &str) -> Result<Vec<Vec<u8>>> {
+        let mut found: Vec<Vec<u8>> = vec![];
+        for pack in &self.packs {
+            let index = pack.index();
+            found.extend(
+                index
+                    .find_prefix(prefix)
+                    .into_iter()
+                    .map(|i| index.hash(i).to_vec()),
+            );
+        }
+        found.sort();
+        found.dedup();
+        Ok(found)
+    }
+
+    fn read_stream(&self, hash: &str) -> Result<ObjectReader<'_>> {
+        let (pack, offset) = self.find(hash)?;
+        let (header, reader) = self.packs[pack].entry_data(offset)?;
+        match type_name_of(header.type_code) {
+            Some(type_name) => Ok(ObjectReader::new(type_name, header.size, Box::new(reader))),
+            None => {
+                let raw = self.read_entry(pack, offset)?;
+                Ok(ObjectReader::from_bytes(&raw.type_name, raw.content))
+            }
+        }
+    }
+}
+
+// the variable length type and size header written in front of each entry.
+pub fn encode_entry_header(type_code: u8, size: u64) -> Vec<u8> {
+    let mut v = vec![(type_code << 4) | (size & 0x0f) as u8];
+    let mut size = size >> 4;
+    while size > 0 {
+        *v.last_mut().unwrap() |= 0x80;
+        v.push((size & 0x7f) as u8);
+        size >>= 7;
+    }
+    v
+}
+
+// writes a pack to `out` entry by entry, keeping the offset, the checksum of everything
+// written so far and the crc32 of the current entry for the index.
+pub struct PackWriter<W: Write> {
+    out: W,
+    hasher: Sha1,
+    offset: u64,
+    crc: u32,
+}
+
+impl<W: Write> PackWriter<W> {
+    // starts the pack with its header.
+    pub fn new(out: W, count: u32) -> Result<Self> {
+        let mut writer = PackWriter {
+            out,
+            hasher: Sha1::new(),
+            offset: 0,
+            crc: 0,
+        };
+        writer.write_all(b"PACK")?;
+        writer.write_all(&2u32.to_be_bytes())?;
+        writer.write_all(&count.to_be_bytes())?;
+        Ok(writer)
+    }
+
+    // continues a pack after its last entry, at `offset`, to append entries to it. the
+    // checksum only covers what is written from here on, so the caller computes the pack's.
+    pub fn resume(out: W, offset: u64) -> Self {
+        PackWriter {
+            out,
+            hasher: Sha1::new(),
+            offset,
+            crc: 0,
+        }
+    }
+
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    // starts an entry at the current offset, which is returned.
+    pub fn begin_entry(&mut self) -> u64 {
+        self.crc = 0;
+        self.offset
+    }
+
+    // the crc32 of what was written since begin_entry.
+    pub fn entry_crc32(&self) -> u32 {
+        self.crc
+    }
+
+    // writes an entry whose (inflated) data is read from `reader`, which must produce
+    // exactly `size` bytes.
+    pub fn write_entry(&mut self, header: &[u8], size: u64, reader: &mut dyn Read) -> Result<()> {
+        self.write_all(header)?;
+        let mut encoder = ZlibEncoder::new(&mut *self, Compression::default());
+        let copied = io::copy(&mut reader.take(size), &mut encoder)?;
+        encoder.finish()?;
+        if copied != size {
+            return Err(GitObjectError::CustomIOError(format!(
+                "object ended {} bytes short of its size {}",
+                size - copied,
+                size
+            )));
+        }
+        Ok(())
+    }
+
+    // appends the checksum and returns it.
+    pub fn finish(mut self) -> Result<Vec<u8>> {
+        let checksum = self.hasher.finalize().to_vec();
+        self.out.write_all(&checksum)?;
+        self.out.flush()?;
+        Ok(checksum)
+    }
+}
+
+impl<W: Write> Write for PackWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.out.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.crc = crc32_update(self.crc, &buf[..n]);
+        self.offset += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+// an object's record in a pack index.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexEntry {
+    pub hash: Vec<u8>,
+    pub crc32: u32,
+    pub offset: u64,
+}
+
+// serializes a version 2 index for the pack with `pack_checksum`; `entries` are sorted
+// by object id in place.
+pub fn encode_pack_index(entries: &mut [IndexEntry], pack_checksum: &[u8]) -> Vec<u8> {
+    entries.sort_by(|a, b| a.hash.cmp(&b.hash));
+    let mut idx = IDX_MAGIC.to_vec();
+    idx.extend_from_slice(&2u32.to_be_bytes());
+    let mut n = 0;
+    for b in 0..256 {
+        while n < entries.len() && entries[n].hash[0] as usize <= b {
+            n += 1;
+        }
+        idx.extend_from_slice(&(n as u32).to_be_bytes());
+    }
+    for e in entries.iter() {
+        idx.extend_from_slice(&e.hash);
+    }
+    for e in entries.iter() {
+        idx.extend_from_slice(&e.crc32.to_be_bytes());
+    }
+    let mut large = vec![];
+    for e in entries.iter() {
+        let v = if e.offset < 0x8000_0000 {
+            e.offset as u32
+        } else {
+            large.push(e.offset);
+            0x8000_0000 | (large.len() - 1) as u32
+        };
+        idx.extend_from_slice(&v.to_be_bytes());
+    }
+    for offset in large {
+        idx.extend_from_slice(&offset.to_be_bytes());
+    }
+    idx.extend_from_slice(pack_checksum);
+    let checksum = hash(&idx);
+    idx.extend_from_slice(&checksum);
+    idx
+}
+
+fn be_u32(b: &[u8]) -> u32 {
+    u32::from_be_bytes([b[0], b[1], b[2], b[3]])
+}
+
+fn be_u64(b: &[u8]) -> u64 {
+    ((be_u32(b) as u64) << 32) | be_u32(&b[4..]) as u64
+}
+
+#[cfg(test)]
+mod test {
+    use crate::pack::*;
+    use crate::test_utils::temp_dir;
+    use crate::utils::compress;
+
+    enum TestBase {
+        // index of an earlier entry in the same pack.
+        Ofs(usize),
+        Ref(Vec<u8>),
+    }
+
+    struct TestEntry {
+        id: Vec<u8>,
+        type_code: u8,
+        base: Option<TestBase>,
+        data: Vec<u8>,
+    }
+
+    fn object(type_name: &str, content: &[u8]) -> TestEntry {
+        TestEntry {
+            id: object_hash(type_name, content),
+            type_code: type_code_of(type_name).unwrap(),
+            base: None,
+            data: content.to_vec(),
+        }
+    }
+
+    fn delta(id: Vec<u8>, base: TestBase, data: Vec<u8>) -> TestEntry {
+        let type_code = match base {
+            TestBase::Ofs(_) => OBJ_OFS_DELTA,
+            TestBase::Ref(_) => OBJ_REF_DELTA,
+        };
+        TestEntry {
+            id,
+            type_code,
+            base: Some(base),
+            data,
+        }
+    }
+
+    // builds a pack and a v2 index by hand; `large_offsets` forces every offset
+    // through the 64-bit table.
+    fn build_pack(entries: &[TestEntry], large_offsets: bool) -> (Vec<u8>, Vec<u8>) {
+        let mut pack = b"PACK".to_vec();
+        pack.extend_from_slice(&2u32.to_be_bytes());
+        pack.extend_from_slice(&(entries.len() as u32).to_be_bytes());
+        let mut offsets: Vec<u64> = vec![];
+        for entry in entries {
+            let offset = pack.len() as u64;
+            pack.extend(encode_entry_header(
+                entry.type_code,
+                entry.data.len() as u64,
+            ));
+            match &entry.base {
+                Some(TestBase::Ofs(i)) => pack.extend(encode_varint(offset - offsets[*i])),
+                Some(TestBase::Ref(h)) => pack.extend_from_slice(h),
+                None => {}
+            }
+            pack.extend(compress(&entry.data));
+            offsets.push(offset);
+        }
+        let checksum = hash(&pack);
+        pack.extend_from_slice(&checksum);
+
+        let mut sorted: Vec<(Vec<u8>, u64)> =
+            entries.iter().map(|e| e.id.clone()).zip(offsets).collect();
+        sorted.sort();
+        let mut idx = IDX_MAGIC.to_vec();
+        idx.extend_from_slice(&2u32.to_be_bytes());
+        for b in 0..256 {
+            let n = sorted.iter().filter(|(h, _)| (h[0] as usize) <= b).count();
+            idx.extend_from_slice(&(n as u32).to_be_bytes());
+        }
+        for (h, _) in &sorted {
+            idx.extend_from_slice(h);
+        }
+        for _ in &sorted {
+            idx.extend_from_slice(&0u32.to_be_bytes());
+        }
+        for (i, (_, offset)) in sorted.iter().enumerate() {
+            let v = if large_offsets {
+                0x8000_0000 | i as u32
+            } else {
+                *offset as u32
+            };
+            idx.extend_from_slice(&v.to_be_bytes());
+        }
+        if large_offsets {
+            for (_, offset) in &sorted {
+                idx.extend_from_slice(&offset.to_be_bytes());
+            }
+        }
+        idx.extend_from_slice(&checksum);
+        let idx_checksum = hash(&idx);
+        idx.extend_from_slice(&idx_checksum);
+        (pack, idx)
+    }
+
+    fn write_pack(dir: &Path, name: &str, entries: &[TestEntry], large_offsets: bool) {
+        let (pack, idx) = build_pack(entries, large_offsets);
+        fs::create_dir_all(dir.join("pack")).unwrap();
+        fs::write(dir.join(format!("pack/pack-{}.pack", name)), &pack).unwrap();
+        fs::write(dir.join(format!("pack/pack-{}.idx", name)), &idx).unwrap();
+    }
+
+    // a delta turning "hello, world\n" into "hello, there, world\n".
+    fn hello_delta() -> Vec<u8> {
+        let mut d = vec![13, 20, 0x90, 7, 6];
+        d.extend_from_slice(b"there,");
+        d.extend_from_slice(&[0x91, 6, 7]);
+        d
+    }
+
+    #[test]
+    fn test_parse_entry_header() {
+        let h = parse_entry_header(&[0x95, 0x0a], 100).unwrap();
+        assert_eq!(h.type_code, OBJ_COMMIT);
+        assert_eq!(h.size, 0xa5);
+        assert_eq!(h.header_len, 2);
+        assert_eq!(encode_entry_header(OBJ_COMMIT, 0xa5), vec![0x95, 0x0a]);
+
+        // offset delta: distance bytes 0x81 0x00 mean ((1 + 1) << 7) | 0 = 256.
+        let h = parse_entry_header(&[0x63, 0x81, 0x00], 1000).unwrap();
+        assert_eq!(h.type_code, OBJ_OFS_DELTA);
+        assert_eq!(h.base, Some(DeltaBase::Offset(1000 - 256)));
+        assert_eq!(h.header_len, 3);
+        for &d in &[1u64, 127, 128, 16511, 16512, 1 << 40] {
+            let mut data = vec![0x60];
+            data.extend(encode_varint(d));
+            let h = parse_entry_header(&data, 1 << 41).unwrap();
+            assert_eq!(h.base, Some(DeltaBase::Offset((1 << 41) - d)));
+        }
+
+        assert!(parse_entry_header(&[0x63, 0x81], 1000).is_err());
+        assert!(parse_entry_header(&[0x50], 0).is_err());
+    }
+
+    #[test]
+    fn test_pack_store() {
+        for &large in &[false, true] {
+            let dir = temp_dir(if large { "pack-large" } else { "pack" });
+            let objects: Vec<(&str, &[u8])> = vec![
+                ("blob", b"hogehoge\n"),
+                ("blob", &[0xff; 300]),
+                ("tree", b""),
+            ];
+            let entries: Vec<TestEntry> = objects.iter().map(|(t, c)| object(t, c)).collect();
+            write_pack(&dir, "test", &entries, large);
+
+            let store = PackObjectStore::open(&dir).unwrap();
+            assert_eq!(store.packs().len(), 1);
+            assert_eq!(store.packs()[0].index().len(), 3);
+            for (type_name, content) in &objects {
+                let hash = hash_to_str(&object_hash(type_name, content));
+                assert!(store.contains(&hash));
+                let raw = store.read(&hash).unwrap();
+                assert_eq!(raw.type_name, *type_name);
+                assert_eq!(raw.content, content.to_vec());
+                assert_eq!(
+                    store.header(&hash).unwrap(),
+                    (type_name.to_string(), content.len() as u64)
+                );
+                let mut body = vec![];
+                store
+                    .read_stream(&hash)
+                    .unwrap()
+                    .read_to_end(&mut body)
+                    .unwrap();
+                assert_eq!(body, content.to_vec());
+            }
+            assert!(!store.contains("e9bc11025c28829eedf6d30cd3b65628648cad50"));
+            let blob = object_hash("blob", b"hogehoge\n");
+            assert_eq!(store.find_by_prefix("e9bc").unwrap(), vec![blob.clone()]);
+            assert_eq!(store.find_by_prefix("e9").unwrap(), vec![blob]);
+            assert_eq!(store.find_by_prefix("").unwrap().len(), 3);
+            assert!(store.find_by_prefix("e9bd").unwrap().is_empty());
+            assert!(store.write("blob", b"").is_err());
+            fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_bad_pack_is_skipped() {
+        let dir = temp_dir("pack-skip-bad");
+        write_pack(&dir, "good", &[object("blob", b"good\n")], false);
+        // an index whose pack is gone, and one cut short.
+        let (_, idx) = build_pack(&[object("blob", b"orphan\n")], false);
+        fs::write(dir.join("pack/pack-orphan.idx"), &idx).unwrap();
+        fs::write(dir.join("pack/pack-short.idx"), &idx[..100]).unwrap();
+
+        let store = PackObjectStore::open(&dir).unwrap();
+        assert_eq!(store.packs().len(), 1);
+        let good = hash_to_str(&object_hash("blob", b"good\n"));
+        assert_eq!(store.read(&good).unwrap().content, b"good\n");
+        assert!(crate::object_store::open_object_store_at(&dir).contains(&good));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_delta_chains() {
+        let dir = temp_dir("pack-delta");
+        let v1 = b"hello, world\n".to_vec();
+        let v2 = b"hello, there, world\n".to_vec();
+        // v3 appends a line to v2 by copying all of it.
+        let v3 = b"hello, there, world\nbye\n".to_vec();
+        let mut d3 = vec![20, 24, 0x90, 20, 4];
+        d3.extend_from_slice(b"bye\n");
+        let id2 = object_hash("blob", &v2);
+        let id3 = object_hash("blob", &v3);
+
+        // v2 is an offset delta against v1; v3 is a ref delta against v2 in another pack.
+        write_pack(
+            &dir,
+            "a",
+            &[
+                object("blob", &v1),
+                delta(id2.clone(), TestBase::Ofs(0), hello_delta()),
+            ],
+            false,
+        );
+        write_pack(
+            &dir,
+            "b",
+            &[delta(id3.clone(), TestBase::Ref(id2.clone()), d3)],
+            false,
+        );
+
+        let store = PackObjectStore::open(&dir).unwrap();
+        assert_eq!(store.read(&hash_to_str(&id2)).unwrap().content, v2);
+        let raw = store.read(&hash_to_str(&id3)).unwrap();
+        assert_eq!(raw.type_name, "blob");
+        assert_eq!(raw.content, v3);
+        assert_eq!(
+            store.header(&hash_to_str(&id3)).unwrap(),
+            (String::from("blob"), v3.len() as u64)
+        );
+        // reading again goes through the cached bases.
+        assert_eq!(store.read(&hash_to_str(&id3)).unwrap().content, v3);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_ref_delta_against_loose_base() {
+        let dir = temp_dir("pack-loose-base");
+        let v1 = b"hello, world\n".to_vec();
+        let v2 = b"hello, there, world\n".to_vec();
+        let id1 = object_hash("blob", &v1);
+        let id2 = object_hash("blob", &v2);
+        write_pack(
+            &dir,
+            "thin",
+            &[delta(id2.clone(), TestBase::Ref(id1), hello_delta())],
+            false,
+        );
+
+        let store = PackObjectStore::open(&dir).unwrap();
+        match store.read(&hash_to_str(&id2)) {
+            Err(GitObjectError::NotFoundError(m)) => assert!(m.contains("delta base")),
+            r => panic!("unexpected result: {:?}", r),
+        }
+
+        let bases = crate::object_store::MemoryObjectStore::new();
+        bases.write("blob", &v1).unwrap();
+        let store = PackObjectStore::open(&dir)
+            .unwrap()
+            .with_base_store(Box::new(bases));
+        assert_eq!(store.read(&hash_to_str(&id2)).unwrap().content, v2);
+        assert_eq!(
+            store.header(&hash_to_str(&id2)).unwrap(),
+            (String::from("blob"), v2.len() as u64)
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_delta_cycle_and_corruption() {
+        let dir = temp_dir("pack-cycle");
+        let a = vec![0xaa; 20];
+        let b = vec![0xbb; 20];
+        let c = object_hash("blob", b"hello, there, world\n");
+        // a and b are deltas against each other.
+        write_pack(
+            &dir,
+            "cycle",
+            &[
+                delta(a.clone(), TestBase::Ref(b.clone()), hello_delta()),
+                delta(b.clone(), TestBase::Ref(a.clone()), hello_delta()),
+                object("blob", b"hello, world!\n"),
+                // the recorded base size (13) does not match the 14 byte base.
+                delta(c.clone(), TestBase::Ofs(2), hello_delta()),
+            ],
+            false,
+        );
+        let store = PackObjectStore::open(&dir).unwrap();
+        for id in &[a, b] {
+            match store.read(&hash_to_str(id)) {
+                Err(GitObjectError::ParseError(m)) => assert!(m.contains("loops")),
+                r => panic!("unexpected result: {:?}", r),
+            }
+            assert!(store.header(&hash_to_str(id)).is_err());
+        }
+        match store.read(&hash_to_str(&c)) {
+            Err(GitObjectError::ParseError(m)) => assert!(m.contains("base size")),
+            r => panic!("unexpected result: {:?}", r),
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_encode_pack_index() {
+        let entries = [object("blob", b"hogehoge\n"), object("tree", b"")];
+        let (pack, idx) = build_pack(&entries, false);
+        let mut records = vec![
+            IndexEntry {
+                hash: entries[0].id.clone(),
+                crc32: 0,
+                offset: 12,
+            },
+            IndexEntry {
+                hash: entries[1].id.clone(),
+                crc32: 0,
+                offset: 12 + 1 + compress(b"hogehoge\n").len() as u64,
+            },
+        ];
+        assert_eq!(
+            encode_pack_index(&mut records, &pack[pack.len() - 20..]),
+            idx
+        );
+
+        // records are now sorted by id.
+        let first = records
+            .iter_mut()
+            .find(|r| r.hash == entries[0].id)
+            .unwrap();
+        first.offset = 1 << 33;
+        let index = PackIndex::parse(&encode_pack_index(&mut records, &[0; 20])).unwrap();
+        let i = index.lookup(&entries[0].id).unwrap();
+        assert_eq!(index.offset(i), 1 << 33);
+    }
+
+    #[test]
+    fn test_corrupt_index() {
+        let (_, mut idx) = build_pack(&[object("blob", b"hogehoge\n")], false);
+        let last = idx.len() - 1;
+        assert!(PackIndex::verify_checksum(&idx));
+        idx[last] ^= 1;
+        assert!(PackIndex::parse(&idx).is_ok());
+        assert!(!PackIndex::verify_checksum(&idx));
+        assert!(PackIndex::parse(&idx[..idx.len() - 41]).is_err());
+        assert!(PackIndex::parse(b"\xfftOc").is_err());
+    }
+}
@@ -0,0 +1,628 @@
+use crate::config::Config;
+use crate::delta::create_delta;
+use crate::git_object::*;
+use crate::index_pack::IndexedPack;
+use crate::object_store::ObjectStore;
+use crate::pack::*;
+use crate::repository::open_repository;
+use crate::revision::resolve_revision;
+use crate::utils::*;
+use std::{
+    collections::{HashSet, VecDeque},
+    fs,
+    io::{self, BufRead, BufWriter, Write},
+    path::{Path, PathBuf},
+    process,
+};
+
+type Result<T> = std::result::Result<T, GitObjectError>;
+
+pub const DEFAULT_WINDOW: usize = 10;
+pub const DEFAULT_DEPTH: usize = 50;
+// core.bigFileThreshold: larger objects are stored whole, never read into memory whole.
+pub const DEFAULT_BIG_FILE_THRESHOLD: u64 = 512 << 20;
+// objects smaller than this are never worth a delta.
+const MIN_DELTA_SIZE: usize = 50;
+// deltas found while searching are kept for writing only when at most this large, and
+// while all of them take at most DELTA_CACHE_SIZE; the others are made again when written.
+const DELTA_CACHE_LIMIT: usize = 1000;
+const DELTA_CACHE_SIZE: usize = 256 << 20;
+
+// how hard to look for deltas.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PackOptions {
+    pub window: usize,
+    pub depth: usize,
+    pub big_file_threshold: u64,
+}
+
+impl Default for PackOptions {
+    fn default() -> Self {
+        PackOptions {
+            window: DEFAULT_WINDOW,
+            depth: DEFAULT_DEPTH,
+            big_file_threshold: DEFAULT_BIG_FILE_THRESHOLD,
+        }
+    }
+}
+
+impl PackOptions {
+    // pack.window, pack.depth and core.bigFileThreshold, or their defaults.
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let mut options = PackOptions::default();
+        if let Some(n) = config.get_int("pack.window")? {
+            options.window = n.max(0) as usize;
+        }
+        if let Some(n) = config.get_int("pack.depth")? {
+            options.depth = n.max(0) as usize;
+        }
+        if let Some(n) = config.get_int("core.bigfilethreshold")? {
+            options.big_file_threshold = n.max(0) as u64;
+        }
+        Ok(options)
+    }
+}
+
+#[derive(Default)]
+struct PackObjectsOption<'a> {
+    stdout: bool,
+    revs: bool,
+    window: Option<usize>,
+    depth: Option<usize>,
+    base_name: Option<&'a String>,
+}
+
+pub fn pack_objects(commands: &[String]) {
+    let option = parse_options(commands);
+    if !option.stdout && option.base_name.is_none() {
+        println!("usage: pack-objects [--revs] [--window=<n>] [--depth=<n>] --stdout");
+        println!("       pack-objects [--revs] [--window=<n>] [--depth=<n>] <base-name>");
+        return;
+    }
+    let repo = match open_repository() {
+        Some(r) => r,
+        None => return,
+    };
+    let store = repo.object_store();
+
+    let lines: Vec<String> = io::stdin().lock().lines().map(|l| l.unwrap()).collect();
+    let objects = if option.revs {
+        let mut include = vec![];
+        let mut exclude = vec![];
+        let rev = |s: &str| resolve_revision(&repo.git_dir, &store, s).ok();
+        for line in lines.iter().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+            let parsed = match line.split_once("..") {
+                Some((from, to)) => rev(from).zip(rev(to)).map(|(from, to)| {
+                    exclude.push(from);
+                    include.push(to);
+                }),
+                None => match line.strip_prefix('^') {
+                    Some(name) => rev(name).map(|h| exclude.push(h)),
+                    None => rev(line).map(|h| include.push(h)),
+                },
+            };
+            if parsed.is_none() {
+                println!("fatal: bad revision '{}'", line);
+                return;
+            }
+        }
+        match list_objects(&store, &include, &exclude) {
+            Ok(o) => o,
+            Err(e) => {
+                println!("fatal: {:?}", e);
+                return;
+            }
+        }
+    } else {
+        let mut objects = vec![];
+        for line in lines.iter().filter(|l| !l.trim().is_empty()) {
+            let (id, name) = line.split_once(' ').unwrap_or((line, ""));
+            match parse_id(id) {
+                Some(h) => objects.push((h, name.to_string())),
+                None => {
+                    println!("fatal: expected object ID, got garbage:\n {}", line);
+                    return;
+                }
+            }
+        }
+        objects
+    };
+
+    let mut pack_options = match Config::load(Some(&repo.git_dir))
+        .and_then(|config| PackOptions::from_config(&config))
+    {
+        Ok(o) => o,
+        Err(e) => {
+            println!("fatal: {:?}", e);
+            return;
+        }
+    };
+    pack_options.window = option.window.unwrap_or(pack_options.window);
+    pack_options.depth = option.depth.unwrap_or(pack_options.depth);
+    let result = if option.stdout {
+        let mut out = BufWriter::new(io::stdout().lock());
+        build_pack(&store, &objects, &pack_options, &mut out)
+    } else {
+        write_pack(&store, &objects, &pack_options, option.base_name.unwrap())
+    };
+    match result {
+        Ok(_) if option.stdout => {}
+        Ok(packed) => println!("{}", hash_to_str(&packed.checksum)),
+        Err(e) => println!("fatal: {:?}", e),
+    }
+}
+
+fn parse_id(s: &str) -> Option<Vec<u8>> {
+    if s.len() == 40 && s.bytes().all(|c| c.is_ascii_hexdigit()) {
+        Some(str_to_hash(s.to_ascii_lowercase()))
+    } else {
+        None
+    }
+}
+
+// every object reachable from `include` but not from `exclude`, commits and tags first,
+// then trees and blobs, each with the path it was found at (empty for commits, tags and
+// root trees). submodule commits are skipped.
+pub fn list_objects(
+    store: &dyn ObjectStore,
+    include: &[Vec<u8>],
+    exclude: &[Vec<u8>],
+) -> Result<Vec<(Vec<u8>, String)>> {
+    let mut seen = HashSet::new();
+    walk(store, exclude, &mut seen, &mut vec![])?;
+    let mut objects = vec![];
+    walk(store, include, &mut seen, &mut objects)?;
+    Ok(objects)
+}
+
+fn walk(
+    store: &dyn ObjectStore,
+    starts: &[Vec<u8>],
+    seen: &mut HashSet<Vec<u8>>,
+    out: &mut Vec<(Vec<u8>, String)>,
+) -> Result<()> {
+    let mut queue: VecDeque<Vec<u8>> = starts.iter().cloned().collect();
+    let mut trees = vec![];
+    while let Some(id) = queue.pop_front() {
+        if seen.contains(&id) {
+            continue;
+        }
+        match load_object_by_hash(store, &hash_to_str(&id))? {
+            GitObject::Commit(commit) => {
+                trees.push(commit.tree);
+                queue.extend(commit.parents);
+            }
+            GitObject::Tag(tag) => queue.push_back(tag.object),
+            GitObject::Tree(_) => {
+                trees.push(id);
+                continue;
+            }
+            GitObject::Blob(_) => {}
+        }
+        seen.insert(id.clone());
+        out.push((id, String::new()));
+    }
+    for tree in trees {
+        walk_tree(store, tree, "", seen, out)?;
+    }
+    Ok(())
+}
+
+fn walk_tree(
+    store: &dyn ObjectStore,
+    id: Vec<u8>,
+    path: &str,
+    seen: &mut HashSet<Vec<u8>>,
+    out: &mut Vec<(Vec<u8>, String)>,
+) -> Result<()> {
+    if !seen.insert(id.clone()) {
+        return Ok(());
+    }
+    let nodes = match load_object_by_hash(store, &hash_to_str(&id))? {
+        GitObject::Tree(nodes) => nodes,
+        o => {
+            return Err(GitObjectError::ParseError(format!(
+                "{} is a {}, not a tree",
+                hash_to_str(&id),
+                o.type_name()
+            )))
+        }
+    };
+    out.push((id, path.to_string()));
+    for node in nodes {
+        let child_path = if path.is_empty() {
+            node.filename.clone()
+        } else {
+            format!("{}/{}", path, node.filename)
+        };
+        match node.node_type {
+            GitNodeType::Tree => walk_tree(store, node.hash, &child_path, seen, out)?,
+            GitNodeType::Blob => {
+                if seen.insert(node.hash.clone()) {
+                    out.push((node.hash, child_path));
+                }
+            }
+            GitNodeType::Commit => {}
+        }
+    }
+    Ok(())
+}
+
+// groups objects with similar names: later characters weigh more, so "a/Makefile" and
+// "b/Makefile" land next to each other. the same hash as git's pack_name_hash.
+fn name_hash(name: &str) -> u32 {
+    name.bytes()
+        .filter(|c| !c.is_ascii_whitespace())
+        .fold(0u32, |hash, c| (hash >> 2).wrapping_add((c as u32) << 24))
+}
+
+struct PackItem {
+    hash: Vec<u8>,
+    type_code: u8,
+    size: u64,
+    name_hash: u32,
+    // index of the base, and the delta against it when it was small enough to keep.
+    delta: Option<(usize, Option<Vec<u8>>)>,
+    depth: usize,
+}
+
+// packs `objects` (ids with the path they were found at, in the order they should be
+// written) into a version 2 pack written to `out`, and returns its index. each object is
+// tried as a delta against the `window` objects before it when sorted by type, name and
+// size, keeping chains at most `depth` long. objects above the big file threshold are
+// left out of that search; objects stored whole are streamed from the store.
+pub fn build_pack(
+    store: &dyn ObjectStore,
+    objects: &[(Vec<u8>, String)],
+    options: &PackOptions,
+    out: &mut dyn Write,
+) -> Result<IndexedPack> {
+    let mut items: Vec<PackItem> = vec![];
+    let mut known = HashSet::new();
+    for (hash, name) in objects {
+        if !known.insert(hash) {
+            continue;
+        }
+        let (type_name, size) = store.header(&hash_to_str(hash))?;
+        let type_code = type_code_of(&type_name).ok_or_else(|| {
+            GitObjectError::ParseError(format!("unknown object type {}", type_name))
+        })?;
+        items.push(PackItem {
+            hash: hash.clone(),
+            type_code,
+            size,
+            name_hash: name_hash(name),
+            delta: None,
+            depth: 0,
+        });
+    }
+
+    find_deltas(store, &mut items, options)?;
+
+    let mut pack = PackWriter::new(out, items.len() as u32)?;
+    let mut offsets: Vec<Option<u64>> = vec![None; items.len()];
+    let mut records = Vec::with_capacity(items.len());
+    for i in 0..items.len() {
+        // a delta's base has to be written before it.
+        let mut chain = vec![i];
+        while let Some((base, _)) = &items[*chain.last().unwrap()].delta {
+            chain.push(*base);
+        }
+        for &j in chain.iter().rev() {
+            if offsets[j].is_some() {
+                continue;
+            }
+            let offset = pack.begin_entry();
+            match items[j]
+                .delta
+                .as_mut()
+                .map(|(base, delta)| (*base, delta.take()))
+            {
+                Some((base, delta)) => {
+                    let delta = match delta {
+                        Some(d) => d,
+                        None => create_delta(
+                            &store.read(&hash_to_str(&items[base].hash))?.content,
+                            &store.read(&hash_to_str(&items[j].hash))?.content,
+                        ),
+                    };
+                    let mut header = encode_entry_header(OBJ_OFS_DELTA, delta.len() as u64);
+                    header.extend(encode_varint(offset - offsets[base].unwrap()));
+                    pack.write_entry(&header, delta.len() as u64, &mut &delta[..])?;
+                }
+                None => {
+                    let item = &items[j];
+                    let header = encode_entry_header(item.type_code, item.size);
+                    let mut reader = store.read_stream(&hash_to_str(&item.hash))?;
+                    pack.write_entry(&header, item.size, &mut reader)?;
+                }
+            }
+            records.push(IndexEntry {
+                hash: items[j].hash.clone(),
+                crc32: pack.entry_crc32(),
+                offset,
+            });
+            offsets[j] = Some(offset);
+        }
+    }
+    let checksum = pack.finish()?;
+    let index = encode_pack_index(&mut records, &checksum);
+    Ok(IndexedPack {
+        index,
+        checksum,
+        object_count: items.len(),
+        appended_bases: 0,
+    })
+}
+
+// packs `objects` as build_pack does into `<prefix>-<checksum>.pack` and its index.
+// the pack is written to a temporary file first, as its name depends on its content.
+pub fn write_pack(
+    store: &dyn ObjectStore,
+    objects: &[(Vec<u8>, String)],
+    options: &PackOptions,
+    prefix: &str,
+) -> Result<IndexedPack> {
+    let tmp_path = PathBuf::from(format!("{}-tmp{}.pack", prefix, process::id()));
+    if let Some(dir) = tmp_path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = BufWriter::new(fs::File::create(&tmp_path)?);
+    let packed = match build_pack(store, objects, options, &mut file) {
+        Ok(p) => p,
+        Err(e) => {
+            drop(file);
+            let _ = fs::remove_file(&tmp_path);
+            return Err(e);
+        }
+    };
+    drop(file);
+    let name = format!("{}-{}", prefix, hash_to_str(&packed.checksum));
+    fs::rename(&tmp_path, format!("{}.pack", name))?;
+    packed.write_index(Path::new(&format!("{}.idx", name)))?;
+    Ok(packed)
+}
+
+fn find_deltas(
+    store: &dyn ObjectStore,
+    items: &mut [PackItem],
+    options: &PackOptions,
+) -> Result<()> {
+    let (window, max_depth) = (options.window, options.depth);
+    if window == 0 {
+        return Ok(());
+    }
+    let mut order: Vec<usize> = (0..items.len())
+        .filter(|&i| items[i].size <= options.big_file_threshold)
+        .collect();
+    // larger objects first: deleting from a base makes smaller deltas than inserting.
+    order.sort_by(|&a, &b| {
+        let (a, b) = (&items[a], &items[b]);
+        (a.type_code, a.name_hash, b.size).cmp(&(b.type_code, b.name_hash, a.size))
+    });
+
+    // the contents of the last `window` objects in that order, the only ones a delta
+    // can be made against.
+    let mut loaded: VecDeque<(usize, Vec<u8>)> = VecDeque::with_capacity(window + 1);
+    let mut cached = 0;
+    for &target in &order {
+        let content = store.read(&hash_to_str(&items[target].hash))?.content;
+        let target_size = content.len();
+        let mut best: Option<(usize, Vec<u8>)> = None;
+        let candidates = if target_size < MIN_DELTA_SIZE {
+            0
+        } else {
+            loaded.len()
+        };
+        for (base, base_content) in loaded.iter().rev().take(candidates) {
+            let (t, b) = (&items[target], &items[*base]);
+            let base_size = base_content.len();
+            if b.type_code != t.type_code || b.depth >= max_depth || base_size < target_size / 32 {
+                continue;
+            }
+            // deeper bases have to pay off more, as git does.
+            let limit = (target_size / 2).saturating_sub(20) * (max_depth - b.depth) / max_depth;
+            let limit = best.as_ref().map_or(limit, |(_, d)| limit.min(d.len()));
+            let delta = create_delta(base_content, &content);
+            if delta.len() < limit {
+                best = Some((*base, delta));
+            }
+        }
+        if let Some((base, delta)) = best {
+            let keep = delta.len() <= DELTA_CACHE_LIMIT && cached + delta.len() <= DELTA_CACHE_SIZE;
+            if keep {
+                cached += delta.len();
+            }
+            items[target].depth = items[base].depth + 1;
+            items[target].delta = Some((base, Some(delta).filter(|_| keep)));
+        }
+        loaded.push_back((target, content));
+        if loaded.len() > window {
+            loaded.pop_front();
+        }
+    }
+    Ok(())
+}
+
+fn parse_options(commands: &[String]) -> PackObjectsOption<'_> {
+    let mut option: PackObjectsOption = Default::default();
+    for token in &commands[2..] {
+        match &token[..] {
+            "--stdout" => option.stdout = true,
+            "--revs" => option.revs = true,
+            _ if token.starts_with("--window=") => option.window = token[9..].parse().ok(),
+            _ if token.starts_with("--depth=") => option.depth = token[8..].parse().ok(),
+            _ if option.base_name.is_none() && !token.starts_with('-') => {
+                option.base_name = Some(token)
+            }
+            _ => println!("ignore option {}", token),
+        }
+    }
+    option
+}
+
+#[cfg(test)]
+mod test {
+    use crate::index_pack::{build_index, IndexedPack};
+    use crate::object_store::MemoryObjectStore;
+    use crate::pack_objects::*;
+    use crate::test_utils::{commit, temp_dir};
+    use std::collections::HashMap;
+
+    // two commits; the second changes a large file a little and adds a small one.
+    fn history(store: &MemoryObjectStore) -> (Vec<u8>, Vec<u8>) {
+        let text: String = (0..2000).map(|i| format!("line {}\n", i)).collect();
+        let mut changed = text.replace("line 1000\n", "line one thousand\n");
+        changed.push_str("the end\n");
+        let first = commit(
+            store,
+            &[],
+            &[("big.txt", text.as_bytes())],
+            1_600_000_000,
+            "commit 0",
+        );
+        let files: &[(&str, &[u8])] = &[("big.txt", changed.as_bytes()), ("small.txt", b"small\n")];
+        let second = commit(store, &[&first], files, 1_600_000_001, "commit 1");
+        (first, second)
+    }
+
+    #[test]
+    fn test_list_objects() {
+        let store = MemoryObjectStore::new();
+        let (first, second) = history(&store);
+        let all = list_objects(&store, std::slice::from_ref(&second), &[]).unwrap();
+        assert_eq!(all.len(), 7);
+        assert_eq!(all[0], (second.clone(), String::new()));
+        assert_eq!(all[1], (first.clone(), String::new()));
+        assert!(all.iter().any(|(_, name)| name == "small.txt"));
+
+        // commit, tree and the two new blobs.
+        let range = list_objects(&store, &[second], &[first]).unwrap();
+        assert_eq!(range.len(), 4);
+        let names: Vec<&str> = range.iter().map(|(_, n)| n.as_str()).collect();
+        assert_eq!(names, vec!["", "", "big.txt", "small.txt"]);
+    }
+
+    // indexes the pack from scratch, as index-pack would.
+    fn index_pack(name: &str, pack: &[u8]) -> IndexedPack {
+        let dir = temp_dir(name);
+        let path = dir.join("pack.pack");
+        fs::write(&path, pack).unwrap();
+        let indexed = build_index(&path, None).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        indexed
+    }
+
+    // delta depth of every entry in the pack, keyed by offset.
+    fn entry_depths(pack: &[u8], packed: &IndexedPack) -> HashMap<u64, usize> {
+        let index = PackIndex::parse(&packed.index).unwrap();
+        let mut bases = HashMap::new();
+        for i in 0..index.len() {
+            let offset = index.offset(i);
+            let header = parse_entry_header(&pack[offset as usize..], offset).unwrap();
+            bases.insert(offset, header.base);
+        }
+        let mut depths = HashMap::new();
+        for &offset in bases.keys() {
+            let mut depth = 0;
+            let mut at = offset;
+            while let Some(Some(DeltaBase::Offset(base))) = bases.get(&at) {
+                depth += 1;
+                at = *base;
+            }
+            depths.insert(offset, depth);
+        }
+        depths
+    }
+
+    #[test]
+    fn test_build_pack() {
+        let store = MemoryObjectStore::new();
+        let (_, second) = history(&store);
+        let objects = list_objects(&store, &[second], &[]).unwrap();
+        let mut pack = vec![];
+        let packed = build_pack(&store, &objects, &PackOptions::default(), &mut pack).unwrap();
+        assert_eq!(packed.object_count, 7);
+        assert_eq!(packed.checksum, pack[pack.len() - 20..].to_vec());
+
+        // one version of big.txt is stored as a delta against the other.
+        let depths = entry_depths(&pack, &packed);
+        assert_eq!(depths.values().filter(|&&d| d == 1).count(), 1);
+        assert!(pack.len() < 2 * 2000 * 9 / 2);
+
+        // indexing the pack from scratch finds the same objects at the same offsets.
+        let indexed = index_pack("build-pack", &pack);
+        assert_eq!(indexed.index, packed.index);
+        let index = PackIndex::parse(&packed.index).unwrap();
+        assert!(objects.iter().all(|(h, _)| index.lookup(h).is_some()));
+    }
+
+    #[test]
+    fn test_delta_depth() {
+        let store = MemoryObjectStore::new();
+        let mut objects = vec![];
+        let mut content: Vec<u8> = (0..4000u32).map(|i| (i % 251) as u8).collect();
+        for i in 0..6 {
+            content.extend_from_slice(format!("revision {}\n", i).as_bytes());
+            objects.push((store.write("blob", &content).unwrap(), String::from("f")));
+        }
+        let mut pack = vec![];
+        let options = PackOptions {
+            depth: 2,
+            ..PackOptions::default()
+        };
+        let packed = build_pack(&store, &objects, &options, &mut pack).unwrap();
+        let depths = entry_depths(&pack, &packed);
+        assert_eq!(depths.len(), 6);
+        assert_eq!(depths.values().max(), Some(&2));
+        index_pack("delta-depth", &pack);
+
+        // without a window every object is stored whole.
+        let mut pack = vec![];
+        let options = PackOptions {
+            window: 0,
+            ..options
+        };
+        let whole = build_pack(&store, &objects, &options, &mut pack).unwrap();
+        assert!(entry_depths(&pack, &whole).values().all(|&d| d == 0));
+    }
+
+    #[test]
+    fn test_big_files_and_large_deltas() {
+        let store = MemoryObjectStore::new();
+        let base: Vec<u8> = (0..8000u32).map(|i| (i * 7 % 253) as u8).collect();
+        // a delta of about 1500 bytes, too large to be kept from the search.
+        let mut changed = base.clone();
+        changed.splice(4000..4000, (0..1500u32).map(|i| (i * 13 % 241) as u8));
+        let objects = vec![
+            (store.write("blob", &base).unwrap(), String::from("f")),
+            (store.write("blob", &changed).unwrap(), String::from("f")),
+        ];
+        let mut pack = vec![];
+        let packed = build_pack(&store, &objects, &PackOptions::default(), &mut pack).unwrap();
+        assert_eq!(entry_depths(&pack, &packed).values().max(), Some(&1));
+        // the delta made again when writing resolves to the same object.
+        let indexed = index_pack("large-deltas", &pack);
+        assert_eq!(indexed.index, packed.index);
+
+        // above the threshold, objects are stored whole.
+        let options = PackOptions {
+            big_file_threshold: 8000,
+            ..PackOptions::default()
+        };
+        let mut pack = vec![];
+        let packed = build_pack(&store, &objects, &options, &mut pack).unwrap();
+        assert!(entry_depths(&pack, &packed).values().all(|&d| d == 0));
+        index_pack("big-files", &pack);
+    }
+
+    #[test]
+    fn test_name_hash() {
+        assert_eq!(name_hash(""), 0);
+        assert_eq!(name_hash("a"), 0x6100_0000);
+        assert_eq!(name_hash("a b"), name_hash("ab"));
+        // the end of the path matters most.
+        let diff = |a: &str, b: &str| (name_hash(a) as i64 - name_hash(b) as i64).abs();
+        assert!(diff("x/Makefile", "y/Makefile") < diff("Makefile", "main.rs"));
+    }
+}
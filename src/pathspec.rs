@@ -0,0 +1,141 @@
+use crate::utils::wildmatch;
+use std::path::{Component, Path};
+
+// a path given on the command line, made relative to the top of the work tree. it
+// names that path and everything below it, or with glob characters whatever the glob
+// matches, where `*` also matches across `/` like in git.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathspecItem {
+    // as it was typed, for messages.
+    pub original: String,
+    pub path: String,
+}
+
+impl PathspecItem {
+    pub fn matches(&self, path: &str) -> bool {
+        if self.path.is_empty() || path == self.path {
+            return true;
+        }
+        if path.starts_with(self.path.as_str()) && path.as_bytes()[self.path.len()] == b'/' {
+            return true;
+        }
+        self.is_glob() && wildmatch(&self.path, path, false)
+    }
+
+    pub fn is_glob(&self) -> bool {
+        self.path.contains(['*', '?', '['])
+    }
+
+    // the directory every match is in or below, to start looking from.
+    pub fn base_dir(&self) -> &str {
+        if !self.is_glob() {
+            return &self.path;
+        }
+        let first_glob = self.path.find(['*', '?', '[']).unwrap_or(0);
+        match self.path[..first_glob].rfind('/') {
+            Some(i) => &self.path[..i],
+            None => "",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pathspec {
+    pub items: Vec<PathspecItem>,
+}
+
+impl Pathspec {
+    // `args` are relative to `prefix`, the current directory below the top of
+    // `work_tree`. the error is the message to show.
+    pub fn parse(work_tree: &Path, prefix: &Path, args: &[&String]) -> Result<Self, String> {
+        let mut items = vec![];
+        for arg in args {
+            items.push(PathspecItem {
+                original: arg.to_string(),
+                path: normalize_path(work_tree, prefix, arg)?,
+            });
+        }
+        Ok(Pathspec { items })
+    }
+
+    // the whole work tree.
+    pub fn everything() -> Self {
+        Pathspec {
+            items: vec![PathspecItem {
+                original: String::from("."),
+                path: String::new(),
+            }],
+        }
+    }
+
+    pub fn matches(&self, path: &str) -> bool {
+        self.items.iter().any(|item| item.matches(path))
+    }
+}
+
+// `arg`, relative to `prefix` or absolute, as a path relative to the top of `work_tree`
+// with `/` between components, `.` and `..` resolved. the top itself is empty.
+pub fn normalize_path(work_tree: &Path, prefix: &Path, arg: &str) -> Result<String, String> {
+    let outside = || {
+        format!(
+            "{}: '{}' is outside repository at '{}'",
+            arg,
+            arg,
+            work_tree.display()
+        )
+    };
+    let path = Path::new(arg);
+    let relative = if path.is_absolute() {
+        path.strip_prefix(work_tree)
+            .map_err(|_| outside())?
+            .to_path_buf()
+    } else {
+        prefix.join(path)
+    };
+    let mut parts: Vec<String> = vec![];
+    for component in relative.components() {
+        match component {
+            Component::Normal(c) => parts.push(c.to_string_lossy().to_string()),
+            Component::ParentDir => {
+                parts.pop().ok_or_else(outside)?;
+            }
+            _ => {}
+        }
+    }
+    Ok(parts.join("/"))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::pathspec::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_pathspec() {
+        let top = PathBuf::from("/work");
+        let normalize = |prefix: &str, arg: &str| normalize_path(&top, Path::new(prefix), arg);
+        assert_eq!(normalize("", "src/main.rs").unwrap(), "src/main.rs");
+        assert_eq!(normalize("src", "main.rs").unwrap(), "src/main.rs");
+        assert_eq!(normalize("src", "../README.md").unwrap(), "README.md");
+        assert_eq!(normalize("src", "./a/./b/").unwrap(), "src/a/b");
+        assert_eq!(normalize("src", "..").unwrap(), "");
+        assert_eq!(normalize("", "/work/src").unwrap(), "src");
+        assert!(normalize("src", "../..").is_err());
+        assert!(normalize("", "/elsewhere/x").is_err());
+
+        let args: Vec<String> = vec!["src".into(), "*.md".into(), "doc/a?.txt".into()];
+        let args: Vec<&String> = args.iter().collect();
+        let pathspec = Pathspec::parse(&top, Path::new(""), &args).unwrap();
+        assert!(pathspec.matches("src"));
+        assert!(pathspec.matches("src/main.rs"));
+        assert!(!pathspec.matches("srcs/main.rs"));
+        assert!(pathspec.matches("README.md"));
+        assert!(pathspec.matches("doc/guide/x.md"));
+        assert!(pathspec.matches("doc/ab.txt"));
+        assert!(!pathspec.matches("doc/abc.txt"));
+        assert_eq!(pathspec.items[0].base_dir(), "src");
+        assert_eq!(pathspec.items[1].base_dir(), "");
+        assert_eq!(pathspec.items[2].base_dir(), "doc");
+        assert!(Pathspec::everything().matches("any/path"));
+    }
+}
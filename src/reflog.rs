@@ -0,0 +1,657 @@
+use crate::config::Config;
+use crate::date::parse_expiry_date;
+use crate::git_object::GitObjectError;
+use crate::git_user::Signature;
+use crate::lockfile::LockFile;
+use crate::object_store::ObjectStore;
+use crate::refs::{lock_ref, read_ref};
+use crate::repository::open_repository;
+use crate::rev_parse::{find_unique_abbrev, DEFAULT_ABBREV};
+use crate::revision::{dwim_ref, reachable_commits};
+use crate::utils::*;
+use std::{
+    collections::HashSet,
+    fmt, fs, io,
+    io::Write,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+type Result<T> = std::result::Result<T, GitObjectError>;
+
+// entries older than this are dropped by `reflog expire`, and those older than the
+// second one as well when the ref can no longer reach them.
+pub const DEFAULT_REFLOG_EXPIRE: &str = "90.days.ago";
+pub const DEFAULT_REFLOG_EXPIRE_UNREACHABLE: &str = "30.days.ago";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReflogEntry {
+    pub old: Vec<u8>,
+    pub new: Vec<u8>,
+    pub committer: Signature,
+    pub message: String,
+}
+
+impl ReflogEntry {
+    // runs of whitespace in the message become single spaces, so an entry stays on one line.
+    pub fn new(old: &[u8], new: &[u8], committer: Signature, message: &str) -> Self {
+        ReflogEntry {
+            old: old.to_vec(),
+            new: new.to_vec(),
+            committer,
+            message: message.split_whitespace().collect::<Vec<_>>().join(" "),
+        }
+    }
+}
+
+// "<old> <new> <name> <<email>> <time> <tz>", then a tab and the message if there is one.
+impl fmt::Display for ReflogEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {}",
+            hash_to_str(&self.old),
+            hash_to_str(&self.new),
+            self.committer
+        )?;
+        if !self.message.is_empty() {
+            write!(f, "\t{}", self.message)?;
+        }
+        Ok(())
+    }
+}
+
+// a line of a log as it was read, with the entry it holds if it is one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReflogLine {
+    pub raw: Vec<u8>,
+    pub entry: Option<ReflogEntry>,
+}
+
+// entries of logs/<refname>, oldest first. a ref without a log has none, and lines
+// that are not entries are skipped, as git does.
+pub fn read_reflog(git_dir: &Path, refname: &str) -> Result<Vec<ReflogEntry>> {
+    Ok(read_reflog_lines(git_dir, refname)?
+        .into_iter()
+        .filter_map(|line| line.entry)
+        .collect())
+}
+
+// every line of logs/<refname>, including those that are not entries.
+pub fn read_reflog_lines(git_dir: &Path, refname: &str) -> Result<Vec<ReflogLine>> {
+    let log = match fs::read(git_dir.join("logs").join(refname)) {
+        Ok(l) => l,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(GitObjectError::IOError(e)),
+    };
+    let log = log.strip_suffix(b"\n").unwrap_or(&log);
+    if log.is_empty() {
+        return Ok(vec![]);
+    }
+    Ok(log
+        .split(|&c| c == b'\n')
+        .map(|raw| ReflogLine {
+            raw: raw.to_vec(),
+            entry: parse_reflog_entry(raw),
+        })
+        .collect())
+}
+
+fn parse_reflog_entry(line: &[u8]) -> Option<ReflogEntry> {
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    let (head, message) = match line.iter().position(|&c| c == b'\t') {
+        Some(tab) => (&line[..tab], &line[tab + 1..]),
+        None => (line, &b""[..]),
+    };
+    if head.len() < 82 || head[40] != b' ' || head[81] != b' ' {
+        return None;
+    }
+    Some(ReflogEntry {
+        old: parse_id(&head[..40])?,
+        new: parse_id(&head[41..81])?,
+        committer: Signature::parse(&head[82..])?,
+        message: String::from_utf8_lossy(message).into_owned(),
+    })
+}
+
+pub fn append_reflog(git_dir: &Path, refname: &str, entry: &ReflogEntry) -> Result<()> {
+    let path = git_dir.join("logs").join(refname);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut log = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)?;
+    log.write_all(format!("{}\n", entry).as_bytes())?;
+    Ok(())
+}
+
+// replaces the whole log with `lines`, written back as they were read.
+pub fn write_reflog(git_dir: &Path, refname: &str, lines: &[&ReflogLine]) -> Result<()> {
+    let mut lock = LockFile::acquire(&git_dir.join("logs").join(refname))?;
+    for line in lines {
+        lock.write_all(&line.raw)?;
+        lock.write_all(b"\n")?;
+    }
+    lock.commit()
+}
+
+// drops the entries of the log of `refname` that `keep` says to, holding the lock of
+// the ref itself, under which updates append to its log. `keep` is given the entries
+// oldest first; lines that are not entries stay as they are.
+pub fn rewrite_reflog<F>(git_dir: &Path, refname: &str, dry_run: bool, keep: F) -> Result<()>
+where
+    F: FnOnce(&[ReflogEntry]) -> Result<Vec<bool>>,
+{
+    let _ref_lock = lock_ref(git_dir, refname)?;
+    let lines = read_reflog_lines(git_dir, refname)?;
+    let entries: Vec<ReflogEntry> = lines.iter().filter_map(|l| l.entry.clone()).collect();
+    let mut keep = keep(&entries)?.into_iter();
+    let kept: Vec<&ReflogLine> = lines
+        .iter()
+        .filter(|l| l.entry.is_none() || keep.next().unwrap_or(true))
+        .collect();
+    if dry_run || kept.len() == lines.len() {
+        return Ok(());
+    }
+    write_reflog(git_dir, refname, &kept)
+}
+
+// removes the log of a deleted ref, and the directories that leaves empty.
+pub fn delete_reflog(git_dir: &Path, refname: &str) -> Result<()> {
+    let logs = git_dir.join("logs");
+    let path = logs.join(refname);
+    match fs::remove_file(&path) {
+        Ok(_) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(GitObjectError::IOError(e)),
+    }
+    let mut dir = path.parent();
+    while let Some(d) = dir.filter(|d| d.starts_with(logs.join("refs")) && *d != logs.join("refs"))
+    {
+        if fs::remove_dir(d).is_err() {
+            break;
+        }
+        dir = d.parent();
+    }
+    Ok(())
+}
+
+// the names of all refs that have a log, sorted.
+pub fn list_reflogs(git_dir: &Path) -> Result<Vec<String>> {
+    let logs = git_dir.join("logs");
+    let mut names = vec![];
+    let mut paths = vec![logs.clone()];
+    while let Some(path) = paths.pop() {
+        if path.is_dir() {
+            for entry in fs::read_dir(&path)? {
+                paths.push(entry?.path());
+            }
+        } else if let Some(name) = path.strip_prefix(&logs).ok().and_then(|p| p.to_str()) {
+            if !name.ends_with(".lock") {
+                names.push(name.replace(std::path::MAIN_SEPARATOR, "/"));
+            }
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+// whether an update of `refname` is logged: always when it already has a log, and
+// otherwise as core.logAllRefUpdates says, which is on by default outside bare
+// repositories and then covers HEAD, branches, remote-tracking branches and notes.
+pub fn should_log(git_dir: &Path, config: &Config, refname: &str) -> Result<bool> {
+    if git_dir.join("logs").join(refname).is_file() {
+        return Ok(true);
+    }
+    let setting = config.get_string("core.logallrefupdates").ok().flatten();
+    if setting
+        .as_deref()
+        .is_some_and(|s| s.eq_ignore_ascii_case("always"))
+    {
+        return Ok(true);
+    }
+    let enabled = match config.get_bool("core.logallrefupdates")? {
+        Some(enabled) => enabled,
+        None => !config.get_bool("core.bare")?.unwrap_or(false),
+    };
+    Ok(enabled
+        && (refname == "HEAD"
+            || ["refs/heads/", "refs/remotes/", "refs/notes/"]
+                .iter()
+                .any(|p| refname.starts_with(p))))
+}
+
+fn parse_id(s: &[u8]) -> Option<Vec<u8>> {
+    if s.len() == 40 && s.iter().all(|c| c.is_ascii_hexdigit()) {
+        Some(str_to_hash(String::from_utf8_lossy(s).to_ascii_lowercase()))
+    } else {
+        None
+    }
+}
+
+// reflog [show] [<ref>] | reflog expire [...] <ref>... | reflog delete <ref>@{<n>}...
+pub fn reflog(commands: &[String]) {
+    match commands.get(2).map(|c| c.as_str()) {
+        Some("expire") => reflog_expire(commands),
+        Some("delete") => reflog_delete(commands),
+        Some("show") => reflog_show(commands.get(3)),
+        other => reflog_show(other.and(commands.get(2))),
+    }
+}
+
+fn reflog_show(refname: Option<&String>) {
+    let repo = match open_repository() {
+        Some(r) => r,
+        None => return,
+    };
+    let store = repo.object_store();
+    let shown = refname.map(|r| r.as_str()).unwrap_or("HEAD");
+    let full = match full_reflog_name(&repo.git_dir, shown) {
+        Some(f) => f,
+        None => {
+            println!(
+                "fatal: ambiguous argument '{}': unknown revision or path not in the working tree.",
+                shown
+            );
+            return;
+        }
+    };
+    let entries = match read_reflog(&repo.git_dir, &full) {
+        Ok(e) => e,
+        Err(e) => {
+            println!("fatal: {:?}", e);
+            return;
+        }
+    };
+    for (n, entry) in entries.iter().rev().enumerate() {
+        println!(
+            "{} {}@{{{}}}: {}",
+            find_unique_abbrev(&store, &entry.new, DEFAULT_ABBREV),
+            shown,
+            n,
+            entry.message
+        );
+    }
+}
+
+#[derive(Default)]
+struct ExpireOption<'a> {
+    expire: Option<&'a str>,
+    expire_unreachable: Option<&'a str>,
+    all: bool,
+    dry_run: bool,
+    verbose: bool,
+    refs: Vec<&'a String>,
+}
+
+fn reflog_expire(commands: &[String]) {
+    let option = parse_expire_options(commands);
+    let repo = match open_repository() {
+        Some(r) => r,
+        None => return,
+    };
+    let store = repo.object_store();
+    let config = match repo.config() {
+        Ok(c) => c,
+        Err(e) => {
+            println!("fatal: {:?}", e);
+            return;
+        }
+    };
+    let cutoff = |given: Option<&str>, name: &str, key: &str, default: &str| -> Option<i64> {
+        let configured = config.get_string(key).ok().flatten();
+        let spec = given
+            .map(|s| s.to_string())
+            .or_else(|| configured.clone())
+            .unwrap_or_else(|| default.to_string());
+        // "now" and "all" take everything, even entries made this very second.
+        if spec == "now" || spec == "all" {
+            return Some(i64::MAX);
+        }
+        match parse_expiry_date(&spec, SystemTime::now()) {
+            Some(Some(t)) => Some(match t.duration_since(UNIX_EPOCH) {
+                Ok(d) => d.as_secs() as i64,
+                Err(_) => i64::MIN,
+            }),
+            // "never" expires nothing.
+            Some(None) => Some(i64::MIN),
+            None if given.is_none() && configured.is_some() => {
+                println!("fatal: '{}' for '{}' is not a valid timestamp", spec, key);
+                None
+            }
+            None => {
+                println!("fatal: invalid timestamp '{}' given to '--{}'", spec, name);
+                None
+            }
+        }
+    };
+    let expire = match cutoff(
+        option.expire,
+        "expire",
+        "gc.reflogexpire",
+        DEFAULT_REFLOG_EXPIRE,
+    ) {
+        Some(c) => c,
+        None => return,
+    };
+    let expire_unreachable = match cutoff(
+        option.expire_unreachable,
+        "expire-unreachable",
+        "gc.reflogexpireunreachable",
+        DEFAULT_REFLOG_EXPIRE_UNREACHABLE,
+    ) {
+        Some(c) => c,
+        None => return,
+    };
+
+    let names = if option.all {
+        match list_reflogs(&repo.git_dir) {
+            Ok(n) => n,
+            Err(e) => {
+                println!("fatal: {:?}", e);
+                return;
+            }
+        }
+    } else {
+        let mut names = vec![];
+        for r in &option.refs {
+            match full_reflog_name(&repo.git_dir, r) {
+                Some(full) => names.push(full),
+                None => println!("error: {} points nowhere!", r),
+            }
+        }
+        names
+    };
+
+    for name in names {
+        let result = rewrite_reflog(&repo.git_dir, &name, option.dry_run, |entries| {
+            let prune = expire_reflog(
+                &repo.git_dir,
+                &store,
+                &name,
+                entries,
+                expire,
+                expire_unreachable,
+            )?;
+            if option.verbose {
+                for (entry, prune) in entries.iter().zip(&prune) {
+                    match (prune, option.dry_run) {
+                        (true, true) => println!("would prune {}", entry.message),
+                        (true, false) => println!("prune {}", entry.message),
+                        (false, _) => println!("keep {}", entry.message),
+                    }
+                }
+            }
+            Ok(prune.into_iter().map(|p| !p).collect())
+        });
+        if let Err(e) = result {
+            println!("error: not expiring the reflog of {}: {:?}", name, e);
+        }
+    }
+}
+
+fn reflog_delete(commands: &[String]) {
+    let repo = match open_repository() {
+        Some(r) => r,
+        None => return,
+    };
+    let mut dry_run = false;
+    // entries to delete by log, counted from the newest.
+    let mut targets: Vec<(String, Vec<usize>)> = vec![];
+    for token in &commands[3..] {
+        match &token[..] {
+            "-n" | "--dry-run" => dry_run = true,
+            _ if token.starts_with('-') => println!("ignore option {}", token),
+            _ => {
+                let parsed = token
+                    .strip_suffix('}')
+                    .and_then(|t| t.rsplit_once("@{"))
+                    .and_then(|(name, n)| Some((name, n.parse::<usize>().ok()?)));
+                let (name, n) = match parsed {
+                    Some(p) => p,
+                    None => {
+                        println!("error: not a reflog: {}", token);
+                        continue;
+                    }
+                };
+                let name = if name.is_empty() { "HEAD" } else { name };
+                let full = match full_reflog_name(&repo.git_dir, name) {
+                    Some(f) => f,
+                    None => {
+                        println!("error: {} points nowhere!", name);
+                        continue;
+                    }
+                };
+                match targets.iter_mut().find(|(f, _)| *f == full) {
+                    Some((_, ns)) => ns.push(n),
+                    None => targets.push((full, vec![n])),
+                }
+            }
+        }
+    }
+
+    for (name, ns) in targets {
+        let result = rewrite_reflog(&repo.git_dir, &name, dry_run, |entries| {
+            let len = entries.len();
+            if let Some(n) = ns.iter().find(|n| **n >= len) {
+                println!("error: no reflog entry {}@{{{}}}", name, n);
+                return Ok(vec![true; len]);
+            }
+            Ok((0..len).map(|i| !ns.contains(&(len - 1 - i))).collect())
+        });
+        if let Err(e) = result {
+            println!("error: {:?}", e);
+        }
+    }
+}
+
+// whether each entry of the log of `refname` is to be pruned: entries made
+// before `expire`, and those made before `expire_unreachable` whose commit the ref no
+// longer reaches. times are in seconds since the epoch. when what the ref reaches can
+// not be worked out, nothing is decided.
+pub fn expire_reflog(
+    git_dir: &Path,
+    store: &dyn ObjectStore,
+    refname: &str,
+    entries: &[ReflogEntry],
+    expire: i64,
+    expire_unreachable: i64,
+) -> Result<Vec<bool>> {
+    let needs_reachable = entries.iter().any(|e| {
+        let time = e.committer.time();
+        time >= expire && time < expire_unreachable
+    });
+    // a deleted ref reaches nothing.
+    let reachable = match read_ref(git_dir, refname)? {
+        Some(tip) if needs_reachable => reachable_commits(store, &tip)?,
+        _ => HashSet::new(),
+    };
+    Ok(entries
+        .iter()
+        .map(|entry| {
+            let time = entry.committer.time();
+            time < expire || (time < expire_unreachable && !reachable.contains(&entry.new))
+        })
+        .collect())
+}
+
+// the full name of the ref `name` stands for, which must have a log.
+fn full_reflog_name(git_dir: &Path, name: &str) -> Option<String> {
+    let full = match name {
+        "HEAD" | "@" => String::from("HEAD"),
+        _ => dwim_ref(git_dir, name).ok().flatten()?.0,
+    };
+    Some(full).filter(|f| git_dir.join("logs").join(f).is_file())
+}
+
+fn parse_expire_options(commands: &[String]) -> ExpireOption<'_> {
+    let mut option: ExpireOption = Default::default();
+    for token in &commands[3..] {
+        match &token[..] {
+            "--all" => option.all = true,
+            "-n" | "--dry-run" => option.dry_run = true,
+            "--verbose" => option.verbose = true,
+            _ if token.starts_with("--expire=") => option.expire = Some(&token[9..]),
+            _ if token.starts_with("--expire-unreachable=") => {
+                option.expire_unreachable = Some(&token[21..])
+            }
+            _ if !token.starts_with('-') => option.refs.push(token),
+            _ => println!("ignore option {}", token),
+        }
+    }
+    option
+}
+
+#[cfg(test)]
+mod test {
+    use crate::object_store::MemoryObjectStore;
+    use crate::reflog::*;
+    use crate::refs::NULL_ID;
+    use crate::test_utils::{commit, set_ref, temp_dir};
+
+    #[test]
+    fn test_reflog() {
+        let git_dir = temp_dir("reflog");
+        let committer = Signature::new("a", "a@example.com", 1_600_000_000, 540);
+        let first = ReflogEntry::new(
+            &NULL_ID,
+            &[1; 20],
+            committer.clone(),
+            "commit (initial):\n first",
+        );
+        let second = ReflogEntry::new(&[1; 20], &[2; 20], committer, "");
+        assert_eq!(
+            first.to_string(),
+            format!(
+                "{} {} a <a@example.com> 1600000000 +0900\tcommit (initial): first",
+                hash_to_str(&NULL_ID),
+                hash_to_str(&[1; 20])
+            )
+        );
+        assert!(!second.to_string().contains('\t'));
+
+        append_reflog(&git_dir, "refs/heads/topic/a", &first).unwrap();
+        append_reflog(&git_dir, "refs/heads/topic/a", &second).unwrap();
+        append_reflog(&git_dir, "HEAD", &first).unwrap();
+        assert_eq!(
+            read_reflog(&git_dir, "refs/heads/topic/a").unwrap(),
+            vec![first.clone(), second]
+        );
+        assert_eq!(read_reflog(&git_dir, "refs/heads/none").unwrap(), vec![]);
+        assert_eq!(
+            list_reflogs(&git_dir).unwrap(),
+            vec!["HEAD", "refs/heads/topic/a"]
+        );
+
+        rewrite_reflog(&git_dir, "refs/heads/topic/a", false, |_| {
+            Ok(vec![true, false])
+        })
+        .unwrap();
+        assert_eq!(
+            read_reflog(&git_dir, "refs/heads/topic/a").unwrap(),
+            vec![first.clone()]
+        );
+        delete_reflog(&git_dir, "refs/heads/topic/a").unwrap();
+        assert!(!git_dir.join("logs/refs/heads").exists());
+
+        // a line that is not an entry is skipped, not the whole log.
+        let mut log = fs::read_to_string(git_dir.join("logs/HEAD")).unwrap();
+        log.insert_str(0, "garbage\n");
+        log.push_str("0123 not an entry\n");
+        fs::write(git_dir.join("logs/HEAD"), log).unwrap();
+        assert_eq!(read_reflog(&git_dir, "HEAD").unwrap(), vec![first.clone()]);
+
+        // rewriting keeps such lines and the bytes of the entries it keeps.
+        let mut log = format!(
+            "garbage\n{} {} Jos",
+            hash_to_str(&[2; 20]),
+            hash_to_str(&[3; 20])
+        )
+        .into_bytes();
+        log.push(0xe9);
+        log.extend_from_slice(b" <j@example.com> 1600000000 +0900\tcommit: kept\n");
+        let kept = log.clone();
+        log.extend(format!("{}\n", first).into_bytes());
+        fs::write(git_dir.join("logs/HEAD"), &log).unwrap();
+        let lines = read_reflog_lines(&git_dir, "HEAD").unwrap();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].entry.is_none());
+        rewrite_reflog(&git_dir, "HEAD", false, |entries| {
+            assert_eq!(entries.len(), 2);
+            Ok(vec![true, false])
+        })
+        .unwrap();
+        let rewritten = fs::read(git_dir.join("logs/HEAD")).unwrap();
+        assert_eq!(rewritten, kept);
+
+        // the log is rewritten under the lock of its ref.
+        let lock = LockFile::acquire(&git_dir.join("HEAD")).unwrap();
+        assert!(rewrite_reflog(&git_dir, "HEAD", false, |e| Ok(vec![false; e.len()])).is_err());
+        drop(lock);
+        assert_eq!(fs::read(git_dir.join("logs/HEAD")).unwrap(), rewritten);
+        fs::remove_dir_all(&git_dir).unwrap();
+    }
+
+    #[test]
+    fn test_expire_reflog() {
+        let git_dir = temp_dir("expire");
+        let store = MemoryObjectStore::new();
+        let first = commit(&store, &[], &[], 0, "first");
+        let second = commit(&store, &[&first], &[], 0, "second");
+        let dropped = commit(&store, &[], &[], 0, "dropped");
+        let entry = |new: &Vec<u8>, time: i64| {
+            ReflogEntry::new(
+                &NULL_ID,
+                new,
+                Signature::new("a", "a@example.com", time, 0),
+                "",
+            )
+        };
+        let entries = vec![
+            entry(&first, 10),
+            entry(&dropped, 20),
+            entry(&second, 30),
+            entry(&first, 100),
+        ];
+        set_ref(&git_dir, "refs/heads/master", &second);
+
+        // before 15 everything goes; before 50 only what master no longer reaches.
+        let prune = expire_reflog(&git_dir, &store, "refs/heads/master", &entries, 15, 50);
+        assert_eq!(prune.unwrap(), vec![true, true, false, false]);
+
+        // when master can not be walked, nothing is pruned at all.
+        set_ref(&git_dir, "refs/heads/master", &[9; 20]);
+        assert!(expire_reflog(&git_dir, &store, "refs/heads/master", &entries, 15, 50).is_err());
+        // unless no entry depends on it.
+        let prune = expire_reflog(&git_dir, &store, "refs/heads/master", &entries, 15, 15);
+        assert_eq!(prune.unwrap(), vec![true, false, false, false]);
+        fs::remove_dir_all(&git_dir).unwrap();
+    }
+
+    #[test]
+    fn test_should_log() {
+        let git_dir = temp_dir("should-log");
+        let config_with = |text: &str| {
+            fs::write(git_dir.join("config"), text).unwrap();
+            Config::load_file(&git_dir.join("config"), None).unwrap()
+        };
+
+        let config = config_with("");
+        assert!(should_log(&git_dir, &config, "HEAD").unwrap());
+        assert!(should_log(&git_dir, &config, "refs/heads/master").unwrap());
+        assert!(!should_log(&git_dir, &config, "refs/tags/v1").unwrap());
+        let config = config_with("[core]\n\tbare = true\n");
+        assert!(!should_log(&git_dir, &config, "refs/heads/master").unwrap());
+        let config = config_with("[core]\n\tlogallrefupdates = always\n");
+        assert!(should_log(&git_dir, &config, "refs/tags/v1").unwrap());
+        // an existing log is always kept up to date.
+        let config = config_with("[core]\n\tlogallrefupdates = false\n");
+        fs::create_dir_all(git_dir.join("logs/refs/tags")).unwrap();
+        fs::write(git_dir.join("logs/refs/tags/v1"), "").unwrap();
+        assert!(should_log(&git_dir, &config, "refs/tags/v1").unwrap());
+        assert!(!should_log(&git_dir, &config, "HEAD").unwrap());
+        fs::remove_dir_all(&git_dir).unwrap();
+    }
+}
@@ -0,0 +1,922 @@
+use crate::config::Config;
+use crate::git_object::*;
+use crate::git_user::reflog_signature;
+use crate::lockfile::LockFile;
+use crate::object_store::{open_object_store_at, ObjectStore};
+use crate::reflog::{append_reflog, delete_reflog, should_log, ReflogEntry};
+use crate::repository::open_repository;
+use crate::rev_parse::{find_unique_abbrev, report_resolve_error, DEFAULT_ABBREV};
+use crate::revision::{peel, resolve_revision};
+use crate::utils::*;
+use std::{fs, io, path::Path};
+
+type Result<T> = std::result::Result<T, GitObjectError>;
+
+pub const NULL_ID: [u8; 20] = [0; 20];
+// symbolic refs pointing at symbolic refs are followed this deep at most.
+const MAX_SYMREF_DEPTH: usize = 5;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RefValue {
+    Direct(Vec<u8>),
+    Symbolic(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ref {
+    pub name: String,
+    pub id: Vec<u8>,
+    // what an annotated tag points at, when packed-refs recorded it.
+    pub peeled: Option<Vec<u8>>,
+}
+
+// a name that can be stored: an all-caps pseudo ref like HEAD, or a valid name below refs/.
+pub fn is_valid_full_name(name: &str) -> bool {
+    let pseudo = !name.is_empty() && name.bytes().all(|c| c.is_ascii_uppercase() || c == b'_');
+    let below_refs = name.strip_prefix("refs/").is_some_and(|rest| {
+        is_valid_ref_name(rest) && !rest.split('/').any(|c| c.starts_with('.'))
+    });
+    pseudo || below_refs
+}
+
+// the value stored for `name`, loose first and then packed, without following it.
+pub fn read_raw_ref(git_dir: &Path, name: &str) -> Result<Option<RefValue>> {
+    if !is_valid_full_name(name) {
+        return Err(invalid_name(name));
+    }
+    let path = git_dir.join(name);
+    if path.is_file() {
+        let content = fs::read_to_string(&path)?;
+        let content = content.trim_end();
+        return match content.strip_prefix("ref: ") {
+            Some(target) => Ok(Some(RefValue::Symbolic(target.trim().to_string()))),
+            None => parse_ref_id(content, name).map(|id| Some(RefValue::Direct(id))),
+        };
+    }
+    Ok(read_packed_refs(git_dir)?
+        .into_iter()
+        .find(|r| r.name == name)
+        .map(|r| RefValue::Direct(r.id)))
+}
+
+// follows symbolic refs from `name` and returns the ref that holds an id, with the id.
+pub fn resolve_ref(git_dir: &Path, name: &str) -> Result<Option<(String, Vec<u8>)>> {
+    let mut name = name.to_string();
+    for _ in 0..MAX_SYMREF_DEPTH {
+        match read_raw_ref(git_dir, &name)? {
+            Some(RefValue::Direct(id)) => return Ok(Some((name, id))),
+            Some(RefValue::Symbolic(target)) => name = target,
+            None => return Ok(None),
+        }
+    }
+    Err(GitObjectError::ParseError(format!(
+        "symbolic ref {} is nested too deeply",
+        name
+    )))
+}
+
+pub fn read_ref(git_dir: &Path, name: &str) -> Result<Option<Vec<u8>>> {
+    Ok(resolve_ref(git_dir, name)?.map(|(_, id)| id))
+}
+
+pub fn read_symbolic_ref(git_dir: &Path, name: &str) -> Result<Option<String>> {
+    match read_raw_ref(git_dir, name)? {
+        Some(RefValue::Symbolic(target)) => Ok(Some(target)),
+        _ => Ok(None),
+    }
+}
+
+// the entries of packed-refs in file order. a `^<id>` line gives the peeled value of
+// the tag before it.
+pub fn read_packed_refs(git_dir: &Path) -> Result<Vec<Ref>> {
+    let packed = match fs::read_to_string(git_dir.join("packed-refs")) {
+        Ok(p) => p,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(GitObjectError::IOError(e)),
+    };
+    let mut refs: Vec<Ref> = vec![];
+    for line in packed
+        .lines()
+        .filter(|l| !l.starts_with('#') && !l.is_empty())
+    {
+        if let Some(peeled) = line.strip_prefix('^') {
+            match refs.last_mut() {
+                Some(r) if r.peeled.is_none() => {
+                    r.peeled = Some(parse_ref_id(peeled, "packed-refs")?)
+                }
+                _ => return Err(bad_packed_line(line)),
+            }
+            continue;
+        }
+        let (id, name) = line.split_once(' ').ok_or_else(|| bad_packed_line(line))?;
+        refs.push(Ref {
+            name: name.to_string(),
+            id: parse_ref_id(id, name)?,
+            peeled: None,
+        });
+    }
+    Ok(refs)
+}
+
+// every ref below refs/, loose ones taking the place of packed ones of the same name,
+// sorted by name. symbolic refs are listed with the id they resolve to. a loose ref
+// that can not be read is left out with a warning.
+pub fn list_refs(git_dir: &Path) -> Result<Vec<Ref>> {
+    let (refs, broken) = list_refs_and_broken(git_dir)?;
+    for (name, _) in broken {
+        eprintln!("warning: ignoring broken ref {}", name);
+    }
+    Ok(refs)
+}
+
+// a ref that can not be read, and why.
+pub type BrokenRef = (String, GitObjectError);
+
+// like list_refs, but the refs that can not be read are returned too, for callers such
+// as gc that must not go on without them.
+pub fn list_refs_and_broken(git_dir: &Path) -> Result<(Vec<Ref>, Vec<BrokenRef>)> {
+    let mut refs = read_packed_refs(git_dir)?;
+    let mut broken = vec![];
+    let mut dirs = vec![git_dir.join("refs")];
+    while let Some(dir) = dirs.pop() {
+        let entries = match fs::read_dir(&dir) {
+            Ok(e) => e,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(GitObjectError::IOError(e)),
+        };
+        for entry in entries {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+                continue;
+            }
+            let name = match path.strip_prefix(git_dir).ok().and_then(|p| p.to_str()) {
+                Some(n) => n.replace(std::path::MAIN_SEPARATOR, "/"),
+                None => continue,
+            };
+            if name.ends_with(".lock") || !is_valid_full_name(&name) {
+                continue;
+            }
+            // a dangling symbolic ref names nothing.
+            let id = match resolve_ref(git_dir, &name) {
+                Ok(Some((_, id))) => id,
+                Ok(None) => continue,
+                Err(e) => {
+                    broken.push((name, e));
+                    continue;
+                }
+            };
+            match refs.iter_mut().find(|r| r.name == name) {
+                Some(r) if r.id == id => {}
+                Some(r) => {
+                    r.id = id;
+                    r.peeled = None;
+                }
+                None => refs.push(Ref {
+                    name,
+                    id,
+                    peeled: None,
+                }),
+            }
+        }
+    }
+    refs.sort_by(|a, b| a.name.cmp(&b.name));
+    broken.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok((refs, broken))
+}
+
+// the objects named by HEAD and every ref, loose or packed.
+pub fn ref_tips(git_dir: &Path) -> Result<Vec<Vec<u8>>> {
+    let mut tips = vec![];
+    // a symbolic HEAD is covered by the ref it points to.
+    if let Some(RefValue::Direct(id)) = read_raw_ref(git_dir, "HEAD")? {
+        tips.push(id);
+    }
+    // objects a broken ref may still need must not look unreachable.
+    let (refs, broken) = list_refs_and_broken(git_dir)?;
+    if let Some((_, e)) = broken.into_iter().next() {
+        return Err(e);
+    }
+    for r in refs {
+        tips.push(r.id);
+        tips.extend(r.peeled);
+    }
+    Ok(tips)
+}
+
+// points `name` at `new`. with `deref` a symbolic ref is followed and the ref it names
+// is updated. `old` makes the update conditional on the current value, where the null
+// id means that the ref must not exist yet. the update is logged with `message` where
+// reflogs are kept.
+pub fn write_ref(
+    git_dir: &Path,
+    name: &str,
+    new: &[u8],
+    old: Option<&[u8]>,
+    deref: bool,
+    message: &str,
+) -> Result<()> {
+    let target = if deref {
+        final_ref_name(git_dir, name)?
+    } else {
+        name.to_string()
+    };
+    check_new_value(git_dir, &target, new)?;
+    let mut lock = lock_ref(git_dir, &target)?;
+    let current = check_old_value(git_dir, &target, old)?;
+    lock.write_all(format!("{}\n", hash_to_str(new)).as_bytes())?;
+    // logged while the ref is still locked, so no other update gets in between.
+    log_ref_update(git_dir, &target, current.as_deref(), new, message)?;
+    lock.commit()
+}
+
+// removes `name`, loose and packed, under the same conditions as write_ref.
+pub fn remove_ref(git_dir: &Path, name: &str, old: Option<&[u8]>, deref: bool) -> Result<()> {
+    let target = if deref {
+        final_ref_name(git_dir, name)?
+    } else {
+        name.to_string()
+    };
+    let lock = lock_ref(git_dir, &target)?;
+    check_old_value(git_dir, &target, old)?;
+    if read_raw_ref(git_dir, &target)?.is_none() {
+        return Err(GitObjectError::NotFoundError(format!(
+            "cannot lock ref '{}': unable to resolve reference '{}'",
+            target, target
+        )));
+    }
+
+    // packed-refs is read under its lock, so that a ref packed meanwhile is not missed;
+    // when the ref is not there the lock goes unused.
+    let mut packed_lock = LockFile::acquire(&git_dir.join("packed-refs"))?;
+    let packed = read_packed_refs(git_dir)?;
+    if packed.iter().any(|r| r.name == target) {
+        // the traits of the header stay as they were: claiming `fully-peeled` for tags
+        // that were never peeled would make them look like they point at no tag.
+        let mut content = String::new();
+        let text = fs::read_to_string(git_dir.join("packed-refs"))?;
+        if let Some(header) = text
+            .lines()
+            .next()
+            .filter(|l| l.starts_with("# pack-refs with:"))
+        {
+            content.push_str(header);
+            content.push('\n');
+        }
+        for r in packed.iter().filter(|r| r.name != target) {
+            content.push_str(&format!("{} {}\n", hash_to_str(&r.id), r.name));
+            if let Some(peeled) = &r.peeled {
+                content.push_str(&format!("^{}\n", hash_to_str(peeled)));
+            }
+        }
+        packed_lock.write_all(content.as_bytes())?;
+        packed_lock.commit()?;
+    }
+    lock.commit_removal()?;
+    delete_reflog(git_dir, &target)?;
+
+    // directories left empty below refs/ go too.
+    let refs_dir = git_dir.join("refs");
+    let mut dir = git_dir.join(&target);
+    while let Some(parent) = dir.parent().map(|p| p.to_path_buf()) {
+        if !parent.starts_with(&refs_dir) || parent == refs_dir || fs::remove_dir(&parent).is_err()
+        {
+            break;
+        }
+        dir = parent;
+    }
+    Ok(())
+}
+
+pub fn write_symbolic_ref(git_dir: &Path, name: &str, target: &str) -> Result<()> {
+    if !is_valid_full_name(target) {
+        return Err(invalid_name(target));
+    }
+    let mut lock = lock_ref(git_dir, name)?;
+    lock.write_all(format!("ref: {}\n", target).as_bytes())?;
+    lock.commit()
+}
+
+// `refs/heads/master` as `master`, for messages.
+pub fn shorten_ref_name(name: &str) -> &str {
+    ["refs/heads/", "refs/tags/", "refs/remotes/", "refs/"]
+        .iter()
+        .find_map(|prefix| name.strip_prefix(prefix))
+        .unwrap_or(name)
+}
+
+// the ref a chain of symbolic refs ends at, which need not exist yet.
+fn final_ref_name(git_dir: &Path, name: &str) -> Result<String> {
+    let mut name = name.to_string();
+    for _ in 0..MAX_SYMREF_DEPTH {
+        match read_raw_ref(git_dir, &name)? {
+            Some(RefValue::Symbolic(target)) => name = target,
+            _ => return Ok(name),
+        }
+    }
+    Err(GitObjectError::ParseError(format!(
+        "symbolic ref {} is nested too deeply",
+        name
+    )))
+}
+
+// appends to the reflog of `name`, and to HEAD's when HEAD points at it.
+fn log_ref_update(
+    git_dir: &Path,
+    name: &str,
+    old: Option<&[u8]>,
+    new: &[u8],
+    message: &str,
+) -> Result<()> {
+    let config = Config::load(Some(git_dir))?;
+    let mut names = vec![name];
+    if name != "HEAD" && final_ref_name(git_dir, "HEAD").ok().as_deref() == Some(name) {
+        names.push("HEAD");
+    }
+    let entry = ReflogEntry::new(
+        old.unwrap_or(&NULL_ID),
+        new,
+        reflog_signature(&config),
+        message,
+    );
+    for name in names {
+        if should_log(git_dir, &config, name)? {
+            append_reflog(git_dir, name, &entry)?;
+        }
+    }
+    Ok(())
+}
+
+// takes the lock of the ref `name`, under which it is updated and its log appended to.
+pub fn lock_ref(git_dir: &Path, name: &str) -> Result<LockFile> {
+    if !is_valid_full_name(name) {
+        return Err(invalid_name(name));
+    }
+    let path = git_dir.join(name);
+    if path.is_dir() {
+        return Err(GitObjectError::WriteError(format!(
+            "cannot lock ref '{}': there is a non-empty directory '{}' blocking it",
+            name,
+            path.display()
+        )));
+    }
+    if let Some(parent) = path.parent() {
+        if fs::create_dir_all(parent).is_err() {
+            return Err(GitObjectError::WriteError(format!(
+                "cannot lock ref '{}': a ref above it is in the way",
+                name
+            )));
+        }
+    }
+    LockFile::acquire(&path).map_err(|e| match e {
+        GitObjectError::WriteError(m) => {
+            GitObjectError::WriteError(format!("cannot lock ref '{}': {}", name, m))
+        }
+        e => e,
+    })
+}
+
+// the current value of `name`, if it is the one expected.
+fn check_old_value(git_dir: &Path, name: &str, old: Option<&[u8]>) -> Result<Option<Vec<u8>>> {
+    let current = read_ref(git_dir, name)?;
+    let old = match old {
+        Some(o) => o,
+        None => return Ok(current),
+    };
+    let message = match current {
+        None if old == NULL_ID => return Ok(None),
+        Some(current) if current == old => return Ok(Some(current)),
+        None => format!("unable to resolve reference '{}'", name),
+        Some(_) if old == NULL_ID => String::from("reference already exists"),
+        Some(current) => format!(
+            "is at {} but expected {}",
+            hash_to_str(&current),
+            hash_to_str(old)
+        ),
+    };
+    Err(GitObjectError::WriteError(format!(
+        "cannot lock ref '{}': {}",
+        name, message
+    )))
+}
+
+// like git, a ref never points at a missing object, and a branch only at a commit.
+fn check_new_value(git_dir: &Path, name: &str, new: &[u8]) -> Result<()> {
+    let store = open_object_store_at(&git_dir.join("objects"));
+    let hash = hash_to_str(new);
+    let message = if !store.contains(&hash) {
+        format!(
+            "trying to write ref '{}' with nonexistent object {}",
+            name, hash
+        )
+    } else if name.starts_with("refs/heads/") && store.header(&hash)?.0 != "commit" {
+        format!(
+            "trying to write non-commit object {} to branch '{}'",
+            hash, name
+        )
+    } else {
+        return Ok(());
+    };
+    Err(GitObjectError::WriteError(format!(
+        "cannot update ref '{}': {}",
+        name, message
+    )))
+}
+
+fn parse_ref_id(s: &str, name: &str) -> Result<Vec<u8>> {
+    if s.len() == 40 && s.bytes().all(|c| c.is_ascii_hexdigit()) {
+        Ok(str_to_hash(s.to_ascii_lowercase()))
+    } else {
+        Err(GitObjectError::ParseError(format!(
+            "ref {} does not hold an object id",
+            name
+        )))
+    }
+}
+
+fn bad_packed_line(line: &str) -> GitObjectError {
+    GitObjectError::ParseError(format!("unexpected line in packed-refs: {}", line))
+}
+
+fn invalid_name(name: &str) -> GitObjectError {
+    GitObjectError::ParseError(format!("invalid ref name: {}", name))
+}
+
+#[derive(Default)]
+struct UpdateRefOption<'a> {
+    delete: bool,
+    no_deref: bool,
+    message: Option<&'a String>,
+    args: Vec<&'a String>,
+}
+
+pub fn update_ref(commands: &[String]) {
+    let option = parse_update_ref_options(commands);
+    let max_args = if option.delete { 2 } else { 3 };
+    if option.args.len() < max_args - 1 || option.args.len() > max_args {
+        println!("usage: update-ref [-m <reason>] [--no-deref] <refname> <new-oid> [<old-oid>]");
+        println!("       update-ref [-m <reason>] [--no-deref] -d <refname> [<old-oid>]");
+        return;
+    }
+    let repo = match open_repository() {
+        Some(r) => r,
+        None => return,
+    };
+    let store = repo.object_store();
+    // an empty or all-zero old value means that the ref must not exist.
+    let resolve = |rev: &str| -> Option<Vec<u8>> {
+        if rev.is_empty() || rev.bytes().all(|c| c == b'0') && rev.len() == 40 {
+            return Some(NULL_ID.to_vec());
+        }
+        match resolve_revision(&repo.git_dir, &store, rev) {
+            Ok(id) => Some(id),
+            Err(e) => {
+                report_resolve_error(&store, rev, &e);
+                println!("fatal: {}: not a valid SHA1", rev);
+                None
+            }
+        }
+    };
+    let name = option.args[0];
+    let deref = !option.no_deref;
+    let result = if option.delete {
+        let old = match option.args.get(1) {
+            Some(rev) => match resolve(rev) {
+                Some(id) => Some(id),
+                None => return,
+            },
+            None => None,
+        };
+        remove_ref(&repo.git_dir, name, old.as_deref(), deref)
+    } else {
+        let new = match resolve(option.args[1]) {
+            Some(id) => id,
+            None => return,
+        };
+        let old = match option.args.get(2) {
+            Some(rev) => match resolve(rev) {
+                Some(id) => Some(id),
+                None => return,
+            },
+            None => None,
+        };
+        let message = option.message.map(|m| m.as_str()).unwrap_or_default();
+        write_ref(&repo.git_dir, name, &new, old.as_deref(), deref, message)
+    };
+    if let Err(e) = result {
+        print_ref_error(&e);
+    }
+}
+
+#[derive(Default)]
+struct SymbolicRefOption<'a> {
+    delete: bool,
+    quiet: bool,
+    short: bool,
+    args: Vec<&'a String>,
+}
+
+pub fn symbolic_ref(commands: &[String]) {
+    let option = parse_symbolic_ref_options(commands);
+    let repo = match open_repository() {
+        Some(r) => r,
+        None => return,
+    };
+    match (&option.args[..], option.delete) {
+        ([name], true) => {
+            if read_symbolic_ref(&repo.git_dir, name)
+                .ok()
+                .flatten()
+                .is_none()
+            {
+                if !option.quiet {
+                    println!("fatal: Cannot delete {}, not a symbolic ref", name);
+                }
+                return;
+            }
+            if let Err(e) = remove_ref(&repo.git_dir, name, None, false) {
+                print_ref_error(&e);
+            }
+        }
+        ([name], false) => match read_symbolic_ref(&repo.git_dir, name) {
+            Ok(Some(target)) if option.short => println!("{}", shorten_ref_name(&target)),
+            Ok(Some(target)) => println!("{}", target),
+            Ok(None) if option.quiet => {}
+            Ok(None) => println!("fatal: ref {} is not a symbolic ref", name),
+            Err(e) => print_ref_error(&e),
+        },
+        ([name, target], false) => {
+            if name.as_str() == "HEAD" && !target.starts_with("refs/") {
+                println!("fatal: Refusing to point HEAD outside of refs/");
+                return;
+            }
+            if let Err(e) = write_symbolic_ref(&repo.git_dir, name, target) {
+                print_ref_error(&e);
+            }
+        }
+        _ => {
+            println!("usage: symbolic-ref [-q] [--short] <name>");
+            println!("       symbolic-ref <name> <ref>");
+            println!("       symbolic-ref --delete [-q] <name>");
+        }
+    }
+}
+
+#[derive(Default)]
+struct ShowRefOption<'a> {
+    head: bool,
+    heads: bool,
+    tags: bool,
+    dereference: bool,
+    verify: bool,
+    quiet: bool,
+    hash: Option<usize>,
+    patterns: Vec<&'a String>,
+}
+
+pub fn show_ref(commands: &[String]) {
+    let option = parse_show_ref_options(commands);
+    let repo = match open_repository() {
+        Some(r) => r,
+        None => return,
+    };
+    let store = repo.object_store();
+
+    let mut refs = vec![];
+    if option.verify {
+        for name in &option.patterns {
+            match resolve_ref(&repo.git_dir, name) {
+                Ok(Some((_, id))) if name.starts_with("refs/") || name.as_str() == "HEAD" => refs
+                    .push(Ref {
+                        name: name.to_string(),
+                        id,
+                        peeled: None,
+                    }),
+                _ => {
+                    if !option.quiet {
+                        println!("fatal: '{}' - not a valid ref", name);
+                    }
+                    return;
+                }
+            }
+        }
+    } else {
+        if option.head {
+            if let Ok(Some(id)) = read_ref(&repo.git_dir, "HEAD") {
+                refs.push(Ref {
+                    name: String::from("HEAD"),
+                    id,
+                    peeled: None,
+                });
+            }
+        }
+        let listed = match list_refs(&repo.git_dir) {
+            Ok(r) => r,
+            Err(e) => {
+                print_ref_error(&e);
+                return;
+            }
+        };
+        let kinds_given = option.heads || option.tags;
+        refs.extend(listed.into_iter().filter(|r| {
+            let kind = (option.heads && r.name.starts_with("refs/heads/"))
+                || (option.tags && r.name.starts_with("refs/tags/"));
+            // a pattern matches whole trailing components of the name.
+            let pattern = option.patterns.is_empty()
+                || option
+                    .patterns
+                    .iter()
+                    .any(|p| r.name == **p || r.name.ends_with(&format!("/{}", p)));
+            (kind || !kinds_given) && pattern
+        }));
+    }
+    if option.quiet {
+        return;
+    }
+
+    let show = |id: &[u8], name: &str| match option.hash {
+        Some(len) => println!("{}", abbrev(&store, id, len)),
+        None => println!("{} {}", hash_to_str(id), name),
+    };
+    for r in &refs {
+        show(&r.id, &r.name);
+        if option.dereference {
+            let peeled = match &r.peeled {
+                Some(p) => Some(p.clone()),
+                None => match store.header(&hash_to_str(&r.id)) {
+                    Ok((t, _)) if t == "tag" => peel(&store, &r.id, None).ok(),
+                    _ => None,
+                },
+            };
+            if let Some(peeled) = peeled {
+                show(&peeled, &format!("{}^{{}}", r.name));
+            }
+        }
+    }
+}
+
+fn abbrev(store: &dyn ObjectStore, id: &[u8], len: usize) -> String {
+    if len >= 40 {
+        hash_to_str(id)
+    } else {
+        find_unique_abbrev(store, id, len)
+    }
+}
+
+fn print_ref_error(e: &GitObjectError) {
+    match e {
+        GitObjectError::WriteError(m)
+        | GitObjectError::ParseError(m)
+        | GitObjectError::NotFoundError(m) => println!("fatal: {}", m),
+        e => println!("fatal: {:?}", e),
+    }
+}
+
+fn parse_update_ref_options(commands: &[String]) -> UpdateRefOption<'_> {
+    let mut option: UpdateRefOption = Default::default();
+    let mut message_flag = false;
+    for token in &commands[2..] {
+        if message_flag {
+            option.message = Some(token);
+            message_flag = false;
+            continue;
+        }
+        match &token[..] {
+            "-d" => option.delete = true,
+            "--no-deref" => option.no_deref = true,
+            "-m" => message_flag = true,
+            _ if !token.starts_with('-') || token.is_empty() => option.args.push(token),
+            _ => println!("ignore option {}", token),
+        }
+    }
+    option
+}
+
+fn parse_symbolic_ref_options(commands: &[String]) -> SymbolicRefOption<'_> {
+    let mut option: SymbolicRefOption = Default::default();
+    for token in &commands[2..] {
+        match &token[..] {
+            "-d" | "--delete" => option.delete = true,
+            "-q" | "--quiet" => option.quiet = true,
+            "--short" => option.short = true,
+            _ if !token.starts_with('-') => option.args.push(token),
+            _ => println!("ignore option {}", token),
+        }
+    }
+    option
+}
+
+fn parse_show_ref_options(commands: &[String]) -> ShowRefOption<'_> {
+    let mut option: ShowRefOption = Default::default();
+    for token in &commands[2..] {
+        match &token[..] {
+            "--head" => option.head = true,
+            "--heads" | "--branches" => option.heads = true,
+            "--tags" => option.tags = true,
+            "-d" | "--dereference" => option.dereference = true,
+            "--verify" => option.verify = true,
+            "-q" | "--quiet" => option.quiet = true,
+            "-s" | "--hash" => option.hash = Some(40),
+            _ if token.starts_with("--hash=") => {
+                option.hash = Some(token[7..].parse().unwrap_or(DEFAULT_ABBREV))
+            }
+            _ if !token.starts_with('-') => option.patterns.push(token),
+            _ => println!("ignore option {}", token),
+        }
+    }
+    option
+}
+
+#[cfg(test)]
+mod test {
+    use crate::reflog::read_reflog;
+    use crate::refs::*;
+    use crate::test_utils::{commit, set_ref, temp_git_dir};
+
+    fn id(n: u8) -> Vec<u8> {
+        vec![n; 20]
+    }
+
+    #[test]
+    fn test_read_refs() {
+        let git_dir = temp_git_dir("refs-read");
+        fs::write(
+            git_dir.join("packed-refs"),
+            format!(
+                "# pack-refs with: peeled fully-peeled sorted \n{} refs/heads/master\n\
+                 {} refs/tags/v1\n^{}\n{} refs/tags/v2\n",
+                hash_to_str(&id(1)),
+                hash_to_str(&id(2)),
+                hash_to_str(&id(3)),
+                hash_to_str(&id(4))
+            ),
+        )
+        .unwrap();
+        fs::create_dir_all(git_dir.join("refs/tags")).unwrap();
+        fs::write(
+            git_dir.join("refs/tags/v2"),
+            format!("{}\n", hash_to_str(&id(5))),
+        )
+        .unwrap();
+        fs::create_dir_all(git_dir.join("refs/remotes/origin")).unwrap();
+        fs::write(
+            git_dir.join("refs/remotes/origin/HEAD"),
+            "ref: refs/heads/master\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            read_raw_ref(&git_dir, "HEAD").unwrap(),
+            Some(RefValue::Symbolic(String::from("refs/heads/master")))
+        );
+        assert_eq!(
+            resolve_ref(&git_dir, "HEAD").unwrap(),
+            Some((String::from("refs/heads/master"), id(1)))
+        );
+        assert_eq!(read_ref(&git_dir, "refs/tags/v2").unwrap(), Some(id(5)));
+        assert_eq!(read_ref(&git_dir, "refs/heads/nothing").unwrap(), None);
+        assert!(read_ref(&git_dir, "refs/../config").is_err());
+
+        let refs = list_refs(&git_dir).unwrap();
+        let names: Vec<&str> = refs.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "refs/heads/master",
+                "refs/remotes/origin/HEAD",
+                "refs/tags/v1",
+                "refs/tags/v2"
+            ]
+        );
+        assert_eq!(refs[1].id, id(1));
+        assert_eq!(refs[2].peeled, Some(id(3)));
+        assert_eq!(refs[3].id, id(5));
+
+        // symbolic refs that point at each other never resolve.
+        fs::write(git_dir.join("refs/heads/a"), "ref: refs/heads/b\n").unwrap();
+        fs::write(git_dir.join("refs/heads/b"), "ref: refs/heads/a\n").unwrap();
+        assert!(resolve_ref(&git_dir, "refs/heads/a").is_err());
+        // and are left out of the list instead of failing it.
+        assert_eq!(list_refs(&git_dir).unwrap(), refs);
+        let (_, broken) = list_refs_and_broken(&git_dir).unwrap();
+        let names: Vec<&str> = broken.iter().map(|b| b.0.as_str()).collect();
+        assert_eq!(names, vec!["refs/heads/a", "refs/heads/b"]);
+
+        // removing a packed ref keeps the header and the peeled tags.
+        remove_ref(&git_dir, "refs/heads/master", None, false).unwrap();
+        let packed = fs::read_to_string(git_dir.join("packed-refs")).unwrap();
+        assert!(packed.starts_with("# pack-refs with: peeled fully-peeled sorted \n"));
+        assert!(packed.contains(&format!("^{}\n", hash_to_str(&id(3)))));
+        fs::remove_dir_all(&git_dir).unwrap();
+    }
+
+    #[test]
+    fn test_write_refs() {
+        let git_dir = temp_git_dir("refs-write");
+        let store = open_object_store_at(&git_dir.join("objects"));
+        // a commit in the objects of `git_dir`, a different one for each `n`.
+        let id = |n: u8| commit(&store, &[], &[], 0, &n.to_string());
+        // HEAD is followed to the unborn branch it names.
+        write_ref(&git_dir, "HEAD", &id(1), Some(&NULL_ID), true, "first").unwrap();
+        assert_eq!(
+            read_ref(&git_dir, "refs/heads/master").unwrap(),
+            Some(id(1))
+        );
+        assert!(write_ref(&git_dir, "HEAD", &id(2), Some(&NULL_ID), true, "").is_err());
+        assert!(write_ref(&git_dir, "HEAD", &id(2), Some(&id(3)), true, "").is_err());
+        write_ref(&git_dir, "HEAD", &id(2), Some(&id(1)), true, "second").unwrap();
+        // the branch and HEAD pointing at it both log the update.
+        for name in &["HEAD", "refs/heads/master"] {
+            let log = read_reflog(&git_dir, name).unwrap();
+            assert_eq!(log.len(), 2, "{}", name);
+            assert_eq!((&log[0].old[..], &log[0].new), (&NULL_ID[..], &id(1)));
+            assert_eq!((&log[1].old, &log[1].message[..]), (&id(1), "second"));
+        }
+        assert_eq!(read_ref(&git_dir, "HEAD").unwrap(), Some(id(2)));
+
+        // a held lock keeps others out.
+        let lock = LockFile::acquire(&git_dir.join("refs/heads/master")).unwrap();
+        assert!(write_ref(&git_dir, "refs/heads/master", &id(3), None, true, "").is_err());
+        drop(lock);
+
+        // directory/file conflicts.
+        write_ref(&git_dir, "refs/heads/topic/one", &id(4), None, true, "").unwrap();
+        assert!(write_ref(&git_dir, "refs/heads/topic", &id(4), None, true, "").is_err());
+        assert!(write_ref(&git_dir, "refs/heads/master/x", &id(4), None, true, "").is_err());
+        assert!(write_ref(&git_dir, "refs/heads/bad..name", &id(4), None, true, "").is_err());
+
+        // missing objects are refused, and so is anything but a commit for a branch.
+        let missing = vec![1; 20];
+        assert!(write_ref(&git_dir, "refs/tags/missing", &missing, None, true, "").is_err());
+        assert!(!git_dir.join("refs/tags/missing").exists());
+        let store = open_object_store_at(&git_dir.join("objects"));
+        let tree = GitObject::new_tree(vec![]).write(&store).unwrap();
+        assert!(write_ref(&git_dir, "refs/heads/tree", &tree, None, true, "").is_err());
+        write_ref(&git_dir, "refs/tags/tree", &tree, None, true, "").unwrap();
+        // deleting needs packed-refs locked too, even when the ref is only loose.
+        let lock = LockFile::acquire(&git_dir.join("packed-refs")).unwrap();
+        assert!(remove_ref(&git_dir, "refs/tags/tree", Some(&tree), true).is_err());
+        assert_eq!(
+            read_ref(&git_dir, "refs/tags/tree").unwrap(),
+            Some(tree.clone())
+        );
+        drop(lock);
+        remove_ref(&git_dir, "refs/tags/tree", Some(&tree), true).unwrap();
+        assert!(!git_dir.join("packed-refs").exists());
+
+        // deleting removes packed and loose copies and the emptied directories.
+        fs::write(
+            git_dir.join("packed-refs"),
+            format!(
+                "{} refs/heads/topic/one\n{} refs/tags/v1\n",
+                hash_to_str(&id(6)),
+                hash_to_str(&id(7))
+            ),
+        )
+        .unwrap();
+        assert!(remove_ref(&git_dir, "refs/heads/topic/one", Some(&id(6)), true).is_err());
+        remove_ref(&git_dir, "refs/heads/topic/one", Some(&id(4)), true).unwrap();
+        assert_eq!(read_ref(&git_dir, "refs/heads/topic/one").unwrap(), None);
+        assert!(!git_dir.join("refs/heads/topic").exists());
+        assert!(!git_dir.join("logs/refs/heads/topic").exists());
+        assert_eq!(read_ref(&git_dir, "refs/tags/v1").unwrap(), Some(id(7)));
+        assert_eq!(
+            fs::read_to_string(git_dir.join("packed-refs")).unwrap(),
+            format!("{} refs/tags/v1\n", hash_to_str(&id(7)))
+        );
+        assert!(remove_ref(&git_dir, "refs/heads/topic/one", None, true).is_err());
+
+        write_symbolic_ref(&git_dir, "HEAD", "refs/heads/other").unwrap();
+        assert_eq!(
+            read_symbolic_ref(&git_dir, "HEAD").unwrap(),
+            Some(String::from("refs/heads/other"))
+        );
+        assert_eq!(read_ref(&git_dir, "HEAD").unwrap(), None);
+        assert_eq!(shorten_ref_name("refs/heads/other"), "other");
+        assert!(!git_dir.join("HEAD.lock").exists());
+        fs::remove_dir_all(&git_dir).unwrap();
+    }
+
+    #[test]
+    fn test_ref_tips() {
+        let git_dir = temp_git_dir("ref-tips");
+        let store = open_object_store_at(&git_dir.join("objects"));
+        let head = commit(&store, &[], &[("file", b"content\n")], 1_600_000_000, "");
+        set_ref(&git_dir, "refs/heads/master", &head);
+        let tag = "0123456789012345678901234567890123456789";
+        fs::write(
+            git_dir.join("packed-refs"),
+            format!("# pack-refs with: peeled\n{} refs/tags/v1\n^{}\n", tag, tag),
+        )
+        .unwrap();
+        // HEAD through master, the tag and what it peels to.
+        let tips = ref_tips(&git_dir).unwrap();
+        assert_eq!(tips.len(), 3);
+        assert!(tips.contains(&head));
+        assert!(tips.contains(&str_to_hash(tag.to_string())));
+
+        fs::write(git_dir.join("refs/heads/broken"), "garbage\n").unwrap();
+        assert!(ref_tips(&git_dir).is_err());
+        fs::remove_dir_all(&git_dir).unwrap();
+    }
+}
@@ -0,0 +1,521 @@
+// extended regular expressions, as the value patterns of `git config` are: `^` and `$`
+// anchors, `.`, `[...]` classes with `[:name:]` classes inside, `\` escapes, `(...)`
+// groups, `|` and the `*`, `+`, `?` and `{m,n}` repetitions. the pattern is compiled
+// to a thompson nfa that is run over all of its states at once, so matching takes time
+// linear in the text however the pattern is written; there are no backreferences.
+
+// more would make a program too large to run in reasonable time.
+const MAX_REPEAT: u32 = 255;
+const MAX_PROGRAM: usize = 100_000;
+
+#[derive(Debug, Clone)]
+enum Node {
+    Empty,
+    Char(char),
+    Any,
+    Class(Class),
+    Start,
+    End,
+    Concat(Vec<Node>),
+    Alternate(Vec<Node>),
+    Repeat(Box<Node>, u32, Option<u32>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Class {
+    ranges: Vec<(char, char)>,
+    negated: bool,
+}
+
+impl Class {
+    fn contains(&self, c: char) -> bool {
+        self.ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi) != self.negated
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(Class),
+    // assertions on the position, taking no character.
+    Start,
+    End,
+    Split(usize, usize),
+    Jump(usize),
+    Match,
+}
+
+#[derive(Debug, Clone)]
+pub struct Regex {
+    program: Vec<Inst>,
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Regex, String> {
+        let chars: Vec<char> = pattern.chars().collect();
+        let mut parser = Parser {
+            chars: &chars,
+            pos: 0,
+        };
+        let node = parser.alternation()?;
+        if parser.pos < chars.len() {
+            // only an unmatched `)` stops the outermost alternation.
+            return Err(String::from("unmatched ) in pattern"));
+        }
+        let mut program = vec![];
+        compile(&node, &mut program)?;
+        program.push(Inst::Match);
+        Ok(Regex { program })
+    }
+
+    // whether the pattern matches anywhere in `text`.
+    pub fn is_match(&self, text: &str) -> bool {
+        let chars: Vec<char> = text.chars().collect();
+        let mut current = Threads::new(self.program.len());
+        let mut next = Threads::new(self.program.len());
+        for i in 0..=chars.len() {
+            // a match may start at every position.
+            self.add_thread(&mut current, 0, i, chars.len());
+            if current
+                .list
+                .iter()
+                .any(|&pc| self.program[pc] == Inst::Match)
+            {
+                return true;
+            }
+            let c = match chars.get(i) {
+                Some(&c) => c,
+                None => break,
+            };
+            next.clear();
+            for k in 0..current.list.len() {
+                let pc = current.list[k];
+                let hit = match &self.program[pc] {
+                    Inst::Char(x) => *x == c,
+                    Inst::Any => true,
+                    Inst::Class(class) => class.contains(c),
+                    _ => false,
+                };
+                if hit {
+                    self.add_thread(&mut next, pc + 1, i + 1, chars.len());
+                }
+            }
+            std::mem::swap(&mut current, &mut next);
+        }
+        false
+    }
+
+    // adds `pc` and every instruction reachable from it without taking a character.
+    fn add_thread(&self, threads: &mut Threads, pc: usize, i: usize, len: usize) {
+        let mut stack = vec![pc];
+        while let Some(pc) = stack.pop() {
+            if threads.seen[pc] {
+                continue;
+            }
+            threads.seen[pc] = true;
+            match self.program[pc] {
+                Inst::Jump(to) => stack.push(to),
+                Inst::Split(a, b) => {
+                    stack.push(b);
+                    stack.push(a);
+                }
+                Inst::Start if i == 0 => stack.push(pc + 1),
+                Inst::End if i == len => stack.push(pc + 1),
+                Inst::Start | Inst::End => {}
+                _ => threads.list.push(pc),
+            }
+        }
+    }
+}
+
+// the states the nfa is in at one position of the text.
+struct Threads {
+    list: Vec<usize>,
+    seen: Vec<bool>,
+}
+
+impl Threads {
+    fn new(size: usize) -> Self {
+        Threads {
+            list: Vec::with_capacity(size),
+            seen: vec![false; size],
+        }
+    }
+
+    fn clear(&mut self) {
+        self.list.clear();
+        self.seen.iter_mut().for_each(|s| *s = false);
+    }
+}
+
+struct Parser<'a> {
+    chars: &'a [char],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn alternation(&mut self) -> Result<Node, String> {
+        let mut branches = vec![self.sequence()?];
+        while self.peek() == Some('|') {
+            self.pos += 1;
+            branches.push(self.sequence()?);
+        }
+        Ok(if branches.len() == 1 {
+            branches.pop().unwrap()
+        } else {
+            Node::Alternate(branches)
+        })
+    }
+
+    fn sequence(&mut self) -> Result<Node, String> {
+        let mut items = vec![];
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let mut atom = self.atom()?;
+            while let Some((min, max)) = self.repetition()? {
+                atom = Node::Repeat(Box::new(atom), min, max);
+            }
+            items.push(atom);
+        }
+        Ok(match items.len() {
+            0 => Node::Empty,
+            1 => items.pop().unwrap(),
+            _ => Node::Concat(items),
+        })
+    }
+
+    fn atom(&mut self) -> Result<Node, String> {
+        let c = self.peek().unwrap();
+        self.pos += 1;
+        Ok(match c {
+            '.' => Node::Any,
+            '^' => Node::Start,
+            '$' => Node::End,
+            '(' => {
+                let inner = self.alternation()?;
+                if self.peek() != Some(')') {
+                    return Err(String::from("unmatched ( in pattern"));
+                }
+                self.pos += 1;
+                inner
+            }
+            '[' => Node::Class(self.bracket()?),
+            '\\' => match self.peek() {
+                None => return Err(String::from("trailing backslash in pattern")),
+                Some(e) => {
+                    self.pos += 1;
+                    match e {
+                        'w' | 'W' => {
+                            let mut ranges = named_class("alnum").unwrap().to_vec();
+                            ranges.push(('_', '_'));
+                            ranges.extend(non_ascii_letters());
+                            Node::Class(Class {
+                                ranges,
+                                negated: e == 'W',
+                            })
+                        }
+                        's' | 'S' => Node::Class(Class {
+                            ranges: named_class("space").unwrap().to_vec(),
+                            negated: e == 'S',
+                        }),
+                        _ => Node::Char(e),
+                    }
+                }
+            },
+            // a repetition with nothing before it stands for itself.
+            _ => Node::Char(c),
+        })
+    }
+
+    // the repetition operator at the current position, as its bounds.
+    fn repetition(&mut self) -> Result<Option<(u32, Option<u32>)>, String> {
+        let bounds = match self.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => match self.bounds()? {
+                Some(b) => return Ok(Some(b)),
+                // not a valid bound: the `{` is an ordinary character.
+                None => return Ok(None),
+            },
+            _ => return Ok(None),
+        };
+        self.pos += 1;
+        Ok(Some(bounds))
+    }
+
+    // `{m}`, `{m,}` or `{m,n}` at the current position.
+    fn bounds(&mut self) -> Result<Option<(u32, Option<u32>)>, String> {
+        let rest: String = self.chars[self.pos + 1..].iter().collect();
+        let end = match rest.find('}') {
+            Some(e) => e,
+            None => return Ok(None),
+        };
+        let inner = &rest[..end];
+        let number = |s: &str| -> Option<u32> {
+            if s.is_empty() || !s.chars().all(|c| c.is_ascii_digit()) {
+                return None;
+            }
+            Some(s.parse().unwrap_or(u32::MAX))
+        };
+        let (min, max) = match inner.split_once(',') {
+            None => match number(inner) {
+                Some(n) => (n, Some(n)),
+                None => return Ok(None),
+            },
+            Some((lo, "")) => match number(lo) {
+                Some(n) => (n, None),
+                None => return Ok(None),
+            },
+            Some((lo, hi)) => match (number(lo), number(hi)) {
+                (Some(lo), Some(hi)) => (lo, Some(hi)),
+                _ => return Ok(None),
+            },
+        };
+        if max.is_some_and(|m| m < min) {
+            return Err(format!("invalid repetition {{{}}} in pattern", inner));
+        }
+        if min > MAX_REPEAT || max.is_some_and(|m| m > MAX_REPEAT) {
+            return Err(format!("repetition {{{}}} is too large", inner));
+        }
+        self.pos += inner.chars().count() + 2;
+        Ok(Some((min, max)))
+    }
+
+    // a bracket expression after its `[`. as in posix, `\` is an ordinary character here.
+    fn bracket(&mut self) -> Result<Class, String> {
+        let mut class = Class {
+            ranges: vec![],
+            negated: false,
+        };
+        if self.peek() == Some('^') {
+            class.negated = true;
+            self.pos += 1;
+        }
+        let start = self.pos;
+        loop {
+            let c = match self.peek() {
+                Some(c) => c,
+                None => return Err(String::from("unmatched [ in pattern")),
+            };
+            // a `]` right at the start is part of the class.
+            if c == ']' && self.pos > start {
+                self.pos += 1;
+                return Ok(class);
+            }
+            if c == '[' && self.chars.get(self.pos + 1) == Some(&':') {
+                let rest: String = self.chars[self.pos + 2..].iter().collect();
+                if let Some(end) = rest.find(":]") {
+                    let name = &rest[..end];
+                    let ranges = named_class(name)
+                        .ok_or_else(|| format!("unknown character class [:{}:]", name))?;
+                    class.ranges.extend_from_slice(ranges);
+                    if name == "alnum" || name == "alpha" {
+                        class.ranges.extend(non_ascii_letters());
+                    }
+                    self.pos += name.chars().count() + 4;
+                    continue;
+                }
+            }
+            self.pos += 1;
+            match (self.peek(), self.chars.get(self.pos + 1)) {
+                (Some('-'), Some(&hi)) if hi != ']' => {
+                    if hi < c {
+                        return Err(format!("invalid range {}-{} in pattern", c, hi));
+                    }
+                    class.ranges.push((c, hi));
+                    self.pos += 2;
+                }
+                _ => class.ranges.push((c, c)),
+            }
+        }
+    }
+}
+
+// latin-1 letters and everything above; close enough to a utf-8 locale's alpha class.
+fn non_ascii_letters() -> Vec<(char, char)> {
+    vec![
+        ('\u{c0}', '\u{d6}'),
+        ('\u{d8}', '\u{f6}'),
+        ('\u{f8}', char::MAX),
+    ]
+}
+
+fn named_class(name: &str) -> Option<&'static [(char, char)]> {
+    Some(match name {
+        "alpha" => &[('a', 'z'), ('A', 'Z')],
+        "digit" => &[('0', '9')],
+        "alnum" => &[('a', 'z'), ('A', 'Z'), ('0', '9')],
+        "upper" => &[('A', 'Z')],
+        "lower" => &[('a', 'z')],
+        "space" => &[(' ', ' '), ('\t', '\r')],
+        "blank" => &[(' ', ' '), ('\t', '\t')],
+        "punct" => &[('!', '/'), (':', '@'), ('[', '`'), ('{', '~')],
+        "xdigit" => &[('0', '9'), ('a', 'f'), ('A', 'F')],
+        "cntrl" => &[('\0', '\u{1f}'), ('\u{7f}', '\u{7f}')],
+        "print" => &[(' ', '~')],
+        "graph" => &[('!', '~')],
+        _ => return None,
+    })
+}
+
+fn compile(node: &Node, program: &mut Vec<Inst>) -> Result<(), String> {
+    if program.len() > MAX_PROGRAM {
+        return Err(String::from("pattern is too large"));
+    }
+    match node {
+        Node::Empty => {}
+        Node::Char(c) => program.push(Inst::Char(*c)),
+        Node::Any => program.push(Inst::Any),
+        Node::Class(class) => program.push(Inst::Class(class.clone())),
+        Node::Start => program.push(Inst::Start),
+        Node::End => program.push(Inst::End),
+        Node::Concat(items) => {
+            for item in items {
+                compile(item, program)?;
+            }
+        }
+        Node::Alternate(branches) => {
+            // each branch but the last is tried through a split, and jumps to the end.
+            let mut jumps = vec![];
+            for (k, branch) in branches.iter().enumerate() {
+                if k + 1 < branches.len() {
+                    let split = program.len();
+                    program.push(Inst::Split(split + 1, 0));
+                    compile(branch, program)?;
+                    jumps.push(program.len());
+                    program.push(Inst::Jump(0));
+                    let next = program.len();
+                    program[split] = Inst::Split(split + 1, next);
+                } else {
+                    compile(branch, program)?;
+                }
+            }
+            let end = program.len();
+            for j in jumps {
+                program[j] = Inst::Jump(end);
+            }
+        }
+        Node::Repeat(inner, min, max) => {
+            for _ in 0..*min {
+                compile(inner, program)?;
+            }
+            match max {
+                None => {
+                    let split = program.len();
+                    program.push(Inst::Split(split + 1, 0));
+                    compile(inner, program)?;
+                    program.push(Inst::Jump(split));
+                    let end = program.len();
+                    program[split] = Inst::Split(split + 1, end);
+                }
+                Some(max) => {
+                    let mut splits = vec![];
+                    for _ in *min..*max {
+                        splits.push(program.len());
+                        program.push(Inst::Split(0, 0));
+                        compile(inner, program)?;
+                    }
+                    let end = program.len();
+                    for s in splits {
+                        program[s] = Inst::Split(s + 1, end);
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::regex::*;
+    use std::time::{Duration, Instant};
+
+    fn matches(pattern: &str, text: &str) -> bool {
+        Regex::new(pattern).unwrap().is_match(text)
+    }
+
+    #[test]
+    fn test_match() {
+        assert!(matches("b", "abc"));
+        assert!(!matches("^b", "abc"));
+        assert!(matches("^a.c$", "abc"));
+        assert!(!matches("^a.c$", "abcd"));
+        assert!(matches("^ab*c$", "ac"));
+        assert!(matches("^ab+c$", "abbbc"));
+        assert!(!matches("^ab+c$", "ac"));
+        assert!(matches("^colou?r$", "color"));
+        assert!(matches("^[0-9]+$", "2024"));
+        assert!(!matches("^[^0-9]+$", "a1"));
+        assert!(matches("^(ab)*$", "abab"));
+        assert!(!matches("^(ab)*$", "aba"));
+        assert!(matches("^(x|yz)+$", "xyzx"));
+        assert!(matches("^one$|^two$", "two"));
+        assert!(matches("a\\.b", "a.b"));
+        assert!(!matches("a\\.b", "axb"));
+        assert!(matches("", "anything"));
+        assert!(matches("^$", ""));
+        assert!(matches("^(a*)*$", "aaa"));
+        assert!(matches("[]a]", "]"));
+        assert!(matches("^[[:digit:][:space:]]+$", "1 2"));
+        assert!(matches("^\\w+$", "a_1"));
+        assert!(!matches("\\s", "ab"));
+        // `\` is ordinary inside brackets.
+        assert!(matches("^[\\]$", "\\"));
+    }
+
+    #[test]
+    fn test_bounds() {
+        assert!(matches("^a{3}$", "aaa"));
+        assert!(!matches("^a{3}$", "aa"));
+        assert!(matches("^a{2,}$", "aaaaa"));
+        assert!(!matches("^a{2,}$", "a"));
+        assert!(matches("^(ab){1,2}$", "abab"));
+        assert!(!matches("^(ab){1,2}$", "ababab"));
+        assert!(matches("^a{0,1}b$", "b"));
+        // not a bound, so the brace is literal.
+        assert!(matches("^a{x}$", "a{x}"));
+        assert!(matches("^a{$", "a{"));
+        assert!(Regex::new("a{3,2}").is_err());
+        assert!(Regex::new("a{1000}").is_err());
+    }
+
+    #[test]
+    fn test_characters() {
+        // `.` and classes take a whole character, not a byte of it.
+        assert!(matches("^.$", "é"));
+        assert!(matches("^caf.$", "café"));
+        assert!(matches("^[é]$", "é"));
+        assert!(!matches("^[^é]$", "é"));
+        assert!(matches("^.{2}$", "日本"));
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(Regex::new("(a").is_err());
+        assert!(Regex::new("a)").is_err());
+        assert!(Regex::new("[a").is_err());
+        assert!(Regex::new("a\\").is_err());
+        assert!(Regex::new("[z-a]").is_err());
+        assert!(Regex::new("[[:nope:]]").is_err());
+    }
+
+    #[test]
+    fn test_no_backtracking() {
+        // exponential for a backtracking matcher.
+        let start = Instant::now();
+        let text = format!("{}c", "a".repeat(5000));
+        assert!(!matches("^(a|a)*b", &text));
+        assert!(!matches("^(a*)*b", &text));
+        assert!(!matches("^(a?){50}a{50}$", &"a".repeat(49)));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,212 @@
+use crate::config::Config;
+use crate::git_object::GitObjectError;
+use crate::index::index_path;
+use crate::object_store::{open_object_store_at, CompositeObjectStore};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+type Result<T> = std::result::Result<T, GitObjectError>;
+
+// where the repository a command works on keeps its data and its files.
+#[derive(Debug, PartialEq)]
+pub struct Repository {
+    pub git_dir: PathBuf,
+    // none for bare repositories.
+    pub work_tree: Option<PathBuf>,
+    // the current directory relative to the top of the work tree; empty at the top.
+    pub prefix: PathBuf,
+}
+
+impl Repository {
+    // the repository of the current directory, honoring $GIT_DIR and $GIT_WORK_TREE.
+    pub fn discover() -> Result<Self> {
+        let cwd = env::current_dir()?;
+        let git_dir = env::var_os("GIT_DIR").map(PathBuf::from);
+        let work_tree = env::var_os("GIT_WORK_TREE").map(PathBuf::from);
+        Repository::discover_from(&cwd, git_dir, work_tree)
+    }
+
+    // an explicit git dir is used as it is, with `cwd` as the top of its work tree unless
+    // its config says core.bare.
+    // otherwise `cwd` and its parents are searched for a `.git` directory or gitfile,
+    // or for a bare repository. relative paths are taken relative to `cwd`.
+    pub fn discover_from(
+        cwd: &Path,
+        git_dir: Option<PathBuf>,
+        work_tree: Option<PathBuf>,
+    ) -> Result<Self> {
+        let (git_dir, found_work_tree) = match git_dir {
+            Some(dir) => {
+                let dir = cwd.join(dir);
+                if !is_git_dir(&dir) {
+                    return Err(GitObjectError::NotFoundError(format!(
+                        "not a git repository: '{}'",
+                        dir.display()
+                    )));
+                }
+                let bare = Config::load_file(&dir.join("config"), None)?
+                    .get_bool("core.bare")?
+                    .unwrap_or(false);
+                let work_tree = if bare { None } else { Some(cwd.to_path_buf()) };
+                (dir, work_tree)
+            }
+            None => find_git_dir(cwd)?,
+        };
+        let work_tree = match work_tree {
+            Some(w) => Some(cwd.join(w)),
+            None => found_work_tree,
+        };
+        let prefix = work_tree
+            .as_ref()
+            .and_then(|w| cwd.strip_prefix(w).ok())
+            .map(|p| p.to_path_buf())
+            .unwrap_or_default();
+        Ok(Repository {
+            git_dir,
+            work_tree,
+            prefix,
+        })
+    }
+
+    pub fn objects_dir(&self) -> PathBuf {
+        self.git_dir.join("objects")
+    }
+
+    pub fn object_store(&self) -> CompositeObjectStore {
+        open_object_store_at(&self.objects_dir())
+    }
+
+    // system, global and repository configuration.
+    pub fn config(&self) -> Result<Config> {
+        Config::load(Some(&self.git_dir))
+    }
+
+    pub fn index_path(&self) -> PathBuf {
+        index_path(&self.git_dir)
+    }
+
+    pub fn is_bare(&self) -> bool {
+        self.work_tree.is_none()
+    }
+}
+
+// the repository of the current directory, or none after telling the user why not.
+pub fn open_repository() -> Option<Repository> {
+    match Repository::discover() {
+        Ok(repo) => Some(repo),
+        Err(GitObjectError::NotFoundError(message)) => {
+            println!("fatal: {}", message);
+            None
+        }
+        Err(e) => {
+            println!("fatal: {:?}", e);
+            None
+        }
+    }
+}
+
+// a directory with HEAD, objects and refs, like git's is_git_directory.
+pub fn is_git_dir(dir: &Path) -> bool {
+    dir.join("HEAD").is_file() && dir.join("objects").is_dir() && dir.join("refs").is_dir()
+}
+
+fn find_git_dir(cwd: &Path) -> Result<(PathBuf, Option<PathBuf>)> {
+    for dir in cwd.ancestors() {
+        let dot_git = dir.join(".git");
+        if dot_git.is_file() {
+            return Ok((read_gitfile(&dot_git)?, Some(dir.to_path_buf())));
+        }
+        if is_git_dir(&dot_git) {
+            return Ok((dot_git, Some(dir.to_path_buf())));
+        }
+        if is_git_dir(dir) {
+            return Ok((dir.to_path_buf(), None));
+        }
+    }
+    Err(GitObjectError::NotFoundError(String::from(
+        "not a git repository (or any of the parent directories): .git",
+    )))
+}
+
+// a `.git` file holds "gitdir: <path>", relative to the directory it is in.
+fn read_gitfile(path: &Path) -> Result<PathBuf> {
+    let content = fs::read_to_string(path)?;
+    let target = match content.trim_end().strip_prefix("gitdir: ") {
+        Some(t) => t,
+        None => {
+            return Err(GitObjectError::ParseError(format!(
+                "invalid gitfile format: {}",
+                path.display()
+            )))
+        }
+    };
+    let git_dir = path.parent().unwrap_or(path).join(target);
+    if !is_git_dir(&git_dir) {
+        return Err(GitObjectError::NotFoundError(format!(
+            "not a git repository: {}",
+            git_dir.display()
+        )));
+    }
+    Ok(git_dir)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::repository::*;
+    use crate::test_utils::temp_dir;
+
+    fn make_git_dir(dir: &Path) {
+        fs::create_dir_all(dir.join("objects")).unwrap();
+        fs::create_dir_all(dir.join("refs")).unwrap();
+        fs::write(dir.join("HEAD"), "ref: refs/heads/master\n").unwrap();
+    }
+
+    #[test]
+    fn test_discover() {
+        let root = temp_dir("repository");
+        let work = root.join("work");
+        let sub = work.join("src/deep");
+        fs::create_dir_all(&sub).unwrap();
+        make_git_dir(&work.join(".git"));
+
+        let repo = Repository::discover_from(&sub, None, None).unwrap();
+        assert_eq!(repo.git_dir, work.join(".git"));
+        assert_eq!(repo.work_tree, Some(work.clone()));
+        assert_eq!(repo.prefix, Path::new("src/deep"));
+        assert_eq!(repo.objects_dir(), work.join(".git/objects"));
+        let repo = Repository::discover_from(&work, None, None).unwrap();
+        assert_eq!(repo.prefix, Path::new(""));
+
+        // a gitfile points somewhere else.
+        let linked = root.join("linked");
+        fs::create_dir_all(&linked).unwrap();
+        fs::write(linked.join(".git"), "gitdir: ../work/.git\n").unwrap();
+        let repo = Repository::discover_from(&linked, None, None).unwrap();
+        assert_eq!(repo.git_dir, linked.join("../work/.git"));
+        assert_eq!(repo.work_tree, Some(linked.clone()));
+        fs::write(linked.join(".git"), "nonsense\n").unwrap();
+        assert!(Repository::discover_from(&linked, None, None).is_err());
+
+        // bare repositories have no work tree, unless one is given.
+        let bare = root.join("bare.git");
+        make_git_dir(&bare);
+        let repo = Repository::discover_from(&bare.join("refs"), None, None).unwrap();
+        assert_eq!(repo.git_dir, bare);
+        assert!(repo.is_bare());
+        let repo = Repository::discover_from(&bare, None, Some(work.clone())).unwrap();
+        assert_eq!(repo.work_tree, Some(work.clone()));
+
+        // an explicit git dir is not searched for, and the current directory is the top.
+        let repo = Repository::discover_from(&root, Some(PathBuf::from("bare.git")), None).unwrap();
+        assert_eq!(repo.git_dir, bare);
+        assert_eq!(repo.work_tree, Some(root.clone()));
+        fs::write(bare.join("config"), "[core]\n\tbare = true\n").unwrap();
+        let repo = Repository::discover_from(&root, Some(PathBuf::from("bare.git")), None).unwrap();
+        assert!(repo.is_bare());
+        assert!(Repository::discover_from(&root, Some(PathBuf::from("work")), None).is_err());
+        assert!(Repository::discover_from(&root, None, None).is_err());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
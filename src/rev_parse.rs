@@ -0,0 +1,204 @@
+use crate::git_object::GitObjectError;
+use crate::object_store::ObjectStore;
+use crate::repository::open_repository;
+use crate::revision::resolve_revision;
+use crate::utils::*;
+
+type Result<T> = std::result::Result<T, GitObjectError>;
+
+// shorter prefixes are never taken as object ids, like git.
+pub const MIN_ABBREV: usize = 4;
+pub const DEFAULT_ABBREV: usize = 7;
+
+#[derive(Default)]
+struct RevParseOption<'a> {
+    verify: bool,
+    short: Option<usize>,
+    names: Vec<&'a String>,
+}
+
+pub fn rev_parse(commands: &[String]) {
+    let option = parse_options(commands);
+    if option.verify && option.names.len() != 1 {
+        println!("fatal: Needed a single revision");
+        return;
+    }
+    let repo = match open_repository() {
+        Some(r) => r,
+        None => return,
+    };
+    let store = repo.object_store();
+    for name in option.names {
+        let hash = match resolve_revision(&repo.git_dir, &store, name) {
+            Ok(h) => h,
+            Err(e) => {
+                report_resolve_error(&store, name, &e);
+                if option.verify {
+                    println!("fatal: Needed a single revision");
+                } else {
+                    println!(
+                        "fatal: ambiguous argument '{}': unknown revision or path not in the working tree.",
+                        name
+                    );
+                }
+                return;
+            }
+        };
+        match option.short {
+            Some(len) => println!("{}", find_unique_abbrev(&store, &hash, len)),
+            None => println!("{}", hash_to_str(&hash)),
+        }
+    }
+}
+
+// resolves a full object id or an unambiguous abbreviation of at least MIN_ABBREV hex
+// digits. a full id is returned as is, whether or not the object exists.
+pub fn resolve_object_id(store: &dyn ObjectStore, name: &str) -> Result<Vec<u8>> {
+    if name.len() < MIN_ABBREV || name.len() > 40 || !name.bytes().all(|c| c.is_ascii_hexdigit()) {
+        return Err(GitObjectError::NotFoundError(name.to_string()));
+    }
+    let prefix = name.to_ascii_lowercase();
+    if prefix.len() == 40 {
+        return Ok(str_to_hash(prefix));
+    }
+    let mut candidates = store.find_by_prefix(&prefix)?;
+    match candidates.len() {
+        0 => Err(GitObjectError::NotFoundError(name.to_string())),
+        1 => Ok(candidates.pop().unwrap()),
+        _ => Err(GitObjectError::AmbiguousError(name.to_string(), candidates)),
+    }
+}
+
+// the shortest prefix of `hash`, at least `min_len` digits long, that names no other
+// object in the store.
+pub fn find_unique_abbrev(store: &dyn ObjectStore, hash: &[u8], min_len: usize) -> String {
+    let hex = hash_to_str(hash);
+    let min_len = min_len.clamp(MIN_ABBREV, 40);
+    let others: Vec<String> = store
+        .find_by_prefix(&hex[..min_len])
+        .unwrap_or_default()
+        .iter()
+        .map(|h| hash_to_str(h))
+        .filter(|h| *h != hex)
+        .collect();
+    let common = others
+        .iter()
+        .map(|o| {
+            o.bytes()
+                .zip(hex.bytes())
+                .take_while(|(a, b)| a == b)
+                .count()
+        })
+        .max()
+        .unwrap_or(0);
+    hex[..(common + 1).clamp(min_len, 40)].to_string()
+}
+
+// explains why `name` did not resolve; ambiguous abbreviations list their candidates.
+pub fn report_resolve_error(store: &dyn ObjectStore, name: &str, e: &GitObjectError) {
+    if let GitObjectError::AmbiguousError(_, candidates) = e {
+        println!("error: short object ID {} is ambiguous", name);
+        println!("hint: The candidates are:");
+        for candidate in candidates {
+            let type_name = match store.header(&hash_to_str(candidate)) {
+                Ok((t, _)) => t,
+                Err(_) => String::from("bad object"),
+            };
+            println!(
+                "hint:   {} {}",
+                find_unique_abbrev(store, candidate, DEFAULT_ABBREV),
+                type_name
+            );
+        }
+    }
+}
+
+fn parse_options(commands: &[String]) -> RevParseOption<'_> {
+    let mut option: RevParseOption = Default::default();
+    for token in &commands[2..] {
+        match &token[..] {
+            "--verify" => option.verify = true,
+            "--short" => option.short = Some(DEFAULT_ABBREV),
+            _ if token.starts_with("--short=") => {
+                option.short = Some(token[8..].parse().unwrap_or(DEFAULT_ABBREV))
+            }
+            _ if !token.starts_with('-') => option.names.push(token),
+            _ => println!("ignore option {}", token),
+        }
+    }
+    option
+}
+
+#[cfg(test)]
+mod test {
+    use crate::object_store::MemoryObjectStore;
+    use crate::rev_parse::*;
+
+    fn store_with(contents: &[&[u8]]) -> (MemoryObjectStore, Vec<Vec<u8>>) {
+        let store = MemoryObjectStore::new();
+        let ids = contents
+            .iter()
+            .map(|c| store.write("blob", c).unwrap())
+            .collect();
+        (store, ids)
+    }
+
+    #[test]
+    fn test_resolve_object_id() {
+        let (store, ids) = store_with(&[b"hogehoge\n"]);
+        let hex = hash_to_str(&ids[0]);
+        assert_eq!(resolve_object_id(&store, &hex).unwrap(), ids[0]);
+        assert_eq!(resolve_object_id(&store, &hex[..4]).unwrap(), ids[0]);
+        assert_eq!(
+            resolve_object_id(&store, &hex[..10].to_ascii_uppercase()).unwrap(),
+            ids[0]
+        );
+        assert!(resolve_object_id(&store, &hex[..3]).is_err());
+        assert!(resolve_object_id(&store, "e9bd").is_err());
+        assert!(resolve_object_id(&store, "e9bg").is_err());
+        // full ids resolve even when the object is not stored.
+        let missing = "0000000000000000000000000000000000000000";
+        assert_eq!(resolve_object_id(&store, missing).unwrap(), vec![0; 20]);
+    }
+
+    #[test]
+    fn test_ambiguous_prefix() {
+        // find two blobs whose ids start with the same four digits.
+        let store = MemoryObjectStore::new();
+        let mut seen = std::collections::HashMap::new();
+        let (a, b) = (0..)
+            .find_map(|i: u32| {
+                let id = store.write("blob", i.to_string().as_bytes()).unwrap();
+                let prefix = hash_to_str(&id)[..4].to_string();
+                seen.insert(prefix, id.clone()).map(|other| (other, id))
+            })
+            .unwrap();
+        let prefix = &hash_to_str(&a)[..4];
+        match resolve_object_id(&store, prefix) {
+            Err(GitObjectError::AmbiguousError(name, candidates)) => {
+                assert_eq!(name, prefix);
+                assert!(candidates.contains(&a) && candidates.contains(&b));
+            }
+            r => panic!("unexpected result: {:?}", r),
+        }
+
+        // each abbreviation is long enough to tell the two apart, and resolves back.
+        let short_a = find_unique_abbrev(&store, &a, MIN_ABBREV);
+        let short_b = find_unique_abbrev(&store, &b, MIN_ABBREV);
+        assert!(short_a.len() > 4 && !short_b.starts_with(&short_a));
+        assert_eq!(resolve_object_id(&store, &short_a).unwrap(), a);
+        assert_eq!(resolve_object_id(&store, &short_b).unwrap(), b);
+    }
+
+    #[test]
+    fn test_find_unique_abbrev() {
+        let (store, ids) = store_with(&[b"hogehoge\n"]);
+        let hex = hash_to_str(&ids[0]);
+        assert_eq!(
+            find_unique_abbrev(&store, &ids[0], DEFAULT_ABBREV),
+            hex[..7]
+        );
+        assert_eq!(find_unique_abbrev(&store, &ids[0], 2), hex[..4]);
+        assert_eq!(find_unique_abbrev(&store, &ids[0], 50), hex);
+    }
+}
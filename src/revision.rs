@@ -0,0 +1,542 @@
+use crate::date::parse_expiry_date;
+use crate::git_object::*;
+use crate::index::{index_path, Index};
+use crate::object_store::ObjectStore;
+use crate::reflog::read_reflog;
+use crate::refs::{is_valid_full_name, read_symbolic_ref, ref_tips, resolve_ref, NULL_ID};
+use crate::rev_parse::resolve_object_id;
+use crate::utils::*;
+use std::{
+    collections::{BinaryHeap, HashMap, HashSet},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+type Result<T> = std::result::Result<T, GitObjectError>;
+
+// the places a short ref name is looked up, in order, like git's ref_rev_parse_rules.
+const REF_RULES: [(&str, &str); 6] = [
+    ("", ""),
+    ("refs/", ""),
+    ("refs/tags/", ""),
+    ("refs/heads/", ""),
+    ("refs/remotes/", ""),
+    ("refs/remotes/", "/HEAD"),
+];
+
+// resolves a revision expression to an object id. the expression is a ref or an
+// (abbreviated) object id, optionally with a reflog selector (`@{n}`, `@{<date>}`,
+// `@{-n}`), followed by any number of `~n`, `^n`, `^{<type>}`, `^{}` and `^{/<text>}`,
+// and then `:<path>` to name an entry of its tree; without a revision the path is
+// looked up in the index. `:/<text>` names the youngest commit reachable from a ref
+// whose message contains the text.
+pub fn resolve_revision(git_dir: &Path, store: &dyn ObjectStore, spec: &str) -> Result<Vec<u8>> {
+    if let Some(pattern) = spec.strip_prefix(":/") {
+        let tips = ref_tips(git_dir)?;
+        return search_message(store, &tips, pattern)?.ok_or_else(|| not_found(spec));
+    }
+    let (rev, path) = match find_outside_braces(spec, |c| c == b':') {
+        Some(i) => (&spec[..i], Some(&spec[i + 1..])),
+        None => (spec, None),
+    };
+    if rev.is_empty() {
+        return match path {
+            Some(path) => lookup_index(git_dir, path)?.ok_or_else(|| not_found(spec)),
+            None => Err(not_found(spec)),
+        };
+    }
+
+    let base_end = find_outside_braces(rev, |c| c == b'~' || c == b'^').unwrap_or(rev.len());
+    let mut id = resolve_base(git_dir, store, &rev[..base_end])?;
+    let mut ops = &rev[base_end..];
+    while !ops.is_empty() {
+        if let Some(rest) = ops.strip_prefix("^{") {
+            let close = rest.find('}').ok_or_else(|| bad_revision(spec))?;
+            let inner = &rest[..close];
+            ops = &rest[close + 1..];
+            id = match inner {
+                "" => peel(store, &id, None)?,
+                _ if inner.starts_with('/') => {
+                    let commit = peel(store, &id, Some("commit"))?;
+                    search_message(store, &[commit], &inner[1..])?.ok_or_else(|| not_found(spec))?
+                }
+                "commit" | "tree" | "blob" | "tag" | "object" => peel(store, &id, Some(inner))?,
+                _ => return Err(bad_revision(spec)),
+            };
+            continue;
+        }
+        // checked before slicing, as anything else may not even be ascii.
+        let op = ops.as_bytes()[0];
+        if op != b'^' && op != b'~' {
+            return Err(bad_revision(spec));
+        }
+        let digits = ops[1..].bytes().take_while(|c| c.is_ascii_digit()).count();
+        let n = match digits {
+            0 => 1,
+            _ => ops[1..=digits].parse().map_err(|_| bad_revision(spec))?,
+        };
+        ops = &ops[1 + digits..];
+        id = match op {
+            // `^0` peels to the commit itself.
+            b'^' if n == 0 => peel(store, &id, Some("commit"))?,
+            b'^' => load_commit(store, &id)?
+                .parents
+                .get(n - 1)
+                .cloned()
+                .ok_or_else(|| not_found(spec))?,
+            _ => {
+                let mut id = peel(store, &id, Some("commit"))?;
+                for _ in 0..n {
+                    id = load_commit(store, &id)?
+                        .parents
+                        .into_iter()
+                        .next()
+                        .ok_or_else(|| not_found(spec))?;
+                }
+                id
+            }
+        };
+    }
+
+    match path {
+        Some(path) => lookup_path(store, &id, path)?.ok_or_else(|| {
+            GitObjectError::NotFoundError(format!("path '{}' does not exist in '{}'", path, rev))
+        }),
+        None => Ok(id),
+    }
+}
+
+// follows tags, and commits to their trees, until an object of `kind` is reached. with
+// no kind, tags are followed until something else is found.
+pub fn peel(store: &dyn ObjectStore, id: &[u8], kind: Option<&str>) -> Result<Vec<u8>> {
+    let mut id = id.to_vec();
+    loop {
+        let hash = hash_to_str(&id);
+        let (type_name, _) = store.header(&hash)?;
+        let done = match kind {
+            Some("object") => true,
+            Some(kind) => kind == type_name,
+            None => type_name != "tag",
+        };
+        if done {
+            return Ok(id);
+        }
+        id = match load_object_by_hash(store, &hash)? {
+            GitObject::Tag(tag) => tag.object,
+            GitObject::Commit(commit) if kind == Some("tree") => commit.tree,
+            _ => {
+                return Err(GitObjectError::NotFoundError(format!(
+                    "{}: expected {} type, but the object dereferences to {} type",
+                    hash,
+                    kind.unwrap_or_default(),
+                    type_name
+                )))
+            }
+        };
+    }
+}
+
+// expands a short ref name the way git does and returns the full name and its id.
+pub fn dwim_ref(git_dir: &Path, name: &str) -> Result<Option<(String, Vec<u8>)>> {
+    if name.is_empty() || name.starts_with('/') || name.contains("..") {
+        return Ok(None);
+    }
+    for (prefix, suffix) in REF_RULES.iter() {
+        // only pseudo refs like HEAD and full names are taken as they are.
+        if prefix.is_empty()
+            && !name.starts_with("refs/")
+            && !name.bytes().all(|c| c.is_ascii_uppercase() || c == b'_')
+        {
+            continue;
+        }
+        let full = format!("{}{}{}", prefix, name, suffix);
+        if !is_valid_full_name(&full) {
+            continue;
+        }
+        if let Some((_, id)) = resolve_ref(git_dir, &full)? {
+            return Ok(Some((full, id)));
+        }
+    }
+    Ok(None)
+}
+
+// the ref or object a name like `master@{1}` or `abc1234~2` starts from.
+fn resolve_base(git_dir: &Path, store: &dyn ObjectStore, name: &str) -> Result<Vec<u8>> {
+    if let Some(at) = name.find("@{").filter(|_| name.ends_with('}')) {
+        let (refname, selector) = (&name[..at], &name[at + 2..name.len() - 1]);
+        if let Some(n) = selector.strip_prefix('-') {
+            let n: usize = n.parse().map_err(|_| bad_revision(name))?;
+            if !refname.is_empty() || n == 0 {
+                return Err(bad_revision(name));
+            }
+            let branch = previous_branch(git_dir, n)?.ok_or_else(|| not_found(name))?;
+            return resolve_base(git_dir, store, &branch);
+        }
+        let full = match refname {
+            // the reflog of the branch HEAD points at, or of HEAD itself when detached.
+            "" => read_symbolic_ref(git_dir, "HEAD")?.unwrap_or_else(|| String::from("HEAD")),
+            "@" => String::from("HEAD"),
+            _ => {
+                dwim_ref(git_dir, refname)?
+                    .ok_or_else(|| not_found(name))?
+                    .0
+            }
+        };
+        return reflog_entry_at(git_dir, &full, selector)?.ok_or_else(|| not_found(name));
+    }
+
+    let name = if name == "@" { "HEAD" } else { name };
+    if name.len() == 40 && name.bytes().all(|c| c.is_ascii_hexdigit()) {
+        return Ok(str_to_hash(name.to_ascii_lowercase()));
+    }
+    if let Some((_, id)) = dwim_ref(git_dir, name)? {
+        return Ok(id);
+    }
+    resolve_object_id(store, name)
+}
+
+// the branch checked out before the n-th last checkout, from HEAD's reflog.
+fn previous_branch(git_dir: &Path, n: usize) -> Result<Option<String>> {
+    let branch = read_reflog(git_dir, "HEAD")?
+        .into_iter()
+        .rev()
+        .filter_map(|e| {
+            let moved = e.message.strip_prefix("checkout: moving from ")?;
+            Some(moved.split(" to ").next()?.to_string())
+        })
+        .nth(n - 1);
+    Ok(branch)
+}
+
+// `n` counts entries back from the newest; anything else is read as a date.
+fn reflog_entry_at(git_dir: &Path, refname: &str, selector: &str) -> Result<Option<Vec<u8>>> {
+    let log = read_reflog(git_dir, refname)?;
+    if let Ok(n) = selector.parse::<usize>() {
+        if n < log.len() {
+            return Ok(Some(log[log.len() - 1 - n].new.clone()));
+        }
+        // one past the oldest entry is the value the ref had before it.
+        return Ok(log
+            .first()
+            .filter(|e| n == log.len() && e.old != NULL_ID)
+            .map(|e| e.old.clone()));
+    }
+    let spec = selector.replace(' ', ".");
+    let cutoff = match parse_expiry_date(&spec, SystemTime::now()) {
+        Some(Some(c)) => c,
+        _ => return Err(bad_revision(selector)),
+    };
+    let cutoff = match cutoff.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs() as i64,
+        Err(_) => i64::MIN,
+    };
+    match log.iter().rev().find(|e| e.committer.time() <= cutoff) {
+        Some(e) => Ok(Some(e.new.clone())),
+        // the log does not go back that far, so the oldest value known is used.
+        None => Ok(log.first().map(|e| match e.old == NULL_ID {
+            true => e.new.clone(),
+            false => e.old.clone(),
+        })),
+    }
+}
+
+// walks the history of `tips` newest first and returns the first commit whose message
+// contains the pattern. "!-" negates the pattern and "!!" stands for a literal "!".
+fn search_message(
+    store: &dyn ObjectStore,
+    tips: &[Vec<u8>],
+    pattern: &str,
+) -> Result<Option<Vec<u8>>> {
+    let (pattern, negate) = match pattern.strip_prefix('!') {
+        Some(rest) if rest.starts_with('!') => (rest, false),
+        Some(rest) if rest.starts_with('-') => (&rest[1..], true),
+        Some(_) => return Err(bad_revision(pattern)),
+        None => (pattern, false),
+    };
+    let mut queue = BinaryHeap::new();
+    let mut seen = HashSet::new();
+    for tip in tips {
+        // tips that are not commits (tagged trees or blobs) have no history to search.
+        if let Ok(id) = peel(store, tip, Some("commit")) {
+            if seen.insert(id.clone()) {
+                queue.push((load_commit(store, &id)?.committer.time(), id));
+            }
+        }
+    }
+    while let Some((_, id)) = queue.pop() {
+        let commit = load_commit(store, &id)?;
+        if String::from_utf8_lossy(&commit.message).contains(pattern) != negate {
+            return Ok(Some(id));
+        }
+        for parent in commit.parents {
+            if seen.insert(parent.clone()) {
+                let time = load_commit(store, &parent)?.committer.time();
+                queue.push((time, parent));
+            }
+        }
+    }
+    Ok(None)
+}
+
+// the object at `path` in the tree of `id`; an empty path names the tree itself.
+fn lookup_path(store: &dyn ObjectStore, id: &[u8], path: &str) -> Result<Option<Vec<u8>>> {
+    let mut id = peel(store, id, Some("tree"))?;
+    for name in path.split('/').filter(|n| !n.is_empty()) {
+        let nodes = match load_object_by_hash(store, &hash_to_str(&id))? {
+            GitObject::Tree(nodes) => nodes,
+            _ => return Ok(None),
+        };
+        match nodes.into_iter().find(|n| n.filename == name) {
+            Some(node) => id = node.hash,
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(id))
+}
+
+// `:<path>` names the stage 0 entry of the path in the index, `:<n>:<path>` stage n.
+fn lookup_index(git_dir: &Path, path: &str) -> Result<Option<Vec<u8>>> {
+    let (stage, path) = match path.as_bytes() {
+        [n @ b'0'..=b'3', b':', ..] => (n - b'0', &path[2..]),
+        _ => (0, path),
+    };
+    let index = Index::read_from(&index_path(git_dir))?;
+    Ok(index.get(path, stage).map(|e| e.id.clone()))
+}
+
+// every commit reachable from `tip` (peeled to a commit), following parents only.
+pub fn reachable_commits(store: &dyn ObjectStore, tip: &[u8]) -> Result<HashSet<Vec<u8>>> {
+    let mut seen = HashSet::new();
+    let mut queue = vec![peel(store, tip, Some("commit"))?];
+    while let Some(id) = queue.pop() {
+        if !seen.insert(id.clone()) {
+            continue;
+        }
+        match load_object_by_hash(store, &hash_to_str(&id))? {
+            GitObject::Commit(commit) => queue.extend(commit.parents),
+            o => {
+                return Err(GitObjectError::ParseError(format!(
+                    "parent {} is a {}, not a commit",
+                    hash_to_str(&id),
+                    o.type_name()
+                )))
+            }
+        }
+    }
+    Ok(seen)
+}
+
+// how many commits are reachable from `ours` but not `theirs`, and the other way round.
+// like git's ahead_behind, commits are walked newest first and marked with the sides
+// they are reachable from, until only commits reachable from both are left to walk:
+// the history below the merge base is never read.
+pub fn ahead_behind(store: &dyn ObjectStore, ours: &[u8], theirs: &[u8]) -> Result<(usize, usize)> {
+    const OURS: u8 = 1;
+    const THEIRS: u8 = 2;
+    const BOTH: u8 = OURS | THEIRS;
+    let mut flags: HashMap<Vec<u8>, u8> = HashMap::new();
+    let mut queue = BinaryHeap::new();
+    // each commit is queued at most once, a side found for it while it waits is walked
+    // with it. the walk ends when all that waits is reachable from both sides.
+    let mut queued = HashSet::new();
+    for &(tip, flag) in &[(ours, OURS), (theirs, THEIRS)] {
+        let id = peel(store, tip, Some("commit"))?;
+        *flags.entry(id.clone()).or_insert(0) |= flag;
+        if queued.insert(id.clone()) {
+            queue.push((load_commit(store, &id)?.committer.time(), id));
+        }
+    }
+    let mut one_sided = queued.iter().filter(|id| flags[*id] != BOTH).count();
+    while one_sided > 0 {
+        let id = match queue.pop() {
+            Some((_, id)) => id,
+            None => break,
+        };
+        queued.remove(&id);
+        let flag = flags[&id];
+        if flag != BOTH {
+            one_sided -= 1;
+        }
+        for parent in load_commit(store, &id)?.parents {
+            let marks = flags.entry(parent.clone()).or_insert(0);
+            // walked again when it is found to be reachable from another side.
+            if *marks & flag == flag {
+                continue;
+            }
+            *marks |= flag;
+            let marks = *marks;
+            if queued.contains(&parent) {
+                if marks == BOTH {
+                    one_sided -= 1;
+                }
+            } else {
+                if marks != BOTH {
+                    one_sided += 1;
+                }
+                let time = load_commit(store, &parent)?.committer.time();
+                queued.insert(parent.clone());
+                queue.push((time, parent));
+            }
+        }
+    }
+    let count = |side: u8| flags.values().filter(|f| **f == side).count();
+    Ok((count(OURS), count(THEIRS)))
+}
+
+fn load_commit(store: &dyn ObjectStore, id: &[u8]) -> Result<GitCommitObject> {
+    let id = peel(store, id, Some("commit"))?;
+    match load_object_by_hash(store, &hash_to_str(&id))? {
+        GitObject::Commit(commit) => Ok(commit),
+        _ => Err(not_found(&hash_to_str(&id))),
+    }
+}
+
+// the first byte matching `pred` that is not inside `{...}`.
+fn find_outside_braces(s: &str, pred: impl Fn(u8) -> bool) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in s.bytes().enumerate() {
+        match c {
+            b'{' => depth += 1,
+            b'}' if depth > 0 => depth -= 1,
+            _ if depth == 0 && pred(c) => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn not_found(spec: &str) -> GitObjectError {
+    GitObjectError::NotFoundError(spec.to_string())
+}
+
+fn bad_revision(spec: &str) -> GitObjectError {
+    GitObjectError::ParseError(format!("invalid revision: {}", spec))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::git_user::Signature;
+    use crate::object_store::LooseObjectStore;
+    use crate::revision::*;
+    use crate::test_utils::{commit, set_ref, temp_git_dir, tree};
+    use std::fs;
+
+    fn reflog_line(old: &[u8], new: &[u8], time: i64, message: &str) -> String {
+        format!(
+            "{} {} a <a@example.com> {} +0000\t{}\n",
+            hash_to_str(old),
+            hash_to_str(new),
+            time,
+            message
+        )
+    }
+
+    #[test]
+    fn test_resolve_revision() {
+        let git_dir = temp_git_dir("revision");
+        let store = LooseObjectStore::new(&git_dir.join("objects"));
+        let files: &[(&str, &[u8])] = &[("dir/file", b"hogehoge\n")];
+        let blob = store.write("blob", b"hogehoge\n").unwrap();
+        let sub = tree(&store, &[("file", b"hogehoge\n")]);
+        let tree = tree(&store, files);
+
+        let first = commit(&store, &[], files, 1_600_000_000, "first");
+        let second = commit(&store, &[&first], files, 1_600_000_100, "second");
+        let third = commit(&store, &[&second], files, 1_600_000_200, "third");
+        let side = commit(&store, &[&first], files, 1_600_000_300, "side");
+        let merge = commit(&store, &[&third, &side], files, 1_600_000_400, "merge");
+        let tag = GitObject::new_tag(
+            hash_to_str(&second),
+            String::from("commit"),
+            String::from("v1"),
+            Signature::new("a", "a@example.com", 1_600_000_500, 0),
+            String::from("v1"),
+        )
+        .write(&store)
+        .unwrap();
+        set_ref(&git_dir, "refs/heads/master", &merge);
+        fs::write(
+            git_dir.join("packed-refs"),
+            format!(
+                "# pack-refs with: peeled\n{} refs/heads/side\n{} refs/tags/v1\n^{}\n",
+                hash_to_str(&side),
+                hash_to_str(&tag),
+                hash_to_str(&second)
+            ),
+        )
+        .unwrap();
+        fs::create_dir_all(git_dir.join("logs/refs/heads")).unwrap();
+        fs::write(
+            git_dir.join("logs/refs/heads/master"),
+            reflog_line(&NULL_ID, &third, 1_600_000_200, "commit")
+                + &reflog_line(&third, &merge, 1_600_000_400, "merge"),
+        )
+        .unwrap();
+        fs::write(
+            git_dir.join("logs/HEAD"),
+            reflog_line(&NULL_ID, &side, 1_600_000_300, "commit")
+                + &reflog_line(
+                    &side,
+                    &merge,
+                    1_600_000_400,
+                    "checkout: moving from side to master",
+                ),
+        )
+        .unwrap();
+
+        let resolve = |spec: &str| resolve_revision(&git_dir, &store, spec);
+        for spec in &["HEAD", "@", "master", "heads/master", "refs/heads/master"] {
+            assert_eq!(resolve(spec).unwrap(), merge, "{}", spec);
+        }
+        assert_eq!(resolve(&hash_to_str(&first)[..7]).unwrap(), first);
+        assert_eq!(resolve("HEAD~").unwrap(), third);
+        assert_eq!(resolve("HEAD~3").unwrap(), first);
+        assert_eq!(resolve("HEAD^^").unwrap(), second);
+        assert_eq!(resolve("HEAD^2").unwrap(), side);
+        assert_eq!(resolve("master^2~1").unwrap(), first);
+        assert_eq!(resolve("HEAD^0").unwrap(), merge);
+        assert_eq!(resolve("side").unwrap(), side);
+
+        assert_eq!(resolve("v1").unwrap(), tag);
+        assert_eq!(resolve("v1^{}").unwrap(), second);
+        assert_eq!(resolve("v1^{commit}").unwrap(), second);
+        assert_eq!(resolve("v1^{tree}").unwrap(), tree);
+        assert_eq!(resolve("v1^{tag}").unwrap(), tag);
+        assert_eq!(resolve("v1~1").unwrap(), first);
+
+        assert_eq!(resolve("master@{0}").unwrap(), merge);
+        assert_eq!(resolve("master@{1}").unwrap(), third);
+        assert_eq!(resolve("@{1}").unwrap(), third);
+        assert_eq!(resolve("HEAD@{1}").unwrap(), side);
+        assert_eq!(resolve("master@{1}^").unwrap(), second);
+        assert_eq!(resolve("master@{now}").unwrap(), merge);
+        assert_eq!(resolve("@{-1}").unwrap(), side);
+        assert!(resolve("master@{3}").is_err());
+
+        assert_eq!(resolve(":/second").unwrap(), second);
+        assert_eq!(resolve(":/!-merge").unwrap(), side);
+        assert_eq!(resolve("HEAD^{/fir}").unwrap(), first);
+        assert!(resolve(":/nothing").is_err());
+
+        assert_eq!(resolve("HEAD:").unwrap(), tree);
+        assert_eq!(resolve("HEAD:dir").unwrap(), sub);
+        assert_eq!(resolve("HEAD~2:dir/file").unwrap(), blob);
+        assert_eq!(resolve("v1:dir/file").unwrap(), blob);
+        assert!(resolve("HEAD:dir/missing").is_err());
+        assert!(resolve("HEAD:dir/file/more").is_err());
+
+        assert!(resolve("HEAD^3").is_err());
+        assert!(resolve("HEAD~5").is_err());
+        assert!(resolve("HEAD^{blob}").is_err());
+        assert!(resolve("HEAD^{nothing}").is_err());
+        assert!(resolve("HEAD^é").is_err());
+        assert!(resolve("HEAD~0é").is_err());
+        assert!(resolve("nothing").is_err());
+        assert!(resolve("config").is_err());
+
+        assert_eq!(ahead_behind(&store, &merge, &side).unwrap(), (3, 0));
+        assert_eq!(ahead_behind(&store, &side, &third).unwrap(), (1, 2));
+        assert_eq!(ahead_behind(&store, &tag, &second).unwrap(), (0, 0));
+        fs::remove_dir_all(&git_dir).unwrap();
+    }
+}
@@ -0,0 +1,319 @@
+use crate::index::Index;
+use crate::pathspec::Pathspec;
+use crate::repository::{open_repository, Repository};
+use crate::revision::resolve_revision;
+use crate::worktree::*;
+use std::{fs, io};
+
+#[derive(Default)]
+struct RmOption<'a> {
+    cached: bool,
+    force: bool,
+    dry_run: bool,
+    recursive: bool,
+    quiet: bool,
+    ignore_unmatch: bool,
+    paths: Vec<&'a String>,
+}
+
+pub fn rm(commands: &[String]) {
+    let option = parse_options(commands);
+    if option.paths.is_empty() {
+        println!("fatal: No pathspec was given. Which files should I remove?");
+        return;
+    }
+    let repo = match open_repository() {
+        Some(r) => r,
+        None => return,
+    };
+    for line in remove_paths(&repo, &option) {
+        println!("{}", line);
+    }
+}
+
+// removes what `option` names and returns what `git rm` prints.
+fn remove_paths(repo: &Repository, option: &RmOption) -> Vec<String> {
+    let mut out = vec![];
+    let work_tree = match &repo.work_tree {
+        Some(w) => w,
+        None => {
+            out.push(String::from(
+                "fatal: this operation must be run in a work tree",
+            ));
+            return out;
+        }
+    };
+    let pathspec = match Pathspec::parse(work_tree, &repo.prefix, &option.paths) {
+        Ok(p) => p,
+        Err(e) => {
+            out.push(format!("fatal: {}", e));
+            return out;
+        }
+    };
+    let index_path = repo.index_path();
+    let mut index = match Index::lock_or_report(&index_path) {
+        Ok(i) => i,
+        Err(message) => {
+            out.push(message);
+            return out;
+        }
+    };
+    let index_mtime = file_mtime(&index_path);
+    let store = repo.object_store();
+
+    let mut paths: Vec<String> = vec![];
+    for item in &pathspec.items {
+        let matched: Vec<&String> = index
+            .entries
+            .iter()
+            .map(|e| &e.path)
+            .filter(|p| item.matches(p))
+            .collect();
+        if matched.is_empty() && !option.ignore_unmatch {
+            out.push(format!(
+                "fatal: pathspec '{}' did not match any files",
+                item.original
+            ));
+            return out;
+        }
+        if !option.recursive && !item.is_glob() && matched.iter().any(|p| **p != item.path) {
+            out.push(format!(
+                "fatal: not removing '{}' recursively without -r",
+                item.original
+            ));
+            return out;
+        }
+        paths.extend(matched.into_iter().cloned());
+    }
+    paths.sort();
+    paths.dedup();
+
+    // what would be lost by removing them, unless forced.
+    if !option.force {
+        let mut staged_both = vec![];
+        let mut staged = vec![];
+        let mut local = vec![];
+        for path in &paths {
+            // an unmerged path has no single staged version to lose.
+            let entry = match index.get(path, 0) {
+                Some(e) => e,
+                None => continue,
+            };
+            let metadata = match fs::symlink_metadata(work_tree.join(path)) {
+                Ok(m) => m,
+                Err(_) => continue,
+            };
+            let local_changes = !is_stat_clean(entry, &metadata, index_mtime)
+                && match entry_from_work_tree(&store, work_tree, path, false) {
+                    Ok(e) => e.id != entry.id || e.mode != entry.mode,
+                    Err(_) => true,
+                };
+            let head = resolve_revision(&repo.git_dir, &store, &format!("HEAD:{}", path)).ok();
+            let staged_changes = head.as_ref() != Some(&entry.id);
+            if local_changes && staged_changes {
+                if !option.cached || !entry.intent_to_add {
+                    staged_both.push(path.as_str());
+                }
+            } else if !option.cached {
+                if staged_changes {
+                    staged.push(path.as_str());
+                }
+                if local_changes {
+                    local.push(path.as_str());
+                }
+            }
+        }
+        let mut refused = false;
+        for (paths, what, hint) in [
+            (
+                staged_both,
+                "staged content different from both the\nfile and the HEAD",
+                "(use -f to force removal)",
+            ),
+            (
+                staged,
+                "changes staged in the index",
+                "(use --cached to keep the file, or -f to force removal)",
+            ),
+            (
+                local,
+                "local modifications",
+                "(use --cached to keep the file, or -f to force removal)",
+            ),
+        ] {
+            if paths.is_empty() {
+                continue;
+            }
+            if paths.len() == 1 {
+                out.push(format!("error: the following file has {}:", what));
+            } else {
+                out.push(format!("error: the following files have {}:", what));
+            }
+            for path in paths {
+                out.push(format!("    {}", path));
+            }
+            out.push(hint.to_string());
+            refused = true;
+        }
+        if refused {
+            return out;
+        }
+    }
+
+    for path in &paths {
+        if !option.quiet {
+            out.push(format!("rm '{}'", path));
+        }
+    }
+    if option.dry_run {
+        return out;
+    }
+    for path in &paths {
+        index.remove(path);
+    }
+    if let Err(e) = index.commit() {
+        out.push(format!("fatal: Unable to write new index file: {:?}", e));
+        return out;
+    }
+    if option.cached {
+        return out;
+    }
+    // the index no longer has them, so the files go last.
+    for path in &paths {
+        let full = work_tree.join(path);
+        // a gitlink's directory only goes when it is empty.
+        let removed = if full.is_dir() && !full.is_symlink() {
+            fs::remove_dir(&full)
+        } else {
+            fs::remove_file(&full)
+        };
+        match removed {
+            Ok(()) => remove_empty_parents(work_tree, path),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => out.push(format!("warning: git rm: '{}': {}", path, e)),
+        }
+    }
+    out
+}
+
+fn parse_options(commands: &[String]) -> RmOption<'_> {
+    let mut option: RmOption = Default::default();
+    let mut only_paths = false;
+    for token in &commands[2..] {
+        match &token[..] {
+            _ if only_paths => option.paths.push(token),
+            "--" => only_paths = true,
+            "--cached" => option.cached = true,
+            "-f" | "--force" => option.force = true,
+            "-n" | "--dry-run" => option.dry_run = true,
+            "-r" => option.recursive = true,
+            "-q" | "--quiet" => option.quiet = true,
+            "--ignore-unmatch" => option.ignore_unmatch = true,
+            _ if token.starts_with('-') => println!("ignore option {}", token),
+            _ => option.paths.push(token),
+        }
+    }
+    option
+}
+
+#[cfg(test)]
+mod test {
+    use crate::init::init_repository;
+    use crate::refs::{write_ref, NULL_ID};
+    use crate::rm::*;
+    use crate::test_utils::{commit, temp_dir};
+
+    fn run(repo: &Repository, args: &[&str]) -> Vec<String> {
+        let commands: Vec<String> = ["git", "rm"]
+            .iter()
+            .chain(args)
+            .map(|s| s.to_string())
+            .collect();
+        remove_paths(repo, &parse_options(&commands))
+    }
+
+    fn stage(repo: &Repository, path: &str, content: &str) {
+        let work_tree = repo.work_tree.as_ref().unwrap();
+        fs::write(work_tree.join(path), content).unwrap();
+        let mut index = Index::read_from(&repo.index_path()).unwrap();
+        let entry = entry_from_work_tree(&repo.object_store(), work_tree, path, true).unwrap();
+        index.add(entry);
+        index.write_to(&repo.index_path()).unwrap();
+    }
+
+    fn staged(repo: &Repository) -> Vec<String> {
+        let index = Index::read_from(&repo.index_path()).unwrap();
+        index.entries.into_iter().map(|e| e.path).collect()
+    }
+
+    #[test]
+    fn test_rm() {
+        let root = temp_dir("rm");
+        init_repository(&root.join(".git"), false, "master", None).unwrap();
+        fs::create_dir_all(root.join("dir")).unwrap();
+        let repo = Repository::discover_from(&root, None, None).unwrap();
+        for path in ["a", "b", "c", "d", "dir/f", "dir/g"] {
+            stage(&repo, path, path);
+        }
+        let files: Vec<(&str, &[u8])> = ["a", "b", "c", "d", "dir/f", "dir/g"]
+            .iter()
+            .map(|p| (*p, p.as_bytes()))
+            .collect();
+        let commit = commit(&repo.object_store(), &[], &files, 0, "base");
+        write_ref(&repo.git_dir, "HEAD", &commit, Some(&NULL_ID), true, "c").unwrap();
+
+        // a is staged, b changed in the work tree and c both, each differently.
+        stage(&repo, "a", "staged");
+        fs::write(root.join("b"), "local").unwrap();
+        stage(&repo, "c", "staged");
+        fs::write(root.join("c"), "local again").unwrap();
+        assert_eq!(
+            run(&repo, &["a", "b", "c"]),
+            vec![
+                "error: the following file has staged content different from both the\nfile and the HEAD:",
+                "    c",
+                "(use -f to force removal)",
+                "error: the following file has changes staged in the index:",
+                "    a",
+                "(use --cached to keep the file, or -f to force removal)",
+                "error: the following file has local modifications:",
+                "    b",
+                "(use --cached to keep the file, or -f to force removal)",
+            ]
+        );
+        assert_eq!(staged(&repo), vec!["a", "b", "c", "d", "dir/f", "dir/g"]);
+
+        // --cached keeps the file, which is only refused when it differs from both.
+        assert_eq!(
+            run(&repo, &["--cached", "c"]),
+            vec![
+                "error: the following file has staged content different from both the\nfile and the HEAD:",
+                "    c",
+                "(use -f to force removal)",
+            ]
+        );
+        assert_eq!(
+            run(&repo, &["--cached", "a", "b"]),
+            vec!["rm 'a'", "rm 'b'"]
+        );
+        assert!(root.join("a").exists() && root.join("b").exists());
+        assert_eq!(run(&repo, &["-f", "c"]), vec!["rm 'c'"]);
+        assert!(!root.join("c").exists());
+        assert_eq!(run(&repo, &["d"]), vec!["rm 'd'"]);
+        assert!(!root.join("d").exists());
+
+        // a directory needs -r, and goes once its files do.
+        assert_eq!(
+            run(&repo, &["dir"]),
+            vec!["fatal: not removing 'dir' recursively without -r"]
+        );
+        assert_eq!(run(&repo, &["-r", "dir"]), vec!["rm 'dir/f'", "rm 'dir/g'"]);
+        assert!(!root.join("dir").exists());
+        assert!(staged(&repo).is_empty());
+        assert_eq!(
+            run(&repo, &["a"]),
+            vec!["fatal: pathspec 'a' did not match any files"]
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,1393 @@
+use crate::git_object::*;
+use crate::ignore::IgnoreRules;
+use crate::index::Index;
+use crate::object_store::ObjectStore;
+use crate::pathspec::Pathspec;
+use crate::reflog::read_reflog;
+use crate::refs::{read_ref, read_symbolic_ref, shorten_ref_name};
+use crate::repository::{open_repository, Repository};
+use crate::rev_parse::{find_unique_abbrev, DEFAULT_ABBREV};
+use crate::revision::{ahead_behind, dwim_ref, peel};
+use crate::utils::hash_to_str;
+use crate::worktree::*;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    fs,
+    path::Path,
+};
+
+type Result<T> = std::result::Result<T, GitObjectError>;
+
+#[derive(Default, Clone, Copy, PartialEq)]
+enum Format {
+    #[default]
+    Long,
+    Short,
+    Porcelain,
+    PorcelainV2,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum UntrackedMode {
+    No,
+    #[default]
+    Normal,
+    All,
+}
+
+#[derive(Default)]
+struct StatusOption<'a> {
+    format: Option<Format>,
+    branch: bool,
+    nul: bool,
+    untracked: UntrackedMode,
+    ignored: bool,
+    paths: Vec<&'a String>,
+}
+
+// a mode and an object id, as a tree or the index has them.
+type Version = (FileMode, Vec<u8>);
+
+// a tracked path that differs between HEAD, the index and the work tree.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    pub path: String,
+    // where a path staged as renamed came from.
+    pub orig_path: Option<String>,
+    // the letters of `status --short`: A, M, D, R or T, ' ' for no change. an
+    // unmerged path has the two letters of its conflict.
+    pub staged: char,
+    pub unstaged: char,
+    pub head: Option<Version>,
+    // none for an entry only intended to be added.
+    pub index: Option<Version>,
+    pub work_tree_mode: Option<FileMode>,
+    // stages 1 to 3 of an unmerged path.
+    pub stages: [Option<Version>; 3],
+    // a gitlink whose repository has another commit checked out.
+    pub new_commits: bool,
+}
+
+impl Change {
+    fn new(path: &str) -> Self {
+        Change {
+            path: path.to_string(),
+            orig_path: None,
+            staged: ' ',
+            unstaged: ' ',
+            head: None,
+            index: None,
+            work_tree_mode: None,
+            stages: [None, None, None],
+            new_commits: false,
+        }
+    }
+
+    pub fn is_unmerged(&self) -> bool {
+        self.stages.iter().any(|s| s.is_some())
+    }
+
+    // which of stages 1, 2 and 3 the path has, as bits 1, 2 and 4.
+    fn stage_mask(&self) -> usize {
+        (0..3)
+            .filter(|i| self.stages[*i].is_some())
+            .map(|i| 1 << i)
+            .sum()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Status {
+    // sorted by path.
+    pub changes: Vec<Change>,
+    // directories end with a slash.
+    pub untracked: Vec<String>,
+    pub ignored: Vec<String>,
+    // whether stat data of unchanged entries was brought up to date in the index, which
+    // is then worth writing.
+    pub refreshed: bool,
+}
+
+// the branch HEAD is on and how it compares to its upstream.
+struct BranchInfo {
+    // none when HEAD is detached.
+    branch: Option<String>,
+    // none before the first commit.
+    head: Option<Vec<u8>>,
+    upstream: Option<String>,
+    // the commits only on the branch and only on its upstream; none when the upstream
+    // is gone.
+    ahead_behind: Option<(usize, usize)>,
+}
+
+pub fn status(commands: &[String]) {
+    let option = parse_options(commands);
+    let repo = match open_repository() {
+        Some(r) => r,
+        None => return,
+    };
+    let work_tree = match &repo.work_tree {
+        Some(w) => w,
+        None => {
+            println!("fatal: this operation must be run in a work tree");
+            return;
+        }
+    };
+    let pathspec = if option.paths.is_empty() {
+        Pathspec::everything()
+    } else {
+        match Pathspec::parse(work_tree, &repo.prefix, &option.paths) {
+            Ok(p) => p,
+            Err(e) => {
+                println!("fatal: {}", e);
+                return;
+            }
+        }
+    };
+    let index_path = repo.index_path();
+    let mut index = match Index::lock_if_free(&index_path) {
+        Ok(i) => i,
+        Err(e) => {
+            println!("fatal: index file corrupt: {:?}", e);
+            return;
+        }
+    };
+    let status = match collect_status(
+        &repo,
+        work_tree,
+        &mut index,
+        &pathspec,
+        option.untracked,
+        option.ignored,
+    ) {
+        Ok(s) => s,
+        Err(e) => {
+            println!("fatal: {:?}", e);
+            return;
+        }
+    };
+    // like git, the refreshed stat data is kept when the index can be written.
+    if status.refreshed {
+        let _ = index.commit();
+    }
+    let store = repo.object_store();
+    let branch = match branch_info(&repo, &store) {
+        Ok(b) => b,
+        Err(e) => {
+            println!("fatal: {:?}", e);
+            return;
+        }
+    };
+    // -z is for scripts, so it means --porcelain unless another format is asked for.
+    let format = match option.format {
+        Some(f) => f,
+        None if option.nul => Format::Porcelain,
+        None => Format::Long,
+    };
+    let prefix = repo.prefix.to_string_lossy().replace('\\', "/");
+    let output = match format {
+        Format::Long => long_format(&repo, &store, &status, &branch, &option, &prefix),
+        Format::Short => short_format(&status, &branch, &option, &prefix),
+        Format::Porcelain => short_format(&status, &branch, &option, ""),
+        Format::PorcelainV2 => porcelain_v2(&status, &branch, &option, &prefix),
+    };
+    print!("{}", output);
+}
+
+// compares HEAD with the index and the index with the work tree for the paths in
+// `pathspec`, and finds the files that are not tracked.
+pub fn collect_status(
+    repo: &Repository,
+    work_tree: &Path,
+    index: &mut Index,
+    pathspec: &Pathspec,
+    untracked: UntrackedMode,
+    show_ignored: bool,
+) -> Result<Status> {
+    let store = repo.object_store();
+    let mut head_tree = BTreeMap::new();
+    if let Some(head) = read_ref(&repo.git_dir, "HEAD")? {
+        let tree = peel(&store, &head, Some("tree"))?;
+        flatten_tree(&store, &tree, "", &mut head_tree)?;
+    }
+    let index_mtime = file_mtime(&repo.index_path());
+    let mut paths: BTreeSet<String> = head_tree.keys().cloned().collect();
+    paths.extend(index.entries.iter().map(|e| e.path.clone()));
+
+    let mut status = Status::default();
+    for path in paths.into_iter().filter(|p| pathspec.matches(p)) {
+        let mut change = Change::new(&path);
+        change.head = head_tree.get(&path).cloned();
+        let metadata = fs::symlink_metadata(work_tree.join(&path)).ok();
+        let stages = index.stages(&path);
+        if stages.iter().any(|e| e.stage != 0) {
+            for entry in stages.iter().filter(|e| e.stage != 0) {
+                change.stages[entry.stage as usize - 1] = Some((entry.mode, entry.id.clone()));
+            }
+            let code = UNMERGED[change.stage_mask() - 1].0.as_bytes();
+            change.staged = code[0] as char;
+            change.unstaged = code[1] as char;
+            change.work_tree_mode = metadata.as_ref().map(FileMode::from_metadata);
+            status.changes.push(change);
+            continue;
+        }
+
+        if let Some(entry) = index.get_mut(&path, 0) {
+            if !entry.intent_to_add {
+                change.index = Some((entry.mode, entry.id.clone()));
+            }
+            change.work_tree_mode = match metadata.as_ref().map(FileMode::from_metadata) {
+                Some(FileMode::Tree) if entry.mode == FileMode::Gitlink => Some(FileMode::Gitlink),
+                Some(FileMode::Tree) => None,
+                mode => mode,
+            };
+            change.unstaged = match &metadata {
+                _ if entry.assume_valid || entry.skip_worktree => ' ',
+                // a directory in place of a file leaves the file deleted.
+                Some(m) if !(m.is_dir() && entry.mode != FileMode::Gitlink) => {
+                    if entry.intent_to_add {
+                        'A'
+                    } else if is_stat_clean(entry, m, index_mtime) {
+                        ' '
+                    } else if m.is_dir() && !work_tree.join(&path).join(".git").exists() {
+                        // a submodule that is not checked out is not a change.
+                        ' '
+                    } else {
+                        match entry_from_work_tree(&store, work_tree, &path, false) {
+                            Ok(found) if kind(found.mode) != kind(entry.mode) => 'T',
+                            Ok(found) if found.mode == FileMode::Gitlink => {
+                                change.new_commits = found.id != entry.id;
+                                if change.new_commits {
+                                    'M'
+                                } else {
+                                    ' '
+                                }
+                            }
+                            Ok(found) if found.id != entry.id || found.mode != entry.mode => 'M',
+                            Ok(found) => {
+                                entry.stat = found.stat;
+                                status.refreshed = true;
+                                ' '
+                            }
+                            Err(_) => ' ',
+                        }
+                    }
+                }
+                _ => 'D',
+            };
+        }
+        change.staged = match (&change.head, &change.index) {
+            (Some(h), Some(i)) if kind(h.0) != kind(i.0) => 'T',
+            (Some(h), Some(i)) if h != i => 'M',
+            (Some(_), None) if !index.stages(&path).is_empty() => ' ',
+            (Some(_), None) => 'D',
+            (None, Some(_)) => 'A',
+            _ => ' ',
+        };
+        if change.staged != ' ' || change.unstaged != ' ' {
+            status.changes.push(change);
+        }
+    }
+    find_renames(&mut status.changes);
+
+    if untracked != UntrackedMode::No {
+        let config = repo.config()?;
+        let mut ignore = IgnoreRules::load(work_tree, &repo.git_dir, &config);
+        let (untracked, ignored) = untracked_files(
+            work_tree,
+            index,
+            pathspec,
+            &mut ignore,
+            untracked,
+            show_ignored,
+        )?;
+        status.untracked = untracked;
+        status.ignored = ignored;
+    }
+    Ok(status)
+}
+
+// the short code and the long description of each set of stages, by stage mask.
+const UNMERGED: [(&str, &str); 7] = [
+    ("DD", "both deleted:"),
+    ("AU", "added by us:"),
+    ("UD", "deleted by them:"),
+    ("UA", "added by them:"),
+    ("DU", "deleted by us:"),
+    ("AA", "both added:"),
+    ("UU", "both modified:"),
+];
+
+// files, symlinks and gitlinks are different kinds of things; a change between them
+// is a type change.
+fn kind(mode: FileMode) -> u8 {
+    match mode {
+        FileMode::Regular | FileMode::Executable => 0,
+        FileMode::Symlink => 1,
+        FileMode::Gitlink => 2,
+        FileMode::Tree => 3,
+    }
+}
+
+// every blob and gitlink below the tree `id`, by its full path.
+fn flatten_tree(
+    store: &dyn ObjectStore,
+    id: &[u8],
+    base: &str,
+    found: &mut BTreeMap<String, Version>,
+) -> Result<()> {
+    let nodes = match load_object_by_hash(store, &hash_to_str(id))? {
+        GitObject::Tree(nodes) => nodes,
+        _ => {
+            return Err(GitObjectError::ParseError(format!(
+                "{} is not a tree",
+                hash_to_str(id)
+            )))
+        }
+    };
+    for node in nodes {
+        let path = format!("{}{}", base, node.filename);
+        if node.mode == FileMode::Tree {
+            flatten_tree(store, &node.hash, &format!("{}/", path), found)?;
+        } else {
+            found.insert(path, (node.mode, node.hash));
+        }
+    }
+    Ok(())
+}
+
+// pairs paths added to the index with deleted ones of the same content, as renames.
+fn find_renames(changes: &mut Vec<Change>) {
+    let mut deleted: Vec<usize> = (0..changes.len())
+        .filter(|i| changes[*i].staged == 'D' && !changes[*i].is_unmerged())
+        .collect();
+    let mut renamed = HashSet::new();
+    for i in 0..changes.len() {
+        if changes[i].staged != 'A' || changes[i].is_unmerged() {
+            continue;
+        }
+        let id = changes[i].index.as_ref().map(|v| &v.1);
+        let found = deleted
+            .iter()
+            .position(|d| changes[*d].head.as_ref().map(|v| &v.1) == id);
+        if let Some(found) = found {
+            let from = deleted.remove(found);
+            changes[i].staged = 'R';
+            changes[i].head = changes[from].head.clone();
+            changes[i].orig_path = Some(changes[from].path.clone());
+            renamed.insert(from);
+        }
+    }
+    let mut i = 0;
+    changes.retain(|_| {
+        i += 1;
+        !renamed.contains(&(i - 1))
+    });
+}
+
+// the untracked and the ignored paths. unless all are shown, a directory with nothing
+// tracked in it is shown instead of its contents.
+fn untracked_files(
+    work_tree: &Path,
+    index: &Index,
+    pathspec: &Pathspec,
+    ignore: &mut IgnoreRules,
+    mode: UntrackedMode,
+    show_ignored: bool,
+) -> Result<(Vec<String>, Vec<String>)> {
+    let tracked: HashSet<&str> = index.entries.iter().map(|e| e.path.as_str()).collect();
+    let mut tracked_dirs = HashSet::new();
+    for path in &tracked {
+        let mut dir = *path;
+        while let Some(i) = dir.rfind('/') {
+            dir = &dir[..i];
+            tracked_dirs.insert(dir);
+        }
+    }
+    let mut found = vec![];
+    for entry in list_work_tree(work_tree, "", ignore, show_ignored)? {
+        // the files of an ignored directory are listed one by one when some of it is
+        // tracked, or when all files are shown.
+        let expand = entry.ignored
+            && entry.is_dir
+            && !work_tree.join(&entry.path).join(".git").exists()
+            && (mode == UntrackedMode::All || tracked_dirs.contains(entry.path.as_str()));
+        if expand {
+            let mut none = IgnoreRules::none(work_tree);
+            for mut inner in list_work_tree(work_tree, &entry.path, &mut none, false)? {
+                inner.ignored = true;
+                found.push(inner);
+            }
+        } else {
+            found.push(entry);
+        }
+    }
+    found.retain(|e| !tracked.contains(e.path.as_str()) && pathspec.matches(&e.path));
+
+    let shown_path = |path: &str, is_dir: bool| {
+        if mode == UntrackedMode::Normal {
+            for (i, _) in path.match_indices('/') {
+                if !tracked_dirs.contains(&path[..i]) {
+                    return path[..i + 1].to_string();
+                }
+            }
+        }
+        if is_dir {
+            format!("{}/", path)
+        } else {
+            path.to_string()
+        }
+    };
+    // like git, a directory where a file is tracked is not shown.
+    let untracked: BTreeSet<String> = found
+        .iter()
+        .filter(|e| !e.ignored)
+        .map(|e| shown_path(&e.path, e.is_dir))
+        .filter(|p| !p.strip_suffix('/').is_some_and(|d| tracked.contains(d)))
+        .collect();
+    // an ignored file in a directory shown as untracked is shown by itself.
+    let ignored: BTreeSet<String> = found
+        .iter()
+        .filter(|e| e.ignored)
+        .map(|e| {
+            let shown = shown_path(&e.path, e.is_dir);
+            match (untracked.contains(&shown), e.is_dir) {
+                (true, true) => format!("{}/", e.path),
+                (true, false) => e.path.clone(),
+                _ => shown,
+            }
+        })
+        .filter(|p| !p.strip_suffix('/').is_some_and(|d| tracked.contains(d)))
+        .collect();
+    Ok((
+        untracked.into_iter().collect(),
+        ignored.into_iter().collect(),
+    ))
+}
+
+fn branch_info(repo: &Repository, store: &dyn ObjectStore) -> Result<BranchInfo> {
+    let branch = read_symbolic_ref(&repo.git_dir, "HEAD")?
+        .and_then(|r| r.strip_prefix("refs/heads/").map(String::from));
+    let mut info = BranchInfo {
+        branch,
+        head: read_ref(&repo.git_dir, "HEAD")?,
+        upstream: None,
+        ahead_behind: None,
+    };
+    if let Some(name) = &info.branch {
+        info.upstream = upstream_of(&repo.config()?, name);
+    }
+    if let (Some(upstream), Some(head)) = (&info.upstream, &info.head) {
+        if let Some(theirs) = read_ref(&repo.git_dir, upstream)? {
+            info.ahead_behind = Some(ahead_behind(store, head, &theirs)?);
+        }
+    }
+    Ok(info)
+}
+
+// the ref `branch` is set to track: branch.<name>.merge, as the fetch refspecs of
+// branch.<name>.remote map it, or as it is for the remote ".".
+fn upstream_of(config: &crate::config::Config, branch: &str) -> Option<String> {
+    let remote = config
+        .get_string(&format!("branch.{}.remote", branch))
+        .ok()??;
+    let merge = config
+        .get_string(&format!("branch.{}.merge", branch))
+        .ok()??;
+    if remote == "." {
+        return Some(merge);
+    }
+    for entry in config.get_all(&format!("remote.{}.fetch", remote)) {
+        let spec = match &entry.value {
+            Some(v) => v.trim_start_matches('+'),
+            None => continue,
+        };
+        let (src, dst) = match spec.split_once(':') {
+            Some(s) => s,
+            None => continue,
+        };
+        match src.strip_suffix('*') {
+            Some(src) => {
+                if let Some(rest) = merge.strip_prefix(src) {
+                    return Some(dst.replacen('*', rest, 1));
+                }
+            }
+            None if src == merge => return Some(dst.to_string()),
+            None => {}
+        }
+    }
+    None
+}
+
+// what a detached HEAD was last checked out from, by the HEAD reflog, and whether it
+// is still there.
+fn detached_from(
+    repo: &Repository,
+    store: &dyn ObjectStore,
+    head: Option<&Vec<u8>>,
+) -> Option<(String, bool)> {
+    let entries = read_reflog(&repo.git_dir, "HEAD").ok()?;
+    let (entry, target) = entries.iter().rev().find_map(|e| {
+        let moving = e.message.strip_prefix("checkout: moving from ")?;
+        let (_, to) = moving.split_once(" to ")?;
+        Some((e, to.trim_end()))
+    })?;
+    // a ref name is only shown when it still points where HEAD went.
+    let name = match dwim_ref(&repo.git_dir, target).ok().flatten() {
+        Some((full, id))
+            if id == entry.new
+                || peel(store, &id, Some("commit")).ok() == Some(entry.new.clone()) =>
+        {
+            full.strip_prefix("refs/tags/")
+                .or_else(|| full.strip_prefix("refs/remotes/"))
+                .unwrap_or(&full)
+                .to_string()
+        }
+        _ => find_unique_abbrev(store, &entry.new, DEFAULT_ABBREV),
+    };
+    Some((name, head == Some(&entry.new)))
+}
+
+fn long_format(
+    repo: &Repository,
+    store: &dyn ObjectStore,
+    status: &Status,
+    branch: &BranchInfo,
+    option: &StatusOption,
+    prefix: &str,
+) -> String {
+    let mut out = String::new();
+    let initial = branch.head.is_none();
+    let merging = repo.git_dir.join("MERGE_HEAD").exists();
+    let show = |path: &str| quote_path(&relative_path(path, prefix), false);
+    match &branch.branch {
+        Some(name) => out += &format!("On branch {}\n", name),
+        None => match detached_from(repo, store, branch.head.as_ref()) {
+            Some((name, true)) => out += &format!("HEAD detached at {}\n", name),
+            Some((name, false)) => out += &format!("HEAD detached from {}\n", name),
+            None => out += "Not currently on any branch.\n",
+        },
+    }
+    if let (Some(upstream), false) = (&branch.upstream, initial) {
+        out += &tracking_message(shorten_ref_name(upstream), branch.ahead_behind);
+        out += "\n";
+    }
+    if initial {
+        out += "\nNo commits yet\n\n";
+    }
+    let unmerged: Vec<&Change> = status.changes.iter().filter(|c| c.is_unmerged()).collect();
+    if merging {
+        if unmerged.is_empty() {
+            out += "All conflicts fixed but you are still merging.\n";
+            out += "  (use \"git commit\" to conclude merge)\n\n";
+        } else {
+            out += "You have unmerged paths.\n";
+            out += "  (fix conflicts and run \"git commit\")\n";
+            out += "  (use \"git merge --abort\" to abort the merge)\n\n";
+        }
+    }
+    let unstage_hint = if merging {
+        ""
+    } else if initial {
+        "  (use \"git rm --cached <file>...\" to unstage)\n"
+    } else {
+        "  (use \"git restore --staged <file>...\" to unstage)\n"
+    };
+
+    let staged: Vec<&Change> = status
+        .changes
+        .iter()
+        .filter(|c| !c.is_unmerged() && c.staged != ' ')
+        .collect();
+    if !staged.is_empty() {
+        out += "Changes to be committed:\n";
+        out += unstage_hint;
+        for change in &staged {
+            let (label, path) = match change.staged {
+                'A' => ("new file:", show(&change.path)),
+                'D' => ("deleted:", show(&change.path)),
+                'R' => {
+                    let orig = change.orig_path.as_deref().unwrap_or_default();
+                    (
+                        "renamed:",
+                        format!("{} -> {}", show(orig), show(&change.path)),
+                    )
+                }
+                'T' => ("typechange:", show(&change.path)),
+                _ => ("modified:", show(&change.path)),
+            };
+            out += &format!("\t{:<12}{}\n", label, path);
+        }
+        out += "\n";
+    }
+
+    if !unmerged.is_empty() {
+        let masks: Vec<usize> = unmerged.iter().map(|c| c.stage_mask()).collect();
+        let both_deleted = masks.contains(&1);
+        let del_mod_conflict = masks.iter().any(|m| *m == 3 || *m == 5);
+        let not_deleted = masks.iter().any(|m| ![1, 3, 5].contains(m));
+        out += "Unmerged paths:\n";
+        out += unstage_hint;
+        out += if !both_deleted && !del_mod_conflict {
+            "  (use \"git add <file>...\" to mark resolution)\n"
+        } else if both_deleted && !del_mod_conflict && !not_deleted {
+            "  (use \"git rm <file>...\" to mark resolution)\n"
+        } else {
+            "  (use \"git add/rm <file>...\" as appropriate to mark resolution)\n"
+        };
+        for change in &unmerged {
+            let label = UNMERGED[change.stage_mask() - 1].1;
+            out += &format!("\t{:<17}{}\n", label, show(&change.path));
+        }
+        out += "\n";
+    }
+
+    let dirty: Vec<&Change> = status
+        .changes
+        .iter()
+        .filter(|c| !c.is_unmerged() && c.unstaged != ' ')
+        .collect();
+    if !dirty.is_empty() {
+        out += "Changes not staged for commit:\n";
+        if dirty.iter().any(|c| c.unstaged == 'D') {
+            out += "  (use \"git add/rm <file>...\" to update what will be committed)\n";
+        } else {
+            out += "  (use \"git add <file>...\" to update what will be committed)\n";
+        }
+        out += "  (use \"git restore <file>...\" to discard changes in working directory)\n";
+        for change in &dirty {
+            let label = match change.unstaged {
+                'A' => "new file:",
+                'D' => "deleted:",
+                'T' => "typechange:",
+                _ => "modified:",
+            };
+            let note = if change.new_commits {
+                " (new commits)"
+            } else {
+                ""
+            };
+            out += &format!("\t{:<12}{}{}\n", label, show(&change.path), note);
+        }
+        out += "\n";
+    }
+
+    let committable = !staged.is_empty() || (merging && unmerged.is_empty());
+    if option.untracked == UntrackedMode::No {
+        if committable {
+            out += "Untracked files not listed (use -u option to show untracked files)\n";
+        }
+    } else {
+        for (title, hint, paths) in [
+            ("Untracked files:", "git add", &status.untracked),
+            ("Ignored files:", "git add -f", &status.ignored),
+        ] {
+            if paths.is_empty() {
+                continue;
+            }
+            out += &format!(
+                "{}\n  (use \"{} <file>...\" to include in what will be committed)\n",
+                title, hint
+            );
+            for path in paths {
+                out += &format!("\t{}\n", show(path));
+            }
+            out += "\n";
+        }
+    }
+
+    // what there is to commit goes without saying.
+    if committable {
+        return out;
+    }
+    out += if !dirty.is_empty() {
+        "no changes added to commit (use \"git add\" and/or \"git commit -a\")\n"
+    } else if !status.untracked.is_empty() {
+        "nothing added to commit but untracked files present (use \"git add\" to track)\n"
+    } else if initial {
+        "nothing to commit (create/copy files and use \"git add\" to track)\n"
+    } else if option.untracked == UntrackedMode::No {
+        "nothing to commit (use -u to show untracked files)\n"
+    } else {
+        "nothing to commit, working tree clean\n"
+    };
+    out
+}
+
+fn tracking_message(upstream: &str, ahead_behind: Option<(usize, usize)>) -> String {
+    let commits = |n: usize| if n == 1 { "commit" } else { "commits" };
+    match ahead_behind {
+        None => format!(
+            "Your branch is based on '{}', but the upstream is gone.\n  \
+             (use \"git branch --unset-upstream\" to fixup)\n",
+            upstream
+        ),
+        Some((0, 0)) => format!("Your branch is up to date with '{}'.\n", upstream),
+        Some((ahead, 0)) => format!(
+            "Your branch is ahead of '{}' by {} {}.\n  \
+             (use \"git push\" to publish your local commits)\n",
+            upstream,
+            ahead,
+            commits(ahead)
+        ),
+        Some((0, behind)) => format!(
+            "Your branch is behind '{}' by {} {}, and can be fast-forwarded.\n  \
+             (use \"git pull\" to update your local branch)\n",
+            upstream,
+            behind,
+            commits(behind)
+        ),
+        Some((ahead, behind)) => format!(
+            "Your branch and '{}' have diverged,\n\
+             and have {} and {} different commits each, respectively.\n  \
+             (use \"git pull\" to merge the remote branch into yours)\n",
+            upstream, ahead, behind
+        ),
+    }
+}
+
+// `--short` and `--porcelain`, which differ in that paths of the latter are relative
+// to the top whatever `prefix` is.
+fn short_format(
+    status: &Status,
+    branch: &BranchInfo,
+    option: &StatusOption,
+    prefix: &str,
+) -> String {
+    let end = if option.nul { '\0' } else { '\n' };
+    let show = |path: &str| {
+        let path = relative_path(path, prefix);
+        if option.nul {
+            path
+        } else {
+            quote_path(&path, true)
+        }
+    };
+    let mut out = String::new();
+    if option.branch {
+        out += "## ";
+        match (&branch.branch, &branch.head) {
+            (None, _) => out += "HEAD (no branch)",
+            (Some(name), None) => out += &format!("No commits yet on {}", name),
+            (Some(name), Some(_)) => out += name,
+        }
+        if let (Some(upstream), Some(_)) = (&branch.upstream, &branch.head) {
+            out += &format!("...{}", shorten_ref_name(upstream));
+            match branch.ahead_behind {
+                None => out += " [gone]",
+                Some((0, 0)) => {}
+                Some((ahead, 0)) => out += &format!(" [ahead {}]", ahead),
+                Some((0, behind)) => out += &format!(" [behind {}]", behind),
+                Some((ahead, behind)) => out += &format!(" [ahead {}, behind {}]", ahead, behind),
+            }
+        }
+        out.push(end);
+    }
+    for change in &status.changes {
+        out += &format!("{}{} ", change.staged, change.unstaged);
+        match &change.orig_path {
+            Some(orig) if option.nul => {
+                out += &format!("{}\0{}", show(&change.path), show(orig));
+            }
+            Some(orig) => out += &format!("{} -> {}", show(orig), show(&change.path)),
+            None => out += &show(&change.path),
+        }
+        out.push(end);
+    }
+    for (mark, paths) in [("??", &status.untracked), ("!!", &status.ignored)] {
+        for path in paths {
+            out += &format!("{} {}{}", mark, show(path), end);
+        }
+    }
+    out
+}
+
+fn porcelain_v2(
+    status: &Status,
+    branch: &BranchInfo,
+    option: &StatusOption,
+    prefix: &str,
+) -> String {
+    let end = if option.nul { '\0' } else { '\n' };
+    let show = |path: &str| {
+        let path = relative_path(path, prefix);
+        if option.nul {
+            path
+        } else {
+            quote_path(&path, false)
+        }
+    };
+    let mode = |v: Option<FileMode>| format!("{:06o}", v.map_or(0, |m| m.to_octal()));
+    let id = |v: &Option<Version>| v.as_ref().map_or("0".repeat(40), |v| hash_to_str(&v.1));
+    let mut out = String::new();
+    if option.branch {
+        let oid = match &branch.head {
+            Some(head) => hash_to_str(head),
+            None => String::from("(initial)"),
+        };
+        out += &format!("# branch.oid {}{}", oid, end);
+        let head = branch.branch.as_deref().unwrap_or("(detached)");
+        out += &format!("# branch.head {}{}", head, end);
+        if let Some(upstream) = &branch.upstream {
+            out += &format!("# branch.upstream {}{}", shorten_ref_name(upstream), end);
+            if let Some((ahead, behind)) = branch.ahead_behind {
+                out += &format!("# branch.ab +{} -{}{}", ahead, behind, end);
+            }
+        }
+    }
+    // unmerged paths come after the others.
+    let changes = status.changes.iter().filter(|c| !c.is_unmerged());
+    for change in changes.chain(status.changes.iter().filter(|c| c.is_unmerged())) {
+        let modes = [&change.head, &change.index, &change.stages[1]];
+        let gitlink = change.work_tree_mode == Some(FileMode::Gitlink)
+            || modes
+                .iter()
+                .any(|v| matches!(v, Some((FileMode::Gitlink, _))));
+        let sub = match (gitlink, change.new_commits) {
+            (false, _) => "N...",
+            (true, false) => "S...",
+            (true, true) => "SC..",
+        };
+        let letters: String = [change.staged, change.unstaged]
+            .iter()
+            .map(|c| if *c == ' ' { '.' } else { *c })
+            .collect();
+        let work_tree_mode = mode(change.work_tree_mode);
+        if change.is_unmerged() {
+            let [s1, s2, s3] = &change.stages;
+            out += &format!(
+                "u {} {} {} {} {} {} {} {} {} {}",
+                letters,
+                sub,
+                mode(s1.as_ref().map(|v| v.0)),
+                mode(s2.as_ref().map(|v| v.0)),
+                mode(s3.as_ref().map(|v| v.0)),
+                work_tree_mode,
+                id(s1),
+                id(s2),
+                id(s3),
+                show(&change.path)
+            );
+        } else {
+            let fields = format!(
+                "{} {} {} {} {} {} {}",
+                letters,
+                sub,
+                mode(change.head.as_ref().map(|v| v.0)),
+                mode(change.index.as_ref().map(|v| v.0)),
+                work_tree_mode,
+                id(&change.head),
+                id(&change.index)
+            );
+            match &change.orig_path {
+                Some(orig) => {
+                    let separator = if option.nul { '\0' } else { '\t' };
+                    out += &format!(
+                        "2 {} R100 {}{}{}",
+                        fields,
+                        show(&change.path),
+                        separator,
+                        show(orig)
+                    );
+                }
+                None => out += &format!("1 {} {}", fields, show(&change.path)),
+            }
+        }
+        out.push(end);
+    }
+    for (mark, paths) in [('?', &status.untracked), ('!', &status.ignored)] {
+        for path in paths {
+            out += &format!("{} {}{}", mark, show(path), end);
+        }
+    }
+    out
+}
+
+// `path`, relative to the top, as seen from `prefix`, the current directory. a
+// directory keeps its trailing slash.
+fn relative_path(path: &str, prefix: &str) -> String {
+    let is_dir = path.ends_with('/');
+    let parts: Vec<&str> = path.split('/').filter(|c| !c.is_empty()).collect();
+    let base: Vec<&str> = prefix.split('/').filter(|c| !c.is_empty()).collect();
+    let common = base.iter().zip(&parts).take_while(|(a, b)| a == b).count();
+    let mut relative = vec![".."; base.len() - common];
+    relative.extend(&parts[common..]);
+    let mut relative = relative.join("/");
+    if relative.is_empty() {
+        relative.push('.');
+    }
+    if is_dir {
+        relative.push('/');
+    }
+    relative
+}
+
+// `path` in double quotes with C escapes when it has special characters, as
+// core.quotePath does; spaces only need them with `quote_spaces`.
+fn quote_path(path: &str, quote_spaces: bool) -> String {
+    let mut quoted = String::new();
+    let mut needs_quotes = false;
+    for b in path.bytes() {
+        let escaped = match b {
+            b'"' => "\\\"".to_string(),
+            b'\\' => "\\\\".to_string(),
+            0x07 => "\\a".to_string(),
+            0x08 => "\\b".to_string(),
+            b'\t' => "\\t".to_string(),
+            b'\n' => "\\n".to_string(),
+            0x0b => "\\v".to_string(),
+            0x0c => "\\f".to_string(),
+            b'\r' => "\\r".to_string(),
+            b' ' if quote_spaces => {
+                needs_quotes = true;
+                quoted.push(' ');
+                continue;
+            }
+            0..=0x1f | 0x7f..=0xff => format!("\\{:03o}", b),
+            _ => {
+                quoted.push(b as char);
+                continue;
+            }
+        };
+        needs_quotes = true;
+        quoted += &escaped;
+    }
+    if needs_quotes {
+        format!("\"{}\"", quoted)
+    } else {
+        quoted
+    }
+}
+
+fn parse_options(commands: &[String]) -> StatusOption<'_> {
+    let mut option: StatusOption = Default::default();
+    let mut only_paths = false;
+    for token in &commands[2..] {
+        match &token[..] {
+            _ if only_paths => option.paths.push(token),
+            "--" => only_paths = true,
+            "-s" | "--short" => option.format = Some(Format::Short),
+            "-b" | "--branch" => option.branch = true,
+            "-sb" | "-bs" => {
+                option.format = Some(Format::Short);
+                option.branch = true;
+            }
+            "--long" => option.format = Some(Format::Long),
+            "--porcelain" | "--porcelain=v1" => option.format = Some(Format::Porcelain),
+            "--porcelain=v2" => option.format = Some(Format::PorcelainV2),
+            "-z" => option.nul = true,
+            "-u" | "-uall" | "--untracked-files" | "--untracked-files=all" => {
+                option.untracked = UntrackedMode::All
+            }
+            "-unormal" | "--untracked-files=normal" => option.untracked = UntrackedMode::Normal,
+            "-uno" | "--untracked-files=no" => option.untracked = UntrackedMode::No,
+            "--ignored" => option.ignored = true,
+            _ if token.starts_with('-') => println!("ignore option {}", token),
+            _ => option.paths.push(token),
+        }
+    }
+    option
+}
+
+#[cfg(test)]
+mod test {
+    use crate::config::{set_config_value, Config};
+    use crate::index::IndexEntry;
+    use crate::init::init_repository;
+    use crate::refs::{write_ref, NULL_ID};
+    use crate::status::*;
+    use crate::test_utils::{commit, temp_dir};
+
+    #[test]
+    fn test_collect_status() {
+        let root = temp_dir("status");
+        init_repository(&root.join(".git"), false, "master", None).unwrap();
+        fs::create_dir_all(root.join("d")).unwrap();
+        for path in ["a", "b", "c", "d/e"] {
+            fs::write(root.join(path), path).unwrap();
+        }
+        fs::write(root.join(".gitignore"), "*.log\n").unwrap();
+        let repo = Repository::discover_from(&root, None, None).unwrap();
+        let store = repo.object_store();
+        let mut index = Index::new();
+        for path in [".gitignore", "a", "b", "c", "d/e"] {
+            index.add(entry_from_work_tree(&store, &root, path, true).unwrap());
+        }
+        let files: &[(&str, &[u8])] = &[
+            (".gitignore", b"*.log\n"),
+            ("a", b"a"),
+            ("b", b"b"),
+            ("c", b"c"),
+            ("d/e", b"d/e"),
+        ];
+        let commit = commit(&store, &[], files, 0, "base");
+        write_ref(
+            &repo.git_dir,
+            "HEAD",
+            &commit,
+            Some(&NULL_ID),
+            true,
+            "commit",
+        )
+        .unwrap();
+        index.write_to(&repo.index_path()).unwrap();
+
+        // b is changed, c staged as renamed to r, d/e deleted and a new directory and a
+        // log file are not tracked.
+        fs::write(root.join("b"), "changed").unwrap();
+        fs::rename(root.join("c"), root.join("r")).unwrap();
+        index.remove("c");
+        index.add(entry_from_work_tree(&store, &root, "r", true).unwrap());
+        fs::remove_file(root.join("d/e")).unwrap();
+        fs::create_dir_all(root.join("new")).unwrap();
+        fs::write(root.join("new/f"), "f").unwrap();
+        fs::write(root.join("x.log"), "log").unwrap();
+
+        let everything = Pathspec::everything();
+        let status = collect_status(
+            &repo,
+            &root,
+            &mut index,
+            &everything,
+            UntrackedMode::Normal,
+            true,
+        )
+        .unwrap();
+        let letters: Vec<(&str, char, char)> = status
+            .changes
+            .iter()
+            .map(|c| (c.path.as_str(), c.staged, c.unstaged))
+            .collect();
+        assert_eq!(
+            letters,
+            vec![("b", ' ', 'M'), ("d/e", ' ', 'D'), ("r", 'R', ' ')]
+        );
+        assert_eq!(status.changes[2].orig_path.as_deref(), Some("c"));
+        assert_eq!(status.untracked, vec!["new/"]);
+        assert_eq!(status.ignored, vec!["x.log"]);
+
+        let status = collect_status(
+            &repo,
+            &root,
+            &mut index,
+            &everything,
+            UntrackedMode::All,
+            false,
+        )
+        .unwrap();
+        assert_eq!(status.untracked, vec!["new/f"]);
+        assert!(status.ignored.is_empty());
+
+        let status = collect_status(
+            &repo,
+            &root,
+            &mut index,
+            &everything,
+            UntrackedMode::No,
+            false,
+        )
+        .unwrap();
+        assert!(status.untracked.is_empty());
+
+        assert_eq!(relative_path("a/b", "a/c"), "../b");
+        assert_eq!(relative_path("a/", "a"), "./");
+        assert_eq!(quote_path("sp ace", true), "\"sp ace\"");
+        assert_eq!(quote_path("sp ace", false), "sp ace");
+        assert_eq!(quote_path("t\tü", false), "\"t\\t\\303\\274\"");
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    fn blob(content: &str) -> String {
+        hash_to_str(&GitObject::Blob(content.as_bytes().to_vec()).to_hash())
+    }
+
+    #[test]
+    fn test_formats() {
+        let root = temp_dir("status-formats");
+        init_repository(&root.join(".git"), false, "master", None).unwrap();
+        let repo = Repository::discover_from(&root, None, None).unwrap();
+        let store = repo.object_store();
+        let mut index = Index::new();
+        for path in ["a", "b", "d", "u"] {
+            fs::write(root.join(path), path).unwrap();
+            index.add(entry_from_work_tree(&store, &root, path, true).unwrap());
+        }
+        let files: &[(&str, &[u8])] = &[("a", b"a"), ("b", b"b"), ("d", b"d"), ("u", b"u")];
+        // the merge base has a parent that is missing: the ahead and behind counts must
+        // not need it.
+        let base = commit(&store, &[&[1; 20]], files, 100, "base");
+        let ours = commit(&store, &[&base], files, 200, "ours");
+        let theirs = commit(&store, &[&base], files, 150, "theirs");
+        let theirs = commit(&store, &[&theirs], files, 300, "theirs again");
+        write_ref(&repo.git_dir, "refs/heads/master", &ours, None, false, "c").unwrap();
+        write_ref(
+            &repo.git_dir,
+            "refs/remotes/origin/master",
+            &theirs,
+            None,
+            false,
+            "c",
+        )
+        .unwrap();
+        let config = repo.git_dir.join("config");
+        set_config_value(&config, "branch.master.remote", "origin", false).unwrap();
+        set_config_value(&config, "branch.master.merge", "refs/heads/master", false).unwrap();
+        let fetch = "+refs/heads/*:refs/remotes/origin/*";
+        set_config_value(&config, "remote.origin.fetch", fetch, false).unwrap();
+
+        // a is staged, b changed, d deleted by them and u changed on both sides.
+        fs::write(root.join("a"), "staged").unwrap();
+        index.add(entry_from_work_tree(&store, &root, "a", true).unwrap());
+        fs::write(root.join("b"), "changed").unwrap();
+        for (path, stages) in [("d", 1..=2), ("u", 1..=3)] {
+            index.remove(path);
+            for stage in stages {
+                let id = GitObject::Blob(path.as_bytes().to_vec()).to_hash();
+                let mut entry = IndexEntry::new(path, &id, FileMode::Regular);
+                entry.stage = stage;
+                index.add(entry);
+            }
+        }
+        fs::write(root.join("new"), "new").unwrap();
+        let status = collect_status(
+            &repo,
+            &root,
+            &mut index,
+            &Pathspec::everything(),
+            UntrackedMode::Normal,
+            false,
+        )
+        .unwrap();
+        let branch = branch_info(&repo, &store).unwrap();
+        assert_eq!(
+            branch.upstream.as_deref(),
+            Some("refs/remotes/origin/master")
+        );
+        assert_eq!(branch.ahead_behind, Some((1, 2)));
+
+        let option = StatusOption {
+            branch: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            short_format(&status, &branch, &option, ""),
+            "## master...origin/master [ahead 1, behind 2]\n\
+             M  a\n M b\nUD d\nUU u\n?? new\n"
+        );
+        let nul = StatusOption {
+            branch: true,
+            nul: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            short_format(&status, &branch, &nul, ""),
+            "## master...origin/master [ahead 1, behind 2]\0M  a\0 M b\0UD d\0UU u\0?? new\0"
+        );
+        let (a, staged, b, d, u) = (blob("a"), blob("staged"), blob("b"), blob("d"), blob("u"));
+        let zero = "0".repeat(40);
+        assert_eq!(
+            porcelain_v2(&status, &branch, &option, ""),
+            format!(
+                "# branch.oid {}\n\
+                 # branch.head master\n\
+                 # branch.upstream origin/master\n\
+                 # branch.ab +1 -2\n\
+                 1 M. N... 100644 100644 100644 {} {} a\n\
+                 1 .M N... 100644 100644 100644 {} {} b\n\
+                 u UD N... 100644 100644 000000 100644 {} {} {} d\n\
+                 u UU N... 100644 100644 100644 100644 {} {} {} u\n\
+                 ? new\n",
+                hash_to_str(&ours),
+                a,
+                staged,
+                b,
+                b,
+                d,
+                d,
+                zero,
+                u,
+                u,
+                u
+            )
+        );
+        assert_eq!(
+            long_format(&repo, &store, &status, &branch, &Default::default(), ""),
+            "On branch master\n\
+             Your branch and 'origin/master' have diverged,\n\
+             and have 1 and 2 different commits each, respectively.\n  \
+             (use \"git pull\" to merge the remote branch into yours)\n\
+             \n\
+             Changes to be committed:\n  \
+             (use \"git restore --staged <file>...\" to unstage)\n\
+             \tmodified:   a\n\
+             \n\
+             Unmerged paths:\n  \
+             (use \"git restore --staged <file>...\" to unstage)\n  \
+             (use \"git add/rm <file>...\" as appropriate to mark resolution)\n\
+             \tdeleted by them: d\n\
+             \tboth modified:   u\n\
+             \n\
+             Changes not staged for commit:\n  \
+             (use \"git add <file>...\" to update what will be committed)\n  \
+             (use \"git restore <file>...\" to discard changes in working directory)\n\
+             \tmodified:   b\n\
+             \n\
+             Untracked files:\n  \
+             (use \"git add <file>...\" to include in what will be committed)\n\
+             \tnew\n\
+             \n"
+        );
+
+        // every set of stages has its code.
+        let mut index = Index::new();
+        for mask in 1..=7u8 {
+            for stage in (1..=3).filter(|s| mask & (1 << (s - 1)) != 0) {
+                let mut entry =
+                    IndexEntry::new(&format!("m{}", mask), &[mask; 20], FileMode::Regular);
+                entry.stage = stage;
+                index.add(entry);
+            }
+        }
+        let pathspec = Pathspec::parse(&root, Path::new(""), &[&String::from("m*")]).unwrap();
+        let status = collect_status(
+            &repo,
+            &root,
+            &mut index,
+            &pathspec,
+            UntrackedMode::No,
+            false,
+        )
+        .unwrap();
+        let codes: Vec<String> = status
+            .changes
+            .iter()
+            .map(|c| format!("{}{} {}", c.staged, c.unstaged, c.path))
+            .collect();
+        assert_eq!(
+            codes,
+            vec!["DD m1", "AU m2", "UD m3", "UA m4", "DU m5", "AA m6", "UU m7"]
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_branch_headers() {
+        let status = Status {
+            changes: vec![
+                Change {
+                    orig_path: Some(String::from("c")),
+                    staged: 'R',
+                    ..Change::new("r")
+                },
+                Change {
+                    unstaged: 'M',
+                    ..Change::new("a b")
+                },
+            ],
+            ..Default::default()
+        };
+        let short = StatusOption {
+            branch: true,
+            ..Default::default()
+        };
+        let nul = StatusOption {
+            branch: true,
+            nul: true,
+            ..Default::default()
+        };
+        let info = |branch: Option<&str>, head: bool, upstream: Option<&str>, ab| BranchInfo {
+            branch: branch.map(String::from),
+            head: if head { Some(vec![7; 20]) } else { None },
+            upstream: upstream.map(String::from),
+            ahead_behind: ab,
+        };
+        let tracking = Some("refs/remotes/origin/topic");
+        for (branch, expected) in [
+            (
+                info(Some("topic"), true, tracking, None),
+                "## topic...origin/topic [gone]",
+            ),
+            (
+                info(Some("topic"), true, tracking, Some((0, 0))),
+                "## topic...origin/topic",
+            ),
+            (
+                info(Some("topic"), true, tracking, Some((2, 0))),
+                "## topic...origin/topic [ahead 2]",
+            ),
+            (
+                info(Some("topic"), true, tracking, Some((0, 3))),
+                "## topic...origin/topic [behind 3]",
+            ),
+            (
+                info(Some("topic"), false, tracking, None),
+                "## No commits yet on topic",
+            ),
+            (info(None, true, None, None), "## HEAD (no branch)"),
+        ] {
+            assert_eq!(
+                short_format(&status, &branch, &short, ""),
+                format!("{}\nR  c -> r\n M \"a b\"\n", expected)
+            );
+        }
+        let branch = info(Some("topic"), true, tracking, None);
+        assert_eq!(
+            short_format(&status, &branch, &nul, ""),
+            "## topic...origin/topic [gone]\0R  r\0c\0 M a b\0"
+        );
+        let zero = "0".repeat(40);
+        assert_eq!(
+            porcelain_v2(&status, &branch, &nul, ""),
+            format!(
+                "# branch.oid {}\0# branch.head topic\0# branch.upstream origin/topic\0\
+                 2 R. N... 000000 000000 000000 {} {} R100 r\0c\0\
+                 1 .M N... 000000 000000 000000 {} {} a b\0",
+                "07".repeat(20),
+                zero,
+                zero,
+                zero,
+                zero
+            )
+        );
+        let branch = info(None, false, None, None);
+        assert_eq!(
+            porcelain_v2(&Status::default(), &branch, &short, ""),
+            "# branch.oid (initial)\n# branch.head (detached)\n"
+        );
+    }
+
+    #[test]
+    fn test_upstream_of() {
+        let dir = temp_dir("status-upstream");
+        let path = dir.join("config");
+        for (key, value) in [
+            ("branch.local.remote", "."),
+            ("branch.local.merge", "refs/heads/master"),
+            ("branch.main.remote", "origin"),
+            ("branch.main.merge", "refs/heads/main"),
+            (
+                "remote.origin.fetch",
+                "refs/heads/main:refs/remotes/origin/main",
+            ),
+            ("branch.other.remote", "origin"),
+            ("branch.other.merge", "refs/heads/other"),
+            ("branch.lost.remote", "gone"),
+            ("branch.lost.merge", "refs/heads/lost"),
+        ] {
+            set_config_value(&path, key, value, false).unwrap();
+        }
+        let config = Config::load_file(&path, None).unwrap();
+        assert_eq!(
+            upstream_of(&config, "local").as_deref(),
+            Some("refs/heads/master")
+        );
+        assert_eq!(
+            upstream_of(&config, "main").as_deref(),
+            Some("refs/remotes/origin/main")
+        );
+        assert_eq!(upstream_of(&config, "other"), None);
+        assert_eq!(upstream_of(&config, "lost"), None);
+        assert_eq!(upstream_of(&config, "none"), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,139 @@
+use crate::git_object::*;
+use crate::git_user::current_signature;
+use crate::refs::{read_ref, write_ref, NULL_ID};
+use crate::repository::open_repository;
+use crate::rev_parse::report_resolve_error;
+use crate::revision::resolve_revision;
+use crate::utils::{hash_to_str, is_valid_ref_name, str_to_hash};
+
+#[derive(Default, Debug)]
+struct TagOption<'a> {
+    annotate: bool,
+    force: bool,
+    message: Option<&'a String>,
+    tag_name: Option<&'a String>,
+    obj_hash: Option<&'a String>,
+}
+
+pub fn tag(commands: &[String]) {
+    let option = parse_options(commands);
+    let name = match option.tag_name {
+        Some(n) => n,
+        None => {
+            println!("fatal: no tag name given");
+            return;
+        }
+    };
+    if !is_valid_ref_name(name) {
+        println!("fatal: '{}' is not a valid tag name.", name);
+        return;
+    }
+    let repo = match open_repository() {
+        Some(r) => r,
+        None => return,
+    };
+    let ref_name = format!("refs/tags/{}", name);
+    if !option.force && read_ref(&repo.git_dir, &ref_name).ok().flatten().is_some() {
+        println!("fatal: tag '{}' already exists", name);
+        return;
+    }
+
+    let store = repo.object_store();
+    let obj_hash = match option.obj_hash {
+        Some(rev) => match resolve_revision(&repo.git_dir, &store, rev) {
+            Ok(h) => hash_to_str(&h),
+            Err(e) => {
+                report_resolve_error(&store, rev, &e);
+                println!("fatal: Failed to resolve '{}' as a valid ref.", rev);
+                return;
+            }
+        },
+        None => match resolve_revision(&repo.git_dir, &store, "HEAD") {
+            Ok(h) => hash_to_str(&h),
+            Err(_) => {
+                println!("fatal: failed to resolve 'HEAD' as a valid ref.");
+                return;
+            }
+        },
+    };
+
+    // -m implies -a, like git.
+    let target = if option.annotate || option.message.is_some() {
+        let message = match option.message {
+            Some(m) => m.to_string(),
+            None => {
+                println!("fatal: no tag message given (use -m)");
+                return;
+            }
+        };
+        let object = match load_object_by_hash(&store, &obj_hash) {
+            Ok(r) => r,
+            Err(e) => {
+                println!("fatal: {:?}", e);
+                return;
+            }
+        };
+        let tagger = match repo
+            .config()
+            .map_err(|e| format!("fatal: {:?}", e))
+            .and_then(|config| current_signature(&config, "COMMITTER"))
+        {
+            Ok(t) => t,
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        };
+        let tag = GitObject::new_tag(
+            obj_hash,
+            object.type_name(),
+            name.to_string(),
+            tagger,
+            message,
+        );
+        tag.write(&store).unwrap();
+        tag.to_hash_str()
+    } else {
+        obj_hash
+    };
+
+    // without -f the tag must still not exist when it is written.
+    let old = if option.force {
+        None
+    } else {
+        Some(&NULL_ID[..])
+    };
+    match write_ref(
+        &repo.git_dir,
+        &ref_name,
+        &str_to_hash(target),
+        old,
+        false,
+        "",
+    ) {
+        Ok(_) => {}
+        Err(GitObjectError::WriteError(m)) => println!("fatal: {}", m),
+        Err(e) => println!("fatal: {:?}", e),
+    }
+}
+
+fn parse_options(commands: &[String]) -> TagOption<'_> {
+    let mut option: TagOption = Default::default();
+    let mut message_flag = false;
+    for token in &commands[2..] {
+        if message_flag {
+            option.message = Some(token);
+            message_flag = false;
+            continue;
+        }
+        match &token[..] {
+            "-a" => option.annotate = true,
+            "-f" => option.force = true,
+            "-m" => message_flag = true,
+            _ if option.tag_name.is_none() => option.tag_name = Some(token),
+            _ if option.obj_hash.is_none() => option.obj_hash = Some(token),
+            _ => println!("ignore option {}", token),
+        }
+    }
+    option
+}
@@ -0,0 +1,77 @@
+use crate::git_object::{FileMode, GitCommitObject, GitObject, GitTreeNode};
+use crate::git_user::Signature;
+use crate::object_store::ObjectStore;
+use crate::utils::hash_to_str;
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    process,
+};
+
+// an empty directory for the test `name`, left over contents of an earlier run removed.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("git-starter-rust-{}-{}", name, process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// a bare git directory with no objects or refs yet and HEAD on master.
+pub fn temp_git_dir(name: &str) -> PathBuf {
+    let dir = temp_dir(name);
+    fs::create_dir_all(dir.join("objects")).unwrap();
+    fs::create_dir_all(dir.join("refs/heads")).unwrap();
+    fs::create_dir_all(dir.join("refs/tags")).unwrap();
+    fs::write(dir.join("HEAD"), "ref: refs/heads/master\n").unwrap();
+    dir
+}
+
+// the tree of `files`, each a path below it and the content of a regular file there.
+pub fn tree(store: &dyn ObjectStore, files: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut nodes = vec![];
+    let mut dirs: BTreeMap<&str, Vec<(&str, &[u8])>> = BTreeMap::new();
+    for &(path, content) in files {
+        match path.split_once('/') {
+            Some((dir, rest)) => dirs.entry(dir).or_default().push((rest, content)),
+            None => {
+                let blob = store.write("blob", content).unwrap();
+                nodes.push(GitTreeNode::new(path.to_string(), &blob, FileMode::Regular));
+            }
+        }
+    }
+    for (dir, files) in dirs {
+        let sub = tree(store, &files);
+        nodes.push(GitTreeNode::new(dir.to_string(), &sub, FileMode::Tree));
+    }
+    GitObject::new_tree(nodes).write(store).unwrap()
+}
+
+// a commit of `files` on top of `parents`, which need not exist, authored and
+// committed by "a" at `time`.
+pub fn commit(
+    store: &dyn ObjectStore,
+    parents: &[&[u8]],
+    files: &[(&str, &[u8])],
+    time: i64,
+    message: &str,
+) -> Vec<u8> {
+    let signature = Signature::new("a", "a@example.com", time, 0);
+    GitObject::Commit(GitCommitObject {
+        tree: tree(store, files),
+        parents: parents.iter().map(|p| p.to_vec()).collect(),
+        author: signature.clone(),
+        committer: signature,
+        extra_headers: vec![],
+        message: format!("{}\n", message).into_bytes(),
+    })
+    .write(store)
+    .unwrap()
+}
+
+// points the loose ref `name` at `id`, without a log entry.
+pub fn set_ref(git_dir: &Path, name: &str, id: &[u8]) {
+    let path = git_dir.join(name);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, format!("{}\n", hash_to_str(id))).unwrap();
+}
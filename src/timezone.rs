@@ -0,0 +1,375 @@
+use std::{env, fs, path::Path};
+
+// offset from UTC in minutes of the local timezone at `time` (unix seconds).
+// the zone is taken from $TZ or /etc/localtime; if neither can be read, UTC is assumed.
+pub fn local_tz_offset(time: i64) -> i32 {
+    let seconds = match env::var("TZ") {
+        Ok(tz) => tz_env_offset(&tz, time),
+        Err(_) => tzfile_offset(Path::new("/etc/localtime"), time),
+    };
+    seconds.map(|s| (s / 60) as i32).unwrap_or(0)
+}
+
+fn tz_env_offset(tz: &str, time: i64) -> Option<i64> {
+    let tz = tz.strip_prefix(':').unwrap_or(tz);
+    if tz.is_empty() {
+        return Some(0);
+    }
+    if tz.starts_with('/') {
+        return tzfile_offset(Path::new(tz), time);
+    }
+    let zoneinfo = Path::new("/usr/share/zoneinfo").join(tz);
+    if !tz.contains("..") && zoneinfo.is_file() {
+        return tzfile_offset(&zoneinfo, time);
+    }
+    PosixTz::parse(tz).map(|rule| rule.offset_at(time))
+}
+
+// reads a TZif file (see tzfile(5)) and returns the UTC offset in seconds at `time`.
+fn tzfile_offset(path: &Path, time: i64) -> Option<i64> {
+    let data = fs::read(path).ok()?;
+    let header = TzHeader::parse(&data)?;
+    let (header, body, time_size) = if header.version >= b'2' {
+        // skip the 32-bit block; the 64-bit one follows with its own header.
+        let rest = data.get(44 + header.block_len(4)..)?;
+        (TzHeader::parse(rest)?, rest.get(44..)?, 8)
+    } else {
+        (header, data.get(44..)?, 4)
+    };
+
+    let read_int = |buf: &[u8], size: usize| -> i64 {
+        if size == 8 {
+            let mut b = [0; 8];
+            b.copy_from_slice(&buf[..8]);
+            i64::from_be_bytes(b)
+        } else {
+            let mut b = [0; 4];
+            b.copy_from_slice(&buf[..4]);
+            i32::from_be_bytes(b) as i64
+        }
+    };
+
+    let times = body.get(..header.timecnt * time_size)?;
+    let indices = body.get(times.len()..times.len() + header.timecnt)?;
+    let types_start = times.len() + indices.len();
+    let types = body.get(types_start..types_start + header.typecnt * 6)?;
+    let utoff = |i: usize| types.get(i * 6..i * 6 + 4).map(|b| read_int(b, 4));
+    // for times after the last transition, the footer rule (v2+) describes the offset.
+    let footer = if time_size == 8 {
+        body.get(header.block_len(8)..).and_then(footer_rule)
+    } else {
+        None
+    };
+
+    let transitions = times.len() / time_size;
+    let last = (0..transitions)
+        .take_while(|&i| read_int(&times[i * time_size..], time_size) <= time)
+        .last();
+    match last {
+        Some(i) if i + 1 == transitions && footer.is_some() => {
+            footer.map(|rule| rule.offset_at(time))
+        }
+        Some(i) => utoff(indices[i] as usize),
+        None if transitions == 0 && footer.is_some() => footer.map(|rule| rule.offset_at(time)),
+        None => utoff(0),
+    }
+}
+
+fn footer_rule(footer: &[u8]) -> Option<PosixTz> {
+    // the footer is "\n<posix tz string>\n".
+    let footer = footer.strip_prefix(b"\n")?;
+    let end = footer.iter().position(|&c| c == b'\n')?;
+    PosixTz::parse(std::str::from_utf8(&footer[..end]).ok()?)
+}
+
+struct TzHeader {
+    version: u8,
+    isutcnt: usize,
+    isstdcnt: usize,
+    leapcnt: usize,
+    timecnt: usize,
+    typecnt: usize,
+    charcnt: usize,
+}
+
+impl TzHeader {
+    fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < 44 || &data[..4] != b"TZif" {
+            return None;
+        }
+        let count = |i: usize| {
+            let mut b = [0; 4];
+            b.copy_from_slice(&data[20 + i * 4..24 + i * 4]);
+            u32::from_be_bytes(b) as usize
+        };
+        Some(TzHeader {
+            version: data[4],
+            isutcnt: count(0),
+            isstdcnt: count(1),
+            leapcnt: count(2),
+            timecnt: count(3),
+            typecnt: count(4),
+            charcnt: count(5),
+        })
+    }
+
+    // length of the data block following the header, for the given transition time size.
+    fn block_len(&self, time_size: usize) -> usize {
+        self.timecnt * time_size
+            + self.timecnt
+            + self.typecnt * 6
+            + self.charcnt
+            + self.leapcnt * (time_size + 4)
+            + self.isstdcnt
+            + self.isutcnt
+    }
+}
+
+// a POSIX TZ rule such as "JST-9" or "CET-1CEST,M3.5.0,M10.5.0/3".
+// offsets are stored as seconds east of UTC.
+#[derive(Debug, PartialEq)]
+struct PosixTz {
+    std_offset: i64,
+    dst: Option<DstRule>,
+}
+
+#[derive(Debug, PartialEq)]
+struct DstRule {
+    offset: i64,
+    start: (TransitionDay, i64),
+    end: (TransitionDay, i64),
+}
+
+#[derive(Debug, PartialEq)]
+enum TransitionDay {
+    // Jn: day 1..=365, february 29th is never counted.
+    Julian(i64),
+    // n: day 0..=365, counting february 29th in leap years.
+    ZeroBased(i64),
+    // Mm.w.d: day d (0 = sunday) of week w (5 = last) of month m.
+    MonthWeekDay(i64, i64, i64),
+}
+
+impl PosixTz {
+    fn parse(s: &str) -> Option<Self> {
+        let mut rest = s;
+        skip_name(&mut rest)?;
+        // POSIX offsets count hours west of UTC.
+        let std_offset = -parse_time(&mut rest)?;
+        if rest.is_empty() {
+            return Some(PosixTz {
+                std_offset,
+                dst: None,
+            });
+        }
+        skip_name(&mut rest)?;
+        let offset = if rest.is_empty() || rest.starts_with(',') {
+            std_offset + 3600
+        } else {
+            -parse_time(&mut rest)?
+        };
+        // without explicit rules, fall back to the US rules like glibc does.
+        let (start, end) = if rest.is_empty() {
+            (
+                (TransitionDay::MonthWeekDay(3, 2, 0), 7200),
+                (TransitionDay::MonthWeekDay(11, 1, 0), 7200),
+            )
+        } else {
+            rest = rest.strip_prefix(',')?;
+            let start = parse_transition(&mut rest)?;
+            rest = rest.strip_prefix(',')?;
+            let end = parse_transition(&mut rest)?;
+            (start, end)
+        };
+        if !rest.is_empty() {
+            return None;
+        }
+        Some(PosixTz {
+            std_offset,
+            dst: Some(DstRule { offset, start, end }),
+        })
+    }
+
+    fn offset_at(&self, time: i64) -> i64 {
+        let dst = match &self.dst {
+            Some(dst) => dst,
+            None => return self.std_offset,
+        };
+        let year = civil_from_days((time + self.std_offset).div_euclid(86400)).0;
+        // start is given in standard time, end in daylight saving time.
+        let start = transition_time(year, &dst.start) - self.std_offset;
+        let end = transition_time(year, &dst.end) - dst.offset;
+        let in_dst = if start < end {
+            start <= time && time < end
+        } else {
+            !(end <= time && time < start)
+        };
+        if in_dst {
+            dst.offset
+        } else {
+            self.std_offset
+        }
+    }
+}
+
+fn skip_name(s: &mut &str) -> Option<()> {
+    let len = if let Some(quoted) = s.strip_prefix('<') {
+        quoted.find('>')? + 2
+    } else {
+        s.find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(s.len())
+    };
+    if len < 3 {
+        return None;
+    }
+    *s = &s[len..];
+    Some(())
+}
+
+// parses "[+-]hh[:mm[:ss]]" into seconds.
+fn parse_time(s: &mut &str) -> Option<i64> {
+    let sign = match s.as_bytes().first() {
+        Some(b'-') => {
+            *s = &s[1..];
+            -1
+        }
+        Some(b'+') => {
+            *s = &s[1..];
+            1
+        }
+        _ => 1,
+    };
+    let mut seconds = 0;
+    for (i, unit) in [3600, 60, 1].iter().enumerate() {
+        if i > 0 {
+            match s.strip_prefix(':') {
+                Some(r) => *s = r,
+                None => break,
+            }
+        }
+        seconds += parse_number(s)? * unit;
+    }
+    Some(sign * seconds)
+}
+
+fn parse_number(s: &mut &str) -> Option<i64> {
+    let len = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let n = s[..len].parse().ok()?;
+    *s = &s[len..];
+    Some(n)
+}
+
+fn parse_transition(s: &mut &str) -> Option<(TransitionDay, i64)> {
+    let day = if let Some(r) = s.strip_prefix('J') {
+        *s = r;
+        TransitionDay::Julian(parse_number(s)?)
+    } else if let Some(r) = s.strip_prefix('M') {
+        *s = r;
+        let month = parse_number(s)?;
+        *s = s.strip_prefix('.')?;
+        let week = parse_number(s)?;
+        *s = s.strip_prefix('.')?;
+        let weekday = parse_number(s)?;
+        TransitionDay::MonthWeekDay(month, week, weekday)
+    } else {
+        TransitionDay::ZeroBased(parse_number(s)?)
+    };
+    let time = match s.strip_prefix('/') {
+        Some(r) => {
+            *s = r;
+            parse_time(s)?
+        }
+        None => 7200,
+    };
+    Some((day, time))
+}
+
+// local time in seconds since the epoch at which the transition happens in `year`.
+fn transition_time(year: i64, transition: &(TransitionDay, i64)) -> i64 {
+    let jan1 = days_from_civil(year, 1, 1);
+    let leap = is_leap_year(year);
+    let day = match transition.0 {
+        TransitionDay::Julian(n) => jan1 + n - 1 + if leap && n >= 60 { 1 } else { 0 },
+        TransitionDay::ZeroBased(n) => jan1 + n,
+        TransitionDay::MonthWeekDay(month, week, weekday) => {
+            let first = days_from_civil(year, month, 1);
+            // 1970-01-01 was a thursday.
+            let first_weekday = (first + 4).rem_euclid(7);
+            let mut day = first + (weekday - first_weekday).rem_euclid(7) + (week - 1) * 7;
+            let next_month = if month == 12 {
+                days_from_civil(year + 1, 1, 1)
+            } else {
+                days_from_civil(year, month + 1, 1)
+            };
+            while day >= next_month {
+                day -= 7;
+            }
+            day
+        }
+    };
+    day * 86400 + transition.1
+}
+
+fn is_leap_year(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+// days since 1970-01-01 of the given proleptic gregorian date.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::timezone::*;
+
+    #[test]
+    fn test_posix_tz_fixed() {
+        let tz = PosixTz::parse("JST-9").unwrap();
+        assert_eq!(tz.offset_at(1600000000), 9 * 3600);
+        let tz = PosixTz::parse("<+0530>-5:30").unwrap();
+        assert_eq!(tz.offset_at(1600000000), 5 * 3600 + 1800);
+    }
+
+    #[test]
+    fn test_posix_tz_dst() {
+        let tz = PosixTz::parse("CET-1CEST,M3.5.0,M10.5.0/3").unwrap();
+        // 2020-01-15 and 2020-07-15.
+        assert_eq!(tz.offset_at(1579046400), 3600);
+        assert_eq!(tz.offset_at(1594771200), 7200);
+        // 2020-03-29 00:59:59 UTC is still winter time, 01:00:00 UTC is summer time.
+        assert_eq!(tz.offset_at(1585443599), 3600);
+        assert_eq!(tz.offset_at(1585443600), 7200);
+
+        // southern hemisphere: daylight saving time spans the new year.
+        let tz = PosixTz::parse("AEST-10AEDT,M10.1.0,M4.1.0/3").unwrap();
+        assert_eq!(tz.offset_at(1579046400), 11 * 3600);
+        assert_eq!(tz.offset_at(1594771200), 10 * 3600);
+    }
+
+    #[test]
+    fn test_civil_days() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2020, 3, 1), 18322);
+        assert_eq!(civil_from_days(18322), (2020, 3, 1));
+    }
+}
@@ -2,10 +2,6 @@ use flate2::{write::ZlibEncoder, Compression};
 use sha1::{Digest, Sha1};
 use std::io::Write;
 
-pub fn hash_to_path_str(hash: &str) -> String {
-    format!(".git/objects/{}/{}", &hash[0..2], &hash[2..])
-}
-
 pub fn hash_to_str(hash: &[u8]) -> String {
     hash.iter()
         .map(|n| format!("{:02x}", n))
@@ -18,6 +14,18 @@ pub fn hash(content: &[u8]) -> Vec<u8> {
     hasher.finalize().to_vec()
 }
 
+pub fn object_header(type_name: &str, size: usize) -> Vec<u8> {
+    format!("{} {}\0", type_name, size).into_bytes()
+}
+
+// the object id: sha-1 over "<type> <size>\0" followed by the raw content.
+pub fn object_hash(type_name: &str, content: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha1::new();
+    hasher.update(object_header(type_name, content.len()));
+    hasher.update(content);
+    hasher.finalize().to_vec()
+}
+
 pub fn str_to_hash(s: String) -> Vec<u8> {
     s.as_bytes()
         .chunks(2)
@@ -34,6 +42,198 @@ pub fn compress(content: &[u8]) -> Vec<u8> {
     bytes.to_vec()
 }
 
+// crc-32 (ieee 802.3), as recorded for each entry of a pack index.
+pub fn crc32(data: &[u8]) -> u32 {
+    crc32_update(0, data)
+}
+
+// continues the crc-32 `crc` of the data before with `data`.
+pub fn crc32_update(crc: u32, data: &[u8]) -> u32 {
+    let mut c = !crc;
+    for &b in data {
+        c = CRC32_TABLE[((c ^ b as u32) & 0xff) as usize] ^ (c >> 8);
+    }
+    !c
+}
+
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {
+                0xedb8_8320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+}
+
+// big-endian base-128 where each continuation byte adds one, as used for OFS_DELTA
+// base distances in packs and path prefixes in version 4 indexes.
+pub fn encode_varint(value: u64) -> Vec<u8> {
+    let mut v = vec![(value & 0x7f) as u8];
+    let mut value = value >> 7;
+    while value > 0 {
+        value -= 1;
+        v.push(0x80 | (value & 0x7f) as u8);
+        value >>= 7;
+    }
+    v.reverse();
+    v
+}
+
+// the value at the start of `data` and the number of bytes it took, or None when it is
+// cut short or overflows.
+pub fn decode_varint(data: &[u8]) -> Option<(u64, usize)> {
+    let mut c = *data.first()?;
+    let mut value = (c & 0x7f) as u64;
+    let mut i = 1;
+    while c & 0x80 != 0 {
+        c = *data.get(i)?;
+        i += 1;
+        value = value.checked_add(1)?.checked_mul(128)? | (c & 0x7f) as u64;
+    }
+    Some((value, i))
+}
+
+// whether a branch or tag name is allowed below refs/, like git check-ref-format.
+pub fn is_valid_ref_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('-')
+        && !name.starts_with('/')
+        && !name.ends_with('/')
+        && !name.ends_with(".lock")
+        && !name.ends_with('.')
+        && !name.contains("..")
+        && !name.contains("//")
+        && !name.contains("@{")
+        && !name
+            .chars()
+            .any(|c| c.is_ascii_control() || " ~^:?*[\\".contains(c))
+}
+
+// matches a glob like git's wildmatch: `?`, `*`, `[...]` classes and `\` escapes. with
+// `pathname` they stay within a path component and only `**` crosses `/`.
+pub fn wildmatch(pattern: &str, text: &str, pathname: bool) -> bool {
+    matches(pattern.as_bytes(), text.as_bytes(), pathname) == Wild::Match
+}
+
+// as in git, a failed match also tells the `*` that called it when trying a longer
+// stretch of text can not help, which keeps patterns with many stars from taking
+// exponential time.
+#[derive(PartialEq)]
+enum Wild {
+    Match,
+    NoMatch,
+    // no suffix of the text can match: the pattern needs more than is left.
+    AbortAll,
+    // only a `**` further out can help, as a `*` would have to cross a `/`.
+    AbortToStarStar,
+}
+
+fn matches(p: &[u8], t: &[u8], pathname: bool) -> Wild {
+    let crosses = |c: u8| pathname && c == b'/';
+    let first = match p.first() {
+        None if t.is_empty() => return Wild::Match,
+        None => return Wild::NoMatch,
+        Some(&c) => c,
+    };
+    if first != b'*' && t.is_empty() {
+        return Wild::AbortAll;
+    }
+    let literal = |c: u8, len: usize| {
+        if t[0] == c {
+            matches(&p[len..], &t[1..], pathname)
+        } else {
+            Wild::NoMatch
+        }
+    };
+    match first {
+        b'*' => {
+            let stars = p.iter().take_while(|&&c| c == b'*').count();
+            let slash = !pathname || stars > 1;
+            let rest = &p[stars..];
+            // `**/` also matches no directory at all.
+            if pathname
+                && slash
+                && rest.first() == Some(&b'/')
+                && matches(&rest[1..], t, pathname) == Wild::Match
+            {
+                return Wild::Match;
+            }
+            if rest.is_empty() {
+                return if !slash && t.contains(&b'/') {
+                    Wild::AbortToStarStar
+                } else {
+                    Wild::Match
+                };
+            }
+            for k in 0..=t.len() {
+                match matches(rest, &t[k..], pathname) {
+                    Wild::NoMatch if !slash && t.get(k) == Some(&b'/') => {
+                        return Wild::AbortToStarStar
+                    }
+                    Wild::NoMatch => {}
+                    Wild::AbortToStarStar if slash => {}
+                    result => return result,
+                }
+            }
+            Wild::AbortAll
+        }
+        b'?' if crosses(t[0]) => Wild::NoMatch,
+        b'?' => matches(&p[1..], &t[1..], pathname),
+        b'[' => match match_class(&p[1..], t[0], b"!^") {
+            Some((true, len)) if !crosses(t[0]) => matches(&p[1 + len..], &t[1..], pathname),
+            Some(_) => Wild::NoMatch,
+            // without a closing `]` it is an ordinary character.
+            None => literal(b'[', 1),
+        },
+        b'\\' if p.len() > 1 => literal(p[1], 2),
+        c => literal(c, 1),
+    }
+}
+
+// whether `c` is in the class `p` starts with (just after the `[`), and the length of
+// the class including the `]`. a class starting with one of `negations` is negated.
+fn match_class(p: &[u8], c: u8, negations: &[u8]) -> Option<(bool, usize)> {
+    let negate = p.first().is_some_and(|n| negations.contains(n));
+    let mut i = negate as usize;
+    let start = i;
+    let mut hit = false;
+    loop {
+        let mut lo = *p.get(i)?;
+        // a `]` right at the start is part of the class.
+        if lo == b']' && i > start {
+            break;
+        }
+        if lo == b'\\' {
+            i += 1;
+            lo = *p.get(i)?;
+        }
+        match (p.get(i + 1), p.get(i + 2)) {
+            (Some(b'-'), Some(&hi)) if hi != b']' => {
+                hit |= lo <= c && c <= hi;
+                i += 3;
+            }
+            _ => {
+                hit |= lo == c;
+                i += 1;
+            }
+        }
+    }
+    Some((hit != negate, i + 1))
+}
+
 #[cfg(test)]
 mod test {
     use crate::utils::*;
@@ -48,4 +248,60 @@ mod test {
         assert_eq!(h[3], 46);
         assert_eq!(h.len(), 20);
     }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn test_varint() {
+        assert_eq!(encode_varint(256), vec![0x81, 0x00]);
+        for &v in &[0u64, 1, 127, 128, 16511, 16512, 1 << 40, u64::MAX] {
+            let data = encode_varint(v);
+            assert_eq!(decode_varint(&data), Some((v, data.len())));
+        }
+        assert_eq!(decode_varint(&[0x81]), None);
+        assert_eq!(decode_varint(&[0xff; 11]), None);
+    }
+
+    #[test]
+    fn test_wildmatch() {
+        assert!(wildmatch("**/work/**", "/tmp/x/work/.git", true));
+        assert!(wildmatch("/tmp/*/.git", "/tmp/x/.git", true));
+        assert!(!wildmatch("/tmp/*/.git", "/tmp/x/y/.git", true));
+        assert!(wildmatch("*.c", "src/a.c", false));
+        assert!(!wildmatch("*.c", "src/a.c", true));
+        assert!(wildmatch("**/a.c", "a.c", true));
+        assert!(wildmatch("*.py[cod]", "x.pyc", true));
+        assert!(!wildmatch("*.py[cod]", "x.pyx", true));
+        assert!(wildmatch("[!a-c]?", "dz", true));
+        assert!(!wildmatch("[!a-c]?", "bz", true));
+        assert!(wildmatch("[]]", "]", true));
+        assert!(wildmatch("\\*", "*", true));
+        assert!(!wildmatch("\\*", "x", true));
+        assert!(wildmatch("a[b", "a[b", true));
+        assert!(wildmatch("a/**/b", "a/b", true));
+        assert!(wildmatch("a/**/b", "a/x/y/b", true));
+        assert!(!wildmatch("a/*/b", "a/x/y/b", true));
+        assert!(wildmatch("a/*/b", "a/x/y/b", false));
+        // many stars against a long name must not take exponential time.
+        let name = "a".repeat(60);
+        assert!(!wildmatch("*a*a*a*a*a*a*a*b", &name, true));
+        assert!(!wildmatch("*a*a*a*a*a*a*a*b", &name, false));
+        assert!(wildmatch("*a*a*a*a*a*a*a*a", &name, true));
+    }
+
+    #[test]
+    fn test_is_valid_ref_name() {
+        for name in &["master", "feature/x", "v1.0", "a-b_c"] {
+            assert!(is_valid_ref_name(name), "{}", name);
+        }
+        for name in &[
+            "", "-x", "/x", "x/", "x.lock", "x.", "a..b", "a//b", "a@{b", "a b", "a~1", "a:b",
+        ] {
+            assert!(!is_valid_ref_name(name), "{}", name);
+        }
+    }
 }
@@ -0,0 +1,253 @@
+use crate::git_object::*;
+use crate::ignore::IgnoreRules;
+use crate::index::{IndexEntry, StatData};
+use crate::object_store::ObjectStore;
+use crate::object_stream::hash_stream;
+use crate::refs::read_ref;
+use crate::repository::Repository;
+use std::{fs, io, io::BufReader, path::Path};
+
+type Result<T> = std::result::Result<T, GitObjectError>;
+
+// a path found in the work tree, relative to its top. a directory stands for all of
+// its contents when it is ignored or holds another repository.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorkTreeEntry {
+    pub path: String,
+    pub is_dir: bool,
+    pub ignored: bool,
+}
+
+// what is below `dir` ("" for the whole work tree), sorted by path. ignored paths are
+// only listed with `with_ignored`; `.git` never is.
+pub fn list_work_tree(
+    work_tree: &Path,
+    dir: &str,
+    ignore: &mut IgnoreRules,
+    with_ignored: bool,
+) -> Result<Vec<WorkTreeEntry>> {
+    let mut found = vec![];
+    let mut dirs = vec![dir.to_string()];
+    while let Some(dir) = dirs.pop() {
+        let entries = match fs::read_dir(work_tree.join(&dir)) {
+            Ok(e) => e,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(_) if !work_tree.join(&dir).is_dir() => continue,
+            Err(e) => return Err(GitObjectError::IOError(e)),
+        };
+        for entry in entries {
+            let entry = entry?;
+            let name = match entry.file_name().into_string() {
+                Ok(n) if n != ".git" => n,
+                Ok(_) => continue,
+                Err(name) => {
+                    let path = Path::new(&dir).join(name);
+                    eprintln!("warning: ignoring {:?}: path is not valid UTF-8", path);
+                    continue;
+                }
+            };
+            let path = if dir.is_empty() {
+                name
+            } else {
+                format!("{}/{}", dir, name)
+            };
+            let is_dir = entry.file_type()?.is_dir();
+            let ignored = ignore.is_excluded(&path, is_dir);
+            if ignored && !with_ignored {
+                continue;
+            }
+            if is_dir && !ignored && !entry.path().join(".git").exists() {
+                dirs.push(path);
+                continue;
+            }
+            found.push(WorkTreeEntry {
+                path,
+                is_dir,
+                ignored,
+            });
+        }
+    }
+    found.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(found)
+}
+
+// an index entry for `path` as it is in the work tree. files and symlinks become blobs,
+// written to `store` with `write` and otherwise only hashed; another repository becomes
+// a gitlink to the commit it has checked out.
+pub fn entry_from_work_tree(
+    store: &dyn ObjectStore,
+    work_tree: &Path,
+    path: &str,
+    write: bool,
+) -> Result<IndexEntry> {
+    let full = work_tree.join(path);
+    let metadata = fs::symlink_metadata(&full)?;
+    let (id, mode) = match FileMode::from_metadata(&metadata) {
+        FileMode::Tree => {
+            let repo = Repository::discover_from(&full, None, None)?;
+            let head = read_ref(&repo.git_dir, "HEAD")?.ok_or_else(|| {
+                GitObjectError::NotFoundError(format!(
+                    "'{}/' does not have a commit checked out",
+                    path
+                ))
+            })?;
+            (head, FileMode::Gitlink)
+        }
+        FileMode::Symlink => {
+            let blob = GitObject::Blob(read_link_target(&full)?);
+            let id = if write {
+                blob.write(store)?
+            } else {
+                blob.to_hash()
+            };
+            (id, FileMode::Symlink)
+        }
+        mode => {
+            // streamed like hash-object, so that large files are never held in memory.
+            let mut reader = BufReader::new(fs::File::open(&full)?);
+            let id = if write {
+                store.write_stream("blob", metadata.len(), &mut reader)?
+            } else {
+                hash_stream("blob", metadata.len(), reader)?
+            };
+            (id, mode)
+        }
+    };
+    let mut entry = IndexEntry::new(path, &id, mode);
+    entry.stat = StatData::from_metadata(&metadata);
+    Ok(entry)
+}
+
+// whether the file can be taken to be unchanged from its stat data alone. an entry
+// whose file changed no earlier than the index was written could have been changed
+// again within the same timestamp, so it is not trusted ("racy git").
+pub fn is_stat_clean(
+    entry: &IndexEntry,
+    metadata: &fs::Metadata,
+    index_mtime: Option<(u32, u32)>,
+) -> bool {
+    let mode = match FileMode::from_metadata(metadata) {
+        FileMode::Tree => FileMode::Gitlink,
+        mode => mode,
+    };
+    let racy = index_mtime.is_some_and(|m| (entry.stat.mtime, entry.stat.mtime_nsec) >= m);
+    mode == entry.mode && StatData::from_metadata(metadata) == entry.stat && !racy
+}
+
+// when the file at `path` was last modified, as the index records it.
+pub fn file_mtime(path: &Path) -> Option<(u32, u32)> {
+    let stat = StatData::from_metadata(&fs::metadata(path).ok()?);
+    Some((stat.mtime, stat.mtime_nsec))
+}
+
+// removes the directories above `path` that are left empty, up to the top.
+pub fn remove_empty_parents(work_tree: &Path, path: &str) {
+    let mut dir = path;
+    while let Some(i) = dir.rfind('/') {
+        dir = &dir[..i];
+        if fs::remove_dir(work_tree.join(dir)).is_err() {
+            break;
+        }
+    }
+}
+
+// a symlink is stored as a blob holding its target path.
+#[cfg(unix)]
+pub fn read_link_target(path: &Path) -> Result<Vec<u8>> {
+    use std::os::unix::ffi::OsStrExt;
+    Ok(fs::read_link(path)?.as_os_str().as_bytes().to_vec())
+}
+
+#[cfg(not(unix))]
+pub fn read_link_target(path: &Path) -> Result<Vec<u8>> {
+    Ok(fs::read_link(path)?.to_string_lossy().as_bytes().to_vec())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::object_store::LooseObjectStore;
+    use crate::test_utils::temp_dir;
+    use crate::utils::object_hash;
+    use crate::worktree::*;
+
+    #[test]
+    fn test_work_tree() {
+        let root = temp_dir("worktree");
+        fs::create_dir_all(root.join(".git/objects")).unwrap();
+        fs::create_dir_all(root.join("src/deep")).unwrap();
+        fs::create_dir_all(root.join("target/debug")).unwrap();
+        fs::write(root.join(".gitignore"), "target/\n*.log\n").unwrap();
+        fs::write(root.join("src/main.rs"), "fn main() {}\n").unwrap();
+        fs::write(root.join("src/deep/a.log"), "log\n").unwrap();
+        fs::write(root.join("src/deep/b.txt"), "b\n").unwrap();
+        fs::write(root.join("target/debug/app"), "binary").unwrap();
+        fs::write(root.join(".git/HEAD"), "ref: refs/heads/master\n").unwrap();
+
+        let mut ignore = IgnoreRules::load(&root, &root.join(".git"), &Default::default());
+        let list = |ignore: &mut IgnoreRules, dir: &str, with_ignored: bool| -> Vec<String> {
+            list_work_tree(&root, dir, ignore, with_ignored)
+                .unwrap()
+                .into_iter()
+                .map(|e| e.path + if e.is_dir { "/" } else { "" })
+                .collect()
+        };
+        assert_eq!(
+            list(&mut ignore, "", false),
+            vec![".gitignore", "src/deep/b.txt", "src/main.rs"]
+        );
+        assert_eq!(
+            list(&mut ignore, "", true),
+            vec![
+                ".gitignore",
+                "src/deep/a.log",
+                "src/deep/b.txt",
+                "src/main.rs",
+                "target/"
+            ]
+        );
+        assert_eq!(list(&mut ignore, "src/deep", false), vec!["src/deep/b.txt"]);
+        // a name that is not utf-8 is left out with a warning.
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+            let name = std::ffi::OsStr::from_bytes(b"bad\xff");
+            fs::write(root.join("src/deep").join(name), "").unwrap();
+            assert_eq!(list(&mut ignore, "src/deep", false), vec!["src/deep/b.txt"]);
+            fs::remove_file(root.join("src/deep").join(name)).unwrap();
+        }
+        assert!(list(&mut ignore, "src/main.rs", false).is_empty());
+
+        let store = LooseObjectStore::new(&root.join(".git/objects"));
+        let entry = entry_from_work_tree(&store, &root, "src/main.rs", false).unwrap();
+        assert_eq!(entry.id, object_hash("blob", b"fn main() {}\n"));
+        assert_eq!(entry.mode, FileMode::Regular);
+        assert_eq!(entry.stat.size, 13);
+        assert!(!store.contains(&crate::utils::hash_to_str(&entry.id)));
+        entry_from_work_tree(&store, &root, "src/main.rs", true).unwrap();
+        assert!(store.contains(&crate::utils::hash_to_str(&entry.id)));
+
+        let metadata = fs::symlink_metadata(root.join("src/main.rs")).unwrap();
+        assert!(is_stat_clean(
+            &entry,
+            &metadata,
+            Some((entry.stat.mtime + 1, 0))
+        ));
+        assert!(!is_stat_clean(
+            &entry,
+            &metadata,
+            Some((entry.stat.mtime, 0))
+        ));
+        assert!(is_stat_clean(&entry, &metadata, None));
+        fs::write(root.join("src/main.rs"), "fn main() { }\n").unwrap();
+        let metadata = fs::symlink_metadata(root.join("src/main.rs")).unwrap();
+        assert!(!is_stat_clean(&entry, &metadata, None));
+
+        remove_empty_parents(&root, "src/deep/b.txt");
+        assert!(root.join("src/deep").exists());
+        fs::remove_file(root.join("src/deep/a.log")).unwrap();
+        fs::remove_file(root.join("src/deep/b.txt")).unwrap();
+        remove_empty_parents(&root, "src/deep/b.txt");
+        assert!(!root.join("src/deep").exists() && root.join("src").exists());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -1,55 +1,70 @@
 use crate::git_object::*;
-use std::{fs, path::Path};
+use crate::index::Index;
+use crate::repository::open_repository;
+use crate::utils::hash_to_str;
 
 #[derive(Default)]
-struct WriteTreeOption {}
-
-pub fn write_tree(commands: &[String]) {
-    let _option = parse_options(commands);
-    let path = Path::new(".");
-    let nodes = write_tree_rec(&path);
-    // println!("nodes: {:?}", nodes);
-    let tree = GitObject::new_tree(nodes);
-    tree.write().unwrap();
-    println!("{}", tree.to_hash_str());
+struct WriteTreeOption<'a> {
+    missing_ok: bool,
+    prefix: Option<&'a str>,
 }
 
-fn write_tree_rec(path: &Path) -> Vec<GitTreeNode> {
-    path.read_dir()
-        .expect("read_dir call failed")
-        .filter_map(|entry| match entry {
-            Ok(entry) if !entry.path().starts_with("./.git") => {
-                // println!("{:?}", entry.path());
-                if entry.file_type().unwrap().is_dir() {
-                    let tree = GitObject::Tree(write_tree_rec(&entry.path()));
-                    Some(GitTreeNode::new(
-                        entry.file_name().into_string().unwrap(),
-                        &tree.to_hash(),
-                        100644,
-                        GitNodeType::Tree,
-                    ))
-                } else {
-                    // println!("read blob: {}", entry.path().display());
-                    let content = fs::read(entry.path()).unwrap();
-                    let blob = GitObject::Blob(content);
-                    blob.write().unwrap();
-                    Some(GitTreeNode::new(
-                        entry.file_name().into_string().unwrap(),
-                        &blob.to_hash(),
-                        100644,
-                        GitNodeType::Blob,
-                    ))
-                }
+pub fn write_tree(commands: &[String]) {
+    let option = parse_options(commands);
+    let repo = match open_repository() {
+        Some(r) => r,
+        None => return,
+    };
+    let index_path = repo.index_path();
+    let mut index = match Index::lock_if_free(&index_path) {
+        Ok(i) => i,
+        Err(e) => {
+            println!("fatal: {:?}", e);
+            return;
+        }
+    };
+    if index.has_conflicts() {
+        for entry in index.entries.iter().filter(|e| e.stage != 0) {
+            println!("{}: unmerged ({})", entry.path, hash_to_str(&entry.id));
+        }
+        println!("fatal: git-write-tree: error building trees");
+        return;
+    }
+    let store = repo.object_store();
+    let before = index.cache_tree.clone();
+    let mut tree = index.cache_tree.take().unwrap_or_default();
+    if let Err(e) = tree.update(&store, &index.entries, option.missing_ok) {
+        match e {
+            GitObjectError::NotFoundError(message) => println!("error: {}", message),
+            e => println!("error: {:?}", e),
+        }
+        println!("fatal: git-write-tree: error building trees");
+        return;
+    }
+    let id = match option.prefix {
+        Some(prefix) => match tree.find(prefix) {
+            Some(t) => t.id.clone(),
+            None => {
+                println!("fatal: git-write-tree: prefix {} not found", prefix);
+                return;
             }
-            _ => None,
-        })
-        .collect()
+        },
+        None => tree.id.clone(),
+    };
+    // the trees just built are kept for the next time, when the index can be written.
+    if before.as_ref() != Some(&tree) {
+        index.cache_tree = Some(tree);
+        let _ = index.commit();
+    }
+    println!("{}", hash_to_str(&id));
 }
-#[allow(clippy::match_single_binding)]
-fn parse_options(commands: &[String]) -> WriteTreeOption {
-    let option: WriteTreeOption = Default::default();
+
+fn parse_options(commands: &[String]) -> WriteTreeOption<'_> {
+    let mut option: WriteTreeOption = Default::default();
     for token in &commands[2..] {
         match &token[..] {
+            "--missing-ok" => option.missing_ok = true,
+            _ if token.starts_with("--prefix=") => option.prefix = Some(&token[9..]),
             _ => println!("ignore option {}", token),
         }
     }